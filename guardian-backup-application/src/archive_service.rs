@@ -0,0 +1,64 @@
+use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
+use guardian_backup_domain::model::files::directory_metadata::DirectoryMetadata;
+use guardian_backup_domain::model::files::file_metadata::FileMetadata;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+pub trait ArchiveService {
+    type Writer: ArchiveWriter;
+    type Reader: ArchiveReader;
+    type Error: Error + 'static;
+
+    /// Opens an archive writing to `target`, or to stdout if no target is given
+    async fn create_writer(target: Option<&Path>) -> Result<Self::Writer, Self::Error>;
+    /// Opens an archive reading from `source`, or from stdin if no source is given
+    async fn open_reader(source: Option<&Path>) -> Result<Self::Reader, Self::Error>;
+}
+
+pub trait ArchiveWriter {
+    type Error: Error + 'static;
+
+    async fn append_directory(
+        &mut self,
+        path: &Path,
+        metadata: &DirectoryMetadata,
+    ) -> Result<(), Self::Error>;
+    async fn append_file(
+        &mut self,
+        path: &Path,
+        metadata: &FileMetadata,
+        blob: impl BlobFetch,
+    ) -> Result<(), Self::Error>;
+    async fn finish(self) -> Result<(), Self::Error>;
+}
+
+pub trait ArchiveReader {
+    type Error: Error + 'static;
+    /// Content of a file entry, read from the archive as it goes
+    type Blob: BlobFetch;
+
+    /// Returns the next directory or regular file of the archive, other entry types are skipped.
+    /// The content of a file entry is only readable until the next entry is requested.
+    async fn next_entry(&mut self) -> Result<Option<ArchiveEntry<Self::Blob>>, Self::Error>;
+}
+
+#[derive(Debug)]
+pub struct ArchiveEntry<B> {
+    pub path: PathBuf,
+    pub kind: ArchiveEntryKind<B>,
+}
+
+#[derive(Debug)]
+pub enum ArchiveEntryKind<B> {
+    Directory { metadata: DirectoryMetadata },
+    File { metadata: FileMetadata, content: B },
+}
+
+impl<B> ArchiveEntry<B> {
+    pub fn last_modified(&self) -> Option<u64> {
+        match &self.kind {
+            ArchiveEntryKind::Directory { .. } => None,
+            ArchiveEntryKind::File { metadata, .. } => Some(metadata.last_modified()),
+        }
+    }
+}
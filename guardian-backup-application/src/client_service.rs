@@ -1,11 +1,28 @@
+use crate::archive_service::{ArchiveEntryKind, ArchiveReader, ArchiveService, ArchiveWriter};
 use crate::client_service::MainClientServiceError::{BlobRepositoryError, FileServiceError};
+use crate::config_service::ConfigService;
 use crate::encoding_service::EncodingService;
 use crate::file_service::File;
 use crate::file_service::FileService;
 use crate::in_memory_repositories::backup_repository::InMemoryBackupRepository;
 use crate::in_memory_repositories::blob_repository::InMemoryBlobFetch;
 use crate::in_memory_repositories::blob_repository::InMemoryBlobRepository;
-use crate::model::client_model::{ClientBackupCommand, ClientCommand, ClientSubcommand};
+use crate::in_memory_repositories::user_repository::InMemoryUserRepository;
+use crate::model::authentication::DeviceToken;
+use crate::model::backup_checkpoint::BackupCheckpoint;
+use crate::model::call::MAX_SNAPSHOT_PAGE;
+use crate::model::client_config::ClientConfig;
+use crate::model::client_model::{
+    ClientBackupCommand, ClientCommand, ClientDeviceCommand, ClientSnapshotCommand,
+    ClientSubcommand, FilePattern, FileVersionSelector,
+};
+use crate::model::command_output::{
+    BackupList, BackupSummary, CommandOutput, ConfigSummary, DeviceTokenCreated, DiffEntry,
+    ExportResult, FileHistory, FileRestored, FileVersion, FindResult, FoundInSnapshot, FoundPath,
+    NodeKind, RestoreResult, SnapshotCreated, SnapshotDetails, SnapshotDiff, SnapshotList,
+    SnapshotSummary, TreeEntry,
+};
+use crate::server_config::MIN_GRACE_PERIOD;
 use guardian_backup_domain::hash_service::HashService;
 use guardian_backup_domain::hash_service::Hasher;
 use guardian_backup_domain::hash_service::PendingHashB;
@@ -15,22 +32,35 @@ use guardian_backup_domain::model::backup::schedule_rule::ScheduleRule;
 use guardian_backup_domain::model::backup::snapshot::Snapshot;
 use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
 use guardian_backup_domain::model::blobs::blob_identifier::BlobIdentifier;
-use guardian_backup_domain::model::device_identifier::DeviceIdentifier;
-use guardian_backup_domain::model::duration::Duration;
+use guardian_backup_domain::model::duration::{Duration, MONTH};
+use guardian_backup_domain::model::files::file_hash::FileHash;
+use guardian_backup_domain::model::files::file_metadata::FileMetadata;
 use guardian_backup_domain::model::files::file_tree::{FileTreeDiffType, FileTreeNode};
 use guardian_backup_domain::model::timestamp::Timestamp;
 use guardian_backup_domain::model::user_identifier::UserIdentifier;
 use guardian_backup_domain::repositories::backup_repository::BackupRepository;
 use guardian_backup_domain::repositories::blob_repository::BlobRepository;
+use guardian_backup_domain::repositories::user_repository::UserRepository;
 use regex::Regex;
+use std::collections::HashSet;
 use std::error::Error;
+use std::ffi::OsString;
 use std::fmt::{Display, Formatter};
 use std::marker::PhantomData;
-use std::path::Path;
 use std::path::PathBuf;
-use std::str::FromStr;
+use std::path::{Component, Path};
+use std::time::Instant;
 use std::vec;
 
+/// Time between two checkpoints of a backup being uploaded
+const CHECKPOINT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+/// Largest archive entry an import holds in memory, larger ones are spooled to a temporary file
+const IMPORT_MEMORY_LIMIT: u64 = 4 * 1024 * 1024;
+
+#[cfg(any(test, feature = "mocks"))]
+use crate::model::mocks::mock_archive_service::MockArchiveService;
+#[cfg(any(test, feature = "mocks"))]
+use crate::model::mocks::mock_config_service::MockConfigService;
 #[cfg(any(test, feature = "mocks"))]
 use crate::model::mocks::mock_encoder_service::MockEncoderService;
 #[cfg(any(test, feature = "mocks"))]
@@ -41,41 +71,90 @@ use crate::model::mocks::mock_hash_service::MOCK_HASHER;
 pub trait ClientService {
     type Error: Error;
 
-    async fn handle_command(&mut self, command: ClientCommand) -> Result<(), Self::Error>;
+    async fn handle_command(
+        &mut self,
+        command: ClientCommand,
+    ) -> Result<CommandOutput, Self::Error>;
 }
 
 pub struct MainClientService<
     B: BackupRepository,
     L: BlobRepository,
+    U: UserRepository,
     E: EncodingService,
     F: FileService,
+    A: ArchiveService,
+    C: ConfigService,
 > {
     user: UserIdentifier,
     backup_repository: B,
     blob_repository: L,
+    user_repository: U,
     encoding_service: PhantomData<E>,
     file_service: PhantomData<F>,
+    archive_service: PhantomData<A>,
+    config_service: PhantomData<C>,
     hash_service: HashService,
+    /// Settings in effect, possibly overridden compared to the stored ones
+    config: ClientConfig,
+    /// Directory holding encoded file trees named after their hash, `None` disables caching
+    tree_cache: Option<PathBuf>,
+    /// Directory holding the checkpoints of backups being created, `None` starts interrupted
+    /// backups over
+    checkpoints: Option<PathBuf>,
 }
 
-impl<B: BackupRepository, L: BlobRepository, E: EncodingService, F: FileService>
-    MainClientService<B, L, E, F>
+impl<
+        B: BackupRepository,
+        L: BlobRepository,
+        U: UserRepository,
+        E: EncodingService,
+        F: FileService,
+        A: ArchiveService,
+        C: ConfigService,
+    > MainClientService<B, L, U, E, F, A, C>
 {
     pub fn new(
         user: UserIdentifier,
         backup_repository: B,
         blob_repository: L,
+        user_repository: U,
         hash_service: HashService,
     ) -> Self {
         Self {
             user,
             backup_repository,
             blob_repository,
+            user_repository,
             encoding_service: PhantomData,
             file_service: PhantomData,
+            archive_service: PhantomData,
+            config_service: PhantomData,
             hash_service,
+            config: ClientConfig::default(),
+            tree_cache: None,
+            checkpoints: None,
         }
     }
+
+    /// Uses the defaults and device identifier of `config`
+    pub fn with_config(mut self, config: ClientConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Keeps downloaded file trees in `directory`, which has to exist
+    pub fn with_tree_cache(mut self, directory: PathBuf) -> Self {
+        self.tree_cache = Some(directory);
+        self
+    }
+
+    /// Keeps checkpoints of backups being created in `directory`, which has to exist and belong
+    /// to one server
+    pub fn with_checkpoints(mut self, directory: PathBuf) -> Self {
+        self.checkpoints = Some(directory);
+        self
+    }
 }
 
 #[cfg(any(test, feature = "mocks"))]
@@ -83,8 +162,11 @@ impl
     MainClientService<
         InMemoryBackupRepository,
         InMemoryBlobRepository,
+        InMemoryUserRepository,
         MockEncoderService,
         MockFileService,
+        MockArchiveService,
+        MockConfigService,
     >
 {
     pub fn new_mock() -> Self {
@@ -92,64 +174,120 @@ impl
             user: UserIdentifier::new("Mock".into()),
             backup_repository: InMemoryBackupRepository::new(),
             blob_repository: InMemoryBlobRepository::new(),
+            user_repository: InMemoryUserRepository::new(),
             encoding_service: PhantomData,
             file_service: PhantomData,
+            archive_service: PhantomData,
+            config_service: PhantomData,
             hash_service: HashService::new(vec![&MOCK_HASHER as &dyn Hasher]),
+            config: ClientConfig::default(),
+            tree_cache: None,
+            checkpoints: None,
         }
     }
 }
 
-impl<B: BackupRepository, L: BlobRepository, E: EncodingService, F: FileService> ClientService
-    for MainClientService<B, L, E, F>
+impl<
+        B: BackupRepository,
+        L: BlobRepository,
+        U: UserRepository,
+        E: EncodingService,
+        F: FileService,
+        A: ArchiveService,
+        C: ConfigService,
+    > ClientService for MainClientService<B, L, U, E, F, A, C>
 {
     type Error = MainClientServiceError;
 
-    async fn handle_command(&mut self, command: ClientCommand) -> Result<(), Self::Error> {
+    async fn handle_command(
+        &mut self,
+        command: ClientCommand,
+    ) -> Result<CommandOutput, Self::Error> {
         match command.subcommand {
-            ClientSubcommand::Server { .. } => {
-                unimplemented!()
+            ClientSubcommand::Server {
+                profile,
+                make_default,
+                address,
+                user_name,
+                password,
+                token,
+                insecure,
+                ca_certificate,
+                fingerprint,
+                device_id,
+                retention_period,
+                interval,
+            } => {
+                // Start from the stored config, so overrides of this run are not persisted
+                let mut config = C::load_config()
+                    .await
+                    .map_err(|e| MainClientServiceError::ConfigError(e.into()))?;
+                let profile = profile.unwrap_or_else(|| config.default_profile.clone());
+                if make_default || !config.profiles.contains_key(&config.default_profile) {
+                    config.default_profile = profile.clone();
+                }
+
+                let server_profile = config.profiles.entry(profile.clone()).or_default();
+                server_profile.server = address;
+                if let Some(user_name) = user_name {
+                    server_profile.user_name = user_name;
+                }
+                server_profile.password = password.or(server_profile.password.take());
+                server_profile.token = token.or(server_profile.token.take());
+                server_profile.tls.insecure = insecure.unwrap_or(server_profile.tls.insecure);
+                server_profile.tls.ca_certificate =
+                    ca_certificate.or(server_profile.tls.ca_certificate.take());
+                server_profile.tls.fingerprint = fingerprint.or(server_profile.tls.fingerprint);
+                config.device_id = device_id.unwrap_or(config.device_id);
+                config.defaults.retention_period =
+                    retention_period.or(config.defaults.retention_period);
+                config.defaults.interval = interval.or(config.defaults.interval);
+                C::save_config(&config)
+                    .await
+                    .map_err(|e| MainClientServiceError::ConfigError(e.into()))?;
+                Ok(CommandOutput::ConfigUpdated(ConfigSummary::new(
+                    &config, &profile,
+                )))
             }
-            ClientSubcommand::Backup(inner) => match inner {
-                ClientBackupCommand::Auto { .. } => {
-                    todo!()
+            ClientSubcommand::Snapshot(inner) => match inner {
+                ClientSnapshotCommand::List { id } => {
+                    Ok(CommandOutput::SnapshotList(self.list_snapshots(id).await?))
                 }
+                ClientSnapshotCommand::Show {
+                    id,
+                    snapshot,
+                    max_depth,
+                } => Ok(CommandOutput::SnapshotDetails(
+                    self.show_snapshot(id, snapshot, max_depth).await?,
+                )),
+            },
+            ClientSubcommand::Backup(inner) => match inner {
+                ClientBackupCommand::Auto { .. } => Err(MainClientServiceError::NotImplemented),
                 ClientBackupCommand::Create {
                     backup_root,
                     retention_period,
                     interval,
                     name,
                 } => {
-                    self.create_backup(backup_root, retention_period, interval, Box::from(name))
-                        .await?;
-                    Ok(())
+                    let retention_period =
+                        retention_period.unwrap_or_else(|| self.retention_period());
+                    let interval = interval
+                        .or(self.config.defaults.interval)
+                        .unwrap_or(Duration::Infinite);
+                    Ok(CommandOutput::SnapshotCreated(
+                        self.create_backup(
+                            backup_root,
+                            retention_period,
+                            interval,
+                            Box::from(name),
+                        )
+                        .await?,
+                    ))
                 }
                 ClientBackupCommand::Restore { backup_root, id } => {
-                    let backup = self
-                        .backup_repository
-                        .get_backup_by_id(&id, &UserIdentifier::new("local".into()))
-                        .await
-                        .map_err(|e| MainClientServiceError::BackupRepositoryError(e.into()))?
-                        .ok_or(MainClientServiceError::BackupNotFound)?; //TODO correct user handling
-
-                    let mut old_file_tree_blob = self
-                        .blob_repository
-                        .fetch_blob(
-                            backup
-                                .snapshots()
-                                .last()
-                                .ok_or(MainClientServiceError::SnapshotNotFound)?
-                                .file_tree_blob(),
-                        )
-                        .await
-                        .map_err(|e| MainClientServiceError::BlobRepositoryError(e.into()))?;
-                    let old_file_tree_data = old_file_tree_blob
-                        .read_to_eof()
-                        .await
-                        .map_err(|e| MainClientServiceError::BlobRepositoryError(e.into()))?;
-                    let old_file_tree: FileTreeNode = E::decode(old_file_tree_data.as_ref())
-                        .map_err(|e| MainClientServiceError::DecodeError(e.into()))?;
-                    drop(old_file_tree_blob);
-                    drop(old_file_tree_data);
+                    let backup = self.fetch_backup(&id).await?;
+                    let snapshot = Self::select_snapshot(&backup, None)?;
+                    let old_file_tree = self.fetch_file_tree(snapshot).await?;
 
                     let new_file_tree = F::generate_file_tree(
                         backup_root.as_path(),
@@ -159,28 +297,105 @@ impl<B: BackupRepository, L: BlobRepository, E: EncodingService, F: FileService>
                     .await
                     .map_err(|e| MainClientServiceError::FileServiceError(e.into()))?;
 
-                    self.resolve_diffs(new_file_tree, old_file_tree, backup_root.as_path())
+                    let changes = self
+                        .resolve_diffs(new_file_tree, old_file_tree, backup_root.as_path())
                         .await?;
-                    Ok(())
+                    Ok(CommandOutput::Restored(RestoreResult {
+                        backup_id: id,
+                        snapshot: snapshot.timestamp().milliseconds_since_epoch(),
+                        target: backup_root.to_string_lossy().into_owned(),
+                        changes,
+                    }))
                 }
                 ClientBackupCommand::List {} => {
-                    todo!()
+                    Ok(CommandOutput::BackupList(self.list_backups().await?))
+                }
+                ClientBackupCommand::Export {
+                    id,
+                    snapshot,
+                    target,
+                } => Ok(CommandOutput::Exported(
+                    self.export_snapshot(id, snapshot, target.as_deref())
+                        .await?,
+                )),
+                ClientBackupCommand::Import {
+                    id,
+                    source,
+                    timestamp,
+                    retention_period,
+                    backup_root,
+                } => {
+                    let retention_period =
+                        retention_period.unwrap_or_else(|| self.retention_period());
+                    Ok(CommandOutput::SnapshotCreated(
+                        self.import_archive(
+                            id,
+                            source.as_deref(),
+                            timestamp,
+                            retention_period,
+                            backup_root,
+                        )
+                        .await?,
+                    ))
+                }
+                ClientBackupCommand::History { id, path } => Ok(CommandOutput::History(
+                    self.file_history(id, path.as_path()).await?,
+                )),
+                ClientBackupCommand::RestoreFile {
+                    id,
+                    path,
+                    version,
+                    target,
+                } => Ok(CommandOutput::FileRestored(
+                    self.restore_file_version(id, path.as_path(), version, target.as_path())
+                        .await?,
+                )),
+                ClientBackupCommand::Find { pattern, id } => {
+                    Ok(CommandOutput::Find(self.find_paths(pattern, id).await?))
+                }
+                ClientBackupCommand::Diff { id, from, to } => Ok(CommandOutput::Diff(
+                    self.diff_snapshots(id, from, to).await?,
+                )),
+            },
+            ClientSubcommand::Device(inner) => match inner {
+                ClientDeviceCommand::CreateToken { device } => {
+                    let device = device.unwrap_or_else(|| self.config.device_id.clone());
+                    let token = DeviceToken::generate();
+                    self.user_repository
+                        .create_user_device(&self.user, &device, token.credential())
+                        .await
+                        .map_err(|e| MainClientServiceError::UserRepositoryError(e.into()))?;
+                    Ok(CommandOutput::DeviceTokenCreated(DeviceTokenCreated {
+                        device: device.to_string(),
+                        token: token.to_string(),
+                    }))
                 }
             },
         }
     }
 }
 
-impl<B: BackupRepository, L: BlobRepository, E: EncodingService, F: FileService>
-    MainClientService<B, L, E, F>
+impl<
+        B: BackupRepository,
+        L: BlobRepository,
+        U: UserRepository,
+        E: EncodingService,
+        F: FileService,
+        A: ArchiveService,
+        C: ConfigService,
+    > MainClientService<B, L, U, E, F, A, C>
 {
+    fn retention_period(&self) -> Duration {
+        self.config.defaults.retention_period.unwrap_or(MONTH)
+    }
+
     async fn create_backup(
         &mut self,
         backup_root: PathBuf,
         retention_period: Duration,
         interval: Duration,
         name: Box<str>,
-    ) -> Result<(), MainClientServiceError> {
+    ) -> Result<SnapshotCreated, MainClientServiceError> {
         let mut schedule = Schedule::new(Vec::new());
 
         if let Duration::Limited { .. } = interval {
@@ -191,101 +406,885 @@ impl<B: BackupRepository, L: BlobRepository, E: EncodingService, F: FileService>
             ));
         }
 
-        let filetree = F::generate_file_tree(
-            backup_root.as_path(),
-            self.hash_service.preferred_hasher(),
-            &self.user,
-        )
-        .await
-        .map_err(|e| MainClientServiceError::FileServiceError(e.into()))?;
-
-        let file_tree_box = E::encode(&filetree);
-        let hasher = self.hash_service.preferred_hasher();
-        let mut hash = hasher.create_hash();
-        hash.update(&file_tree_box);
-        let hash = hash.finalize();
-        let file_tree_blob = InMemoryBlobFetch::new(file_tree_box.into());
+        let backup_id = BackupId(name);
+        let mut checkpoint = match self.load_checkpoint(&backup_id, &backup_root).await {
+            Some(mut checkpoint) => {
+                log::info!(
+                    "Resuming the interrupted backup, {} blobs were uploaded",
+                    checkpoint.uploaded.len()
+                );
+                if !self.unchanged_since_scan(&checkpoint).await {
+                    checkpoint.file_tree = self.scan(&backup_root).await?;
+                }
+                checkpoint
+            }
+            None => BackupCheckpoint {
+                backup: backup_id.clone(),
+                root: backup_root.clone(),
+                started: Timestamp::now(),
+                file_tree: self.scan(&backup_root).await?,
+                uploaded: HashSet::new(),
+            },
+        };
+        self.save_checkpoint(&checkpoint).await;
 
-        let file_tree_blob_identifier = BlobIdentifier::new(hash, self.user.clone());
-        self.blob_repository
-            .insert_blob(file_tree_blob_identifier.clone(), file_tree_blob)
-            .await
-            .map_err(|e| MainClientServiceError::BlobRepositoryError(e.into()))?;
+        let file_tree_blob_identifier = self
+            .insert_in_memory_blob(E::encode(&checkpoint.file_tree))
+            .await?;
 
         let mut blobs = vec![file_tree_blob_identifier.clone()];
         blobs.append(
             &mut self
-                .insert_into_repository_from_file_tree(&filetree, backup_root.clone())
+                .insert_into_repository_from_file_tree(&mut checkpoint)
                 .await?,
         );
 
-        let snapshots = vec![Snapshot::new(
+        let snapshot = Snapshot::new(
             Timestamp::now(),
             Timestamp::now() + &retention_period,
             file_tree_blob_identifier,
             blobs,
-        )];
+        );
+        let created = SnapshotCreated {
+            backup_id,
+            new_backup: true,
+            summary: SnapshotSummary::from(&snapshot),
+            file_count: count_files(&checkpoint.file_tree),
+            total_size: checkpoint.file_tree.size(),
+        };
 
         let backup = Backup::new(
-            BackupId::from_str(name.as_ref()).unwrap(),
-            DeviceIdentifier::default(),
+            created.backup_id.clone(),
+            self.config.device_id.clone(),
             schedule,
             Box::from(backup_root),
-            snapshots,
+            vec![snapshot],
         );
 
         self.backup_repository
             .create_backup(&self.user, backup)
             .await
             .map_err(|e| MainClientServiceError::BackupRepositoryError(e.into()))?;
-        Ok(())
+        if let Some(path) = self.checkpoint_path(&created.backup_id) {
+            if let Err(e) = F::delete_file(&path).await {
+                log::warn!("Failed to delete checkpoint {}: {e}", path.display());
+            }
+        }
+        Ok(created)
     }
 
-    async fn insert_into_repository_from_file_tree(
+    async fn scan(&self, backup_root: &Path) -> Result<FileTreeNode, MainClientServiceError> {
+        F::generate_file_tree(
+            backup_root,
+            self.hash_service.preferred_hasher(),
+            &self.user,
+        )
+        .await
+        .map_err(|e| MainClientServiceError::FileServiceError(e.into()))
+    }
+
+    /// Where the checkpoint of `backup` is kept, named after the hash of the user and backup
+    fn checkpoint_path(&self, backup: &BackupId) -> Option<PathBuf> {
+        let directory = self.checkpoints.as_ref()?;
+        let mut hash = self.hash_service.preferred_hasher().create_hash();
+        hash.update(format!("{}/{}", self.user, backup.0).as_bytes());
+        Some(directory.join(format!("{}.checkpoint", hash.finalize())))
+    }
+
+    /// The checkpoint of an interrupted backup of `backup_root` into `backup`, if it is recent
+    /// enough for the server to still keep its blobs
+    async fn load_checkpoint(
+        &self,
+        backup: &BackupId,
+        backup_root: &Path,
+    ) -> Option<BackupCheckpoint> {
+        let path = self.checkpoint_path(backup)?;
+        let file = F::get_file(&path).await.ok()?;
+        let data = file.get_as_blob().await.ok()?.read_to_eof().await.ok()?;
+        let checkpoint: BackupCheckpoint = match E::decode(data.as_ref()) {
+            Ok(checkpoint) => checkpoint,
+            Err(e) => {
+                log::warn!("Ignoring unreadable checkpoint {}: {e}", path.display());
+                return None;
+            }
+        };
+
+        let age = Timestamp::now()
+            .milliseconds_since_epoch()
+            .saturating_sub(checkpoint.started.milliseconds_since_epoch());
+        let max_age = MIN_GRACE_PERIOD.as_millis() as u64 / 2;
+        (checkpoint.backup == *backup && checkpoint.root == backup_root && age < max_age)
+            .then_some(checkpoint)
+    }
+
+    async fn save_checkpoint(&self, checkpoint: &BackupCheckpoint) {
+        let Some(path) = self.checkpoint_path(&checkpoint.backup) else {
+            return;
+        };
+        let data = E::encode(checkpoint);
+        let metadata = FileMetadata {
+            file_size: data.len() as u64,
+            last_modified: Timestamp::now().milliseconds_since_epoch(),
+            permissions: None,
+        };
+        let blob = InMemoryBlobFetch::new(data.into());
+        if let Err(e) = F::write_file(&path, &metadata, blob).await {
+            log::warn!("Failed to save checkpoint {}: {e}", path.display());
+        }
+    }
+
+    /// Whether the files not uploaded yet still have the size and modification time of the scan
+    async fn unchanged_since_scan(&self, checkpoint: &BackupCheckpoint) -> bool {
+        for (path, blob, metadata) in checkpoint_files(checkpoint) {
+            if checkpoint.uploaded.contains(blob) {
+                continue;
+            }
+            let Ok(file) = F::get_file(&path).await else {
+                return false;
+            };
+            let size = file.get_size().await.ok();
+            let last_modified = file.get_last_modified().await.ok();
+            if size != Some(metadata.file_size()) || last_modified != Some(metadata.last_modified) {
+                log::info!("{} changed since the scan, scanning again", path.display());
+                return false;
+            }
+        }
+        true
+    }
+
+    async fn insert_in_memory_blob(
         &mut self,
-        file_tree_node: &FileTreeNode,
-        backup_root_path: PathBuf,
-    ) -> Result<Vec<BlobIdentifier>, MainClientServiceError> {
-        let files = file_tree_node.iter(backup_root_path.parent().unwrap().into());
-        let mut associated_blobs = vec![];
-        for (path, file_node) in files {
-            if let FileTreeNode::File {
-                name,
-                blob: blob_identifier,
-                ..
-            } = file_node
+        data: impl Into<Box<[u8]>>,
+    ) -> Result<BlobIdentifier, MainClientServiceError> {
+        let data = data.into();
+        let mut hash = self.hash_service.preferred_hasher().create_hash();
+        hash.update(&data);
+        let blob_identifier = BlobIdentifier::new(hash.finalize(), self.user.clone());
+
+        self.blob_repository
+            .insert_blob(blob_identifier.clone(), InMemoryBlobFetch::new(data.into()))
+            .await
+            .map_err(|e| MainClientServiceError::BlobRepositoryError(e.into()))?;
+        Ok(blob_identifier)
+    }
+
+    /// Uploads the content of an archive entry, which can only be read once. Files too large to
+    /// hold in memory are hashed while being written to `spool` and uploaded from there.
+    async fn insert_archive_blob(
+        &mut self,
+        mut content: impl BlobFetch,
+        metadata: &FileMetadata,
+        spool: &Path,
+    ) -> Result<BlobIdentifier, MainClientServiceError> {
+        if content.total_len() <= IMPORT_MEMORY_LIMIT {
+            let data = content
+                .read_to_eof()
+                .await
+                .map_err(|e| MainClientServiceError::ArchiveError(e.into()))?;
+            return self.insert_in_memory_blob(data).await;
+        }
+
+        let mut hashing = HashingBlobFetch {
+            inner: content,
+            hash: self.hash_service.preferred_hasher().create_hash(),
+        };
+        F::write_file(spool, metadata, &mut hashing)
+            .await
+            .map_err(|e| FileServiceError(e.into()))?;
+        let blob_identifier = BlobIdentifier::new(hashing.hash.finalize(), self.user.clone());
+
+        let inserted = self.insert_file_blob(spool, &blob_identifier).await;
+        if let Err(e) = F::delete_file(spool).await {
+            log::warn!("Failed to delete {}: {e}", spool.display());
+        }
+        inserted.map(|()| blob_identifier)
+    }
+
+    /// Uploads the file at `path` as the blob `blob_identifier`
+    async fn insert_file_blob(
+        &mut self,
+        path: &Path,
+        blob_identifier: &BlobIdentifier,
+    ) -> Result<(), MainClientServiceError> {
+        let file = F::get_file(path)
+            .await
+            .map_err(|e| MainClientServiceError::FileServiceError(e.into()))?;
+        let blob = file
+            .get_as_blob()
+            .await
+            .map_err(|e| MainClientServiceError::FileServiceError(e.into()))?;
+        self.blob_repository
+            .insert_blob(blob_identifier.clone(), blob)
+            .await
+            .map_err(|e| MainClientServiceError::BlobRepositoryError(e.into()))
+    }
+
+    async fn import_archive(
+        &mut self,
+        id: BackupId,
+        source: Option<&Path>,
+        timestamp: Option<Timestamp>,
+        retention_period: Duration,
+        backup_root: Option<PathBuf>,
+    ) -> Result<SnapshotCreated, MainClientServiceError> {
+        let mut archive = A::open_reader(source)
+            .await
+            .map_err(|e| MainClientServiceError::ArchiveError(e.into()))?;
+
+        // Large entries are spooled to a directory nobody else can plant files or links in
+        let spool_dir = std::env::temp_dir().join(format!("guardian-import-{}", random_name()));
+        F::create_private_dir(&spool_dir)
+            .await
+            .map_err(|e| FileServiceError(e.into()))?;
+        let spool = spool_dir.join("entry");
+        let read = async {
+            let mut file_tree = FileTreeNode::Directory {
+                name: OsString::new(),
+                metadata: Default::default(),
+                children: vec![],
+            };
+            let mut blobs = vec![];
+            let mut known_blobs = HashSet::new();
+            let mut newest_modification = None;
+
+            while let Some(entry) = archive
+                .next_entry()
+                .await
+                .map_err(|e| MainClientServiceError::ArchiveError(e.into()))?
+            {
+                let Some(path) = normalize_archive_path(entry.path.as_path()) else {
+                    log::warn!("Skipping archive entry {}", entry.path.display());
+                    continue;
+                };
+                newest_modification = newest_modification.max(entry.last_modified());
+                let name = path
+                    .file_name()
+                    .expect("normalized paths end in a name")
+                    .into();
+
+                let node = match entry.kind {
+                    ArchiveEntryKind::Directory { metadata } => FileTreeNode::Directory {
+                        name,
+                        metadata,
+                        children: vec![],
+                    },
+                    ArchiveEntryKind::File { metadata, content } => {
+                        let blob = self
+                            .insert_archive_blob(content, &metadata, spool.as_path())
+                            .await?;
+                        if known_blobs.insert(blob.clone()) {
+                            blobs.push(blob.clone());
+                        }
+                        FileTreeNode::File {
+                            name,
+                            blob,
+                            metadata,
+                        }
+                    }
+                };
+                file_tree.insert(path.as_path(), node);
+            }
+            Ok::<_, MainClientServiceError>((file_tree, blobs, known_blobs, newest_modification))
+        }
+        .await;
+        if let Err(e) = F::delete_dir_all(&spool_dir).await {
+            log::warn!("Failed to delete {}: {e}", spool_dir.display());
+        }
+        let (file_tree, mut blobs, mut known_blobs, newest_modification) = read?;
+
+        // Archives usually contain a single top level directory, which becomes the root
+        let file_tree = match file_tree {
+            FileTreeNode::Directory { mut children, .. }
+                if children.len() == 1 && matches!(children[0], FileTreeNode::Directory { .. }) =>
             {
-                let file = F::get_file(path.join(name).as_path())
+                children.remove(0)
+            }
+            FileTreeNode::Directory {
+                metadata, children, ..
+            } => FileTreeNode::Directory {
+                name: backup_root
+                    .as_deref()
+                    .and_then(Path::file_name)
+                    .map(OsString::from)
+                    .unwrap_or_else(|| id.0.as_ref().into()),
+                metadata,
+                children,
+            },
+            _ => unreachable!("the archive root is always a directory"),
+        };
+
+        let file_tree_blob_identifier = self.insert_in_memory_blob(E::encode(&file_tree)).await?;
+        if known_blobs.insert(file_tree_blob_identifier.clone()) {
+            blobs.insert(0, file_tree_blob_identifier.clone());
+        }
+
+        let timestamp = timestamp
+            .or(newest_modification.map(Timestamp::from_milliseconds))
+            .unwrap_or_else(Timestamp::now);
+        let snapshot = Snapshot::new(
+            timestamp,
+            timestamp + &retention_period,
+            file_tree_blob_identifier,
+            blobs,
+        );
+
+        let existing = self
+            .backup_repository
+            .get_backup_by_id(&id, &self.user)
+            .await
+            .map_err(|e| MainClientServiceError::BackupRepositoryError(e.into()))?;
+        let created = SnapshotCreated {
+            backup_id: id.clone(),
+            new_backup: existing.is_none(),
+            summary: SnapshotSummary::from(&snapshot),
+            file_count: count_files(&file_tree),
+            total_size: file_tree.size(),
+        };
+        match existing {
+            Some(mut backup) => {
+                backup.add_snapshot(snapshot);
+                self.backup_repository
+                    .update_backup(backup, &self.user)
                     .await
-                    .map_err(|e| MainClientServiceError::FileServiceError(e.into()))?;
-                let blob = file
-                    .get_as_blob()
+                    .map_err(|e| MainClientServiceError::BackupRepositoryError(e.into()))?;
+            }
+            None => {
+                let file_root = backup_root.unwrap_or_else(|| PathBuf::from(file_tree.name()));
+                let backup = Backup::new(
+                    id,
+                    self.config.device_id.clone(),
+                    Schedule::default(),
+                    file_root.into(),
+                    vec![snapshot],
+                );
+                self.backup_repository
+                    .create_backup(&self.user, backup)
                     .await
-                    .map_err(|e| MainClientServiceError::FileServiceError(e.into()))?;
-                self.blob_repository
-                    .insert_blob(blob_identifier.clone(), blob)
+                    .map_err(|e| MainClientServiceError::BackupRepositoryError(e.into()))?;
+            }
+        }
+        Ok(created)
+    }
+
+    async fn diff_snapshots(
+        &mut self,
+        id: BackupId,
+        from: Option<Timestamp>,
+        to: Option<Timestamp>,
+    ) -> Result<SnapshotDiff, MainClientServiceError> {
+        let backup = self.fetch_backup(&id).await?;
+        let from_snapshot = Self::select_snapshot(&backup, from)?;
+        let old_file_tree = self.fetch_file_tree(from_snapshot).await?;
+
+        let new_file_tree = match to {
+            Some(to) => {
+                self.fetch_file_tree(Self::select_snapshot(&backup, Some(to))?)
+                    .await?
+            }
+            None => F::generate_file_tree(
+                backup.file_root(),
+                self.hash_service.preferred_hasher(),
+                &self.user,
+            )
+            .await
+            .map_err(|e| MainClientServiceError::FileServiceError(e.into()))?,
+        };
+
+        let mut entries: Vec<DiffEntry> = new_file_tree
+            .diff_with_moves(&old_file_tree, PathBuf::new().into())
+            .into_iter()
+            .map(DiffEntry::from)
+            .collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(SnapshotDiff {
+            from_snapshot: from_snapshot.timestamp().milliseconds_since_epoch(),
+            to_snapshot: to.map(|e| e.milliseconds_since_epoch()),
+            backup_id: id,
+            entries,
+        })
+    }
+
+    async fn list_backups(&mut self) -> Result<BackupList, MainClientServiceError> {
+        let mut backups: Vec<BackupSummary> = self
+            .backup_repository
+            .get_backup_overviews(&self.user)
+            .await
+            .map_err(|e| MainClientServiceError::BackupRepositoryError(e.into()))?
+            .iter()
+            .map(BackupSummary::from)
+            .collect();
+        backups.sort_by(|a, b| a.id.0.cmp(&b.id.0));
+        Ok(BackupList { backups })
+    }
+
+    async fn list_snapshots(
+        &mut self,
+        id: BackupId,
+    ) -> Result<SnapshotList, MainClientServiceError> {
+        let page_length = MAX_SNAPSHOT_PAGE as usize;
+        let mut snapshots: Vec<SnapshotSummary> = Vec::new();
+        loop {
+            let after = snapshots
+                .last()
+                .map(|e| Timestamp::from_milliseconds(e.timestamp));
+            let page = self
+                .backup_repository
+                .get_snapshots(&id, &self.user, after, page_length)
+                .await
+                .map_err(|e| MainClientServiceError::BackupRepositoryError(e.into()))?
+                .ok_or(MainClientServiceError::BackupNotFound)?;
+            let last_page = page.len() < page_length;
+            snapshots.extend(page.iter().map(SnapshotSummary::from));
+            if last_page {
+                break;
+            }
+        }
+        Ok(SnapshotList {
+            backup_id: id,
+            snapshots,
+        })
+    }
+
+    async fn show_snapshot(
+        &mut self,
+        id: BackupId,
+        snapshot: Option<Timestamp>,
+        max_depth: Option<usize>,
+    ) -> Result<SnapshotDetails, MainClientServiceError> {
+        let backup = self.fetch_backup(&id).await?;
+        let snapshot = Self::select_snapshot(&backup, snapshot)?;
+        let file_tree = self.fetch_file_tree(snapshot).await?;
+
+        Ok(SnapshotDetails {
+            backup_id: id,
+            summary: SnapshotSummary::from(snapshot),
+            file_count: count_files(&file_tree),
+            directory_count: file_tree
+                .iter(PathBuf::new())
+                .filter(|(_, node)| matches!(node, FileTreeNode::Directory { .. }))
+                .count(),
+            total_size: file_tree.size(),
+            tree: TreeEntry::new(&file_tree, max_depth),
+        })
+    }
+
+    async fn file_history(
+        &mut self,
+        id: BackupId,
+        path: &Path,
+    ) -> Result<FileHistory, MainClientServiceError> {
+        let backup = self.fetch_backup(&id).await?;
+        let path = Self::path_in_backup(&backup, path);
+        let versions = self.file_versions(&backup, path.as_path()).await?;
+
+        Ok(FileHistory {
+            backup_id: id,
+            path: path.to_string_lossy().into_owned(),
+            versions: versions
+                .iter()
+                .map(|e| FileVersion {
+                    hash: e.blob.hash().to_string(),
+                    size: e.metadata.file_size(),
+                    last_modified: e.metadata.last_modified(),
+                    first_snapshot: e.first_snapshot.milliseconds_since_epoch(),
+                    last_snapshot: e.last_snapshot.milliseconds_since_epoch(),
+                })
+                .collect(),
+        })
+    }
+
+    async fn restore_file_version(
+        &mut self,
+        id: BackupId,
+        path: &Path,
+        version: FileVersionSelector,
+        target: &Path,
+    ) -> Result<FileRestored, MainClientServiceError> {
+        let backup = self.fetch_backup(&id).await?;
+        let path = Self::path_in_backup(&backup, path);
+
+        let (blob, metadata) = match version {
+            FileVersionSelector::Latest | FileVersionSelector::Snapshot(_) => {
+                let timestamp = match version {
+                    FileVersionSelector::Snapshot(timestamp) => Some(timestamp),
+                    _ => None,
+                };
+                let file_tree = self
+                    .fetch_file_tree(Self::select_snapshot(&backup, timestamp)?)
+                    .await?;
+                match file_tree.find(path.as_path()) {
+                    Some(FileTreeNode::File { blob, metadata, .. }) => {
+                        (blob.clone(), metadata.clone())
+                    }
+                    _ => return Err(MainClientServiceError::PathNotFound),
+                }
+            }
+            FileVersionSelector::Hash(prefix) => {
+                let prefix = prefix.to_lowercase();
+                let mut matching = self
+                    .file_versions(&backup, path.as_path())
+                    .await?
+                    .into_iter()
+                    .filter(|e| e.blob.hash().to_string().starts_with(&prefix));
+                match (matching.next(), matching.next()) {
+                    (Some(version), None) => (version.blob, version.metadata),
+                    (None, _) => return Err(MainClientServiceError::VersionNotFound),
+                    (Some(_), Some(_)) => return Err(MainClientServiceError::AmbiguousVersion),
+                }
+            }
+        };
+
+        let restored = FileRestored {
+            backup_id: id,
+            path: path.to_string_lossy().into_owned(),
+            hash: blob.hash().to_string(),
+            size: metadata.file_size(),
+            target: target.to_string_lossy().into_owned(),
+        };
+        self.restore_blob(target, &blob, &metadata).await?;
+        Ok(restored)
+    }
+
+    /// Writes the blob to `path`, continuing where an interrupted restore of it stopped
+    async fn restore_blob(
+        &mut self,
+        path: &Path,
+        blob: &BlobIdentifier,
+        metadata: &FileMetadata,
+    ) -> Result<(), MainClientServiceError> {
+        let version = blob.hash();
+        let written = F::partial_length(path, version)
+            .await
+            .map_err(|e| FileServiceError(e.into()))?;
+        if written == 0 || written >= metadata.file_size() {
+            let blob = self
+                .blob_repository
+                .fetch_blob(blob)
+                .await
+                .map_err(|e| BlobRepositoryError(e.into()))?;
+            return F::continue_file(path, metadata, version, 0, blob)
+                .await
+                .map_err(|e| FileServiceError(e.into()));
+        }
+
+        let blob = self
+            .blob_repository
+            .fetch_blob_range(blob, written, metadata.file_size() - written)
+            .await
+            .map_err(|e| BlobRepositoryError(e.into()))?;
+        F::continue_file(path, metadata, version, written, blob)
+            .await
+            .map_err(|e| FileServiceError(e.into()))
+    }
+
+    /// Collects the distinct versions of the file at `path`, ordered by first appearance
+    async fn file_versions(
+        &mut self,
+        backup: &Backup,
+        path: &Path,
+    ) -> Result<Vec<VersionedFile>, MainClientServiceError> {
+        let mut snapshots: Vec<&Snapshot> = backup.snapshots().iter().collect();
+        snapshots.sort_by_key(|e| e.timestamp());
+
+        let mut versions: Vec<VersionedFile> = vec![];
+        for snapshot in snapshots {
+            let file_tree = self.fetch_file_tree(snapshot).await?;
+            let Some(FileTreeNode::File { blob, metadata, .. }) = file_tree.find(path) else {
+                continue;
+            };
+
+            match versions.iter_mut().find(|e| e.blob.hash() == blob.hash()) {
+                Some(version) => version.last_snapshot = snapshot.timestamp(),
+                None => versions.push(VersionedFile {
+                    blob: blob.clone(),
+                    metadata: metadata.clone(),
+                    first_snapshot: snapshot.timestamp(),
+                    last_snapshot: snapshot.timestamp(),
+                }),
+            }
+        }
+
+        if versions.is_empty() {
+            return Err(MainClientServiceError::PathNotFound);
+        }
+        Ok(versions)
+    }
+
+    async fn find_paths(
+        &mut self,
+        pattern: FilePattern,
+        id: Option<BackupId>,
+    ) -> Result<FindResult, MainClientServiceError> {
+        let (pattern, regex, match_file_name) = match pattern {
+            FilePattern::Glob(glob) => {
+                let regex = glob_to_regex(glob.as_str());
+                (glob.clone(), regex, !glob.contains('/'))
+            }
+            FilePattern::Regex(regex) => (regex.clone(), regex, false),
+        };
+        let regex = Regex::new(regex.as_str())
+            .map_err(|e| MainClientServiceError::InvalidPattern(e.into()))?;
+
+        let mut backups = match id {
+            Some(id) => vec![self.fetch_backup(&id).await?],
+            None => self
+                .backup_repository
+                .get_backups(&self.user)
+                .await
+                .map_err(|e| MainClientServiceError::BackupRepositoryError(e.into()))?
+                .collect(),
+        };
+        backups.sort_by(|a, b| a.id().0.cmp(&b.id().0));
+
+        let mut matches: Vec<FoundPath> = vec![];
+        for backup in &backups {
+            let mut snapshots: Vec<&Snapshot> = backup.snapshots().iter().collect();
+            snapshots.sort_by_key(|e| e.timestamp());
+            let first_match = matches.len();
+
+            for snapshot in snapshots {
+                let file_tree = self.fetch_file_tree(snapshot).await?;
+                for (parent, node) in file_tree.iter(PathBuf::new()) {
+                    let path = parent.join(node.name());
+                    let Ok(path) = path.strip_prefix(file_tree.name()) else {
+                        continue;
+                    };
+                    let candidate = match match_file_name {
+                        true => node.name().to_string_lossy(),
+                        false => path.to_string_lossy(),
+                    };
+                    if path.as_os_str().is_empty() || !regex.is_match(candidate.as_ref()) {
+                        continue;
+                    }
+
+                    let path = path.to_string_lossy().into_owned();
+                    let kind = NodeKind::from(node);
+                    let found = FoundInSnapshot {
+                        timestamp: snapshot.timestamp().milliseconds_since_epoch(),
+                        size: node.size(),
+                    };
+                    match matches[first_match..]
+                        .iter_mut()
+                        .find(|e| e.path == path && e.kind == kind)
+                    {
+                        Some(existing) => existing.snapshots.push(found),
+                        None => matches.push(FoundPath {
+                            backup_id: backup.id().clone(),
+                            path,
+                            kind,
+                            snapshots: vec![found],
+                        }),
+                    }
+                }
+            }
+            matches[first_match..].sort_by(|a, b| a.path.cmp(&b.path));
+        }
+
+        Ok(FindResult { pattern, matches })
+    }
+
+    /// Accepts paths relative to the backup root or absolute paths within it
+    fn path_in_backup(backup: &Backup, path: &Path) -> PathBuf {
+        path.strip_prefix(backup.file_root())
+            .unwrap_or(path)
+            .to_path_buf()
+    }
+
+    async fn export_snapshot(
+        &mut self,
+        id: BackupId,
+        snapshot: Option<Timestamp>,
+        target: Option<&Path>,
+    ) -> Result<ExportResult, MainClientServiceError> {
+        let backup = self.fetch_backup(&id).await?;
+        let snapshot = Self::select_snapshot(&backup, snapshot)?;
+        let file_tree = self.fetch_file_tree(snapshot).await?;
+
+        let mut archive = A::create_writer(target)
+            .await
+            .map_err(|e| MainClientServiceError::ArchiveError(e.into()))?;
+
+        for (path, node) in file_tree.iter(PathBuf::new()) {
+            let path = path.join(node.name());
+            match node {
+                FileTreeNode::File { blob, metadata, .. } => {
+                    let blob = self
+                        .blob_repository
+                        .fetch_blob(blob)
+                        .await
+                        .map_err(|e| BlobRepositoryError(e.into()))?;
+                    archive
+                        .append_file(path.as_path(), metadata, blob)
+                        .await
+                        .map_err(|e| MainClientServiceError::ArchiveError(e.into()))?;
+                }
+                FileTreeNode::Directory { metadata, .. } => archive
+                    .append_directory(path.as_path(), metadata)
                     .await
-                    .map_err(|e| MainClientServiceError::BlobRepositoryError(e.into()))?;
-                associated_blobs.push(blob_identifier.clone())
+                    .map_err(|e| MainClientServiceError::ArchiveError(e.into()))?,
+                FileTreeNode::SymbolicLink { .. } => {
+                    log::warn!("Skipping symbolic link {}", path.display());
+                }
+            }
+        }
+
+        archive
+            .finish()
+            .await
+            .map_err(|e| MainClientServiceError::ArchiveError(e.into()))?;
+
+        Ok(ExportResult {
+            backup_id: id,
+            snapshot: snapshot.timestamp().milliseconds_since_epoch(),
+            target: target.map(|e| e.to_string_lossy().into_owned()),
+            file_count: count_files(&file_tree),
+            total_size: file_tree.size(),
+        })
+    }
+
+    async fn fetch_backup(&mut self, id: &BackupId) -> Result<Backup, MainClientServiceError> {
+        self.backup_repository
+            .get_backup_by_id(id, &self.user)
+            .await
+            .map_err(|e| MainClientServiceError::BackupRepositoryError(e.into()))?
+            .ok_or(MainClientServiceError::BackupNotFound)
+    }
+
+    fn select_snapshot(
+        backup: &Backup,
+        timestamp: Option<Timestamp>,
+    ) -> Result<&Snapshot, MainClientServiceError> {
+        match timestamp {
+            None => backup.latest_snapshot(),
+            Some(timestamp) => backup.find_snapshot(timestamp),
+        }
+        .ok_or(MainClientServiceError::SnapshotNotFound)
+    }
+
+    async fn fetch_file_tree(
+        &mut self,
+        snapshot: &Snapshot,
+    ) -> Result<FileTreeNode, MainClientServiceError> {
+        let hash = snapshot.file_tree_blob().hash();
+        let cache_path = self
+            .tree_cache
+            .as_ref()
+            .map(|e| e.join(format!("{hash}.tree")));
+        if let Some(cache_path) = &cache_path {
+            if let Some(file_tree_data) = self.read_cached_tree(cache_path, hash).await {
+                return E::decode(file_tree_data.as_ref())
+                    .map_err(|e| MainClientServiceError::DecodeError(e.into()));
+            }
+        }
+
+        let mut file_tree_blob = self
+            .blob_repository
+            .fetch_blob(snapshot.file_tree_blob())
+            .await
+            .map_err(|e| MainClientServiceError::BlobRepositoryError(e.into()))?;
+        let file_tree_data = file_tree_blob
+            .read_to_eof()
+            .await
+            .map_err(|e| MainClientServiceError::BlobRepositoryError(e.into()))?;
+
+        if let Some(cache_path) = &cache_path {
+            let metadata = FileMetadata {
+                file_size: file_tree_data.len() as u64,
+                last_modified: Timestamp::now().milliseconds_since_epoch(),
+                permissions: None,
+            };
+            let blob = InMemoryBlobFetch::new(file_tree_data.clone().into());
+            if let Err(e) = F::write_file(cache_path, &metadata, blob).await {
+                log::warn!("Failed to cache file tree {}: {e}", cache_path.display());
             }
         }
-        Ok(associated_blobs)
+
+        E::decode(file_tree_data.as_ref())
+            .map_err(|e| MainClientServiceError::DecodeError(e.into()))
+    }
+
+    /// Returns the cached file tree data if it exists and still matches its hash
+    async fn read_cached_tree(&self, path: &Path, expected_hash: &FileHash) -> Option<Box<[u8]>> {
+        let file = F::get_file(path).await.ok()?;
+        let data = file.get_as_blob().await.ok()?.read_to_eof().await.ok()?;
+
+        let mut hash = self.hash_service.preferred_hasher().create_hash();
+        hash.update(&data);
+        (hash.finalize() == *expected_hash).then_some(data)
+    }
+
+    /// Uploads the files of the scan not uploaded yet and returns the blobs of all of them. The
+    /// uploaded blobs are saved in the checkpoint every [CHECKPOINT_INTERVAL].
+    async fn insert_into_repository_from_file_tree(
+        &mut self,
+        checkpoint: &mut BackupCheckpoint,
+    ) -> Result<Vec<BlobIdentifier>, MainClientServiceError> {
+        let files: Vec<(PathBuf, BlobIdentifier)> = checkpoint_files(checkpoint)
+            .map(|(path, blob, _)| (path, blob.clone()))
+            .collect();
+        let mut last_checkpoint = Instant::now();
+        for (path, blob_identifier) in &files {
+            if checkpoint.uploaded.contains(blob_identifier) {
+                continue;
+            }
+
+            self.insert_file_blob(path, blob_identifier).await?;
+            checkpoint.uploaded.insert(blob_identifier.clone());
+
+            if last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL {
+                self.save_checkpoint(checkpoint).await;
+                last_checkpoint = Instant::now();
+            }
+        }
+        Ok(files.into_iter().map(|(_, blob)| blob).collect())
     }
 }
 
-impl<B: BackupRepository, L: BlobRepository, E: EncodingService, F: FileService>
-    MainClientService<B, L, E, F>
+/// The files of the scan with their path, blob and metadata
+fn checkpoint_files(
+    checkpoint: &BackupCheckpoint,
+) -> impl Iterator<Item = (PathBuf, &BlobIdentifier, &FileMetadata)> {
+    checkpoint
+        .file_tree
+        .iter(checkpoint.root.parent().unwrap().into())
+        .filter_map(|(path, node)| match node {
+            FileTreeNode::File {
+                name,
+                blob,
+                metadata,
+            } => Some((path.join(name), blob, metadata)),
+            _ => None,
+        })
+}
+
+impl<
+        B: BackupRepository,
+        L: BlobRepository,
+        U: UserRepository,
+        E: EncodingService,
+        F: FileService,
+        A: ArchiveService,
+        C: ConfigService,
+    > MainClientService<B, L, U, E, F, A, C>
 {
     pub async fn resolve_diffs(
         &mut self,
         current_state: FileTreeNode,
         expected_state: FileTreeNode,
         root: &Path,
-    ) -> Result<(), MainClientServiceError> {
-        let diffs = expected_state.diff_to(&current_state, root.into());
+    ) -> Result<Vec<DiffEntry>, MainClientServiceError> {
+        let mut diffs = expected_state.diff_with_moves(&current_state, root.into());
+        // Renames go first, so moved nodes are in place before anything else is touched
+        diffs.sort_by_key(|e| !matches!(e.diff_type, FileTreeDiffType::Moved { .. }));
 
+        let mut applied = Vec::with_capacity(diffs.len());
         for diff in diffs {
+            applied.push(DiffEntry::from(diff.clone()));
             match diff.diff_type {
+                FileTreeDiffType::Moved { ref from } => F::rename(from, diff.path().as_path())
+                    .await
+                    .map_err(|e| FileServiceError(e.into()))?,
                 FileTreeDiffType::Created => {
                     self.recursive_create_in_fs(diff.location.as_ref(), &diff.node)
                         .await?;
@@ -297,16 +1296,8 @@ impl<B: BackupRepository, L: BlobRepository, E: EncodingService, F: FileService>
                         metadata,
                     } = diff.node
                     {
-                        F::write_file(
-                            diff.location.join(name).as_path(),
-                            &metadata,
-                            self.blob_repository
-                                .fetch_blob(&blob)
-                                .await
-                                .map_err(|e| BlobRepositoryError(e.into()))?,
-                        )
-                        .await
-                        .map_err(|e| FileServiceError(e.into()))?
+                        self.restore_blob(diff.location.join(name).as_path(), &blob, &metadata)
+                            .await?
                     }
                 }
                 FileTreeDiffType::Deleted => match diff.node {
@@ -320,45 +1311,28 @@ impl<B: BackupRepository, L: BlobRepository, E: EncodingService, F: FileService>
                             .await
                             .map_err(|e| FileServiceError(e.into()))?
                     }
-                    FileTreeNode::SymbolicLink { .. } => {
-                        unimplemented!()
+                    FileTreeNode::SymbolicLink { name, .. } => {
+                        let path = diff.location.join(name);
+                        log::warn!("Not deleting symbolic link {}", path.display());
                     }
                 },
                 FileTreeDiffType::ChangedType => {
-                    F::delete_dir_all(diff.location.join(diff.node.name()).as_path())
-                        .await
-                        .map_err(|e| FileServiceError(e.into()))?;
-
-                    match diff.node {
-                        FileTreeNode::File {
-                            name,
-                            blob,
-                            metadata,
-                        } => F::write_file(
-                            diff.location.join(name).as_path(),
-                            &metadata,
-                            self.blob_repository
-                                .fetch_blob(&blob)
-                                .await
-                                .map_err(|e| BlobRepositoryError(e.into()))?,
-                        )
-                        .await
-                        .map_err(|e| FileServiceError(e.into()))?,
-                        FileTreeNode::Directory {
-                            name,
-                            metadata,
-                            children,
-                        } => F::create_dir(diff.location.join(name).as_path())
+                    let path = diff.path();
+                    match diff.previous {
+                        Some(FileTreeNode::Directory { .. }) => F::delete_dir_all(path.as_path())
+                            .await
+                            .map_err(|e| FileServiceError(e.into()))?,
+                        _ => F::delete_file(path.as_path())
                             .await
                             .map_err(|e| FileServiceError(e.into()))?,
-                        FileTreeNode::SymbolicLink { .. } => {
-                            unimplemented!()
-                        }
                     }
+
+                    self.recursive_create_in_fs(diff.location.as_ref(), &diff.node)
+                        .await?;
                 }
             }
         }
-        Ok(())
+        Ok(applied)
     }
 
     async fn recursive_create_in_fs(
@@ -371,16 +1345,10 @@ impl<B: BackupRepository, L: BlobRepository, E: EncodingService, F: FileService>
                 name,
                 metadata,
                 blob,
-            } => F::write_file(
-                path.join(name).as_path(),
-                metadata,
-                self.blob_repository
-                    .fetch_blob(blob)
-                    .await
-                    .map_err(|e| BlobRepositoryError(e.into()))?,
-            )
-            .await
-            .map_err(|e| FileServiceError(e.into()))?,
+            } => {
+                self.restore_blob(path.join(name).as_path(), blob, metadata)
+                    .await?
+            }
             FileTreeNode::Directory { name, children, .. } => {
                 F::create_dir(path.join(name).as_path())
                     .await
@@ -390,8 +1358,8 @@ impl<B: BackupRepository, L: BlobRepository, E: EncodingService, F: FileService>
                     Box::pin(self.recursive_create_in_fs(path.join(name).as_path(), child)).await?;
                 }
             }
-            FileTreeNode::SymbolicLink { .. } => {
-                unimplemented!()
+            FileTreeNode::SymbolicLink { name, .. } => {
+                log::warn!("Skipping symbolic link {}", path.join(name).display());
             }
         }
 
@@ -399,15 +1367,153 @@ impl<B: BackupRepository, L: BlobRepository, E: EncodingService, F: FileService>
     }
 }
 
+fn count_files(file_tree: &FileTreeNode) -> usize {
+    file_tree
+        .iter(PathBuf::new())
+        .filter(|(_, node)| matches!(node, FileTreeNode::File { .. }))
+        .count()
+}
+
+/// Hashes a blob while it is read
+struct HashingBlobFetch<B: BlobFetch> {
+    inner: B,
+    hash: Box<dyn PendingHashB>,
+}
+
+impl<B: BlobFetch> BlobFetch for HashingBlobFetch<B> {
+    type Error = B::Error;
+
+    fn remaining_len(&self) -> u64 {
+        self.inner.remaining_len()
+    }
+
+    fn total_len(&self) -> u64 {
+        self.inner.total_len()
+    }
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let read = self.inner.read(buf).await?;
+        self.hash.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// A distinct version of a file found while walking the snapshots of a backup
+struct VersionedFile {
+    blob: BlobIdentifier,
+    metadata: FileMetadata,
+    first_snapshot: Timestamp,
+    last_snapshot: Timestamp,
+}
+
+/// Translates a glob into an anchored regex, where `*`, `?` and `[...]` stay within one
+/// path component and `**` spans any number of them
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.next_if_eq(&'/').is_some() {
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let class: String = chars.clone().take_while(|e| *e != ']').collect();
+                if class.is_empty() || chars.clone().nth(class.chars().count()).is_none() {
+                    regex.push_str("\\[");
+                    continue;
+                }
+                chars.nth(class.chars().count());
+
+                regex.push('[');
+                let class = match class.strip_prefix('!') {
+                    Some(negated) => {
+                        regex.push('^');
+                        negated
+                    }
+                    None => class.as_str(),
+                };
+                for c in class.chars() {
+                    if matches!(c, '\\' | '[' | '&' | '~') {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                }
+                regex.push(']');
+            }
+            c => regex.push_str(regex::escape(c.encode_utf8(&mut [0; 4])).as_str()),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// Strips leading `/` and `.` components, archive entries escaping the root via `..` are rejected
+fn normalize_archive_path(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+            Component::ParentDir => return None,
+        }
+    }
+    (!normalized.as_os_str().is_empty()).then_some(normalized)
+}
+
+/// 128 random bits in hex, for names others can't guess
+fn random_name() -> String {
+    let mut random = [0u8; 16];
+    getrandom::getrandom(&mut random).expect("The OS provides randomness");
+    random.iter().map(|e| format!("{e:02x}")).collect()
+}
+
 #[derive(Debug)]
 pub enum MainClientServiceError {
     BackupNotFound,
     SnapshotNotFound,
+    PathNotFound,
+    VersionNotFound,
+    AmbiguousVersion,
     FileServiceError(Box<dyn Error>),
     DecodeError(Box<dyn Error>),
     FailReceiveBlob(Box<dyn Error>),
     BackupRepositoryError(Box<dyn Error>),
     BlobRepositoryError(Box<dyn Error>),
+    UserRepositoryError(Box<dyn Error>),
+    ArchiveError(Box<dyn Error>),
+    InvalidPattern(Box<dyn Error>),
+    ConfigError(Box<dyn Error>),
+    NotImplemented,
+}
+
+impl MainClientServiceError {
+    /// Stable identifier of the error kind for scripts, e.g. `backup_not_found`
+    pub fn code(&self) -> &'static str {
+        match self {
+            MainClientServiceError::BackupNotFound => "backup_not_found",
+            MainClientServiceError::SnapshotNotFound => "snapshot_not_found",
+            MainClientServiceError::PathNotFound => "path_not_found",
+            MainClientServiceError::VersionNotFound => "version_not_found",
+            MainClientServiceError::AmbiguousVersion => "ambiguous_version",
+            MainClientServiceError::FileServiceError(_) => "file_system_error",
+            MainClientServiceError::DecodeError(_) => "decode_error",
+            MainClientServiceError::FailReceiveBlob(_) => "blob_transfer_error",
+            MainClientServiceError::BackupRepositoryError(_) => "backup_repository_error",
+            MainClientServiceError::BlobRepositoryError(_) => "blob_repository_error",
+            MainClientServiceError::UserRepositoryError(_) => "user_repository_error",
+            MainClientServiceError::ArchiveError(_) => "archive_error",
+            MainClientServiceError::InvalidPattern(_) => "invalid_pattern",
+            MainClientServiceError::ConfigError(_) => "config_error",
+            MainClientServiceError::NotImplemented => "not_implemented",
+        }
+    }
 }
 
 impl Display for MainClientServiceError {
@@ -419,13 +1525,27 @@ impl Display for MainClientServiceError {
             MainClientServiceError::BlobRepositoryError(err) => {
                 write!(f, "BlobRepositoryError({err})")
             }
+            MainClientServiceError::UserRepositoryError(err) => {
+                write!(f, "UserRepositoryError({err})")
+            }
             MainClientServiceError::BackupNotFound => write!(f, "BackupID not found"),
             MainClientServiceError::SnapshotNotFound => write!(f, "SnapshotNotFound"),
+            MainClientServiceError::PathNotFound => write!(f, "Path not found in the backup"),
+            MainClientServiceError::VersionNotFound => write!(f, "No version matches the hash"),
+            MainClientServiceError::AmbiguousVersion => {
+                write!(f, "The hash prefix matches more than one version")
+            }
             MainClientServiceError::FailReceiveBlob(err) => {
                 write!(f, "Failed to receive BLOB ({err})")
             }
             MainClientServiceError::DecodeError(err) => write!(f, "Failed to decode ({err})"),
             MainClientServiceError::FileServiceError(err) => write!(f, "FileServiceError({err})"),
+            MainClientServiceError::ArchiveError(err) => write!(f, "ArchiveError({err})"),
+            MainClientServiceError::InvalidPattern(err) => write!(f, "Invalid pattern ({err})"),
+            MainClientServiceError::ConfigError(err) => write!(f, "ConfigError({err})"),
+            MainClientServiceError::NotImplemented => {
+                write!(f, "This command is not implemented yet")
+            }
         }
     }
 }
@@ -449,9 +1569,20 @@ impl Error for CreateErrors {}
 
 #[cfg(test)]
 mod tests {
-    use crate::client_service::{ClientService, MainClientService};
-    use crate::model::client_model::ClientBackupCommand::Create;
-    use crate::model::client_model::{ClientCommand, ClientSubcommand};
+    use crate::client_service::{glob_to_regex, ClientService, MainClientService};
+    use crate::encoding_service::EncodingService;
+    use crate::in_memory_repositories::blob_repository::InMemoryBlobFetch;
+    use crate::model::authentication::DeviceToken;
+    use crate::model::client_config::ClientConfig;
+    use crate::model::client_model::ClientBackupCommand::{
+        Create, Diff, Find, History, Import, List, RestoreFile,
+    };
+    use crate::model::client_model::{
+        ClientCommand, ClientDeviceCommand, ClientSnapshotCommand, ClientSubcommand, FilePattern,
+        FileVersionSelector,
+    };
+    use crate::model::command_output::CommandOutput;
+    use crate::model::mocks::mock_encoder_service::MockEncoderService;
     use guardian_backup_domain::model::backup::backup::{Backup, BackupId};
     use guardian_backup_domain::model::backup::schedule::Schedule;
     use guardian_backup_domain::model::backup::schedule_rule::ScheduleRule;
@@ -460,9 +1591,14 @@ mod tests {
     use guardian_backup_domain::model::device_identifier::DeviceIdentifier;
     use guardian_backup_domain::model::duration::{Duration, MONTH};
     use guardian_backup_domain::model::files::file_hash::FileHash;
+    use guardian_backup_domain::model::files::file_metadata::FileMetadata;
+    use guardian_backup_domain::model::files::file_tree::FileTreeNode;
     use guardian_backup_domain::model::timestamp::Timestamp;
+    use guardian_backup_domain::model::user::User;
     use guardian_backup_domain::model::user_identifier::UserIdentifier;
     use guardian_backup_domain::repositories::backup_repository::BackupRepository;
+    use guardian_backup_domain::repositories::blob_repository::BlobRepository;
+    use guardian_backup_domain::repositories::user_repository::UserRepository;
     use std::path::{Path, PathBuf};
 
     #[tokio::test]
@@ -485,6 +1621,48 @@ mod tests {
             .unwrap();
     }
 
+    #[tokio::test]
+    async fn test_list_summarizes_backups() {
+        let mut client_service = MainClientService::new_mock();
+        let expiry = |e: Timestamp| (e + &MONTH).unwrap().milliseconds_since_epoch();
+        let earliest_expiry = expiry(Timestamp::now());
+        client_service
+            .create_backup("/a/b".parse().unwrap(), MONTH, MONTH, "Second".into())
+            .await
+            .unwrap();
+        client_service
+            .create_backup(
+                "/a/c".parse().unwrap(),
+                Duration::Infinite,
+                Duration::Infinite,
+                "First".into(),
+            )
+            .await
+            .unwrap();
+
+        let output = client_service
+            .handle_command(ClientCommand {
+                subcommand: ClientSubcommand::Backup(List {}),
+            })
+            .await
+            .unwrap();
+        let CommandOutput::BackupList(list) = output else {
+            panic!("Expected a backup list");
+        };
+
+        assert_eq!(list.backups.len(), 2);
+        let (first, second) = (&list.backups[0], &list.backups[1]);
+        assert_eq!(first.id, BackupId("First".into()));
+        assert_eq!(first.file_root, "/a/c");
+        assert_eq!(first.snapshot_count, 1);
+        assert_eq!(first.next_expiry, None);
+        assert!(first.schedule.is_empty());
+        assert_eq!(second.schedule.len(), 1);
+        assert_eq!(second.schedule[0].interval, MONTH.get_duration());
+        let next_expiry = second.next_expiry.unwrap();
+        assert!((earliest_expiry..=expiry(Timestamp::now())).contains(&next_expiry));
+    }
+
     #[tokio::test]
     async fn test_if_create_backup_contains_right_backup() {
         let mut client_service = MainClientService::new_mock();
@@ -559,6 +1737,466 @@ mod tests {
         assert_eq!(backups_repo, expected_backup);
     }
 
+    #[tokio::test]
+    async fn test_import_creates_backup_with_snapshot_from_archive() {
+        let mut client_service = MainClientService::new_mock();
+        client_service
+            .handle_command(ClientCommand {
+                subcommand: ClientSubcommand::Backup(Import {
+                    id: BackupId("Imported".into()),
+                    source: None,
+                    timestamp: None,
+                    retention_period: Some(Duration::Infinite),
+                    backup_root: None,
+                }),
+            })
+            .await
+            .unwrap();
+
+        let backup = client_service
+            .backup_repository
+            .get_backup_by_id(&BackupId("Imported".into()), &client_service.user)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(backup.file_root(), Path::new("mock"));
+        assert_eq!(backup.snapshots().len(), 1);
+        assert_eq!(
+            backup.snapshots()[0].timestamp(),
+            Timestamp::from_milliseconds(223355779)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_adds_snapshot_to_existing_backup() {
+        let mut client_service = MainClientService::new_mock();
+        for timestamp in [1, 2] {
+            client_service
+                .handle_command(ClientCommand {
+                    subcommand: ClientSubcommand::Backup(Import {
+                        id: BackupId("Imported".into()),
+                        source: None,
+                        timestamp: Some(Timestamp::from_milliseconds(timestamp)),
+                        retention_period: Some(Duration::Infinite),
+                        backup_root: None,
+                    }),
+                })
+                .await
+                .unwrap();
+        }
+
+        let backup = client_service
+            .backup_repository
+            .get_backup_by_id(&BackupId("Imported".into()), &client_service.user)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            backup.latest_snapshot().unwrap().timestamp(),
+            Timestamp::from_milliseconds(2)
+        );
+        assert_eq!(backup.snapshots().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_diff_against_unchanged_file_system_is_empty() {
+        let mut client_service = MainClientService::new_mock();
+        client_service
+            .create_backup(
+                "/a/b".parse().unwrap(),
+                MONTH,
+                Duration::Infinite,
+                "Testname".into(),
+            )
+            .await
+            .unwrap();
+
+        let output = client_service
+            .handle_command(ClientCommand {
+                subcommand: ClientSubcommand::Backup(Diff {
+                    id: BackupId("Testname".into()),
+                    from: None,
+                    to: None,
+                }),
+            })
+            .await
+            .unwrap();
+
+        let CommandOutput::Diff(diff) = output else {
+            panic!("Expected a diff");
+        };
+        let backup = client_service
+            .backup_repository
+            .get_backup_by_id(&BackupId("Testname".into()), &client_service.user)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            diff.from_snapshot,
+            backup
+                .latest_snapshot()
+                .unwrap()
+                .timestamp()
+                .milliseconds_since_epoch()
+        );
+        assert_eq!(diff.to_snapshot, None);
+        assert!(diff.entries.is_empty());
+    }
+
+    fn report_tree(content: u8, size: u64) -> FileTreeNode {
+        let mut root = FileTreeNode::Directory {
+            name: "docs".into(),
+            metadata: Default::default(),
+            children: vec![],
+        };
+        root.insert(
+            Path::new("reports/report.docx"),
+            FileTreeNode::File {
+                name: "report.docx".into(),
+                blob: BlobIdentifier::new(
+                    FileHash::Blake3 {
+                        hash: Box::new([content]),
+                    },
+                    UserIdentifier::new("Mock".into()),
+                ),
+                metadata: FileMetadata {
+                    file_size: size,
+                    last_modified: u64::from(content),
+                    permissions: None,
+                },
+            },
+        );
+        root
+    }
+
+    #[tokio::test]
+    async fn test_create_uses_configured_defaults() {
+        let mut config = ClientConfig::default();
+        config.defaults.retention_period = Some(Duration::Limited { milliseconds: 1000 });
+        let mut client_service = MainClientService::new_mock().with_config(config);
+
+        let output = client_service
+            .handle_command(ClientCommand {
+                subcommand: ClientSubcommand::Backup(Create {
+                    backup_root: "/a/b".into(),
+                    retention_period: None,
+                    interval: None,
+                    name: "Configured".into(),
+                }),
+            })
+            .await
+            .unwrap();
+        let CommandOutput::SnapshotCreated(created) = output else {
+            panic!("Expected a created snapshot");
+        };
+        assert_eq!(
+            created.summary.expiration_time,
+            Some(created.summary.timestamp + 1000)
+        );
+
+        let output = client_service
+            .handle_command(ClientCommand {
+                subcommand: ClientSubcommand::Server {
+                    profile: None,
+                    make_default: false,
+                    address: "10.0.0.2:8998".into(),
+                    user_name: Some("alice".into()),
+                    password: None,
+                    token: None,
+                    insecure: Some(true),
+                    ca_certificate: None,
+                    fingerprint: None,
+                    device_id: None,
+                    retention_period: None,
+                    interval: None,
+                },
+            })
+            .await
+            .unwrap();
+        let CommandOutput::ConfigUpdated(summary) = output else {
+            panic!("Expected the updated config");
+        };
+        assert_eq!(summary.server, "10.0.0.2:8998");
+        assert_eq!(summary.user_name, "alice");
+        assert!(!summary.password_set);
+        assert!(summary.insecure);
+    }
+
+    #[tokio::test]
+    async fn test_server_updates_named_profile() {
+        let mut client_service = MainClientService::new_mock();
+        let server = |profile: &str, make_default| ClientCommand {
+            subcommand: ClientSubcommand::Server {
+                profile: Some(profile.into()),
+                make_default,
+                address: "10.0.0.2:8998".into(),
+                user_name: Some("alice".into()),
+                password: None,
+                token: None,
+                insecure: None,
+                ca_certificate: None,
+                fingerprint: None,
+                device_id: None,
+                retention_period: None,
+                interval: None,
+            },
+        };
+
+        let output = client_service
+            .handle_command(server("office", false))
+            .await
+            .unwrap();
+        let CommandOutput::ConfigUpdated(summary) = output else {
+            panic!("Expected the updated config");
+        };
+        assert_eq!(summary.profile, "office");
+        assert!(!summary.is_default);
+        assert_eq!(summary.profiles, ["default", "office"]);
+        assert_eq!(summary.server, "10.0.0.2:8998");
+
+        let output = client_service
+            .handle_command(server("home", true))
+            .await
+            .unwrap();
+        let CommandOutput::ConfigUpdated(summary) = output else {
+            panic!("Expected the updated config");
+        };
+        assert_eq!(summary.profile, "home");
+        assert!(summary.is_default);
+        assert_eq!(summary.profiles, ["default", "home"]);
+    }
+
+    #[tokio::test]
+    async fn test_missing_backup_reports_error_code() {
+        let mut client_service = MainClientService::new_mock();
+        let error = client_service
+            .handle_command(ClientCommand {
+                subcommand: ClientSubcommand::Snapshot(ClientSnapshotCommand::List {
+                    id: BackupId("missing".into()),
+                }),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), "backup_not_found");
+    }
+
+    #[tokio::test]
+    async fn test_create_token_registers_this_device() {
+        let mut client_service = MainClientService::new_mock();
+        client_service
+            .user_repository
+            .create_user(&User::new(UserIdentifier::new("Mock".into()), vec![]))
+            .await
+            .unwrap();
+
+        let output = client_service
+            .handle_command(ClientCommand {
+                subcommand: ClientSubcommand::Device(ClientDeviceCommand::CreateToken {
+                    device: None,
+                }),
+            })
+            .await
+            .unwrap();
+        let CommandOutput::DeviceTokenCreated(created) = output else {
+            panic!("Expected a created token");
+        };
+        let token: DeviceToken = created.token.parse().unwrap();
+
+        let credential = client_service
+            .user_repository
+            .get_device_credential(&client_service.user, &DeviceIdentifier::default())
+            .await
+            .unwrap();
+        assert_eq!(credential, Some(token.credential()));
+    }
+
+    /// The backup `docs` with three snapshots of `reports/report.docx`, the first two of them
+    /// identical
+    async fn create_report_backup(
+        backups: &mut impl BackupRepository,
+        blobs: &mut impl BlobRepository,
+        user: &UserIdentifier,
+    ) {
+        let mut backup = Backup::new(
+            BackupId("docs".into()),
+            DeviceIdentifier::default(),
+            Schedule::default(),
+            Path::new("/home/docs").into(),
+            vec![],
+        );
+        for (timestamp, content) in [(1u8, 1u8), (2, 1), (3, 2)] {
+            blobs
+                .insert_blob(
+                    BlobIdentifier::new(
+                        FileHash::Blake3 {
+                            hash: Box::new([content]),
+                        },
+                        UserIdentifier::new("Mock".into()),
+                    ),
+                    InMemoryBlobFetch::new([content; 10].into()),
+                )
+                .await
+                .unwrap();
+            let tree_blob = BlobIdentifier::new(
+                FileHash::Blake3 {
+                    hash: Box::new([0xff, timestamp]),
+                },
+                UserIdentifier::new("Mock".into()),
+            );
+            blobs
+                .insert_blob(
+                    tree_blob.clone(),
+                    InMemoryBlobFetch::new(
+                        MockEncoderService::encode(report_tree(content, 10 * u64::from(content)))
+                            .into(),
+                    ),
+                )
+                .await
+                .unwrap();
+            backup.add_snapshot(Snapshot::new(
+                Timestamp::from_milliseconds(timestamp.into()),
+                None,
+                tree_blob,
+                vec![],
+            ));
+        }
+        backups.create_backup(user, backup).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_history_lists_distinct_versions() {
+        let mut client_service = MainClientService::new_mock();
+        create_report_backup(
+            &mut client_service.backup_repository,
+            &mut client_service.blob_repository,
+            &client_service.user,
+        )
+        .await;
+
+        let output = client_service
+            .handle_command(ClientCommand {
+                subcommand: ClientSubcommand::Backup(History {
+                    id: BackupId("docs".into()),
+                    path: "/home/docs/reports/report.docx".into(),
+                }),
+            })
+            .await
+            .unwrap();
+        let CommandOutput::History(history) = output else {
+            panic!("Expected a history");
+        };
+        assert_eq!(history.path, "reports/report.docx");
+        assert_eq!(history.versions.len(), 2);
+        assert_eq!(history.versions[0].hash, "01");
+        assert_eq!(history.versions[0].first_snapshot, 1);
+        assert_eq!(history.versions[0].last_snapshot, 2);
+        assert_eq!(history.versions[1].size, 20);
+
+        let restore = |version| ClientCommand {
+            subcommand: ClientSubcommand::Backup(RestoreFile {
+                id: BackupId("docs".into()),
+                path: "reports/report.docx".into(),
+                version,
+                target: "/tmp/report.docx".into(),
+            }),
+        };
+        assert!(client_service
+            .handle_command(restore(FileVersionSelector::Hash("02".into())))
+            .await
+            .is_ok());
+        assert!(client_service
+            .handle_command(restore(FileVersionSelector::Hash("0".into())))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_find_matches_paths_in_all_snapshots() {
+        let mut client_service = MainClientService::new_mock();
+        create_report_backup(
+            &mut client_service.backup_repository,
+            &mut client_service.blob_repository,
+            &client_service.user,
+        )
+        .await;
+
+        let find = |pattern| ClientCommand {
+            subcommand: ClientSubcommand::Backup(Find { pattern, id: None }),
+        };
+        let output = client_service
+            .handle_command(find(FilePattern::Glob("*.DOCX".into())))
+            .await
+            .unwrap();
+        let CommandOutput::Find(result) = output else {
+            panic!("Expected find results");
+        };
+        assert!(result.matches.is_empty());
+
+        let output = client_service
+            .handle_command(find(FilePattern::Regex("port.*docx".into())))
+            .await
+            .unwrap();
+        let CommandOutput::Find(result) = output else {
+            panic!("Expected find results");
+        };
+        assert_eq!(result.matches.len(), 1);
+        assert_eq!(result.matches[0].path, "reports/report.docx");
+        let sizes: Vec<u64> = result.matches[0].snapshots.iter().map(|e| e.size).collect();
+        assert_eq!(sizes, [10, 10, 20]);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_show_limits_depth() {
+        let mut client_service = MainClientService::new_mock();
+        create_report_backup(
+            &mut client_service.backup_repository,
+            &mut client_service.blob_repository,
+            &client_service.user,
+        )
+        .await;
+
+        let output = client_service
+            .handle_command(ClientCommand {
+                subcommand: ClientSubcommand::Snapshot(ClientSnapshotCommand::Show {
+                    id: BackupId("docs".into()),
+                    snapshot: Some(Timestamp::from_milliseconds(2)),
+                    max_depth: Some(0),
+                }),
+            })
+            .await
+            .unwrap();
+        let CommandOutput::SnapshotDetails(details) = output else {
+            panic!("Expected snapshot details");
+        };
+        assert_eq!(details.summary.timestamp, 2);
+        assert_eq!(details.summary.expiration_time, None);
+        assert_eq!(details.file_count, 1);
+        assert_eq!(details.directory_count, 2);
+        assert_eq!(details.total_size, 10);
+        assert!(details.tree.children.is_empty());
+        assert_eq!(details.tree.omitted_children, 1);
+    }
+
+    #[test]
+    fn test_glob_to_regex() {
+        let matches = |glob, path| {
+            regex::Regex::new(&glob_to_regex(glob))
+                .unwrap()
+                .is_match(path)
+        };
+
+        assert!(matches("*.docx", "report.docx"));
+        assert!(!matches("*.docx", "reports/report.docx"));
+        assert!(matches("reports/**/*.docx", "reports/report.docx"));
+        assert!(matches("reports/**/*.docx", "reports/2024/q1/report.docx"));
+        assert!(matches("report-[0-9].txt", "report-1.txt"));
+        assert!(!matches("report-[!0-9].txt", "report-1.txt"));
+        assert!(matches("a+b?(c)", "a+bx(c)"));
+        assert!(matches("[", "["));
+    }
+
     #[tokio::test]
     async fn test_functionality() {
         let mut client_service = MainClientService::new_mock();
@@ -0,0 +1,10 @@
+use crate::model::client_config::ClientConfig;
+use std::error::Error;
+
+pub trait ConfigService {
+    type Error: Error + 'static;
+
+    /// Reads the stored configuration, the default one if nothing was stored yet
+    async fn load_config() -> Result<ClientConfig, Self::Error>;
+    async fn save_config(config: &ClientConfig) -> Result<(), Self::Error>;
+}
@@ -20,12 +20,32 @@ pub trait FileService {
 
     async fn delete_file(path: &Path) -> Result<(), Self::Error>;
     async fn delete_dir_all(path: &Path) -> Result<(), Self::Error>;
+    /// Writes to a partial file first, which takes the place of `path` once complete. Writes that
+    /// may be continued after an interruption go through [FileService::continue_file] instead.
     async fn write_file(
         path: &Path,
         file_meta: &FileMetadata,
         blob: impl BlobFetch,
     ) -> Result<(), Self::Error>;
+    /// Bytes an interrupted write of the blob with the hash `version` to `path` left in its
+    /// partial file, 0 if none
+    async fn partial_length(path: &Path, version: &FileHash) -> Result<u64, Self::Error>;
+    /// Writes `blob` after the first `offset` bytes of the partial file of `version`, creating it
+    /// if there is none, and moves it to `path` once complete. Partial files are kept apart by
+    /// the hash of their blob, so another version is never continued.
+    async fn continue_file(
+        path: &Path,
+        file_meta: &FileMetadata,
+        version: &FileHash,
+        offset: u64,
+        blob: impl BlobFetch,
+    ) -> Result<(), Self::Error>;
     async fn create_dir(path: &Path) -> Result<(), Self::Error>;
+    /// Creates the directory `path` only the current user can access, fails if anything is at
+    /// `path` already
+    async fn create_private_dir(path: &Path) -> Result<(), Self::Error>;
+    /// Moves a file or directory within the same file system
+    async fn rename(from: &Path, to: &Path) -> Result<(), Self::Error>;
 }
 
 pub trait File {
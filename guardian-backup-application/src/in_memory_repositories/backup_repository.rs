@@ -1,4 +1,7 @@
 use guardian_backup_domain::model::backup::backup::{Backup, BackupId};
+use guardian_backup_domain::model::backup::backup_overview::BackupOverview;
+use guardian_backup_domain::model::backup::snapshot::Snapshot;
+use guardian_backup_domain::model::timestamp::Timestamp;
 use guardian_backup_domain::model::user_identifier::UserIdentifier;
 use guardian_backup_domain::repositories::backup_repository::BackupRepository;
 use std::collections::HashMap;
@@ -41,6 +44,31 @@ impl BackupRepository for InMemoryBackupRepository {
             .and_then(|backups| backups.get(id).cloned()))
     }
 
+    async fn get_backup_overviews(
+        &mut self,
+        user: &UserIdentifier,
+    ) -> Result<Vec<BackupOverview>, Self::Error> {
+        Ok(self
+            .backups
+            .get(user)
+            .map(|backups| backups.values().map(BackupOverview::from).collect())
+            .unwrap_or_default())
+    }
+
+    async fn get_snapshots(
+        &mut self,
+        id: &BackupId,
+        user: &UserIdentifier,
+        after: Option<Timestamp>,
+        limit: usize,
+    ) -> Result<Option<Vec<Snapshot>>, Self::Error> {
+        Ok(self
+            .backups
+            .get(user)
+            .and_then(|backups| backups.get(id))
+            .map(|backup| backup.snapshot_page(after, limit)))
+    }
+
     async fn update_backup(
         &mut self,
         backup: Backup,
@@ -1,24 +1,40 @@
 use crate::in_memory_repositories::blob_repository::BlobRepositoryError::ReadBlobError;
+use crate::model::response::{ErrorCode, ToErrorCode};
 use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
 use guardian_backup_domain::model::blobs::blob_identifier::BlobIdentifier;
+use guardian_backup_domain::model::timestamp::Timestamp;
+use guardian_backup_domain::model::user_identifier::UserIdentifier;
 use guardian_backup_domain::repositories::blob_repository::BlobRepository;
 use std::cmp::min;
 use std::collections::HashMap;
 use std::convert::Infallible;
 use std::fmt::{Display, Formatter};
 use std::ops::Deref;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, MutexGuard};
 
+/// Clones share the blobs. Uploads are read before the blobs are locked, so a slow one doesn't
+/// hold up the others.
+#[derive(Clone)]
 pub struct InMemoryBlobRepository {
-    blobs: HashMap<BlobIdentifier, Arc<[u8]>>,
+    blobs: Arc<Mutex<HashMap<BlobIdentifier, StoredBlob>>>,
+}
+
+struct StoredBlob {
+    data: Arc<[u8]>,
+    /// When the blob was last inserted
+    inserted: Timestamp,
 }
 
 impl InMemoryBlobRepository {
     pub fn new() -> Self {
         InMemoryBlobRepository {
-            blobs: HashMap::new(),
+            blobs: Arc::default(),
         }
     }
+
+    fn blobs(&self) -> MutexGuard<'_, HashMap<BlobIdentifier, StoredBlob>> {
+        self.blobs.lock().expect("Not poisoned")
+    }
 }
 
 impl BlobRepository for InMemoryBlobRepository {
@@ -29,7 +45,8 @@ impl BlobRepository for InMemoryBlobRepository {
         id: BlobIdentifier,
         mut blob: impl BlobFetch,
     ) -> Result<(), Self::Error> {
-        if self.blobs.contains_key(&id) {
+        if let Some(stored) = self.blobs().get_mut(&id) {
+            stored.inserted = Timestamp::now();
             return Ok(());
         }
 
@@ -37,33 +54,84 @@ impl BlobRepository for InMemoryBlobRepository {
             .read_to_eof()
             .await
             .map_err(|e| ReadBlobError(e.into()))?;
-        self.blobs.insert(id, data.into());
+        self.blobs().entry(id).or_insert(StoredBlob {
+            data: data.into(),
+            inserted: Timestamp::now(),
+        });
         Ok(())
     }
 
     async fn delete_blob(&mut self, blob: &BlobIdentifier) -> Result<(), Self::Error> {
-        self.blobs
+        self.blobs()
             .remove(blob)
             .ok_or(BlobRepositoryError::BlobNotFound)
             .map(|_| ())
     }
 
     async fn fetch_blob(&mut self, blob: &BlobIdentifier) -> Result<impl BlobFetch, Self::Error> {
-        Ok(InMemoryBlobFetch {
-            data: self
-                .blobs
+        Ok(InMemoryBlobFetch::new(
+            self.blobs()
                 .get(blob)
                 .ok_or(BlobRepositoryError::BlobNotFound)?
+                .data
                 .clone(),
-            cursor: 0,
+        ))
+    }
+
+    async fn fetch_blob_range(
+        &mut self,
+        blob: &BlobIdentifier,
+        offset: u64,
+        length: u64,
+    ) -> Result<impl BlobFetch, Self::Error> {
+        let data = self
+            .blobs()
+            .get(blob)
+            .ok_or(BlobRepositoryError::BlobNotFound)?
+            .data
+            .clone();
+        if offset > data.len() as u64 {
+            return Err(BlobRepositoryError::InvalidRange);
+        }
+
+        let start = offset as usize;
+        let end = start + min(length, (data.len() - start) as u64) as usize;
+        Ok(InMemoryBlobFetch {
+            data,
+            start,
+            cursor: start,
+            end,
         })
     }
+
+    async fn used_space(&mut self, user: &UserIdentifier) -> Result<u64, Self::Error> {
+        Ok(self
+            .blobs()
+            .iter()
+            .filter(|(id, _)| id.user() == user)
+            .map(|(_, stored)| stored.data.len() as u64)
+            .sum())
+    }
+
+    async fn blobs_inserted_before(
+        &mut self,
+        before: Timestamp,
+    ) -> Result<Vec<BlobIdentifier>, Self::Error> {
+        Ok(self
+            .blobs()
+            .iter()
+            .filter(|(_, stored)| stored.inserted < before)
+            .map(|(id, _)| id.clone())
+            .collect())
+    }
 }
 
 #[derive(Debug)]
 pub enum BlobRepositoryError {
     BlobNotFound,
     ReadBlobError(Box<dyn std::error::Error>),
+    /// The range starts after the end of the blob
+    InvalidRange,
 }
 
 impl Display for BlobRepositoryError {
@@ -71,21 +139,41 @@ impl Display for BlobRepositoryError {
         match self {
             BlobRepositoryError::BlobNotFound => write!(f, "BlobNotFound"),
             ReadBlobError(e) => write!(f, "BlobReadError{e}"),
+            BlobRepositoryError::InvalidRange => write!(f, "InvalidRange"),
         }
     }
 }
 
 impl std::error::Error for BlobRepositoryError {}
 
+impl ToErrorCode for BlobRepositoryError {
+    fn error_code(&self) -> ErrorCode {
+        match self {
+            BlobRepositoryError::BlobNotFound => ErrorCode::NotFound,
+            // The upload broke off
+            ReadBlobError(_) | BlobRepositoryError::InvalidRange => ErrorCode::InvalidRequest,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct InMemoryBlobFetch {
     data: Arc<[u8]>,
+    /// The fetched range of `data`
+    start: usize,
     cursor: usize,
+    end: usize,
 }
 
 impl InMemoryBlobFetch {
     pub fn new(data: Arc<[u8]>) -> Self {
-        Self { data, cursor: 0 }
+        let end = data.len();
+        Self {
+            data,
+            start: 0,
+            cursor: 0,
+            end,
+        }
     }
 }
 
@@ -93,15 +181,15 @@ impl BlobFetch for InMemoryBlobFetch {
     type Error = Infallible;
 
     fn remaining_len(&self) -> u64 {
-        (self.data.len() - self.cursor) as u64
+        (self.end - self.cursor) as u64
     }
 
     fn total_len(&self) -> u64 {
-        self.data.len() as u64
+        (self.end - self.start) as u64
     }
 
     async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        let to_read = min(self.data.len() - self.cursor, buf.len());
+        let to_read = min(self.end - self.cursor, buf.len());
         buf.split_at_mut(to_read).0.copy_from_slice(
             self.data
                 .deref()
@@ -113,4 +201,9 @@ impl BlobFetch for InMemoryBlobFetch {
         self.cursor += to_read;
         Ok(to_read)
     }
+
+    async fn rewind(&mut self) -> Result<bool, Self::Error> {
+        self.cursor = self.start;
+        Ok(true)
+    }
 }
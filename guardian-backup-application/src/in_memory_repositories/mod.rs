@@ -1,2 +1,3 @@
 pub mod backup_repository;
 pub mod blob_repository;
+pub mod user_repository;
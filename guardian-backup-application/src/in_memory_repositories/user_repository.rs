@@ -0,0 +1,182 @@
+use crate::model::response::{ErrorCode, ToErrorCode};
+use guardian_backup_domain::model::credential::Credential;
+use guardian_backup_domain::model::device_identifier::DeviceIdentifier;
+use guardian_backup_domain::model::user::User;
+use guardian_backup_domain::model::user_identifier::UserIdentifier;
+use guardian_backup_domain::repositories::user_repository::UserRepository;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+#[derive(Default)]
+struct StoredUser {
+    credential: Option<Credential>,
+    devices: Vec<(DeviceIdentifier, Credential)>,
+}
+
+#[derive(Default)]
+pub struct InMemoryUserRepository {
+    users: HashMap<UserIdentifier, StoredUser>,
+}
+
+impl InMemoryUserRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the user or replaces their credential
+    pub fn set_credential(&mut self, user: UserIdentifier, credential: Credential) {
+        self.users.entry(user).or_default().credential = Some(credential);
+    }
+
+    /// Adds the device of an existing user or replaces its token
+    pub fn set_device(
+        &mut self,
+        user: &UserIdentifier,
+        device: &DeviceIdentifier,
+        token: Credential,
+    ) -> Result<(), UserRepositoryError> {
+        let devices = &mut self
+            .users
+            .get_mut(user)
+            .ok_or(UserRepositoryError::UserNotFound)?
+            .devices;
+        match devices
+            .iter_mut()
+            .find(|(identifier, _)| identifier == device)
+        {
+            Some((_, existing)) => *existing = token,
+            None => devices.push((device.clone(), token)),
+        }
+        Ok(())
+    }
+
+    /// Every device of every user with the token it logs in with
+    pub fn devices(
+        &self,
+    ) -> impl Iterator<Item = (&UserIdentifier, &DeviceIdentifier, &Credential)> {
+        self.users.iter().flat_map(|(user, stored)| {
+            stored
+                .devices
+                .iter()
+                .map(move |(device, token)| (user, device, token))
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+}
+
+impl UserRepository for InMemoryUserRepository {
+    type Error = UserRepositoryError;
+
+    async fn get_user(&self, identifier: &UserIdentifier) -> Result<User, Self::Error> {
+        match self.users.contains_key(identifier) {
+            true => Ok(User::new(identifier.clone(), vec![])),
+            false => Err(UserRepositoryError::UserNotFound),
+        }
+    }
+
+    async fn get_credential(
+        &self,
+        identifier: &UserIdentifier,
+    ) -> Result<Option<Credential>, Self::Error> {
+        Ok(self
+            .users
+            .get(identifier)
+            .and_then(|e| e.credential.clone()))
+    }
+
+    async fn get_device_credential(
+        &self,
+        user: &UserIdentifier,
+        device: &DeviceIdentifier,
+    ) -> Result<Option<Credential>, Self::Error> {
+        Ok(self.users.get(user).and_then(|e| {
+            e.devices
+                .iter()
+                .find(|(identifier, _)| identifier == device)
+                .map(|(_, token)| token.clone())
+        }))
+    }
+
+    async fn get_user_devices(
+        &self,
+        user: &UserIdentifier,
+    ) -> Result<Cow<'_, [DeviceIdentifier]>, Self::Error> {
+        self.users
+            .get(user)
+            .map(|e| Cow::Owned(e.devices.iter().map(|(device, _)| device.clone()).collect()))
+            .ok_or(UserRepositoryError::UserNotFound)
+    }
+
+    async fn create_user(&mut self, user: &User) -> Result<(), Self::Error> {
+        if self.users.contains_key(user.identifier()) {
+            return Err(UserRepositoryError::UserExists);
+        }
+        self.users
+            .insert(user.identifier().clone(), StoredUser::default());
+        Ok(())
+    }
+
+    async fn create_user_device(
+        &mut self,
+        user: &UserIdentifier,
+        device: &DeviceIdentifier,
+        token: Credential,
+    ) -> Result<(), Self::Error> {
+        self.set_device(user, device, token)
+    }
+
+    async fn delete_user(&mut self, identifier: &UserIdentifier) -> Result<(), Self::Error> {
+        self.users
+            .remove(identifier)
+            .map(|_| ())
+            .ok_or(UserRepositoryError::UserNotFound)
+    }
+
+    async fn delete_device(
+        &mut self,
+        user: &UserIdentifier,
+        device: &DeviceIdentifier,
+    ) -> Result<(), Self::Error> {
+        let devices = &mut self
+            .users
+            .get_mut(user)
+            .ok_or(UserRepositoryError::UserNotFound)?
+            .devices;
+        devices.retain(|(identifier, _)| identifier != device);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum UserRepositoryError {
+    UserNotFound,
+    UserExists,
+}
+
+impl Display for UserRepositoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserRepositoryError::UserNotFound => write!(f, "User not found"),
+            UserRepositoryError::UserExists => write!(f, "User already exists"),
+        }
+    }
+}
+
+impl std::error::Error for UserRepositoryError {}
+
+impl ToErrorCode for UserRepositoryError {
+    fn error_code(&self) -> ErrorCode {
+        match self {
+            UserRepositoryError::UserNotFound => ErrorCode::NotFound,
+            UserRepositoryError::UserExists => ErrorCode::Conflict,
+        }
+    }
+}
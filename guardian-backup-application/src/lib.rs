@@ -1,9 +1,15 @@
 #![allow(async_fn_in_trait)]
+pub mod archive_service;
+pub mod blake_hash_service;
 pub mod client_service;
+pub mod config_service;
 pub mod encoding_service;
 pub mod file_service;
 pub mod in_memory_repositories;
 pub mod model;
+pub mod multiplexer;
 pub mod remote_repositories;
+pub mod retrying_connection;
 pub mod server_config;
 pub mod server_service;
+pub mod shared_repository;
@@ -0,0 +1,248 @@
+use guardian_backup_domain::model::credential::Credential;
+use guardian_backup_domain::model::device_identifier::DeviceIdentifier;
+use guardian_backup_domain::model::password_hash_algorithms::PasswordHashAlg;
+use guardian_backup_domain::model::user_identifier::UserIdentifier;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
+
+/// Length of the random challenge the server sends for every login
+pub const NONCE_LENGTH: usize = 32;
+
+/// Messages the client sends while logging in, before any [Call](crate::model::call::Call).
+///
+/// The client never sends its secret, the password hash derived with the algorithm of the
+/// challenge or the [DeviceToken]. It sends a [login_proof] over the nonce, which the server
+/// checks with [verify_login_proof] against the [stored_key] of the secret. Like in SCRAM, the
+/// stored key can't be turned into a proof, so a leaked user store doesn't let anyone log in.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub enum LoginCall {
+    Request {
+        user: UserIdentifier,
+        /// Log in with the token of this device instead of the password
+        device: Option<DeviceIdentifier>,
+    },
+    Proof([u8; 32]),
+}
+
+/// Answers of the server to a [LoginCall]
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub enum LoginResponse {
+    Challenge {
+        algorithm: PasswordHashAlg,
+        nonce: [u8; NONCE_LENGTH],
+    },
+    TokenChallenge {
+        nonce: [u8; NONCE_LENGTH],
+    },
+    Accepted,
+    Rejected,
+    TooManyAttempts {
+        retry_after_seconds: u64,
+    },
+}
+
+/// Key only the holder of `secret` knows, the server stores its hash
+fn client_key(secret: &[u8]) -> [u8; 32] {
+    let key = blake3::hash(secret);
+    blake3::keyed_hash(key.as_bytes(), b"Client Key").into()
+}
+
+/// What the server stores to verify logins with `secret`
+pub fn stored_key(secret: &[u8]) -> [u8; 32] {
+    blake3::hash(&client_key(secret)).into()
+}
+
+/// Signature of the challenge `nonce` sent to `user`, the server can compute it as well
+fn client_signature(
+    stored_key: &[u8; 32],
+    nonce: &[u8; NONCE_LENGTH],
+    user: &UserIdentifier,
+) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new_keyed(stored_key);
+    hasher.update(nonce);
+    hasher.update(user.to_string().as_bytes());
+    hasher.finalize().into()
+}
+
+fn xor(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    std::array::from_fn(|i| a[i] ^ b[i])
+}
+
+/// Proof of knowing `secret` for the challenge `nonce` sent to `user`, the client key masked
+/// with the signature of the challenge
+pub fn login_proof(secret: &[u8], nonce: &[u8; NONCE_LENGTH], user: &UserIdentifier) -> [u8; 32] {
+    let client_key = client_key(secret);
+    let stored_key = blake3::hash(&client_key).into();
+    xor(&client_key, &client_signature(&stored_key, nonce, user))
+}
+
+/// Recovers the client key from `proof` and checks in constant time that it hashes to
+/// `stored_key`
+pub fn verify_login_proof(
+    stored_key: &[u8],
+    nonce: &[u8; NONCE_LENGTH],
+    user: &UserIdentifier,
+    proof: &[u8; 32],
+) -> bool {
+    let Ok(stored_key) = <[u8; 32]>::try_from(stored_key) else {
+        return false;
+    };
+    let client_key = xor(proof, &client_signature(&stored_key, nonce, user));
+    blake3::hash(&client_key) == blake3::Hash::from(stored_key)
+}
+
+/// What an authenticated connection may do
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Scope {
+    /// Logged in with the password, everything of the user is accessible
+    Full,
+    /// Logged in with a device token, only backups of the device can be created and extended
+    Device(DeviceIdentifier),
+}
+
+/// Long-lived secret a device logs in with instead of the password of its user
+#[derive(Clone, Eq, PartialEq)]
+pub struct DeviceToken([u8; 32]);
+
+impl DeviceToken {
+    const PREFIX: &'static str = "gbt_";
+
+    pub fn generate() -> Self {
+        let mut token = [0; 32];
+        getrandom::getrandom(&mut token).expect("The OS provides randomness");
+        Self(token)
+    }
+
+    /// Proof of knowing the token for the challenge `nonce` sent to `user`
+    pub fn login_proof(&self, nonce: &[u8; NONCE_LENGTH], user: &UserIdentifier) -> [u8; 32] {
+        login_proof(&self.0, nonce, user)
+    }
+
+    /// What the server stores to verify the token. It can neither be turned back into the
+    /// token nor into a login proof.
+    pub fn credential(&self) -> Credential {
+        Credential::DeviceToken {
+            stored_key: stored_key(&self.0).into(),
+        }
+    }
+}
+
+impl Debug for DeviceToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DeviceToken(..)")
+    }
+}
+
+impl Display for DeviceToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::PREFIX)?;
+        self.0.iter().try_for_each(|e| write!(f, "{e:02x}"))
+    }
+}
+
+impl FromStr for DeviceToken {
+    type Err = DeviceTokenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s
+            .strip_prefix(Self::PREFIX)
+            .ok_or(DeviceTokenError)?
+            .as_bytes();
+        if hex.len() != 64 {
+            return Err(DeviceTokenError);
+        }
+        let mut token = [0; 32];
+        for (byte, pair) in token.iter_mut().zip(hex.chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| DeviceTokenError)?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| DeviceTokenError)?;
+        }
+        Ok(Self(token))
+    }
+}
+
+#[derive(Debug)]
+pub struct DeviceTokenError;
+
+impl Display for DeviceTokenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Expected a device token like gbt_ followed by 64 hex digits"
+        )
+    }
+}
+
+impl Error for DeviceTokenError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::authentication::{
+        login_proof, stored_key, verify_login_proof, DeviceToken, NONCE_LENGTH,
+    };
+    use guardian_backup_domain::model::credential::Credential;
+    use guardian_backup_domain::model::user_identifier::UserIdentifier;
+
+    #[test]
+    fn test_login_proof_needs_the_secret() {
+        let user = UserIdentifier::new("user".into());
+        let nonce = [7; NONCE_LENGTH];
+        let stored_key = stored_key(b"secret");
+
+        let proof = login_proof(b"secret", &nonce, &user);
+        assert!(verify_login_proof(&stored_key, &nonce, &user, &proof));
+        assert!(!verify_login_proof(
+            &stored_key,
+            &[8; NONCE_LENGTH],
+            &user,
+            &proof
+        ));
+        let other_user = UserIdentifier::new("other".into());
+        assert!(!verify_login_proof(
+            &stored_key,
+            &nonce,
+            &other_user,
+            &proof
+        ));
+        // Whoever only knows the stored key can't log in with it
+        let from_stored_key = login_proof(&stored_key, &nonce, &user);
+        assert!(!verify_login_proof(
+            &stored_key,
+            &nonce,
+            &user,
+            &from_stored_key
+        ));
+    }
+
+    #[test]
+    fn test_device_token_round_trip() {
+        let token = DeviceToken::generate();
+        let parsed: DeviceToken = token.to_string().parse().unwrap();
+
+        assert_eq!(parsed, token);
+        assert_ne!(DeviceToken::generate(), token);
+        assert!("gbt_12".parse::<DeviceToken>().is_err());
+        assert!(token.to_string()[4..].parse::<DeviceToken>().is_err());
+    }
+
+    #[test]
+    fn test_device_token_credential_does_not_log_in() {
+        let token = DeviceToken::generate();
+        let Credential::DeviceToken { stored_key } = token.credential() else {
+            panic!("Expected a device token credential");
+        };
+        let user = UserIdentifier::new("user".into());
+        let nonce = [7; NONCE_LENGTH];
+
+        let proof = token.login_proof(&nonce, &user);
+        assert!(verify_login_proof(&stored_key, &nonce, &user, &proof));
+        let from_stored_key = login_proof(&stored_key, &nonce, &user);
+        assert!(!verify_login_proof(
+            &stored_key,
+            &nonce,
+            &user,
+            &from_stored_key
+        ));
+    }
+}
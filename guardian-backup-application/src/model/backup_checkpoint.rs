@@ -0,0 +1,19 @@
+use guardian_backup_domain::model::backup::backup::BackupId;
+use guardian_backup_domain::model::blobs::blob_identifier::BlobIdentifier;
+use guardian_backup_domain::model::files::file_tree::FileTreeNode;
+use guardian_backup_domain::model::timestamp::Timestamp;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// Progress of a backup being created, kept so an interrupted one continues where it stopped
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackupCheckpoint {
+    pub backup: BackupId,
+    pub root: PathBuf,
+    /// When the interrupted backup started, its blobs are on the server since then
+    pub started: Timestamp,
+    /// The scan of `root` the snapshot is created from
+    pub file_tree: FileTreeNode,
+    pub uploaded: HashSet<BlobIdentifier>,
+}
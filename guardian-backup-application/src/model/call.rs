@@ -1,12 +1,44 @@
-use guardian_backup_domain::model::backup::backup::Backup;
+use guardian_backup_domain::model::backup::backup::{Backup, BackupId};
 use guardian_backup_domain::model::blobs::blob_identifier::BlobIdentifier;
+use guardian_backup_domain::model::credential::Credential;
+use guardian_backup_domain::model::device_identifier::DeviceIdentifier;
+use guardian_backup_domain::model::timestamp::Timestamp;
 use serde::{Deserialize, Serialize};
 
+/// Most snapshots the server answers a [Call::GetSnapshots] with
+pub const MAX_SNAPSHOT_PAGE: u32 = 1024;
+
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
 pub enum Call {
     CreateBackup(Backup),
+    /// Every backup with all its snapshots, prefer [Call::ListBackups] and [Call::GetBackup]
     GetBackups,
+    /// Answered with a [Response::Backup](crate::model::response::Response::Backup)
+    GetBackup(BackupId),
+    /// The backups without their snapshots, answered with a
+    /// [Response::BackupOverviews](crate::model::response::Response::BackupOverviews)
+    ListBackups,
+    /// A page of the snapshots of a backup ordered by timestamp, answered with a
+    /// [Response::Snapshots](crate::model::response::Response::Snapshots). The next page starts
+    /// after the last snapshot of this one, a page shorter than `limit` is the last.
+    GetSnapshots {
+        backup: BackupId,
+        after: Option<Timestamp>,
+        limit: u32,
+    },
     PatchBackup(Backup),
     CreateBlob(BlobIdentifier),
     GetBlob(BlobIdentifier),
+    /// The `length` bytes of a blob from `offset` on, fewer if it ends before. Continues a fetch
+    /// that broke off.
+    GetBlobRange {
+        id: BlobIdentifier,
+        offset: u64,
+        length: u64,
+    },
+    /// Registers a device of the user with the hash of its token, needs the password login
+    CreateDevice {
+        device: DeviceIdentifier,
+        token: Credential,
+    },
 }
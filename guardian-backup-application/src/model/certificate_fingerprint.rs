@@ -0,0 +1,96 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// SHA-256 hash of a certificate in DER form, displayed as colon separated hex like the output of
+/// `openssl x509 -noout -fingerprint -sha256`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct CertificateFingerprint(pub [u8; 32]);
+
+impl CertificateFingerprint {
+    /// Fingerprint of `certificate` in DER form
+    pub fn of(certificate: &[u8]) -> Self {
+        let digest = ring::digest::digest(&ring::digest::SHA256, certificate);
+        Self(digest.as_ref().try_into().expect("SHA-256 has 32 bytes"))
+    }
+}
+
+impl Display for CertificateFingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (index, byte) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, ":")?;
+            }
+            write!(f, "{byte:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for CertificateFingerprint {
+    type Err = CertificateFingerprintError;
+
+    /// Accepts hex digits with or without colons, and an `sha256:` or openssl style prefix
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.rsplit_once(['=', ' ']).map_or(s, |(_, hex)| hex);
+        let hex = hex.strip_prefix("sha256:").unwrap_or(hex);
+        let digits: Vec<u8> = hex.bytes().filter(|e| *e != b':').collect();
+        if digits.len() != 64 {
+            return Err(CertificateFingerprintError);
+        }
+
+        let mut fingerprint = [0; 32];
+        for (byte, pair) in fingerprint.iter_mut().zip(digits.chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| CertificateFingerprintError)?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| CertificateFingerprintError)?;
+        }
+        Ok(Self(fingerprint))
+    }
+}
+
+#[derive(Debug)]
+pub struct CertificateFingerprintError;
+
+impl Display for CertificateFingerprintError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Expected a SHA-256 fingerprint of 32 hex encoded bytes")
+    }
+}
+
+impl Error for CertificateFingerprintError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::certificate_fingerprint::CertificateFingerprint;
+
+    #[test]
+    fn test_parse_fingerprint() {
+        let fingerprint = CertificateFingerprint([0xab; 32]);
+        let displayed = fingerprint.to_string();
+
+        assert_eq!(&displayed[..6], "AB:AB:");
+        assert_eq!(
+            displayed.parse::<CertificateFingerprint>().unwrap(),
+            fingerprint
+        );
+        let openssl = format!("sha256 Fingerprint={displayed}");
+        assert_eq!(
+            openssl.parse::<CertificateFingerprint>().unwrap(),
+            fingerprint
+        );
+        let plain = format!("sha256:{}", "ab".repeat(32));
+        assert_eq!(
+            plain.parse::<CertificateFingerprint>().unwrap(),
+            fingerprint
+        );
+        assert!("AB:CD".parse::<CertificateFingerprint>().is_err());
+    }
+
+    #[test]
+    fn test_fingerprint_of_certificate() {
+        let fingerprint = CertificateFingerprint::of(b"");
+        assert!(fingerprint
+            .to_string()
+            .starts_with("E3:B0:C4:42:98:FC:1C:14"));
+    }
+}
@@ -1,5 +1,239 @@
+use crate::model::authentication::DeviceToken;
+use crate::model::certificate_fingerprint::CertificateFingerprint;
 use guardian_backup_domain::model::device_identifier::DeviceIdentifier;
+use guardian_backup_domain::model::duration::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 
+/// Name of the profile created for configs without profiles
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Settings of the client, stored between runs by a [crate::config_service::ConfigService]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredClientConfig")]
 pub struct ClientConfig {
+    /// Profile used if none is selected explicitly
+    pub default_profile: String,
+    pub profiles: BTreeMap<String, ServerProfile>,
+    /// Identifies this machine in the backups it creates
+    #[serde(with = "display_format")]
     pub device_id: DeviceIdentifier,
+    pub defaults: ClientDefaults,
+    pub retry: RetrySettings,
+}
+
+/// A backup server and the account used on it
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerProfile {
+    /// Address of the backup server as `host:port`, see [ServerAddress](crate::model::server_address::ServerAddress)
+    pub server: String,
+    pub user_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Logs in as this device instead of with the password, only allows creating backups
+    #[serde(
+        with = "optional_display_format",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub token: Option<DeviceToken>,
+    pub tls: TlsSettings,
+}
+
+/// How the connection to the server is secured. Without a CA certificate or fingerprint the
+/// server certificate has to be signed by one of the well-known roots.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsSettings {
+    /// Connect over unencrypted TCP, the password and files can be read on the network
+    pub insecure: bool,
+    /// Certificate (PEM) the server certificate has to be signed with, instead of the system roots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_certificate: Option<PathBuf>,
+    /// Only accept the server certificate with this fingerprint, e.g. for a self-signed one.
+    /// Takes precedence over the CA certificate.
+    #[serde(
+        with = "optional_display_format",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub fingerprint: Option<CertificateFingerprint>,
+}
+
+/// Options used when a command does not set them
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientDefaults {
+    /// How long snapshots are kept; `None` means ~30 days
+    #[serde(
+        with = "optional_display_format",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub retention_period: Option<Duration>,
+    /// Time between two snapshots of a new backup; `None` means only manual snapshots
+    #[serde(
+        with = "optional_display_format",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub interval: Option<Duration>,
+}
+
+/// How requests that failed because of the network are sent again, see
+/// [RetryingConnection](crate::retrying_connection::RetryingConnection)
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetrySettings {
+    /// Times a request is sent at most, including the first time
+    pub attempts: u32,
+    /// Wait before the first retry, doubled for every further one
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Up to this share of each wait is left out at random, so clients that lost their
+    /// connections at once don't retry at once
+    pub jitter_percent: u8,
+}
+
+impl ClientConfig {
+    /// Returns the profile called `name`, or the default profile
+    pub fn profile(&self, name: Option<&str>) -> Option<&ServerProfile> {
+        self.profiles.get(name.unwrap_or(&self.default_profile))
+    }
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            default_profile: DEFAULT_PROFILE.into(),
+            profiles: BTreeMap::from([(DEFAULT_PROFILE.into(), ServerProfile::default())]),
+            device_id: DeviceIdentifier::default(),
+            defaults: ClientDefaults::default(),
+            retry: RetrySettings::default(),
+        }
+    }
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+            jitter_percent: 50,
+        }
+    }
+}
+
+impl Default for ServerProfile {
+    fn default() -> Self {
+        Self {
+            server: "127.0.0.1:8998".into(),
+            user_name: "TestUser".into(),
+            password: None,
+            token: None,
+            tls: TlsSettings::default(),
+        }
+    }
+}
+
+/// The config file format, which also accepts the profile keys at the top level as written
+/// before profiles existed
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct StoredClientConfig {
+    default_profile: Option<String>,
+    profiles: BTreeMap<String, ServerProfile>,
+    #[serde(with = "display_format")]
+    device_id: DeviceIdentifier,
+    defaults: ClientDefaults,
+    retry: RetrySettings,
+    server: Option<String>,
+    user_name: Option<String>,
+    password: Option<String>,
+}
+
+impl From<StoredClientConfig> for ClientConfig {
+    fn from(mut value: StoredClientConfig) -> Self {
+        let legacy = [&value.server, &value.user_name, &value.password];
+        if value.profiles.is_empty() || legacy.iter().any(|e| e.is_some()) {
+            let defaults = ServerProfile::default();
+            value
+                .profiles
+                .entry(DEFAULT_PROFILE.into())
+                .or_insert(ServerProfile {
+                    server: value.server.unwrap_or(defaults.server),
+                    user_name: value.user_name.unwrap_or(defaults.user_name),
+                    password: value.password,
+                    token: None,
+                    tls: TlsSettings::default(),
+                });
+        }
+
+        let default_profile = match value.default_profile {
+            Some(name) => name,
+            None if value.profiles.contains_key(DEFAULT_PROFILE) => DEFAULT_PROFILE.into(),
+            None => value.profiles.keys().next().cloned().unwrap_or_default(),
+        };
+
+        Self {
+            default_profile,
+            profiles: value.profiles,
+            device_id: value.device_id,
+            defaults: value.defaults,
+            retry: value.retry,
+        }
+    }
+}
+
+/// Stores values in their [std::fmt::Display] format, which is readable and editable
+mod display_format {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr<Err: Display>,
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+mod optional_display_format {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => super::display_format::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr<Err: Display>,
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Wrapper<T: FromStr<Err: Display>>(
+            #[serde(deserialize_with = "super::display_format::deserialize")] T,
+        );
+
+        Ok(Option::<Wrapper<T>>::deserialize(deserializer)?.map(|e| e.0))
+    }
 }
@@ -1,5 +1,9 @@
+use crate::model::authentication::DeviceToken;
+use crate::model::certificate_fingerprint::CertificateFingerprint;
 use guardian_backup_domain::model::backup::backup::BackupId;
+use guardian_backup_domain::model::device_identifier::DeviceIdentifier;
 use guardian_backup_domain::model::duration::Duration;
+use guardian_backup_domain::model::timestamp::Timestamp;
 use std::path::PathBuf;
 
 pub struct ClientCommand {
@@ -7,18 +11,67 @@ pub struct ClientCommand {
 }
 
 pub enum ClientSubcommand {
-    /// Add or change to a new Server
+    /// Store the server and account to use in a profile of the [crate::model::client_config::ClientConfig]
     Server {
-        /// Set URL of the backup server
-        url: String,
-        /// Set username on the backup server
-        user_name: String,
-        /// Set user password on the backup server
-        password: String,
+        /// Select the profile to create or change; default is the default profile
+        profile: Option<String>,
+        /// Make the profile the default one
+        make_default: bool,
+        /// Set the address of the backup server as `ip:port`
+        address: String,
+        /// Set username on the backup server; unchanged if `None`
+        user_name: Option<String>,
+        /// Set user password on the backup server; unchanged if `None`
+        password: Option<String>,
+        /// Set the device token to log in with instead of the password; unchanged if `None`
+        token: Option<DeviceToken>,
+        /// Connect without TLS or not; unchanged if `None`
+        insecure: Option<bool>,
+        /// Set the CA certificate the server certificate is checked against; unchanged if `None`
+        ca_certificate: Option<PathBuf>,
+        /// Pin the server certificate with this fingerprint; unchanged if `None`
+        fingerprint: Option<CertificateFingerprint>,
+        /// Set the identifier of this device; unchanged if `None`
+        device_id: Option<DeviceIdentifier>,
+        /// Set the default retention period of new snapshots; unchanged if `None`
+        retention_period: Option<Duration>,
+        /// Set the default interval of new backups; unchanged if `None`
+        interval: Option<Duration>,
     },
 
     /// Create an (automated) backup, restore from a backup
     Backup(ClientBackupCommand),
+
+    /// Inspect the snapshots of a backup
+    Snapshot(ClientSnapshotCommand),
+
+    /// Manage the devices of the user
+    Device(ClientDeviceCommand),
+}
+
+pub enum ClientDeviceCommand {
+    /// Create a token a device can log in with to create backups, replacing its previous one
+    CreateToken {
+        /// Select the device; default is this one
+        device: Option<DeviceIdentifier>,
+    },
+}
+
+pub enum ClientSnapshotCommand {
+    /// List all snapshots of a backup
+    List {
+        /// Select the [BackupId] to list
+        id: BackupId,
+    },
+    /// Show the file tree and statistics of a snapshot
+    Show {
+        /// Select the [BackupId] containing the snapshot
+        id: BackupId,
+        /// Select the [guardian_backup_domain::model::backup::snapshot::Snapshot] taken at this time; default is the most recent one
+        snapshot: Option<Timestamp>,
+        /// Only include directory contents up to this depth below the root
+        max_depth: Option<usize>,
+    },
 }
 
 pub enum ClientBackupCommand {
@@ -33,10 +86,10 @@ pub enum ClientBackupCommand {
     Create {
         /// Set path which will be backed up
         backup_root: PathBuf,
-        /// Set how long the backup should be saved (e.g. 30d)
-        retention_period: Duration,
-        /// Set the interval between two Backups
-        interval: Duration,
+        /// Set how long the backup should be saved (e.g. 30d); default is the configured one
+        retention_period: Option<Duration>,
+        /// Set the interval between two Backups; default is the configured one
+        interval: Option<Duration>,
         /// Set a unique name for the backup to be displayed with
         name: String,
     },
@@ -49,4 +102,75 @@ pub enum ClientBackupCommand {
     },
     /// List all Backups on the server
     List {},
+    /// Write a snapshot as a tar archive without restoring it to disk
+    Export {
+        /// Select the [BackupId] to export
+        id: BackupId,
+        /// Select the [guardian_backup_domain::model::backup::snapshot::Snapshot] taken at this time; default is the most recent one
+        snapshot: Option<Timestamp>,
+        /// Write the archive into this file instead of stdout
+        target: Option<PathBuf>,
+    },
+    /// Read a tar archive and add it as a new snapshot to a backup
+    Import {
+        /// Add the snapshot to this [BackupId]; the backup is created if it doesn't exist yet
+        id: BackupId,
+        /// Read the archive from this file instead of stdin
+        source: Option<PathBuf>,
+        /// Set the time of the snapshot; default is the newest modification time in the archive
+        timestamp: Option<Timestamp>,
+        /// Set how long the snapshot should be saved; default is the configured one
+        retention_period: Option<Duration>,
+        /// Set the path the archive contents originate from, used when a new backup is created
+        backup_root: Option<PathBuf>,
+    },
+    /// List every distinct version of a file across all snapshots
+    History {
+        /// Select the [BackupId] to search
+        id: BackupId,
+        /// Path of the file, relative to the backup root or absolute within it
+        path: PathBuf,
+    },
+    /// Restore a single version of a file to a chosen location
+    RestoreFile {
+        /// Select the [BackupId] to restore from
+        id: BackupId,
+        /// Path of the file, relative to the backup root or absolute within it
+        path: PathBuf,
+        /// Select which version of the file is restored
+        version: FileVersionSelector,
+        /// Write the file to this path
+        target: PathBuf,
+    },
+    /// Search all snapshots for files and directories matching a pattern
+    Find {
+        pattern: FilePattern,
+        /// Only search this [BackupId]; default is every backup of the user
+        id: Option<BackupId>,
+    },
+    /// Compare two snapshots, or a snapshot with the files currently on disk
+    Diff {
+        /// Select the [BackupId] to compare
+        id: BackupId,
+        /// Select the older [guardian_backup_domain::model::backup::snapshot::Snapshot]; default is the most recent one
+        from: Option<Timestamp>,
+        /// Select the newer [guardian_backup_domain::model::backup::snapshot::Snapshot]; default is the backup root on disk
+        to: Option<Timestamp>,
+    },
+}
+
+pub enum FileVersionSelector {
+    /// The version in the most recent snapshot
+    Latest,
+    /// The version in the snapshot taken at this time
+    Snapshot(Timestamp),
+    /// The version whose content hash starts with this hex prefix
+    Hash(String),
+}
+
+pub enum FilePattern {
+    /// Matched against the file name, or the whole path relative to the backup root if it contains a `/`
+    Glob(String),
+    /// Searched for anywhere in the path relative to the backup root
+    Regex(String),
 }
@@ -0,0 +1,425 @@
+use crate::model::client_config::ClientConfig;
+use guardian_backup_domain::model::backup::backup::BackupId;
+use guardian_backup_domain::model::backup::backup_overview::BackupOverview;
+use guardian_backup_domain::model::backup::snapshot::Snapshot;
+use guardian_backup_domain::model::duration::Duration;
+use guardian_backup_domain::model::files::file_tree::{
+    FileTreeDiff, FileTreeDiffType, FileTreeNode,
+};
+use serde::Serialize;
+
+/// Result of a [crate::model::client_model::ClientCommand], rendered by the client frontend
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CommandOutput {
+    Diff(SnapshotDiff),
+    History(FileHistory),
+    Find(FindResult),
+    BackupList(BackupList),
+    SnapshotList(SnapshotList),
+    SnapshotDetails(SnapshotDetails),
+    SnapshotCreated(SnapshotCreated),
+    Restored(RestoreResult),
+    FileRestored(FileRestored),
+    Exported(ExportResult),
+    ConfigUpdated(ConfigSummary),
+    DeviceTokenCreated(DeviceTokenCreated),
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotDiff {
+    pub backup_id: BackupId,
+    /// Timestamp of the older snapshot in milliseconds since the unix epoch
+    pub from_snapshot: u64,
+    /// Timestamp of the newer snapshot, `None` if compared against the file system
+    pub to_snapshot: Option<u64>,
+    pub entries: Vec<DiffEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffEntry {
+    /// Path relative to the backup root
+    pub path: String,
+    pub change: DiffChange,
+    /// Previous path of a [DiffChange::Moved] entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    pub old_kind: Option<NodeKind>,
+    pub new_kind: Option<NodeKind>,
+    pub old_size: Option<u64>,
+    pub new_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_hash: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffChange {
+    Added,
+    Removed,
+    Modified,
+    TypeChanged,
+    Moved,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    File,
+    Directory,
+    SymbolicLink,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileHistory {
+    pub backup_id: BackupId,
+    /// Path relative to the backup root
+    pub path: String,
+    /// Distinct versions ordered by the snapshot they first appeared in
+    pub versions: Vec<FileVersion>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileVersion {
+    pub hash: String,
+    pub size: u64,
+    /// Modification time in milliseconds since the unix epoch
+    pub last_modified: u64,
+    /// Timestamp of the oldest snapshot containing this version
+    pub first_snapshot: u64,
+    /// Timestamp of the newest snapshot containing this version
+    pub last_snapshot: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FindResult {
+    pub pattern: String,
+    pub matches: Vec<FoundPath>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FoundPath {
+    pub backup_id: BackupId,
+    /// Path relative to the backup root
+    pub path: String,
+    pub kind: NodeKind,
+    /// Snapshots containing the path, ordered by timestamp
+    pub snapshots: Vec<FoundInSnapshot>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FoundInSnapshot {
+    /// Timestamp of the snapshot in milliseconds since the unix epoch
+    pub timestamp: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackupList {
+    pub backups: Vec<BackupSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackupSummary {
+    pub id: BackupId,
+    pub device: String,
+    pub file_root: String,
+    pub schedule: Vec<ScheduleRuleSummary>,
+    pub snapshot_count: usize,
+    /// Timestamps in milliseconds since the unix epoch, `None` without snapshots
+    pub oldest_snapshot: Option<u64>,
+    pub newest_snapshot: Option<u64>,
+    /// Earliest expiration time of all snapshots, `None` if none of them expires
+    pub next_expiry: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduleRuleSummary {
+    /// Milliseconds between two snapshots, `None` if infinite
+    pub interval: Option<u64>,
+    /// Milliseconds a snapshot is kept, `None` if infinite
+    pub snapshot_lifetime: Option<u64>,
+    pub last_execution: u64,
+}
+
+impl From<&BackupOverview> for BackupSummary {
+    fn from(value: &BackupOverview) -> Self {
+        Self {
+            id: value.id().clone(),
+            device: value.device().to_string(),
+            file_root: value.file_root().to_string_lossy().into_owned(),
+            schedule: value
+                .schedule()
+                .rules()
+                .iter()
+                .map(|e| ScheduleRuleSummary {
+                    interval: e.interval().get_duration(),
+                    snapshot_lifetime: e.lifetime().get_duration(),
+                    last_execution: e.last_execution().milliseconds_since_epoch(),
+                })
+                .collect(),
+            snapshot_count: value.snapshot_count(),
+            oldest_snapshot: value
+                .oldest_snapshot()
+                .map(|e| e.milliseconds_since_epoch()),
+            newest_snapshot: value
+                .newest_snapshot()
+                .map(|e| e.milliseconds_since_epoch()),
+            next_expiry: value.next_expiry().map(|e| e.milliseconds_since_epoch()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotList {
+    pub backup_id: BackupId,
+    /// Ordered by timestamp
+    pub snapshots: Vec<SnapshotSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotSummary {
+    /// Timestamp in milliseconds since the unix epoch
+    pub timestamp: u64,
+    /// `None` if the snapshot never expires
+    pub expiration_time: Option<u64>,
+    pub blob_count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotDetails {
+    pub backup_id: BackupId,
+    #[serde(flatten)]
+    pub summary: SnapshotSummary,
+    pub file_count: usize,
+    pub directory_count: usize,
+    /// Sum of all file sizes in bytes
+    pub total_size: u64,
+    pub tree: TreeEntry,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TreeEntry {
+    pub name: String,
+    pub kind: NodeKind,
+    /// Size of the file, or of all files below the directory
+    pub size: u64,
+    /// Ordered by name
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TreeEntry>,
+    /// Number of children left out because of the depth limit
+    #[serde(skip_serializing_if = "is_zero")]
+    pub omitted_children: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotCreated {
+    pub backup_id: BackupId,
+    /// `false` if the snapshot was added to an existing backup
+    pub new_backup: bool,
+    #[serde(flatten)]
+    pub summary: SnapshotSummary,
+    pub file_count: usize,
+    /// Sum of all file sizes in bytes
+    pub total_size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RestoreResult {
+    pub backup_id: BackupId,
+    /// Timestamp of the restored snapshot in milliseconds since the unix epoch
+    pub snapshot: u64,
+    pub target: String,
+    /// Changes applied to the target in order, with absolute paths
+    pub changes: Vec<DiffEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileRestored {
+    pub backup_id: BackupId,
+    /// Path relative to the backup root
+    pub path: String,
+    pub hash: String,
+    pub size: u64,
+    pub target: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportResult {
+    pub backup_id: BackupId,
+    /// Timestamp of the exported snapshot in milliseconds since the unix epoch
+    pub snapshot: u64,
+    /// `None` if the archive was written to stdout
+    pub target: Option<String>,
+    pub file_count: usize,
+    /// Sum of all file sizes in bytes
+    pub total_size: u64,
+}
+
+/// A new device token, which is only shown this one time
+#[derive(Debug, Serialize)]
+pub struct DeviceTokenCreated {
+    pub device: String,
+    pub token: String,
+}
+
+/// A profile of the stored [ClientConfig] without the password
+#[derive(Debug, Serialize)]
+pub struct ConfigSummary {
+    pub profile: String,
+    pub is_default: bool,
+    /// Names of all profiles
+    pub profiles: Vec<String>,
+    pub server: String,
+    pub user_name: String,
+    pub password_set: bool,
+    pub token_set: bool,
+    /// Whether the connection is unencrypted
+    pub insecure: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_certificate: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    pub device_id: String,
+    /// Milliseconds, `None` if not configured
+    pub default_retention_period: Option<u64>,
+    /// Milliseconds, `None` if not configured or infinite
+    pub default_interval: Option<u64>,
+}
+
+impl ConfigSummary {
+    /// Summarizes `profile` of `value`, which has to exist
+    pub fn new(value: &ClientConfig, profile: &str) -> Self {
+        let server_profile = &value.profiles[profile];
+        Self {
+            profile: profile.to_string(),
+            is_default: value.default_profile == profile,
+            profiles: value.profiles.keys().cloned().collect(),
+            server: server_profile.server.clone(),
+            user_name: server_profile.user_name.clone(),
+            password_set: server_profile.password.is_some(),
+            token_set: server_profile.token.is_some(),
+            insecure: server_profile.tls.insecure,
+            ca_certificate: server_profile
+                .tls
+                .ca_certificate
+                .as_ref()
+                .map(|e| e.to_string_lossy().into_owned()),
+            fingerprint: server_profile.tls.fingerprint.map(|e| e.to_string()),
+            device_id: value.device_id.to_string(),
+            default_retention_period: value
+                .defaults
+                .retention_period
+                .and_then(Duration::get_duration),
+            default_interval: value.defaults.interval.and_then(Duration::get_duration),
+        }
+    }
+}
+
+impl From<&Snapshot> for SnapshotSummary {
+    fn from(value: &Snapshot) -> Self {
+        Self {
+            timestamp: value.timestamp().milliseconds_since_epoch(),
+            expiration_time: value
+                .expiration_time()
+                .map(|e| e.milliseconds_since_epoch()),
+            blob_count: value.associated_blobs().len(),
+        }
+    }
+}
+
+impl TreeEntry {
+    /// Converts `node` including the children up to `max_depth` levels below it
+    pub fn new(node: &FileTreeNode, max_depth: Option<usize>) -> Self {
+        let mut children = vec![];
+        let mut omitted_children = 0;
+        if let FileTreeNode::Directory {
+            children: nodes, ..
+        } = node
+        {
+            match max_depth {
+                Some(0) => omitted_children = nodes.len(),
+                _ => {
+                    children = nodes
+                        .iter()
+                        .map(|e| TreeEntry::new(e, max_depth.map(|e| e - 1)))
+                        .collect();
+                    children.sort_by(|a, b| a.name.cmp(&b.name));
+                }
+            }
+        }
+
+        Self {
+            name: node.name().to_string_lossy().into_owned(),
+            kind: NodeKind::from(node),
+            size: node.size(),
+            children,
+            omitted_children,
+        }
+    }
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+impl DiffEntry {
+    /// Difference between the new and the old size in bytes
+    pub fn size_delta(&self) -> i128 {
+        i128::from(self.new_size.unwrap_or(0)) - i128::from(self.old_size.unwrap_or(0))
+    }
+}
+
+impl From<FileTreeDiff> for DiffEntry {
+    fn from(value: FileTreeDiff) -> Self {
+        let path = value.path().to_string_lossy().into_owned();
+        let mut from = None;
+        let (change, old, new) = match value.diff_type {
+            FileTreeDiffType::Created => (DiffChange::Added, None, Some(value.node)),
+            FileTreeDiffType::Moved { from: source } => {
+                from = Some(source.to_string_lossy().into_owned());
+                (
+                    DiffChange::Moved,
+                    Some(value.node.clone()),
+                    Some(value.node),
+                )
+            }
+            FileTreeDiffType::Deleted => (DiffChange::Removed, Some(value.node), None),
+            FileTreeDiffType::Updated => (DiffChange::Modified, value.previous, Some(value.node)),
+            FileTreeDiffType::ChangedType => {
+                (DiffChange::TypeChanged, value.previous, Some(value.node))
+            }
+        };
+
+        Self {
+            path,
+            change,
+            from,
+            old_kind: old.as_ref().map(NodeKind::from),
+            new_kind: new.as_ref().map(NodeKind::from),
+            old_size: old.as_ref().map(FileTreeNode::size),
+            new_size: new.as_ref().map(FileTreeNode::size),
+            old_hash: old.as_ref().and_then(content_hash),
+            new_hash: new.as_ref().and_then(content_hash),
+        }
+    }
+}
+
+impl From<&FileTreeNode> for NodeKind {
+    fn from(value: &FileTreeNode) -> Self {
+        match value {
+            FileTreeNode::File { .. } => NodeKind::File,
+            FileTreeNode::Directory { .. } => NodeKind::Directory,
+            FileTreeNode::SymbolicLink { .. } => NodeKind::SymbolicLink,
+        }
+    }
+}
+
+fn content_hash(node: &FileTreeNode) -> Option<String> {
+    match node {
+        FileTreeNode::File { blob, .. } => Some(blob.hash().to_string()),
+        _ => None,
+    }
+}
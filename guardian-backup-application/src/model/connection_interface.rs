@@ -1,34 +1,69 @@
+use crate::model::authentication::Scope;
 use crate::model::call::Call;
 use crate::model::response::Response;
 use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
 use guardian_backup_domain::model::user_identifier::UserIdentifier;
+use std::convert::Infallible;
 use std::fmt::Debug;
 use std::future::Future;
+use std::io::ErrorKind;
 
 pub trait ConnectionClientInterface {
     type Error: std::error::Error + 'static;
-    async fn send_request(
+    /// Responses don't borrow the connection, a blob can be read while more requests are sent
+    type Response: IncomingResponse + Send + 'static;
+    fn send_request(
         &mut self,
         command: Call,
-    ) -> Result<impl IncomingResponse + 'static, Self::Error>;
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send;
     async fn send_request_with_blob(
         &mut self,
         command: &Call,
         blob: impl BlobFetch,
-    ) -> Result<impl IncomingResponse, Self::Error>;
+    ) -> Result<Self::Response, Self::Error>;
+}
+
+/// Connection errors that may not happen again when the request is sent again, e.g. a reset
+/// connection, unlike e.g. a rejected login or a protocol error
+pub trait TransientError {
+    fn is_transient(&self) -> bool;
+}
+
+impl TransientError for std::io::Error {
+    fn is_transient(&self) -> bool {
+        matches!(
+            self.kind(),
+            ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::ConnectionRefused
+                | ErrorKind::NotConnected
+                | ErrorKind::BrokenPipe
+                | ErrorKind::TimedOut
+                | ErrorKind::UnexpectedEof
+                | ErrorKind::Interrupted
+        )
+    }
+}
+
+impl TransientError for Infallible {
+    fn is_transient(&self) -> bool {
+        match *self {}
+    }
 }
 
 pub trait IncomingResponse: Debug {
-    type Error: std::error::Error;
+    type Error: std::error::Error + 'static;
+    type Blob: BlobFetch + 'static;
     fn inner(&self) -> &Response;
     fn into_inner(self) -> Response;
-    async fn receive_blob(self) -> Result<impl BlobFetch + 'static, Self::Error>;
+    fn receive_blob(self) -> impl Future<Output = Result<Self::Blob, Self::Error>> + Send;
 }
 
 pub trait ConnectionServerInterface {
     type Error: std::error::Error;
-    type Call: IncomingCall;
-    async fn receive_request(&mut self) -> Result<impl UnhandledIncomingCall, Self::Error>;
+    /// Calls don't borrow the connection, several can be handled at once
+    type Call: UnhandledIncomingCall;
+    async fn receive_request(&mut self) -> Result<Self::Call, Self::Error>;
 }
 
 pub trait UnhandledIncomingCall: IncomingCall {
@@ -48,5 +83,7 @@ pub trait IncomingCall {
         blob_data: impl BlobFetch,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
     fn user(&self) -> &UserIdentifier;
+    /// What the login of the connection allows
+    fn scope(&self) -> &Scope;
     fn receive_blob(&mut self) -> impl Future<Output = Result<impl BlobFetch, Self::Error>> + Send;
 }
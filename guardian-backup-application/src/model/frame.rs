@@ -0,0 +1,119 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// Identifies a request and the frames answering it, the client picks a new one per request
+pub type RequestId = u32;
+
+/// Most bytes of blob data in one frame
+pub const MAX_CHUNK_LENGTH: usize = 64 * 1024;
+/// Longest message a receiver accepts unless configured otherwise
+pub const MAX_MESSAGE_LENGTH: u32 = 64 * 1024 * 1024;
+/// Blob chunks of a request a receiver buffers, the sender waits for a
+/// [FrameKind::WindowUpdate] before sending more
+pub const WINDOW: u32 = 16;
+
+/// After the login everything is sent in frames, so the requests of a client share one
+/// connection and several blobs can be transferred at once. Requests and responses are a
+/// [FrameKind::Message], followed by a [FrameKind::BlobLength] and that many bytes of
+/// [FrameKind::BlobData] frames.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum FrameKind {
+    /// A CBOR encoded [Call](crate::model::call::Call) or
+    /// [Response](crate::model::response::Response)
+    Message,
+    /// The big endian `u64` length of the blob following the message, zero without a blob
+    BlobLength,
+    /// A chunk of the blob, at most [MAX_CHUNK_LENGTH] bytes
+    BlobData,
+    /// The receiver took this many chunks (big endian `u32`) of the blob of the request
+    WindowUpdate,
+}
+
+impl FrameKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            FrameKind::Message => 0,
+            FrameKind::BlobLength => 1,
+            FrameKind::BlobData => 2,
+            FrameKind::WindowUpdate => 3,
+        }
+    }
+}
+
+impl TryFrom<u8> for FrameKind {
+    type Error = FrameError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(FrameKind::Message),
+            1 => Ok(FrameKind::BlobLength),
+            2 => Ok(FrameKind::BlobData),
+            3 => Ok(FrameKind::WindowUpdate),
+            unknown => Err(FrameError::UnknownKind(unknown)),
+        }
+    }
+}
+
+/// Precedes the payload of every frame
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct FrameHeader {
+    pub request: RequestId,
+    pub kind: FrameKind,
+    /// Length of the payload
+    pub length: u32,
+}
+
+impl FrameHeader {
+    pub const LENGTH: usize = 9;
+
+    pub fn to_bytes(&self) -> [u8; Self::LENGTH] {
+        let mut bytes = [0; Self::LENGTH];
+        bytes[..4].copy_from_slice(&self.request.to_be_bytes());
+        bytes[4] = self.kind.to_byte();
+        bytes[5..].copy_from_slice(&self.length.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: [u8; Self::LENGTH]) -> Result<Self, FrameError> {
+        let [r0, r1, r2, r3, kind, l0, l1, l2, l3] = bytes;
+        Ok(Self {
+            request: u32::from_be_bytes([r0, r1, r2, r3]),
+            kind: kind.try_into()?,
+            length: u32::from_be_bytes([l0, l1, l2, l3]),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum FrameError {
+    UnknownKind(u8),
+}
+
+impl Display for FrameError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::UnknownKind(kind) => write!(f, "Unknown frame kind {kind}"),
+        }
+    }
+}
+
+impl Error for FrameError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::frame::{FrameHeader, FrameKind};
+
+    #[test]
+    fn test_frame_header_round_trip() {
+        let header = FrameHeader {
+            request: 0x01020304,
+            kind: FrameKind::BlobData,
+            length: 65536,
+        };
+        let bytes = header.to_bytes();
+
+        assert_eq!(bytes, [1, 2, 3, 4, 2, 0, 1, 0, 0]);
+        assert_eq!(FrameHeader::from_bytes(bytes).unwrap(), header);
+        assert!(FrameHeader::from_bytes([0, 0, 0, 1, 9, 0, 0, 0, 0]).is_err());
+    }
+}
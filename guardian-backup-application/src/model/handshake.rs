@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Oldest protocol version this build speaks
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+/// Newest protocol version this build speaks, raised on every change older peers can't read
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// First message on a connection, the client sends it before the login and the server answers
+/// with a [HelloResponse]. Fields added later are skipped by older peers.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct Hello {
+    pub min_version: u16,
+    pub max_version: u16,
+    pub features: Features,
+    /// Name and version of the program, only logged
+    pub agent: Box<str>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub enum HelloResponse {
+    Accepted {
+        version: u16,
+        features: Features,
+        agent: Box<str>,
+    },
+    /// The server closes the connection after this answer
+    Incompatible(Incompatibility),
+}
+
+/// What two peers agreed on. Every feature this build implements is also
+/// [required](Features::REQUIRED), so nothing depends on it yet besides logging: a peer lacking
+/// one is refused with an [Incompatibility] instead of being served with less.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Negotiated {
+    pub version: u16,
+    pub features: Features,
+}
+
+impl Hello {
+    /// What this build speaks
+    pub fn new(agent: impl Into<Box<str>>) -> Self {
+        Self {
+            min_version: MIN_PROTOCOL_VERSION,
+            max_version: PROTOCOL_VERSION,
+            features: Features::SUPPORTED,
+            agent: agent.into(),
+        }
+    }
+
+    /// Picks the newest version both speak and the features both support. Optional features
+    /// only one side supports are left out, missing [required](Features::REQUIRED) ones fail.
+    pub fn negotiate(&self, peer: &Hello) -> Result<Negotiated, Incompatibility> {
+        let version = self.max_version.min(peer.max_version);
+        if version < self.min_version.max(peer.min_version) {
+            return Err(Incompatibility::Version {
+                min_version: self.min_version,
+                max_version: self.max_version,
+            });
+        }
+
+        let features = self.features.intersection(peer.features);
+        let missing = Features::REQUIRED.difference(peer.features);
+        if !missing.is_empty() {
+            return Err(Incompatibility::MissingFeatures(missing));
+        }
+        Ok(Negotiated { version, features })
+    }
+}
+
+/// Why a peer refused to talk, from its point of view
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub enum Incompatibility {
+    /// The refusing peer only speaks these versions
+    Version { min_version: u16, max_version: u16 },
+    /// The other peer lacks these features the refusing one requires
+    MissingFeatures(Features),
+}
+
+/// Optional parts of the protocol, a set of flags. Unknown flags of newer peers are kept, so
+/// they are simply not negotiated.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, Default)]
+#[serde(transparent)]
+pub struct Features(u32);
+
+impl Features {
+    /// Blobs are compressed for the transfer
+    pub const COMPRESSION: Features = Features(1);
+    /// Blobs are sent in chunks with flow control, several at once on a connection
+    pub const CHUNKING: Features = Features(1 << 1);
+    /// Blobs are encrypted by the client before they are sent
+    pub const ENCRYPTION: Features = Features(1 << 2);
+    /// Several calls are sent in one message
+    pub const BATCH_CALLS: Features = Features(1 << 3);
+
+    /// What this build implements
+    pub const SUPPORTED: Features = Features::CHUNKING;
+    /// What this build can't talk without
+    pub const REQUIRED: Features = Features::CHUNKING;
+
+    const NAMES: [(Features, &'static str); 4] = [
+        (Features::COMPRESSION, "compression"),
+        (Features::CHUNKING, "chunking"),
+        (Features::ENCRYPTION, "encryption"),
+        (Features::BATCH_CALLS, "batch calls"),
+    ];
+
+    pub const fn intersection(self, other: Features) -> Features {
+        Features(self.0 & other.0)
+    }
+
+    /// The features of `self` that `other` lacks
+    pub const fn difference(self, other: Features) -> Features {
+        Features(self.0 & !other.0)
+    }
+
+    pub const fn contains(self, other: Features) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl Display for Features {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut names = Self::NAMES
+            .iter()
+            .filter(|(feature, _)| self.contains(*feature))
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>();
+        let known = Self::NAMES
+            .iter()
+            .fold(0, |known, (feature, _)| known | feature.0);
+        let unknown = format!("{:#x}", self.0 & !known);
+        if self.0 & !known != 0 {
+            names.push(&unknown);
+        }
+
+        match names.is_empty() {
+            true => write!(f, "none"),
+            false => write!(f, "{}", names.join(", ")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::handshake::{Features, Hello, Incompatibility, Negotiated};
+
+    #[test]
+    fn test_negotiate() {
+        let server = Hello::new("server");
+        let newer_client = Hello {
+            min_version: 1,
+            max_version: 3,
+            features: Features(0xff),
+            agent: "client".into(),
+        };
+        assert_eq!(
+            server.negotiate(&newer_client),
+            Ok(Negotiated {
+                version: 1,
+                features: Features::CHUNKING,
+            })
+        );
+
+        let too_new = Hello {
+            min_version: 2,
+            ..newer_client.clone()
+        };
+        assert_eq!(
+            server.negotiate(&too_new),
+            Err(Incompatibility::Version {
+                min_version: 1,
+                max_version: 1,
+            })
+        );
+
+        let without_chunking = Hello {
+            features: Features::COMPRESSION,
+            ..newer_client
+        };
+        assert_eq!(
+            server.negotiate(&without_chunking),
+            Err(Incompatibility::MissingFeatures(Features::CHUNKING))
+        );
+        assert_eq!(Features(0x13).to_string(), "compression, chunking, 0x10");
+    }
+}
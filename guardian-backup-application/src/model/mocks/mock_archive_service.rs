@@ -0,0 +1,97 @@
+use crate::archive_service::{
+    ArchiveEntry, ArchiveEntryKind, ArchiveReader, ArchiveService, ArchiveWriter,
+};
+use crate::in_memory_repositories::blob_repository::InMemoryBlobFetch;
+use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
+use guardian_backup_domain::model::files::directory_metadata::DirectoryMetadata;
+use guardian_backup_domain::model::files::file_metadata::FileMetadata;
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
+
+pub struct MockArchiveService {}
+
+impl ArchiveService for MockArchiveService {
+    type Writer = MockArchiveWriter;
+    type Reader = MockArchiveReader;
+    type Error = Infallible;
+
+    async fn create_writer(_target: Option<&Path>) -> Result<Self::Writer, Self::Error> {
+        Ok(MockArchiveWriter {})
+    }
+
+    async fn open_reader(_source: Option<&Path>) -> Result<Self::Reader, Self::Error> {
+        Ok(MockArchiveReader {
+            entries: vec![
+                ArchiveEntry {
+                    path: PathBuf::from("mock"),
+                    kind: ArchiveEntryKind::Directory {
+                        metadata: DirectoryMetadata::default(),
+                    },
+                },
+                ArchiveEntry {
+                    path: PathBuf::from("mock/a.txt"),
+                    kind: ArchiveEntryKind::File {
+                        metadata: FileMetadata {
+                            file_size: 3,
+                            last_modified: 223355779,
+                            permissions: None,
+                        },
+                        content: InMemoryBlobFetch::new([1, 2, 3].into()),
+                    },
+                },
+                ArchiveEntry {
+                    path: PathBuf::from("mock/sub/b.txt"),
+                    kind: ArchiveEntryKind::File {
+                        metadata: FileMetadata {
+                            file_size: 0,
+                            last_modified: 123456789,
+                            permissions: None,
+                        },
+                        content: InMemoryBlobFetch::new([].into()),
+                    },
+                },
+            ]
+            .into_iter(),
+        })
+    }
+}
+
+pub struct MockArchiveWriter {}
+
+impl ArchiveWriter for MockArchiveWriter {
+    type Error = Infallible;
+
+    async fn append_directory(
+        &mut self,
+        _path: &Path,
+        _metadata: &DirectoryMetadata,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn append_file(
+        &mut self,
+        _path: &Path,
+        _metadata: &FileMetadata,
+        _blob: impl BlobFetch,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn finish(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+pub struct MockArchiveReader {
+    entries: std::vec::IntoIter<ArchiveEntry<InMemoryBlobFetch>>,
+}
+
+impl ArchiveReader for MockArchiveReader {
+    type Error = Infallible;
+    type Blob = InMemoryBlobFetch;
+
+    async fn next_entry(&mut self) -> Result<Option<ArchiveEntry<Self::Blob>>, Self::Error> {
+        Ok(self.entries.next())
+    }
+}
@@ -0,0 +1,17 @@
+use crate::config_service::ConfigService;
+use crate::model::client_config::ClientConfig;
+use std::convert::Infallible;
+
+pub struct MockConfigService {}
+
+impl ConfigService for MockConfigService {
+    type Error = Infallible;
+
+    async fn load_config() -> Result<ClientConfig, Self::Error> {
+        Ok(ClientConfig::default())
+    }
+
+    async fn save_config(_config: &ClientConfig) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
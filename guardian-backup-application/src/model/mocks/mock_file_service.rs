@@ -36,6 +36,7 @@ impl FileService for MockFileService {
             metadata: FileMetadata {
                 file_size: 42,
                 last_modified: 123456789,
+                permissions: None,
             },
         })
     }
@@ -56,9 +57,31 @@ impl FileService for MockFileService {
         Ok(())
     }
 
+    async fn partial_length(_path: &Path, _version: &FileHash) -> Result<u64, Self::Error> {
+        Ok(0)
+    }
+
+    async fn continue_file(
+        _path: &Path,
+        _file_meta: &FileMetadata,
+        _version: &FileHash,
+        _offset: u64,
+        _blob: impl BlobFetch,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
     async fn create_dir(path: &Path) -> Result<(), Self::Error> {
         Ok(())
     }
+
+    async fn create_private_dir(_path: &Path) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn rename(_from: &Path, _to: &Path) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 pub struct MockFile {
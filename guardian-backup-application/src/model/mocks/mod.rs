@@ -1,5 +1,7 @@
 #![cfg(any(test, feature = "mocks"))]
 
+pub mod mock_archive_service;
+pub mod mock_config_service;
 pub mod mock_encoder_service;
 pub mod mock_file_service;
 pub mod mock_hash_service;
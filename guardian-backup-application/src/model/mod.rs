@@ -1,9 +1,14 @@
+pub mod authentication;
+pub mod backup_checkpoint;
 pub mod call;
+pub mod certificate_fingerprint;
 pub mod client_backup_service;
 pub mod client_config;
 pub mod client_model;
+pub mod command_output;
 pub mod connection_interface;
-pub mod credential;
+pub mod frame;
+pub mod handshake;
 pub mod mocks;
-pub mod password_hash_algorithms;
 pub mod response;
+pub mod server_address;
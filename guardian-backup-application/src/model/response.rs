@@ -1,11 +1,145 @@
 use guardian_backup_domain::model::backup::backup::Backup;
+use guardian_backup_domain::model::backup::backup_overview::BackupOverview;
+use guardian_backup_domain::model::backup::snapshot::Snapshot;
 use guardian_backup_domain::model::blobs::blob_identifier::BlobIdentifier;
 use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
 pub enum Response {
     Successful,
-    Error(Box<str>),
+    Error(ErrorResponse),
     BackupList(Box<[Backup]>),
+    Backup(Backup),
+    BackupOverviews(Box<[BackupOverview]>),
+    Snapshots(Box<[Snapshot]>),
     BlobCreated(BlobIdentifier),
+    /// The call exceeded a limit of the server or wasn't understood, nothing was changed
+    Rejected(Rejection),
+}
+
+impl Response {
+    /// The error the server answered with, a [Rejection] is one as well
+    pub fn error(&self) -> Option<ErrorResponse> {
+        match self {
+            Response::Error(error) => Some(error.clone()),
+            Response::Rejected(rejection) => Some(rejection.clone().into()),
+            _ => None,
+        }
+    }
+}
+
+/// What kind of error the server answered with, so clients can react without parsing messages
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+pub enum ErrorCode {
+    /// The backup or blob doesn't exist
+    NotFound,
+    /// The login doesn't allow the call, e.g. device tokens restoring files
+    NoPermission,
+    /// The user stores as much as allowed
+    QuotaExceeded,
+    /// The call contradicts what the server has stored
+    Conflict,
+    /// The call can't be carried out as sent, retrying won't help
+    InvalidRequest,
+    /// The server failed, retrying later might help
+    Internal,
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorCode::NotFound => write!(f, "Not found"),
+            ErrorCode::NoPermission => write!(f, "No permission"),
+            ErrorCode::QuotaExceeded => write!(f, "Quota exceeded"),
+            ErrorCode::Conflict => write!(f, "Conflict"),
+            ErrorCode::InvalidRequest => write!(f, "Invalid request"),
+            ErrorCode::Internal => write!(f, "Internal server error"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct ErrorResponse {
+    pub code: ErrorCode,
+    /// Details for humans, clients must not depend on them
+    pub message: Box<str>,
+}
+
+impl Display for ErrorResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl Error for ErrorResponse {}
+
+/// Repository errors the server answers with an [ErrorCode]
+pub trait ToErrorCode {
+    fn error_code(&self) -> ErrorCode;
+}
+
+impl ToErrorCode for Infallible {
+    fn error_code(&self) -> ErrorCode {
+        match *self {}
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub enum Rejection {
+    /// The encoded call is longer than `limit` bytes
+    CallTooLarge { limit: u32 },
+    /// The blob is longer than `limit` bytes
+    BlobTooLarge { limit: u64 },
+    /// The blobs of the user would take more than `limit` bytes
+    UploadQuotaExceeded { limit: u64 },
+    /// The call couldn't be decoded, e.g. it is from a newer protocol version
+    InvalidCall,
+    /// The blob doesn't have the hash of its identifier
+    HashMismatch,
+    /// The server can't compute the hash of the blob identifier
+    UnsupportedHash,
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejection::CallTooLarge { limit } => {
+                write!(f, "The call is larger than the limit of {limit} bytes")
+            }
+            Rejection::BlobTooLarge { limit } => {
+                write!(f, "The blob is larger than the limit of {limit} bytes")
+            }
+            Rejection::UploadQuotaExceeded { limit } => {
+                write!(f, "The upload exceeds the quota of {limit} bytes")
+            }
+            Rejection::InvalidCall => write!(f, "The server doesn't understand the call"),
+            Rejection::HashMismatch => write!(f, "The blob doesn't match its hash"),
+            Rejection::UnsupportedHash => write!(f, "The server doesn't support the hash"),
+        }
+    }
+}
+
+impl Rejection {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Rejection::UploadQuotaExceeded { .. } => ErrorCode::QuotaExceeded,
+            Rejection::CallTooLarge { .. }
+            | Rejection::BlobTooLarge { .. }
+            | Rejection::InvalidCall
+            | Rejection::HashMismatch
+            | Rejection::UnsupportedHash => ErrorCode::InvalidRequest,
+        }
+    }
+}
+
+impl From<Rejection> for ErrorResponse {
+    fn from(rejection: Rejection) -> Self {
+        Self {
+            code: rejection.code(),
+            message: rejection.to_string().into(),
+        }
+    }
 }
@@ -0,0 +1,141 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+/// Port used when an address doesn't name one
+pub const DEFAULT_PORT: u16 = 8998;
+
+/// Address of a backup server as `host:port`, where the host is a hostname or an IP literal.
+/// IPv6 literals are written in brackets (`[::1]:8998`). Hostnames are resolved on connect.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ServerAddress {
+    host: String,
+    port: u16,
+}
+
+impl ServerAddress {
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+        }
+    }
+
+    /// Hostname or IP literal, without brackets
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl From<SocketAddr> for ServerAddress {
+    fn from(value: SocketAddr) -> Self {
+        Self::new(value.ip().to_string(), value.port())
+    }
+}
+
+impl FromStr for ServerAddress {
+    type Err = ServerAddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (host, port) = if let Some(bracketed) = s.strip_prefix('[') {
+            let (host, rest) = bracketed
+                .split_once(']')
+                .ok_or(ServerAddressError::UnclosedBracket)?;
+            if host.parse::<std::net::Ipv6Addr>().is_err() {
+                return Err(ServerAddressError::InvalidHost);
+            }
+            match rest {
+                "" => (host, None),
+                _ => (
+                    host,
+                    Some(
+                        rest.strip_prefix(':')
+                            .ok_or(ServerAddressError::InvalidPort)?,
+                    ),
+                ),
+            }
+        } else if s.parse::<IpAddr>().is_ok() {
+            // A bare IPv6 literal can't carry a port, its colons are ambiguous
+            (s, None)
+        } else {
+            match s.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (s, None),
+            }
+        };
+
+        let valid_host = |e: char| e.is_ascii_alphanumeric() || e == '-' || e == '.';
+        if host.parse::<IpAddr>().is_err() && (host.is_empty() || !host.chars().all(valid_host)) {
+            return Err(ServerAddressError::InvalidHost);
+        }
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| ServerAddressError::InvalidPort)?,
+            None => DEFAULT_PORT,
+        };
+        Ok(Self::new(host, port))
+    }
+}
+
+impl Display for ServerAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ServerAddressError {
+    InvalidHost,
+    InvalidPort,
+    UnclosedBracket,
+}
+
+impl Display for ServerAddressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerAddressError::InvalidHost => write!(f, "Expected a hostname or IP address"),
+            ServerAddressError::InvalidPort => write!(f, "Expected a port between 0 and 65535"),
+            ServerAddressError::UnclosedBracket => write!(f, "Missing ] after the IPv6 address"),
+        }
+    }
+}
+
+impl Error for ServerAddressError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::server_address::{ServerAddress, DEFAULT_PORT};
+
+    #[test]
+    fn test_parse_server_address() {
+        let parse = |e: &str| e.parse::<ServerAddress>().ok();
+
+        assert_eq!(
+            parse("backup.internal:9000"),
+            Some(ServerAddress::new("backup.internal", 9000))
+        );
+        assert_eq!(
+            parse("192.168.1.5"),
+            Some(ServerAddress::new("192.168.1.5", DEFAULT_PORT))
+        );
+        assert_eq!(
+            parse("[fe80::1]:9000"),
+            Some(ServerAddress::new("fe80::1", 9000))
+        );
+        assert_eq!(parse("::1"), Some(ServerAddress::new("::1", DEFAULT_PORT)));
+        assert_eq!(parse("[::1"), None);
+        assert_eq!(parse("[backup.internal]:9000"), None);
+        assert_eq!(parse("backup.internal:99999"), None);
+        assert_eq!(parse(":9000"), None);
+
+        assert_eq!(ServerAddress::new("::1", 9000).to_string(), "[::1]:9000");
+    }
+}
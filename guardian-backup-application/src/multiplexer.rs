@@ -0,0 +1,606 @@
+use crate::model::frame::{
+    FrameHeader, FrameKind, RequestId, MAX_CHUNK_LENGTH, MAX_MESSAGE_LENGTH, WINDOW,
+};
+use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
+use std::cmp::min;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, MutexGuard};
+use std::time::Duration;
+use tokio::io::{sink, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, Mutex, Semaphore};
+
+/// A received [frame](FrameKind)
+#[derive(Debug)]
+pub struct Frame {
+    pub request: RequestId,
+    pub kind: FrameKind,
+    pub payload: Vec<u8>,
+    /// The message was longer than [ReadLimits::max_message_length] and skipped, the payload is
+    /// empty
+    pub oversized: bool,
+}
+
+/// What a [Demultiplexer] accepts from the peer
+#[derive(Debug, Clone, Copy)]
+pub struct ReadLimits {
+    /// Longer messages are skipped and marked [oversized](Frame::oversized)
+    pub max_message_length: u32,
+    /// Time a frame gets to arrive completely once it has begun
+    pub read_timeout: Option<Duration>,
+    /// Time the peer may send nothing
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for ReadLimits {
+    fn default() -> Self {
+        Self {
+            max_message_length: MAX_MESSAGE_LENGTH,
+            read_timeout: None,
+            idle_timeout: None,
+        }
+    }
+}
+
+/// Reads the next frame without allocating more than the limits allow. Oversized messages are
+/// skipped so only their request fails, other oversized frames break the protocol.
+async fn read_frame(
+    rx: &mut (impl AsyncRead + Unpin),
+    limits: &ReadLimits,
+) -> std::io::Result<Frame> {
+    let mut header = [0; FrameHeader::LENGTH];
+    with_timeout(limits.idle_timeout, rx.read_exact(&mut header[..1])).await?;
+    with_timeout(limits.read_timeout, async {
+        rx.read_exact(&mut header[1..]).await?;
+        let header = FrameHeader::from_bytes(header).map_err(protocol_error)?;
+        let max_length = match header.kind {
+            FrameKind::Message => limits.max_message_length,
+            FrameKind::BlobLength => size_of::<u64>() as u32,
+            FrameKind::BlobData => MAX_CHUNK_LENGTH as u32,
+            FrameKind::WindowUpdate => size_of::<u32>() as u32,
+        };
+
+        let oversized = header.length > max_length;
+        let payload = match (oversized, header.kind) {
+            (false, _) => {
+                let mut payload = vec![0; header.length as usize];
+                rx.read_exact(&mut payload).await?;
+                payload
+            }
+            (true, FrameKind::Message) => {
+                let length = u64::from(header.length);
+                let skipped = tokio::io::copy(&mut (&mut *rx).take(length), &mut sink()).await?;
+                if skipped < length {
+                    return Err(Error::from(ErrorKind::UnexpectedEof));
+                }
+                Vec::new()
+            }
+            (true, kind) => {
+                return Err(protocol_error(format!(
+                    "{kind:?} frame of {} bytes",
+                    header.length
+                )))
+            }
+        };
+        Ok(Frame {
+            request: header.request,
+            kind: header.kind,
+            payload,
+            oversized,
+        })
+    })
+    .await
+}
+
+/// Fails with [ErrorKind::TimedOut] if `future` takes longer than `duration`
+async fn with_timeout<T>(
+    duration: Option<Duration>,
+    future: impl Future<Output = std::io::Result<T>>,
+) -> std::io::Result<T> {
+    match duration {
+        Some(duration) => tokio::time::timeout(duration, future)
+            .await
+            .map_err(|_| Error::new(ErrorKind::TimedOut, "The peer took too long"))?,
+        None => future.await,
+    }
+}
+
+async fn write_frame(
+    tx: &mut (impl AsyncWrite + Unpin),
+    request: RequestId,
+    kind: FrameKind,
+    payload: &[u8],
+) -> std::io::Result<()> {
+    let length = u32::try_from(payload.len())
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "The frame is too long"))?;
+    let header = FrameHeader {
+        request,
+        kind,
+        length,
+    };
+    tx.write_all(&header.to_bytes()).await?;
+    tx.write_all(payload).await
+}
+
+fn protocol_error(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Error {
+    Error::new(ErrorKind::InvalidData, error)
+}
+
+fn connection_closed() -> Error {
+    Error::new(ErrorKind::ConnectionAborted, "The connection was closed")
+}
+
+/// Writes the frames of all requests of a connection, the frames of concurrent requests
+/// interleave
+#[derive(Debug)]
+pub struct FrameWriter<W> {
+    tx: Arc<Mutex<BufWriter<W>>>,
+    windows: Arc<std::sync::Mutex<Windows>>,
+}
+
+/// Chunks the receivers of the blobs being sent have room for
+#[derive(Debug, Default)]
+struct Windows {
+    open: HashMap<RequestId, Arc<Semaphore>>,
+    closed: bool,
+}
+
+impl<W> Clone for FrameWriter<W> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            windows: self.windows.clone(),
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send> FrameWriter<W> {
+    pub fn new(tx: W) -> Self {
+        Self {
+            tx: Arc::new(Mutex::new(BufWriter::new(tx))),
+            windows: Arc::default(),
+        }
+    }
+
+    /// Sends the message of `request` without a blob
+    pub async fn send(&self, request: RequestId, message: &[u8]) -> std::io::Result<()> {
+        let mut tx = self.tx.lock().await;
+        write_frame(&mut *tx, request, FrameKind::Message, message).await?;
+        write_frame(
+            &mut *tx,
+            request,
+            FrameKind::BlobLength,
+            &0u64.to_be_bytes(),
+        )
+        .await?;
+        tx.flush().await
+    }
+
+    /// Sends the message of `request` followed by `blob`. The connection is closed if the blob
+    /// can't be sent completely, the receiver would wait for the rest of it otherwise.
+    pub async fn send_with_blob(
+        &self,
+        request: RequestId,
+        message: &[u8],
+        mut blob: impl BlobFetch,
+    ) -> std::io::Result<()> {
+        let window = self.open_window(request)?;
+        let result = self.send_blob(request, message, &mut blob, &window).await;
+        self.lock_windows().open.remove(&request);
+        if result.is_err() {
+            self.shutdown().await;
+        }
+        result
+    }
+
+    async fn send_blob(
+        &self,
+        request: RequestId,
+        message: &[u8],
+        blob: &mut impl BlobFetch,
+        window: &Semaphore,
+    ) -> std::io::Result<()> {
+        let length = blob.remaining_len().to_be_bytes();
+        {
+            let mut tx = self.tx.lock().await;
+            write_frame(&mut *tx, request, FrameKind::Message, message).await?;
+            write_frame(&mut *tx, request, FrameKind::BlobLength, &length).await?;
+            tx.flush().await?;
+        }
+
+        let mut chunk = vec![0; MAX_CHUNK_LENGTH];
+        while blob.remaining_len() > 0 {
+            let mut filled = 0;
+            while filled < chunk.len() && blob.remaining_len() > 0 {
+                let read = blob
+                    .read(&mut chunk[filled..])
+                    .await
+                    .map_err(|e| Error::other(e.to_string()))?;
+                if read == 0 {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "The blob ended before its length",
+                    ));
+                }
+                filled += read;
+            }
+
+            window
+                .acquire()
+                .await
+                .map_err(|_| connection_closed())?
+                .forget();
+            let mut tx = self.tx.lock().await;
+            write_frame(&mut *tx, request, FrameKind::BlobData, &chunk[..filled]).await?;
+            tx.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Lets the sender of the blob of `request` send `chunks` more chunks
+    async fn grant(&self, request: RequestId, chunks: u32) -> std::io::Result<()> {
+        let mut tx = self.tx.lock().await;
+        write_frame(
+            &mut *tx,
+            request,
+            FrameKind::WindowUpdate,
+            &chunks.to_be_bytes(),
+        )
+        .await?;
+        tx.flush().await
+    }
+
+    /// Closes the connection, e.g. after the peer violated the protocol
+    pub async fn shutdown(&self) {
+        // Only fails if the connection is gone already
+        let _ = self.tx.lock().await.shutdown().await;
+    }
+
+    fn open_window(&self, request: RequestId) -> std::io::Result<Arc<Semaphore>> {
+        let mut windows = self.lock_windows();
+        if windows.closed {
+            return Err(connection_closed());
+        }
+        let window = Arc::new(Semaphore::new(WINDOW as usize));
+        windows.open.insert(request, window.clone());
+        Ok(window)
+    }
+
+    /// The receiver of `request` took `chunks` chunks. A window never grows beyond [WINDOW], so
+    /// a peer granting too much can't overflow it.
+    fn widen_window(&self, request: RequestId, chunks: u32) {
+        if let Some(window) = self.lock_windows().open.get(&request) {
+            let room = (WINDOW as usize).saturating_sub(window.available_permits());
+            window.add_permits(min(room, chunks as usize));
+        }
+    }
+
+    fn close_windows(&self) {
+        let mut windows = self.lock_windows();
+        windows.closed = true;
+        windows.open.values().for_each(|e| e.close());
+    }
+
+    fn lock_windows(&self) -> MutexGuard<'_, Windows> {
+        self.windows.lock().expect("Not poisoned")
+    }
+}
+
+/// Hands the frames read from a connection to the requests they belong to
+#[derive(Debug)]
+pub struct Demultiplexer<W> {
+    writer: FrameWriter<W>,
+    routes: Arc<std::sync::Mutex<Routes>>,
+    limits: ReadLimits,
+}
+
+#[derive(Debug, Default)]
+struct Routes {
+    open: HashMap<RequestId, Route>,
+    closed: bool,
+}
+
+#[derive(Debug)]
+struct Route {
+    frames: mpsc::Sender<Frame>,
+    expected: Expected,
+}
+
+/// The frame a request receives next, the route is done once no blob data is left
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+enum Expected {
+    Message,
+    BlobLength,
+    BlobData { remaining: u64 },
+}
+
+enum Routed {
+    Delivered,
+    /// Blob data nobody reads anymore, the sender still needs room to finish the blob
+    Abandoned(RequestId),
+    NewRequest(Frame),
+}
+
+impl<W> Clone for Demultiplexer<W> {
+    fn clone(&self) -> Self {
+        Self {
+            writer: self.writer.clone(),
+            routes: self.routes.clone(),
+            limits: self.limits,
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send + 'static> Demultiplexer<W> {
+    pub fn new(writer: FrameWriter<W>) -> Self {
+        Self {
+            writer,
+            routes: Arc::default(),
+            limits: ReadLimits::default(),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: ReadLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Receives the frames answering `request`, starting with the message
+    pub fn register(&self, request: RequestId) -> std::io::Result<IncomingFrames<W>> {
+        self.open_route(request, Expected::Message)
+    }
+
+    /// Routes the frames read from `rx` until the message of a request nobody registered
+    /// arrives, which is returned with the frames following it
+    pub async fn next_request(
+        &self,
+        rx: &mut (impl AsyncRead + Unpin),
+    ) -> std::io::Result<(Frame, IncomingFrames<W>)> {
+        loop {
+            let frame = read_frame(rx, &self.limits).await?;
+            if frame.kind == FrameKind::WindowUpdate {
+                let chunks = frame
+                    .payload
+                    .try_into()
+                    .map(u32::from_be_bytes)
+                    .map_err(|_| protocol_error("Invalid window update"))?;
+                self.writer.widen_window(frame.request, chunks);
+                continue;
+            }
+
+            match self.route(frame)? {
+                Routed::Delivered => {}
+                // Granted in the background, reading must never wait for writing or two peers
+                // sending blobs to each other could block forever
+                Routed::Abandoned(request) => {
+                    let writer = self.writer.clone();
+                    tokio::spawn(async move { writer.grant(request, 1).await });
+                }
+                Routed::NewRequest(frame) => {
+                    let frames = self.open_route(frame.request, Expected::BlobLength)?;
+                    return Ok((frame, frames));
+                }
+            }
+        }
+    }
+
+    /// Fails the pending requests and blob transfers, the connection is gone
+    pub fn close(&self) {
+        let mut routes = self.lock_routes();
+        routes.closed = true;
+        routes.open.clear();
+        self.writer.close_windows();
+    }
+
+    fn open_route(
+        &self,
+        request: RequestId,
+        expected: Expected,
+    ) -> std::io::Result<IncomingFrames<W>> {
+        let mut routes = self.lock_routes();
+        if routes.closed {
+            return Err(connection_closed());
+        }
+        // Room for the message, the blob length and a full window of chunks
+        let (frames, receiver) = mpsc::channel(WINDOW as usize + 2);
+        routes.open.insert(request, Route { frames, expected });
+        Ok(IncomingFrames {
+            request,
+            frames: receiver,
+            writer: self.writer.clone(),
+        })
+    }
+
+    fn route(&self, frame: Frame) -> std::io::Result<Routed> {
+        let mut routes = self.lock_routes();
+        let Some(route) = routes.open.get_mut(&frame.request) else {
+            return match frame.kind {
+                FrameKind::Message => Ok(Routed::NewRequest(frame)),
+                kind => Err(protocol_error(format!(
+                    "{kind:?} frame for the unknown request {}",
+                    frame.request
+                ))),
+            };
+        };
+
+        route.expected = match (route.expected, frame.kind) {
+            (Expected::Message, FrameKind::Message) => Expected::BlobLength,
+            (Expected::BlobLength, FrameKind::BlobLength) => Expected::BlobData {
+                remaining: blob_length(&frame)?,
+            },
+            (Expected::BlobData { remaining }, FrameKind::BlobData) => Expected::BlobData {
+                remaining: remaining
+                    .checked_sub(frame.payload.len() as u64)
+                    .ok_or_else(|| protocol_error("More blob data than announced"))?,
+            },
+            (_, kind) => {
+                return Err(protocol_error(format!(
+                    "Unexpected {kind:?} frame for the request {}",
+                    frame.request
+                )))
+            }
+        };
+        let done = route.expected == Expected::BlobData { remaining: 0 };
+
+        let (request, kind) = (frame.request, frame.kind);
+        let abandoned = match route.frames.try_send(frame) {
+            Ok(()) => false,
+            Err(TrySendError::Closed(_)) => true,
+            Err(TrySendError::Full(_)) => {
+                return Err(protocol_error(
+                    "The peer sent more blob chunks than its window",
+                ))
+            }
+        };
+        if done {
+            routes.open.remove(&request);
+        }
+
+        Ok(match abandoned && kind == FrameKind::BlobData {
+            true => Routed::Abandoned(request),
+            false => Routed::Delivered,
+        })
+    }
+
+    fn lock_routes(&self) -> MutexGuard<'_, Routes> {
+        self.routes.lock().expect("Not poisoned")
+    }
+}
+
+fn blob_length(frame: &Frame) -> std::io::Result<u64> {
+    frame
+        .payload
+        .as_slice()
+        .try_into()
+        .map(u64::from_be_bytes)
+        .map_err(|_| protocol_error("Invalid blob length"))
+}
+
+/// The frames a connection received for one request
+#[derive(Debug)]
+pub struct IncomingFrames<W> {
+    request: RequestId,
+    frames: mpsc::Receiver<Frame>,
+    writer: FrameWriter<W>,
+}
+
+impl<W: AsyncWrite + Unpin + Send> IncomingFrames<W> {
+    /// Waits for the message answering the request
+    pub async fn message(&mut self) -> std::io::Result<Vec<u8>> {
+        let frame = self.next().await?;
+        match frame.oversized {
+            true => Err(protocol_error("The message is longer than the limit")),
+            false => Ok(frame.payload),
+        }
+    }
+
+    /// Receives the blob following the message, `None` if there is none
+    pub async fn receive_blob(mut self) -> std::io::Result<Option<FrameBlobFetch<W>>> {
+        let total_len = blob_length(&self.next().await?)?;
+        Ok((total_len > 0).then(|| FrameBlobFetch {
+            frames: self,
+            chunk: Vec::new(),
+            position: 0,
+            total_len,
+            read: 0,
+        }))
+    }
+
+    async fn next(&mut self) -> std::io::Result<Frame> {
+        self.frames.recv().await.ok_or_else(connection_closed)
+    }
+}
+
+/// Reads a blob from its [FrameKind::BlobData] frames and lets the sender continue as chunks
+/// are taken
+#[derive(Debug)]
+pub struct FrameBlobFetch<W> {
+    frames: IncomingFrames<W>,
+    chunk: Vec<u8>,
+    position: usize,
+    total_len: u64,
+    read: u64,
+}
+
+impl<W: AsyncWrite + Unpin + Send> BlobFetch for FrameBlobFetch<W> {
+    type Error = std::io::Error;
+
+    fn remaining_len(&self) -> u64 {
+        self.total_len - self.read
+    }
+
+    fn total_len(&self) -> u64 {
+        self.total_len
+    }
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        while self.position == self.chunk.len() {
+            if buf.is_empty() || self.remaining_len() == 0 {
+                return Ok(0);
+            }
+            self.chunk = self.frames.next().await?.payload;
+            self.position = 0;
+            self.frames.writer.grant(self.frames.request, 1).await?;
+        }
+
+        let read = min(buf.len(), self.chunk.len() - self.position);
+        buf[..read].copy_from_slice(&self.chunk[self.position..self.position + read]);
+        self.position += read;
+        self.read += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::in_memory_repositories::blob_repository::InMemoryBlobFetch;
+    use crate::model::frame::{MAX_CHUNK_LENGTH, WINDOW};
+    use crate::multiplexer::{Demultiplexer, FrameWriter};
+    use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
+
+    #[tokio::test]
+    async fn test_interleaved_blobs() {
+        let (client, server) = tokio::io::duplex(4096);
+        let (mut client_rx, client_tx) = tokio::io::split(client);
+        let (mut server_rx, server_tx) = tokio::io::split(server);
+        let client = FrameWriter::new(client_tx);
+        let client_frames = Demultiplexer::new(client.clone());
+        let server = Demultiplexer::new(FrameWriter::new(server_tx));
+        tokio::spawn(async move { client_frames.next_request(&mut client_rx).await });
+
+        // Larger than a window, so neither upload finishes before the other is read
+        let length = MAX_CHUNK_LENGTH * (WINDOW as usize + 4);
+        let first = vec![1; length];
+        let second = vec![2; length];
+        let uploads = async {
+            tokio::try_join!(
+                client.send_with_blob(1, b"first", InMemoryBlobFetch::new(first.clone().into())),
+                client.send_with_blob(2, b"second", InMemoryBlobFetch::new(second.clone().into())),
+            )
+        };
+        let downloads = async {
+            let (message, first_frames) = server.next_request(&mut server_rx).await.unwrap();
+            assert_eq!(message.payload, b"first");
+            let (message, second_frames) = server.next_request(&mut server_rx).await.unwrap();
+            assert_eq!(message.payload, b"second");
+            let routing = async { server.next_request(&mut server_rx).await };
+
+            // The second blob is read first, the first one has to wait for room
+            let reading = async {
+                let mut blob = second_frames.receive_blob().await.unwrap().unwrap();
+                assert_eq!(blob.read_to_eof().await.unwrap().as_ref(), second);
+                let mut blob = first_frames.receive_blob().await.unwrap().unwrap();
+                assert_eq!(blob.read_to_eof().await.unwrap().as_ref(), first);
+            };
+            tokio::select! {
+                _ = routing => panic!("The connection closed"),
+                _ = reading => {}
+            }
+        };
+
+        let (uploaded, _) = tokio::join!(uploads, downloads);
+        uploaded.unwrap();
+    }
+}
@@ -1,10 +1,14 @@
 use crate::model::call::Call;
 use crate::model::connection_interface::ConnectionClientInterface;
 use crate::model::connection_interface::IncomingResponse;
-use crate::model::response::Response;
+use crate::model::response::{ErrorCode, ErrorResponse, Response};
 use guardian_backup_domain::model::backup::backup::{Backup, BackupId};
+use guardian_backup_domain::model::backup::backup_overview::BackupOverview;
+use guardian_backup_domain::model::backup::snapshot::Snapshot;
+use guardian_backup_domain::model::timestamp::Timestamp;
 use guardian_backup_domain::model::user_identifier::UserIdentifier;
 use guardian_backup_domain::repositories::backup_repository::BackupRepository;
+use std::fmt::{Display, Formatter};
 
 pub struct RemoteBackupRepository<C: ConnectionClientInterface> {
     connection_interface: C,
@@ -16,37 +20,75 @@ impl<C: ConnectionClientInterface> RemoteBackupRepository<C> {
             connection_interface,
         }
     }
+
+    /// Sends a call the server answers with [Response::Successful]
+    async fn send(&mut self, call: Call) -> Result<(), RemoteBackupRepositoryError> {
+        match self.request(call).await? {
+            Response::Successful => Ok(()),
+            response => Err(RemoteBackupRepositoryError::from_response(&response)),
+        }
+    }
+
+    async fn request(&mut self, call: Call) -> Result<Response, RemoteBackupRepositoryError> {
+        let res = self
+            .connection_interface
+            .send_request(call)
+            .await
+            .map_err(|e| RemoteBackupRepositoryError::Connectivity(e.into()))?;
+        Ok(res.into_inner())
+    }
 }
 
 impl<C: ConnectionClientInterface> BackupRepository for RemoteBackupRepository<C> {
-    type Error = C::Error;
+    type Error = RemoteBackupRepositoryError;
 
     async fn get_backups(
         &mut self,
         _user: &UserIdentifier, //TODO user handling
     ) -> Result<Box<dyn Iterator<Item = Backup> + '_>, Self::Error> {
-        let res = self
-            .connection_interface
-            .send_request(Call::GetBackups)
-            .await?;
-
-        let res = res.into_inner();
-
-        if let Response::BackupList(backups) = res {
-            Ok(Box::new(backups.into_vec().into_iter()))
-        } else {
-            todo!()
+        match self.request(Call::GetBackups).await? {
+            Response::BackupList(backups) => Ok(Box::new(backups.into_vec().into_iter())),
+            response => Err(RemoteBackupRepositoryError::from_response(&response)),
         }
     }
 
     async fn get_backup_by_id(
         &mut self,
         id: &BackupId,
-        user: &UserIdentifier,
+        _user: &UserIdentifier,
     ) -> Result<Option<Backup>, Self::Error> {
-        self.get_backups(user)
-            .await
-            .map(|mut e| e.find(|e| e.id() == id))
+        match self.request(Call::GetBackup(id.clone())).await? {
+            Response::Backup(backup) => Ok(Some(backup)),
+            response => RemoteBackupRepositoryError::not_found(&response),
+        }
+    }
+
+    async fn get_backup_overviews(
+        &mut self,
+        _user: &UserIdentifier,
+    ) -> Result<Vec<BackupOverview>, Self::Error> {
+        match self.request(Call::ListBackups).await? {
+            Response::BackupOverviews(backups) => Ok(backups.into_vec()),
+            response => Err(RemoteBackupRepositoryError::from_response(&response)),
+        }
+    }
+
+    async fn get_snapshots(
+        &mut self,
+        id: &BackupId,
+        _user: &UserIdentifier,
+        after: Option<Timestamp>,
+        limit: usize,
+    ) -> Result<Option<Vec<Snapshot>>, Self::Error> {
+        let call = Call::GetSnapshots {
+            backup: id.clone(),
+            after,
+            limit: limit.try_into().unwrap_or(u32::MAX),
+        };
+        match self.request(call).await? {
+            Response::Snapshots(snapshots) => Ok(Some(snapshots.into_vec())),
+            response => RemoteBackupRepositoryError::not_found(&response),
+        }
     }
 
     async fn update_backup(
@@ -56,13 +98,7 @@ impl<C: ConnectionClientInterface> BackupRepository for RemoteBackupRepository<C
     ) -> Result<(), Self::Error> {
         let call = Call::PatchBackup(backup);
         //TODO User check
-        let res = self.connection_interface.send_request(call).await?;
-
-        if let &Response::Successful = res.inner() {
-            Ok(())
-        } else {
-            todo!()
-        }
+        self.send(call).await
     }
 
     async fn create_backup(
@@ -71,12 +107,47 @@ impl<C: ConnectionClientInterface> BackupRepository for RemoteBackupRepository<C
         backup: Backup,
     ) -> Result<(), Self::Error> {
         let call = Call::CreateBackup(backup);
-        let res = self.connection_interface.send_request(call).await?;
+        self.send(call).await
+    }
+}
 
-        if &Response::Successful == res.inner() {
-            Ok(())
-        } else {
-            todo!()
+#[derive(Debug)]
+pub enum RemoteBackupRepositoryError {
+    Connectivity(Box<dyn std::error::Error>),
+    /// The server answered with an error
+    Server(ErrorResponse),
+    UnexpectedResponse,
+}
+
+impl RemoteBackupRepositoryError {
+    fn from_response(response: &Response) -> Self {
+        response
+            .error()
+            .map_or(Self::UnexpectedResponse, Self::Server)
+    }
+
+    /// `None` if the server doesn't know the backup, the error of `response` otherwise
+    fn not_found<T>(response: &Response) -> Result<Option<T>, Self> {
+        match Self::from_response(response) {
+            Self::Server(ErrorResponse {
+                code: ErrorCode::NotFound,
+                ..
+            }) => Ok(None),
+            error => Err(error),
         }
     }
 }
+
+impl Display for RemoteBackupRepositoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemoteBackupRepositoryError::Connectivity(inner) => write!(f, "Connectivity({inner})"),
+            RemoteBackupRepositoryError::Server(inner) => write!(f, "{inner}"),
+            RemoteBackupRepositoryError::UnexpectedResponse => {
+                write!(f, "Unexpected response of the server")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RemoteBackupRepositoryError {}
@@ -1,11 +1,19 @@
 use crate::model::call::Call;
 use crate::model::connection_interface::ConnectionClientInterface;
 use crate::model::connection_interface::IncomingResponse;
-use crate::model::response::Response;
+use crate::model::response::{ErrorResponse, Response};
 use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
 use guardian_backup_domain::model::blobs::blob_identifier::BlobIdentifier;
+use guardian_backup_domain::model::timestamp::Timestamp;
+use guardian_backup_domain::model::user_identifier::UserIdentifier;
 use guardian_backup_domain::repositories::blob_repository::BlobRepository;
 use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// Times a fetch is continued after breaking off without any byte arriving in between
+const MAX_RESUMES: u32 = 3;
+/// Wait before continuing a fetch, multiplied by the attempt
+const RESUME_DELAY: Duration = Duration::from_millis(500);
 
 pub struct RemoteBlobRepository<C: ConnectionClientInterface> {
     connectivity_service: C,
@@ -19,7 +27,9 @@ impl<C: ConnectionClientInterface> RemoteBlobRepository<C> {
     }
 }
 
-impl<C: ConnectionClientInterface> BlobRepository for RemoteBlobRepository<C> {
+impl<C: ConnectionClientInterface + Clone + Send + 'static> BlobRepository
+    for RemoteBlobRepository<C>
+{
     type Error = RemoteBlobRepositoryError;
 
     async fn insert_blob(
@@ -34,36 +44,159 @@ impl<C: ConnectionClientInterface> BlobRepository for RemoteBlobRepository<C> {
             .await
             .map_err(|e| RemoteBlobRepositoryError::Connectivity(e.into()))?;
 
-        if &Response::Successful != res.inner() {
-            println!("{:?}", res.inner());
-            todo!()
+        match res.inner() {
+            Response::Successful => Ok(()),
+            response => Err(RemoteBlobRepositoryError::from_response(response)),
         }
-
-        Ok(())
     }
 
-    async fn delete_blob(&mut self, id: &BlobIdentifier) -> Result<(), Self::Error> {
-        panic!("Never call delete on a remote blob repository")
+    async fn delete_blob(&mut self, _id: &BlobIdentifier) -> Result<(), Self::Error> {
+        Err(RemoteBlobRepositoryError::Unsupported)
     }
 
     async fn fetch_blob(&mut self, id: &BlobIdentifier) -> Result<impl BlobFetch, Self::Error> {
-        let call = Call::GetBlob(id.clone());
-        let res = self
-            .connectivity_service
-            .send_request(call)
-            .await
-            .map_err(|e| RemoteBlobRepositoryError::Connectivity(e.into()))?;
+        let mut connection = self.connectivity_service.clone();
+        let fetch = request_blob(&mut connection, Call::GetBlob(id.clone())).await?;
+        Ok(ResumingBlobFetch::new(connection, id.clone(), 0, fetch))
+    }
+
+    async fn fetch_blob_range(
+        &mut self,
+        id: &BlobIdentifier,
+        offset: u64,
+        length: u64,
+    ) -> Result<impl BlobFetch, Self::Error> {
+        let mut connection = self.connectivity_service.clone();
+        let call = Call::GetBlobRange {
+            id: id.clone(),
+            offset,
+            length,
+        };
+        let fetch = request_blob(&mut connection, call).await?;
+        Ok(ResumingBlobFetch::new(
+            connection,
+            id.clone(),
+            offset,
+            fetch,
+        ))
+    }
+
+    async fn used_space(&mut self, _user: &UserIdentifier) -> Result<u64, Self::Error> {
+        Err(RemoteBlobRepositoryError::Unsupported)
+    }
+
+    async fn blobs_inserted_before(
+        &mut self,
+        _before: Timestamp,
+    ) -> Result<Vec<BlobIdentifier>, Self::Error> {
+        Err(RemoteBlobRepositoryError::Unsupported)
+    }
+}
+
+async fn request_blob<C: ConnectionClientInterface>(
+    connection: &mut C,
+    call: Call,
+) -> Result<<C::Response as IncomingResponse>::Blob, RemoteBlobRepositoryError> {
+    let res = connection
+        .send_request(call)
+        .await
+        .map_err(|e| RemoteBlobRepositoryError::Connectivity(e.into()))?;
 
-        if &Response::Successful != res.inner() {
-            println!("{:?}", res.inner());
-            todo!()
+    if res.inner() != &Response::Successful {
+        return Err(RemoteBlobRepositoryError::from_response(res.inner()));
+    }
+
+    res.receive_blob()
+        .await
+        .map_err(|e| RemoteBlobRepositoryError::IncomingRequest(e.into()))
+}
+
+/// A blob fetched from the server. If the fetch breaks off, the rest of the blob is fetched with
+/// a [Call::GetBlobRange], over a new connection if the old one was lost.
+pub struct ResumingBlobFetch<C: ConnectionClientInterface> {
+    connection: C,
+    id: BlobIdentifier,
+    /// Offset of the fetched range in the blob
+    start: u64,
+    /// Offset of the next byte to read
+    position: u64,
+    end: u64,
+    fetch: <C::Response as IncomingResponse>::Blob,
+    /// Resumes since the last byte arrived
+    resumes: u32,
+}
+
+impl<C: ConnectionClientInterface> ResumingBlobFetch<C> {
+    fn new(
+        connection: C,
+        id: BlobIdentifier,
+        start: u64,
+        fetch: <C::Response as IncomingResponse>::Blob,
+    ) -> Self {
+        Self {
+            connection,
+            id,
+            start,
+            position: start,
+            end: start + fetch.total_len(),
+            fetch,
+            resumes: 0,
         }
+    }
+}
 
-        let res_blob = res
-            .receive_blob()
-            .await
-            .map_err(|e| RemoteBlobRepositoryError::IncomingRequest(e.into()))?;
-        Ok(res_blob)
+impl<C: ConnectionClientInterface + Send> BlobFetch for ResumingBlobFetch<C> {
+    type Error = RemoteBlobRepositoryError;
+
+    fn remaining_len(&self) -> u64 {
+        self.end - self.position
+    }
+
+    fn total_len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        loop {
+            // Only the messages of errors are kept, the errors may not be sent to other threads
+            // while the fetch is resumed
+            let mut broke_off = match self.fetch.read(buf).await {
+                Ok(0) if self.position < self.end => "The blob ended early".to_string(),
+                Ok(read) => {
+                    self.position += read as u64;
+                    self.resumes = 0;
+                    return Ok(read);
+                }
+                Err(e) => e.to_string(),
+            };
+
+            loop {
+                if self.resumes >= MAX_RESUMES {
+                    return Err(RemoteBlobRepositoryError::IncomingRequest(broke_off.into()));
+                }
+                self.resumes += 1;
+                log::warn!(
+                    "Fetching blob {} broke off at byte {} ({broke_off}), resuming",
+                    self.id.hash(),
+                    self.position
+                );
+                tokio::time::sleep(RESUME_DELAY * self.resumes).await;
+
+                let call = Call::GetBlobRange {
+                    id: self.id.clone(),
+                    offset: self.position,
+                    length: self.end - self.position,
+                };
+                match request_blob(&mut self.connection, call).await {
+                    Ok(fetch) if fetch.total_len() == self.end - self.position => {
+                        self.fetch = fetch;
+                        break;
+                    }
+                    Ok(_) => return Err(RemoteBlobRepositoryError::UnexpectedResponse),
+                    Err(e) => broke_off = e.to_string(),
+                }
+            }
+        }
     }
 }
 
@@ -71,6 +204,19 @@ impl<C: ConnectionClientInterface> BlobRepository for RemoteBlobRepository<C> {
 pub enum RemoteBlobRepositoryError {
     Connectivity(Box<dyn std::error::Error>),
     IncomingRequest(Box<dyn std::error::Error>),
+    /// The server answered with an error
+    Server(ErrorResponse),
+    UnexpectedResponse,
+    /// Only the server deletes blobs and knows the space they take and when they were stored
+    Unsupported,
+}
+
+impl RemoteBlobRepositoryError {
+    fn from_response(response: &Response) -> Self {
+        response
+            .error()
+            .map_or(Self::UnexpectedResponse, Self::Server)
+    }
 }
 
 impl Display for RemoteBlobRepositoryError {
@@ -80,6 +226,13 @@ impl Display for RemoteBlobRepositoryError {
             RemoteBlobRepositoryError::IncomingRequest(inner) => {
                 write!(f, "IncomingRequest({inner})")
             }
+            RemoteBlobRepositoryError::Server(inner) => write!(f, "{inner}"),
+            RemoteBlobRepositoryError::UnexpectedResponse => {
+                write!(f, "Unexpected response of the server")
+            }
+            RemoteBlobRepositoryError::Unsupported => {
+                write!(f, "Not supported by the remote blob repository")
+            }
         }
     }
 }
@@ -0,0 +1,125 @@
+use crate::model::call::Call;
+use crate::model::connection_interface::{ConnectionClientInterface, IncomingResponse};
+use crate::model::response::{ErrorResponse, Response};
+use guardian_backup_domain::model::credential::Credential;
+use guardian_backup_domain::model::device_identifier::DeviceIdentifier;
+use guardian_backup_domain::model::user::User;
+use guardian_backup_domain::model::user_identifier::UserIdentifier;
+use guardian_backup_domain::repositories::user_repository::UserRepository;
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+
+/// Manages the devices of the logged in user on the server. Users themselves and credentials
+/// are only managed on the server.
+pub struct RemoteUserRepository<C: ConnectionClientInterface> {
+    connection_interface: C,
+}
+
+impl<C: ConnectionClientInterface> RemoteUserRepository<C> {
+    pub fn new(connection_interface: C) -> Self {
+        Self {
+            connection_interface,
+        }
+    }
+}
+
+impl<C: ConnectionClientInterface> UserRepository for RemoteUserRepository<C> {
+    type Error = RemoteUserRepositoryError;
+
+    async fn get_user(&self, _identifier: &UserIdentifier) -> Result<User, Self::Error> {
+        Err(RemoteUserRepositoryError::Unsupported)
+    }
+
+    // Not `async fn`, that would borrow `self` and need a `Sync` connection to be `Send`
+    #[allow(clippy::manual_async_fn)]
+    fn get_credential(
+        &self,
+        _identifier: &UserIdentifier,
+    ) -> impl Future<Output = Result<Option<Credential>, Self::Error>> + Send {
+        async { Err(RemoteUserRepositoryError::Unsupported) }
+    }
+
+    #[allow(clippy::manual_async_fn)]
+    fn get_device_credential(
+        &self,
+        _user: &UserIdentifier,
+        _device: &DeviceIdentifier,
+    ) -> impl Future<Output = Result<Option<Credential>, Self::Error>> + Send {
+        async { Err(RemoteUserRepositoryError::Unsupported) }
+    }
+
+    async fn get_user_devices(
+        &self,
+        _user: &UserIdentifier,
+    ) -> Result<Cow<'_, [DeviceIdentifier]>, Self::Error> {
+        Err(RemoteUserRepositoryError::Unsupported)
+    }
+
+    async fn create_user(&mut self, _user: &User) -> Result<(), Self::Error> {
+        Err(RemoteUserRepositoryError::Unsupported)
+    }
+
+    async fn create_user_device(
+        &mut self,
+        _user: &UserIdentifier,
+        device: &DeviceIdentifier,
+        token: Credential,
+    ) -> Result<(), Self::Error> {
+        let call = Call::CreateDevice {
+            device: device.clone(),
+            token,
+        };
+        let res = self
+            .connection_interface
+            .send_request(call)
+            .await
+            .map_err(|e| RemoteUserRepositoryError::Connectivity(e.into()))?;
+
+        match res.into_inner() {
+            Response::Successful => Ok(()),
+            response => Err(response.error().map_or(
+                RemoteUserRepositoryError::UnexpectedResponse,
+                RemoteUserRepositoryError::Server,
+            )),
+        }
+    }
+
+    async fn delete_user(&mut self, _identifier: &UserIdentifier) -> Result<(), Self::Error> {
+        Err(RemoteUserRepositoryError::Unsupported)
+    }
+
+    async fn delete_device(
+        &mut self,
+        _user: &UserIdentifier,
+        _device: &DeviceIdentifier,
+    ) -> Result<(), Self::Error> {
+        Err(RemoteUserRepositoryError::Unsupported)
+    }
+}
+
+#[derive(Debug)]
+pub enum RemoteUserRepositoryError {
+    Connectivity(Box<dyn std::error::Error>),
+    /// The server answered with an error
+    Server(ErrorResponse),
+    UnexpectedResponse,
+    Unsupported,
+}
+
+impl Display for RemoteUserRepositoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemoteUserRepositoryError::Connectivity(inner) => write!(f, "Connectivity({inner})"),
+            RemoteUserRepositoryError::Server(inner) => write!(f, "{inner}"),
+            RemoteUserRepositoryError::UnexpectedResponse => {
+                write!(f, "Unexpected response of the server")
+            }
+            RemoteUserRepositoryError::Unsupported => {
+                write!(f, "Not supported by the remote user repository")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RemoteUserRepositoryError {}
@@ -0,0 +1,220 @@
+use crate::model::call::Call;
+use crate::model::client_config::RetrySettings;
+use crate::model::connection_interface::{ConnectionClientInterface, TransientError};
+use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
+use std::time::Duration;
+
+/// Sends requests of the inner connection again while they fail with
+/// [transient](TransientError) errors, waiting exponentially longer before every attempt.
+/// Requests with a blob are only sent again if the blob can be [rewound](BlobFetch::rewind).
+#[derive(Clone)]
+pub struct RetryingConnection<C> {
+    inner: C,
+    settings: RetrySettings,
+}
+
+impl<C> RetryingConnection<C> {
+    pub fn new(inner: C, settings: RetrySettings) -> Self {
+        Self { inner, settings }
+    }
+
+    /// Whether a request that failed `attempt` times is sent again after an error
+    fn retries(&self, attempt: u32, error: &impl TransientError) -> bool {
+        attempt < self.settings.attempts && error.is_transient()
+    }
+
+    /// Wait before the attempt after `attempt` failed ones
+    fn backoff(&self, attempt: u32) -> Duration {
+        let doublings = attempt.saturating_sub(1).min(63);
+        let backoff = self
+            .settings
+            .initial_backoff_ms
+            .saturating_mul(1 << doublings)
+            .min(self.settings.max_backoff_ms);
+
+        let mut random = [0; 4];
+        getrandom::getrandom(&mut random).expect("The OS provides randomness");
+        let jitter = backoff as u128 * self.settings.jitter_percent.min(100) as u128 / 100
+            * u32::from_le_bytes(random) as u128
+            / u32::MAX as u128;
+        Duration::from_millis(backoff - jitter as u64)
+    }
+}
+
+impl<C: ConnectionClientInterface<Error: TransientError> + Send> ConnectionClientInterface
+    for RetryingConnection<C>
+{
+    type Error = C::Error;
+    type Response = C::Response;
+
+    async fn send_request(&mut self, command: Call) -> Result<Self::Response, Self::Error> {
+        let mut attempt = 1;
+        loop {
+            // Only the message is kept, the error may not be sent to other threads while waiting
+            let failure = match self.inner.send_request(command.clone()).await {
+                Err(e) if self.retries(attempt, &e) => e.to_string(),
+                result => return result,
+            };
+
+            let backoff = self.backoff(attempt);
+            log::warn!("Request failed ({failure}), retrying in {backoff:?}");
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+
+    async fn send_request_with_blob(
+        &mut self,
+        command: &Call,
+        mut blob: impl BlobFetch,
+    ) -> Result<Self::Response, Self::Error> {
+        let mut attempt = 1;
+        loop {
+            let error = match self.inner.send_request_with_blob(command, &mut blob).await {
+                Ok(response) => return Ok(response),
+                Err(e) if self.retries(attempt, &e) => e,
+                Err(e) => return Err(e),
+            };
+            match blob.rewind().await {
+                Ok(true) => {}
+                Ok(false) => return Err(error),
+                Err(e) => {
+                    log::warn!("Cannot read the blob again to retry ({e})");
+                    return Err(error);
+                }
+            }
+
+            let backoff = self.backoff(attempt);
+            log::warn!("Upload failed ({error}), retrying in {backoff:?}");
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::in_memory_repositories::blob_repository::InMemoryBlobFetch;
+    use crate::model::call::Call;
+    use crate::model::client_config::RetrySettings;
+    use crate::model::connection_interface::{ConnectionClientInterface, IncomingResponse};
+    use crate::model::response::Response;
+    use crate::retrying_connection::RetryingConnection;
+    use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
+    use guardian_backup_domain::model::blobs::blob_identifier::BlobIdentifier;
+    use guardian_backup_domain::model::files::file_hash::FileHash;
+    use guardian_backup_domain::model::user_identifier::UserIdentifier;
+    use std::convert::Infallible;
+    use std::io::{Error, ErrorKind};
+    use std::sync::Arc;
+
+    /// Fails the first `failures` requests with `kind` after reading a part of their blob
+    struct FlakyConnection {
+        failures: u32,
+        kind: ErrorKind,
+        attempts: u32,
+        uploaded: Vec<Box<[u8]>>,
+    }
+
+    #[derive(Debug)]
+    struct FlakyResponse();
+
+    impl ConnectionClientInterface for FlakyConnection {
+        type Error = Error;
+        type Response = FlakyResponse;
+
+        async fn send_request(&mut self, _command: Call) -> Result<Self::Response, Self::Error> {
+            self.attempts += 1;
+            match self.failures {
+                0 => Ok(FlakyResponse()),
+                _ => {
+                    self.failures -= 1;
+                    Err(Error::from(self.kind))
+                }
+            }
+        }
+
+        async fn send_request_with_blob(
+            &mut self,
+            command: &Call,
+            mut blob: impl BlobFetch,
+        ) -> Result<Self::Response, Self::Error> {
+            let mut head = [0; 2];
+            let read = blob.read(&mut head).await.unwrap();
+            let response = self.send_request(command.clone()).await?;
+            let rest = blob.read_to_eof().await.unwrap();
+            self.uploaded.push([&head[..read], &rest].concat().into());
+            Ok(response)
+        }
+    }
+
+    impl IncomingResponse for FlakyResponse {
+        type Error = Infallible;
+        type Blob = InMemoryBlobFetch;
+
+        fn inner(&self) -> &Response {
+            &Response::Successful
+        }
+
+        fn into_inner(self) -> Response {
+            Response::Successful
+        }
+
+        async fn receive_blob(self) -> Result<Self::Blob, Self::Error> {
+            Ok(InMemoryBlobFetch::new(Arc::new([])))
+        }
+    }
+
+    fn blob_identifier() -> BlobIdentifier {
+        let hash = FileHash::Blake3 { hash: [1].into() };
+        BlobIdentifier::new(hash, UserIdentifier::new("user".into()))
+    }
+
+    fn connection(failures: u32, kind: ErrorKind) -> RetryingConnection<FlakyConnection> {
+        let settings = RetrySettings {
+            attempts: 3,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 2,
+            jitter_percent: 50,
+        };
+        let inner = FlakyConnection {
+            failures,
+            kind,
+            attempts: 0,
+            uploaded: Vec::new(),
+        };
+        RetryingConnection::new(inner, settings)
+    }
+
+    #[tokio::test]
+    async fn test_transient_errors_are_retried_and_fatal_ones_are_not() {
+        let call = Call::GetBlob(blob_identifier());
+
+        let mut reset = connection(2, ErrorKind::ConnectionReset);
+        assert!(reset.send_request(call.clone()).await.is_ok());
+        assert_eq!(reset.inner.attempts, 3);
+
+        let mut flaky = connection(3, ErrorKind::TimedOut);
+        assert!(flaky.send_request(call.clone()).await.is_err());
+        assert_eq!(flaky.inner.attempts, 3);
+
+        let mut denied = connection(1, ErrorKind::PermissionDenied);
+        assert!(denied.send_request(call).await.is_err());
+        assert_eq!(denied.inner.attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_blobs_are_uploaded_again_from_their_beginning() {
+        let id = blob_identifier();
+        let data: Arc<[u8]> = Arc::new(*b"blob data");
+
+        let mut connection = connection(1, ErrorKind::BrokenPipe);
+        let blob = InMemoryBlobFetch::new(data.clone());
+        let response = connection
+            .send_request_with_blob(&Call::CreateBlob(id), blob)
+            .await;
+        assert!(response.is_ok());
+        assert_eq!(connection.inner.attempts, 2);
+        assert_eq!(connection.inner.uploaded, vec![data.to_vec().into()]);
+    }
+}
@@ -1,14 +1,110 @@
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+
+/// Shortest grace period of the garbage collection, clients resume interrupted backups only
+/// within half of it
+pub const MIN_GRACE_PERIOD: Duration = Duration::from_secs(2 * 24 * 60 * 60);
 
 pub struct ServerConfig {
-    pub bind_to: SocketAddr,
+    /// Addresses the server listens on, e.g. `0.0.0.0:8998` and `[::]:8998`
+    pub bind_to: Vec<SocketAddr>,
+    /// Certificate the server presents, `None` serves unencrypted TCP
+    pub tls: Option<ServerTlsConfig>,
+    pub limits: ServerLimits,
+    pub garbage_collection: GarbageCollection,
+    pub password_hashing: PasswordHashing,
+}
+
+/// What a client may send, protects the server from broken and malicious clients
+#[derive(Debug, Clone)]
+pub struct ServerLimits {
+    /// Connections served at once, further clients wait until one closes
+    pub max_connections: usize,
+    /// Calls of one connection handled at once, its frames aren't read while that many are
+    pub max_calls_per_connection: usize,
+    /// Longest encoded call, longer ones are rejected
+    pub max_call_length: u32,
+    /// Longest blob, longer ones are rejected
+    pub max_blob_length: u64,
+    /// Most bytes of blobs a user may store, raise it for users backing up more
+    pub max_user_upload: u64,
+    /// Time the TLS handshake, the hello, the login and every frame get to arrive once begun
+    pub read_timeout: Duration,
+    /// Connections sending nothing for this long are closed
+    pub idle_timeout: Duration,
+}
+
+impl Default for ServerLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: 64,
+            max_calls_per_connection: 16,
+            max_call_length: 16 * 1024 * 1024,
+            max_blob_length: 4 * 1024 * 1024 * 1024,
+            max_user_upload: 256 * 1024 * 1024 * 1024,
+            read_timeout: Duration::from_secs(30),
+            idle_timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Deletes the blobs no snapshot references
+#[derive(Debug, Clone)]
+pub struct GarbageCollection {
+    /// Time between two collections
+    pub interval: Duration,
+    /// Blobs are kept this long after their upload, so backups being uploaded or resumed keep
+    /// theirs. At least [MIN_GRACE_PERIOD].
+    pub grace_period: Duration,
+}
+
+impl Default for GarbageCollection {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60 * 60),
+            grace_period: Duration::from_secs(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+/// Argon2id parameters the passwords of the users are hashed with. Unknown users get
+/// challenges with them, so they can't be told apart from known ones.
+#[derive(Debug, Clone)]
+pub struct PasswordHashing {
+    /// Memory in KiB
+    pub memory_cost: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for PasswordHashing {
+    /// The defaults of Argon2id
+    fn default() -> Self {
+        Self {
+            memory_cost: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+/// PEM files of the server certificate
+pub struct ServerTlsConfig {
+    /// The server certificate followed by the intermediate certificates, if any
+    pub certificate_chain: PathBuf,
+    pub private_key: PathBuf,
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
-            bind_to: "0.0.0.0:8998".parse().unwrap(),
+            bind_to: vec!["0.0.0.0:8998".parse().unwrap()],
+            tls: None,
+            limits: ServerLimits::default(),
+            garbage_collection: GarbageCollection::default(),
+            password_hashing: PasswordHashing::default(),
         }
     }
 }
@@ -19,10 +115,14 @@ impl ServerConfig {
         static PORT_COUNTER: AtomicU16 = AtomicU16::new(18998);
 
         Self {
-            bind_to: SocketAddr::V4(SocketAddrV4::new(
+            bind_to: vec![SocketAddr::V4(SocketAddrV4::new(
                 Ipv4Addr::new(127, 0, 0, 1),
                 PORT_COUNTER.fetch_add(1, Ordering::SeqCst),
-            )),
+            ))],
+            tls: None,
+            limits: ServerLimits::default(),
+            garbage_collection: GarbageCollection::default(),
+            password_hashing: PasswordHashing::default(),
         }
     }
 }
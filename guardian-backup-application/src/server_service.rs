@@ -1,15 +1,31 @@
-use crate::model::call::Call;
+use crate::model::authentication::Scope;
+use crate::model::call::{Call, MAX_SNAPSHOT_PAGE};
 use crate::model::connection_interface::{IncomingCall, UnhandledIncomingCall};
-use crate::model::response::Response;
+use crate::model::response::{ErrorCode, ErrorResponse, Rejection, Response, ToErrorCode};
+use crate::server_config::ServerLimits;
 use crate::server_service::ServerServiceError::{
     BackupIdNotFound, BackupRepositoryError, BlobFetchError, BlobRepositoryError, NoPermission,
-    ResponseError,
+    Rejected, ResponseError, UserRepositoryError,
 };
+use guardian_backup_domain::hash_service::{HashService, PendingHashB};
 use guardian_backup_domain::model::backup::backup::BackupId;
+use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
+use guardian_backup_domain::model::blobs::blob_identifier::BlobIdentifier;
+use guardian_backup_domain::model::credential::Credential;
+use guardian_backup_domain::model::files::file_hash::FileHash;
+use guardian_backup_domain::model::timestamp::Timestamp;
+use guardian_backup_domain::model::user_identifier::UserIdentifier;
 use guardian_backup_domain::repositories::backup_repository::BackupRepository;
 use guardian_backup_domain::repositories::blob_repository::BlobRepository;
+use guardian_backup_domain::repositories::user_repository::UserRepository;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
 
 pub trait ServerService {
     type Error;
@@ -20,12 +36,30 @@ pub trait ServerService {
     ) -> Result<(), Self::Error>;
 }
 
-pub struct MainServerService<B: BackupRepository, L: BlobRepository> {
+/// Clones share the repositories they were created with and can handle calls at the same time
+#[derive(Clone)]
+pub struct MainServerService<B: BackupRepository, L: BlobRepository, U: UserRepository> {
     backup_repository: B,
     blob_repository: L,
+    user_repository: U,
+    /// Checks uploaded blobs against the hash of their identifier
+    hash_service: Rc<HashService>,
+    /// Held while a backup is read and written back, so snapshots added at the same time by
+    /// another call aren't lost
+    backup_writes: Arc<Mutex<()>>,
+    /// Bytes of the blobs being uploaded per user, they count towards the quota already
+    uploads: Arc<Mutex<HashMap<UserIdentifier, u64>>>,
+    max_blob_length: u64,
+    max_user_upload: u64,
 }
 
-impl<B: BackupRepository, L: BlobRepository> ServerService for MainServerService<B, L> {
+impl<B: BackupRepository, L: BlobRepository, U: UserRepository> ServerService
+    for MainServerService<B, L, U>
+where
+    B::Error: ToErrorCode,
+    L::Error: ToErrorCode,
+    U::Error: ToErrorCode,
+{
     type Error = ServerServiceError;
 
     async fn handle_incoming_request(
@@ -35,7 +69,14 @@ impl<B: BackupRepository, L: BlobRepository> ServerService for MainServerService
         let (call_variant, mut call) = call.into_inner();
 
         if let Err(err) = self.internal_handle(&mut call, call_variant).await {
-            call.answer(Response::Error(format!("{err}").into()))
+            let response = match err {
+                Rejected(rejection) => Response::Rejected(rejection),
+                err => Response::Error(ErrorResponse {
+                    code: err.code(),
+                    message: err.to_string().into(),
+                }),
+            };
+            call.answer(response)
                 .await
                 .map_err(|e| ResponseError(e.into()))?;
         }
@@ -44,27 +85,164 @@ impl<B: BackupRepository, L: BlobRepository> ServerService for MainServerService
     }
 }
 
-impl<B: BackupRepository, L: BlobRepository> MainServerService<B, L> {
-    pub fn new(backup_repository: B, blob_repository: L) -> Self {
+impl<B: BackupRepository, L: BlobRepository, U: UserRepository> MainServerService<B, L, U>
+where
+    B::Error: ToErrorCode,
+    L::Error: ToErrorCode,
+    U::Error: ToErrorCode,
+{
+    pub fn new(
+        backup_repository: B,
+        blob_repository: L,
+        user_repository: U,
+        hash_service: HashService,
+    ) -> Self {
         Self {
             backup_repository,
             blob_repository,
+            user_repository,
+            hash_service: Rc::new(hash_service),
+            backup_writes: Arc::default(),
+            uploads: Arc::default(),
+            max_blob_length: u64::MAX,
+            max_user_upload: u64::MAX,
         }
     }
 
+    /// Rejects blobs and uploads exceeding the limits
+    pub fn with_limits(mut self, limits: &ServerLimits) -> Self {
+        self.max_blob_length = limits.max_blob_length;
+        self.max_user_upload = limits.max_user_upload;
+        self
+    }
+
+    /// Counts `length` bytes towards the quota of `user` until they are
+    /// [released](Self::release_upload)
+    async fn reserve_upload(
+        &mut self,
+        user: &UserIdentifier,
+        length: u64,
+    ) -> Result<(), ServerServiceError> {
+        // Held while reading the used space, so uploads finishing meanwhile aren't missed
+        let mut uploads = self.uploads.lock().await;
+        let used = self
+            .blob_repository
+            .used_space(user)
+            .await
+            .map_err(|e| BlobRepositoryError(e.error_code(), e.into()))?;
+        let uploading = uploads.entry(user.clone()).or_default();
+        if used.saturating_add(*uploading).saturating_add(length) > self.max_user_upload {
+            return Err(Rejected(Rejection::UploadQuotaExceeded {
+                limit: self.max_user_upload,
+            }));
+        }
+        *uploading += length;
+        Ok(())
+    }
+
+    async fn release_upload(&self, user: &UserIdentifier, length: u64) {
+        let mut uploads = self.uploads.lock().await;
+        if let Some(uploading) = uploads.get_mut(user) {
+            *uploading -= length;
+            if *uploading == 0 {
+                uploads.remove(user);
+            }
+        }
+    }
+
+    /// Deletes the blobs no snapshot references that were last inserted more than
+    /// `grace_period` ago and returns how many. Backups being uploaded or resumed don't reference
+    /// their blobs yet, the grace period keeps them.
+    pub async fn collect_garbage(
+        &mut self,
+        grace_period: Duration,
+    ) -> Result<usize, ServerServiceError> {
+        let now = Timestamp::now().milliseconds_since_epoch();
+        let before = Timestamp::from_milliseconds(
+            now.saturating_sub(grace_period.as_millis().try_into().unwrap_or(u64::MAX)),
+        );
+        // Held so no snapshot referencing a deleted blob is added meanwhile
+        let _writing = self.backup_writes.lock().await;
+        let candidates = self
+            .blob_repository
+            .blobs_inserted_before(before)
+            .await
+            .map_err(|e| BlobRepositoryError(e.error_code(), e.into()))?;
+
+        let mut referenced: HashMap<UserIdentifier, HashSet<BlobIdentifier>> = HashMap::new();
+        let mut deleted = 0;
+        for blob in candidates {
+            if !referenced.contains_key(blob.user()) {
+                let blobs = self
+                    .backup_repository
+                    .get_backups(blob.user())
+                    .await
+                    .map_err(|e| BackupRepositoryError(e.error_code(), e.into()))?
+                    .flat_map(|backup| backup.into_snapshots())
+                    .flat_map(|snapshot| {
+                        let mut blobs = snapshot.associated_blobs().to_vec();
+                        blobs.push(snapshot.file_tree_blob().clone());
+                        blobs
+                    })
+                    .collect();
+                referenced.insert(blob.user().clone(), blobs);
+            }
+
+            if !referenced[blob.user()].contains(&blob) {
+                self.blob_repository
+                    .delete_blob(&blob)
+                    .await
+                    .map_err(|e| BlobRepositoryError(e.error_code(), e.into()))?;
+                deleted += 1;
+            }
+        }
+
+        Ok(deleted)
+    }
+
     async fn internal_handle(
         &mut self,
         call: &mut impl IncomingCall,
         call_variant: Call,
     ) -> Result<(), ServerServiceError> {
         let user = call.user();
+        let device = match call.scope() {
+            Scope::Full => None,
+            Scope::Device(device) => Some(device.clone()),
+        };
 
         match call_variant {
             Call::CreateBackup(backup) => {
-                self.backup_repository
-                    .create_backup(user, backup)
-                    .await
-                    .map_err(|err| BackupRepositoryError(err.into()))?;
+                let writing = self.backup_writes.lock().await;
+                let existing = match &device {
+                    None => None,
+                    Some(device) if backup.device() != device => return Err(NoPermission),
+                    Some(_) => self
+                        .backup_repository
+                        .get_backup_by_id(backup.id(), user)
+                        .await
+                        .map_err(|e| BackupRepositoryError(e.error_code(), e.into()))?,
+                };
+
+                match existing {
+                    // Devices can't replace backups, creating an existing one only adds snapshots
+                    Some(mut existing) => {
+                        if Some(existing.device()) != device.as_ref() {
+                            return Err(NoPermission);
+                        }
+                        existing.merge_snapshots(backup.into_snapshots());
+                        self.backup_repository
+                            .update_backup(existing, user)
+                            .await
+                            .map_err(|e| BackupRepositoryError(e.error_code(), e.into()))?;
+                    }
+                    None => self
+                        .backup_repository
+                        .create_backup(user, backup)
+                        .await
+                        .map_err(|err| BackupRepositoryError(err.error_code(), err.into()))?,
+                }
+                drop(writing);
                 call.answer(Response::Successful)
                     .await
                     .map_err(|err| ResponseError(err.into()))?;
@@ -75,26 +253,95 @@ impl<B: BackupRepository, L: BlobRepository> MainServerService<B, L> {
                     .backup_repository
                     .get_backups(call.user())
                     .await
-                    .map_err(|e| BackupRepositoryError(e.into()))?;
-                call.answer(Response::BackupList(backups.collect()))
+                    .map_err(|e| BackupRepositoryError(e.error_code(), e.into()))?
+                    .filter(|e| device.as_ref().is_none_or(|device| e.device() == device))
+                    .collect();
+                call.answer(Response::BackupList(backups))
+                    .await
+                    .map_err(|e| ResponseError(e.into()))?;
+            }
+
+            Call::GetBackup(id) => {
+                let backup = self
+                    .backup_repository
+                    .get_backup_by_id(&id, user)
+                    .await
+                    .map_err(|e| BackupRepositoryError(e.error_code(), e.into()))?
+                    .filter(|e| device.as_ref().is_none_or(|device| e.device() == device))
+                    .ok_or(BackupIdNotFound(id))?;
+                call.answer(Response::Backup(backup))
+                    .await
+                    .map_err(|e| ResponseError(e.into()))?;
+            }
+
+            Call::ListBackups => {
+                let backups = self
+                    .backup_repository
+                    .get_backup_overviews(user)
+                    .await
+                    .map_err(|e| BackupRepositoryError(e.error_code(), e.into()))?
+                    .into_iter()
+                    .filter(|e| device.as_ref().is_none_or(|device| e.device() == device))
+                    .collect();
+                call.answer(Response::BackupOverviews(backups))
+                    .await
+                    .map_err(|e| ResponseError(e.into()))?;
+            }
+
+            Call::GetSnapshots {
+                backup,
+                after,
+                limit,
+            } => {
+                let limit = limit.min(MAX_SNAPSHOT_PAGE) as usize;
+                // Devices only see their own backups, their snapshots need the backup anyway
+                let snapshots = match &device {
+                    None => self
+                        .backup_repository
+                        .get_snapshots(&backup, user, after, limit)
+                        .await
+                        .map_err(|e| BackupRepositoryError(e.error_code(), e.into()))?,
+                    Some(device) => self
+                        .backup_repository
+                        .get_backup_by_id(&backup, user)
+                        .await
+                        .map_err(|e| BackupRepositoryError(e.error_code(), e.into()))?
+                        .filter(|e| e.device() == device)
+                        .map(|e| e.snapshot_page(after, limit)),
+                }
+                .ok_or(BackupIdNotFound(backup))?;
+                call.answer(Response::Snapshots(snapshots.into()))
                     .await
                     .map_err(|e| ResponseError(e.into()))?;
             }
 
             Call::PatchBackup(mut backup) => {
-                let origin = self
+                let writing = self.backup_writes.lock().await;
+                let mut origin = self
                     .backup_repository
                     .get_backup_by_id(backup.id(), call.user())
                     .await
-                    .map_err(|e| BackupRepositoryError(e.into()))?
+                    .map_err(|e| BackupRepositoryError(e.error_code(), e.into()))?
                     .ok_or_else(|| BackupIdNotFound(backup.id().clone()))?;
 
-                backup.merge_snapshots(origin.into_snapshots());
+                let patched = match &device {
+                    None => {
+                        backup.merge_snapshots(origin.into_snapshots());
+                        backup
+                    }
+                    // Devices may only add snapshots to their own backups
+                    Some(device) if origin.device() == device => {
+                        origin.merge_snapshots(backup.into_snapshots());
+                        origin
+                    }
+                    Some(_) => return Err(NoPermission),
+                };
 
                 self.backup_repository
-                    .update_backup(backup, call.user())
+                    .update_backup(patched, call.user())
                     .await
-                    .map_err(|e| BackupRepositoryError(e.into()))?;
+                    .map_err(|e| BackupRepositoryError(e.error_code(), e.into()))?;
+                drop(writing);
 
                 call.answer(Response::Successful)
                     .await
@@ -106,14 +353,48 @@ impl<B: BackupRepository, L: BlobRepository> MainServerService<B, L> {
                     return Err(NoPermission);
                 }
 
+                let user = call.user().clone();
                 let blob = call
                     .receive_blob()
                     .await
                     .map_err(|e| BlobFetchError(e.into()))?;
-                self.blob_repository
-                    .insert_blob(id, blob)
-                    .await
-                    .map_err(|e| BlobRepositoryError(e.into()))?;
+                let length = blob.total_len();
+                let hasher = self.hash_service.find_compatible_hasher(id.hash());
+                let reserved = match (length > self.max_blob_length, hasher) {
+                    (true, _) => Err(Rejected(Rejection::BlobTooLarge {
+                        limit: self.max_blob_length,
+                    })),
+                    (false, None) => Err(Rejected(Rejection::UnsupportedHash)),
+                    (false, Some(hasher)) => {
+                        self.reserve_upload(&user, length).await.map(|_| hasher)
+                    }
+                };
+                let hasher = match reserved {
+                    Ok(hasher) => hasher,
+                    Err(err) => {
+                        discard(blob).await?;
+                        return Err(err);
+                    }
+                };
+
+                let mismatch = Arc::new(AtomicBool::new(false));
+                let (inserted, discarded) = {
+                    let mut blob = VerifyingBlobFetch {
+                        blob,
+                        hash: hasher.create_hash(),
+                        expected: id.hash().clone(),
+                        mismatch: mismatch.clone(),
+                    };
+                    let inserted = self.blob_repository.insert_blob(id, &mut blob).await;
+                    // Repositories stop reading blobs they have already, like failing ones do
+                    (inserted, discard(&mut blob.blob).await)
+                };
+                self.release_upload(&user, length).await;
+                if mismatch.load(Ordering::Relaxed) {
+                    return Err(Rejected(Rejection::HashMismatch));
+                }
+                inserted.map_err(|e| BlobRepositoryError(e.error_code(), e.into()))?;
+                discarded?;
 
                 call.answer(Response::Successful)
                     .await
@@ -121,7 +402,7 @@ impl<B: BackupRepository, L: BlobRepository> MainServerService<B, L> {
             }
 
             Call::GetBlob(id) => {
-                if id.user() != call.user() {
+                if id.user() != call.user() || device.is_some() {
                     return Err(NoPermission);
                 }
 
@@ -129,36 +410,212 @@ impl<B: BackupRepository, L: BlobRepository> MainServerService<B, L> {
                     .blob_repository
                     .fetch_blob(&id)
                     .await
-                    .map_err(|e| BlobRepositoryError(e.into()))?;
+                    .map_err(|e| BlobRepositoryError(e.error_code(), e.into()))?;
                 call.answer_with_blob(Response::Successful, blob)
                     .await
                     .map_err(|e| ResponseError(e.into()))?;
             }
+
+            Call::GetBlobRange { id, offset, length } => {
+                if id.user() != call.user() || device.is_some() {
+                    return Err(NoPermission);
+                }
+
+                let blob = self
+                    .blob_repository
+                    .fetch_blob_range(&id, offset, length)
+                    .await
+                    .map_err(|e| BlobRepositoryError(e.error_code(), e.into()))?;
+                call.answer_with_blob(Response::Successful, blob)
+                    .await
+                    .map_err(|e| ResponseError(e.into()))?;
+            }
+
+            Call::CreateDevice {
+                device: new_device,
+                token,
+            } => {
+                if device.is_some() || !matches!(token, Credential::DeviceToken { .. }) {
+                    return Err(NoPermission);
+                }
+
+                self.user_repository
+                    .create_user_device(call.user(), &new_device, token)
+                    .await
+                    .map_err(|e| UserRepositoryError(e.error_code(), e.into()))?;
+                call.answer(Response::Successful)
+                    .await
+                    .map_err(|e| ResponseError(e.into()))?;
+            }
         }
 
         Ok(())
     }
 }
 
+/// Hashes a blob while the repository reads it. The last read fails if the hash doesn't match,
+/// so the repository never stores the blob.
+struct VerifyingBlobFetch<B> {
+    blob: B,
+    hash: Box<dyn PendingHashB>,
+    expected: FileHash,
+    mismatch: Arc<AtomicBool>,
+}
+
+impl<B: BlobFetch> BlobFetch for VerifyingBlobFetch<B> {
+    type Error = VerificationError;
+
+    fn remaining_len(&self) -> u64 {
+        self.blob.remaining_len()
+    }
+
+    fn total_len(&self) -> u64 {
+        self.blob.total_len()
+    }
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let read = self
+            .blob
+            .read(buf)
+            .await
+            .map_err(|e| VerificationError::Read(e.to_string()))?;
+        self.hash.update(&buf[..read]);
+        if self.blob.remaining_len() == 0 && self.hash.finalize() != self.expected {
+            self.mismatch.store(true, Ordering::Relaxed);
+            return Err(VerificationError::HashMismatch);
+        }
+        Ok(read)
+    }
+}
+
+#[derive(Debug)]
+enum VerificationError {
+    Read(String),
+    HashMismatch,
+}
+
+impl Display for VerificationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerificationError::Read(inner) => write!(f, "Read({inner})"),
+            VerificationError::HashMismatch => write!(f, "HashMismatch"),
+        }
+    }
+}
+
+impl Error for VerificationError {}
+
+/// Reads the rest of a blob nobody stores, its sender waits for room to send it
+async fn discard(mut blob: impl BlobFetch) -> Result<(), ServerServiceError> {
+    let mut buffer = vec![0; 64 * 1024];
+    while blob
+        .read(&mut buffer)
+        .await
+        .map_err(|e| BlobFetchError(e.into()))?
+        > 0
+    {}
+    Ok(())
+}
+
 #[derive(Debug)]
 pub enum ServerServiceError {
-    BackupRepositoryError(Box<dyn Error>),
-    BlobRepositoryError(Box<dyn Error>),
+    BackupRepositoryError(ErrorCode, Box<dyn Error>),
+    BlobRepositoryError(ErrorCode, Box<dyn Error>),
+    UserRepositoryError(ErrorCode, Box<dyn Error>),
     ResponseError(Box<dyn Error>),
     BackupIdNotFound(BackupId),
     BlobFetchError(Box<dyn Error>),
     NoPermission,
+    Rejected(Rejection),
+}
+
+impl ServerServiceError {
+    /// What the client is told
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            BackupRepositoryError(code, _)
+            | BlobRepositoryError(code, _)
+            | UserRepositoryError(code, _) => *code,
+            ResponseError(_) => ErrorCode::Internal,
+            BackupIdNotFound(_) => ErrorCode::NotFound,
+            BlobFetchError(_) => ErrorCode::InvalidRequest,
+            NoPermission => ErrorCode::NoPermission,
+            Rejected(rejection) => rejection.code(),
+        }
+    }
 }
 
 impl Display for ServerServiceError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            BackupRepositoryError(inner) => write!(f, "BackupRepositoryError({inner})"),
-            BlobRepositoryError(inner) => write!(f, "BlobRepositoryError({inner})"),
+            BackupRepositoryError(_, inner) => write!(f, "BackupRepositoryError({inner})"),
+            BlobRepositoryError(_, inner) => write!(f, "BlobRepositoryError({inner})"),
+            UserRepositoryError(_, inner) => write!(f, "UserRepositoryError({inner})"),
             ResponseError(inner) => write!(f, "ResponseError({inner})"),
             BackupIdNotFound(inner) => write!(f, "BackupIdNotFound({inner})"),
             BlobFetchError(inner) => write!(f, "BlobFetchError({inner})"),
             NoPermission => write!(f, "NoPermission"),
+            Rejected(rejection) => write!(f, "Rejected({rejection})"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::in_memory_repositories::backup_repository::InMemoryBackupRepository;
+    use crate::in_memory_repositories::blob_repository::{
+        InMemoryBlobFetch, InMemoryBlobRepository,
+    };
+    use crate::in_memory_repositories::user_repository::InMemoryUserRepository;
+    use crate::server_service::MainServerService;
+    use crate::shared_repository::SharedRepository;
+    use guardian_backup_domain::hash_service::HashService;
+    use guardian_backup_domain::model::backup::backup::{Backup, BackupId};
+    use guardian_backup_domain::model::backup::schedule::Schedule;
+    use guardian_backup_domain::model::backup::snapshot::Snapshot;
+    use guardian_backup_domain::model::blobs::blob_identifier::BlobIdentifier;
+    use guardian_backup_domain::model::device_identifier::DeviceIdentifier;
+    use guardian_backup_domain::model::files::file_hash::FileHash;
+    use guardian_backup_domain::model::timestamp::Timestamp;
+    use guardian_backup_domain::model::user_identifier::UserIdentifier;
+    use guardian_backup_domain::repositories::backup_repository::BackupRepository;
+    use guardian_backup_domain::repositories::blob_repository::BlobRepository;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_garbage_collection_keeps_referenced_and_recent_blobs() {
+        let user = UserIdentifier::new("TestUser".into());
+        let blob = |n: u8| BlobIdentifier::new(FileHash::Blake3 { hash: [n].into() }, user.clone());
+        let mut backups = SharedRepository::new(InMemoryBackupRepository::new());
+        let mut blobs = InMemoryBlobRepository::new();
+        let mut service = MainServerService::new(
+            backups.clone(),
+            blobs.clone(),
+            InMemoryUserRepository::new(),
+            HashService::new(vec![]),
+        );
+        for n in 0..3 {
+            let data = InMemoryBlobFetch::new([n; 10].into());
+            blobs.insert_blob(blob(n), data).await.unwrap();
+        }
+        let snapshot = Snapshot::new(Timestamp::now(), None, blob(0), vec![blob(1)]);
+        let backup = Backup::new(
+            BackupId("Backup".into()),
+            DeviceIdentifier::default(),
+            Schedule::new(vec![]),
+            Path::new("/a").into(),
+            vec![snapshot],
+        );
+        backups.create_backup(&user, backup).await.unwrap();
+
+        let hour = Duration::from_secs(60 * 60);
+        assert_eq!(service.collect_garbage(hour).await.unwrap(), 0);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert_eq!(service.collect_garbage(Duration::ZERO).await.unwrap(), 1);
+        assert!(blobs.fetch_blob(&blob(2)).await.is_err());
+        for n in 0..2 {
+            assert!(blobs.fetch_blob(&blob(n)).await.is_ok());
         }
     }
 }
@@ -0,0 +1,155 @@
+use guardian_backup_domain::model::backup::backup::{Backup, BackupId};
+use guardian_backup_domain::model::backup::backup_overview::BackupOverview;
+use guardian_backup_domain::model::backup::snapshot::Snapshot;
+use guardian_backup_domain::model::credential::Credential;
+use guardian_backup_domain::model::device_identifier::DeviceIdentifier;
+use guardian_backup_domain::model::timestamp::Timestamp;
+use guardian_backup_domain::model::user::User;
+use guardian_backup_domain::model::user_identifier::UserIdentifier;
+use guardian_backup_domain::repositories::backup_repository::BackupRepository;
+use guardian_backup_domain::repositories::user_repository::UserRepository;
+use std::borrow::Cow;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// A repository used by several owners, e.g. the login of the connectivity and the server service,
+/// or the calls the server handles at the same time. Every access locks the whole repository.
+pub struct SharedRepository<R> {
+    inner: Arc<Mutex<R>>,
+}
+
+impl<R> SharedRepository<R> {
+    pub fn new(repository: R) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(repository)),
+        }
+    }
+}
+
+impl<R> Clone for SharedRepository<R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<U: UserRepository + Send> UserRepository for SharedRepository<U> {
+    type Error = U::Error;
+
+    async fn get_user(&self, identifier: &UserIdentifier) -> Result<User, Self::Error> {
+        self.inner.lock().await.get_user(identifier).await
+    }
+
+    async fn get_credential(
+        &self,
+        identifier: &UserIdentifier,
+    ) -> Result<Option<Credential>, Self::Error> {
+        self.inner.lock().await.get_credential(identifier).await
+    }
+
+    async fn get_device_credential(
+        &self,
+        user: &UserIdentifier,
+        device: &DeviceIdentifier,
+    ) -> Result<Option<Credential>, Self::Error> {
+        self.inner
+            .lock()
+            .await
+            .get_device_credential(user, device)
+            .await
+    }
+
+    async fn get_user_devices(
+        &self,
+        user: &UserIdentifier,
+    ) -> Result<Cow<'_, [DeviceIdentifier]>, Self::Error> {
+        let inner = self.inner.lock().await;
+        let devices = inner.get_user_devices(user).await?;
+        Ok(Cow::Owned(devices.into_owned()))
+    }
+
+    async fn create_user(&mut self, user: &User) -> Result<(), Self::Error> {
+        self.inner.lock().await.create_user(user).await
+    }
+
+    async fn create_user_device(
+        &mut self,
+        user: &UserIdentifier,
+        device: &DeviceIdentifier,
+        token: Credential,
+    ) -> Result<(), Self::Error> {
+        self.inner
+            .lock()
+            .await
+            .create_user_device(user, device, token)
+            .await
+    }
+
+    async fn delete_user(&mut self, identifier: &UserIdentifier) -> Result<(), Self::Error> {
+        self.inner.lock().await.delete_user(identifier).await
+    }
+
+    async fn delete_device(
+        &mut self,
+        user: &UserIdentifier,
+        device: &DeviceIdentifier,
+    ) -> Result<(), Self::Error> {
+        self.inner.lock().await.delete_device(user, device).await
+    }
+}
+
+impl<B: BackupRepository> BackupRepository for SharedRepository<B> {
+    type Error = B::Error;
+
+    async fn get_backups(
+        &mut self,
+        user: &UserIdentifier,
+    ) -> Result<Box<dyn Iterator<Item = Backup> + '_>, Self::Error> {
+        let mut inner = self.inner.lock().await;
+        let backups = inner.get_backups(user).await?.collect::<Vec<_>>();
+        Ok(Box::new(backups.into_iter()))
+    }
+
+    async fn get_backup_by_id(
+        &mut self,
+        id: &BackupId,
+        user: &UserIdentifier,
+    ) -> Result<Option<Backup>, Self::Error> {
+        self.inner.lock().await.get_backup_by_id(id, user).await
+    }
+
+    async fn get_backup_overviews(
+        &mut self,
+        user: &UserIdentifier,
+    ) -> Result<Vec<BackupOverview>, Self::Error> {
+        self.inner.lock().await.get_backup_overviews(user).await
+    }
+
+    async fn get_snapshots(
+        &mut self,
+        id: &BackupId,
+        user: &UserIdentifier,
+        after: Option<Timestamp>,
+        limit: usize,
+    ) -> Result<Option<Vec<Snapshot>>, Self::Error> {
+        let mut inner = self.inner.lock().await;
+        inner.get_snapshots(id, user, after, limit).await
+    }
+
+    async fn update_backup(
+        &mut self,
+        backup: Backup,
+        user: &UserIdentifier,
+    ) -> Result<(), Self::Error> {
+        self.inner.lock().await.update_backup(backup, user).await
+    }
+
+    async fn create_backup(
+        &mut self,
+        user: &UserIdentifier,
+        backup: Backup,
+    ) -> Result<(), Self::Error> {
+        self.inner.lock().await.create_backup(user, backup).await
+    }
+}
@@ -24,12 +24,12 @@ impl HashService {
         preferred
     }
 
-    pub fn find_compatible_hasher(&self, hash: &FileHash) -> &'static dyn Hasher {
-        *self
-            .supported_hashers
+    /// The hasher producing hashes like `hash`, `None` if none is supported
+    pub fn find_compatible_hasher(&self, hash: &FileHash) -> Option<&'static dyn Hasher> {
+        self.supported_hashers
             .iter()
             .find(|e| e.can_compare_hash(hash))
-            .unwrap()
+            .copied()
     }
 }
 
@@ -39,7 +39,7 @@ pub trait Hasher {
     fn create_hash(&self) -> Box<dyn PendingHashB>;
 }
 
-pub trait PendingHashB {
+pub trait PendingHashB: Send {
     fn update(&mut self, data: &[u8]);
     fn finalize(&self) -> FileHash;
 }
@@ -1,6 +1,7 @@
 use crate::model::backup::schedule::Schedule;
 use crate::model::backup::snapshot::Snapshot;
 use crate::model::device_identifier::DeviceIdentifier;
+use crate::model::timestamp::Timestamp;
 use serde::{Deserialize, Serialize};
 use std::convert::Infallible;
 use std::fmt::{Display, Formatter};
@@ -64,6 +65,22 @@ impl Backup {
     pub fn snapshots(&self) -> &Vec<Snapshot> {
         &self.snapshots
     }
+    pub fn latest_snapshot(&self) -> Option<&Snapshot> {
+        self.snapshots.iter().max_by_key(|e| e.timestamp())
+    }
+    pub fn find_snapshot(&self, timestamp: Timestamp) -> Option<&Snapshot> {
+        self.snapshots.iter().find(|e| e.timestamp() == timestamp)
+    }
+    /// At most `limit` snapshots newer than `after`, ordered by timestamp
+    pub fn snapshot_page(&self, after: Option<Timestamp>, limit: usize) -> Vec<Snapshot> {
+        let mut snapshots: Vec<&Snapshot> = self
+            .snapshots
+            .iter()
+            .filter(|e| after.is_none_or(|after| e.timestamp() > after))
+            .collect();
+        snapshots.sort_by_key(|e| e.timestamp());
+        snapshots.into_iter().take(limit).cloned().collect()
+    }
     pub fn into_snapshots(self) -> impl IntoIterator<Item = Snapshot> {
         self.snapshots.into_iter()
     }
@@ -0,0 +1,70 @@
+use crate::model::backup::backup::{Backup, BackupId};
+use crate::model::backup::schedule::Schedule;
+use crate::model::backup::snapshot::Snapshot;
+use crate::model::device_identifier::DeviceIdentifier;
+use crate::model::timestamp::Timestamp;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// A [Backup] without its snapshots, enough to list backups
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct BackupOverview {
+    id: BackupId,
+    device: DeviceIdentifier,
+    schedule: Schedule,
+    file_root: Box<Path>,
+    snapshot_count: usize,
+    oldest_snapshot: Option<Timestamp>,
+    newest_snapshot: Option<Timestamp>,
+    next_expiry: Option<Timestamp>,
+}
+
+impl BackupOverview {
+    pub fn id(&self) -> &BackupId {
+        &self.id
+    }
+    pub fn device(&self) -> &DeviceIdentifier {
+        &self.device
+    }
+    pub fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+    pub fn file_root(&self) -> &Path {
+        &self.file_root
+    }
+    pub fn snapshot_count(&self) -> usize {
+        self.snapshot_count
+    }
+    /// `None` without snapshots
+    pub fn oldest_snapshot(&self) -> Option<Timestamp> {
+        self.oldest_snapshot
+    }
+    /// `None` without snapshots
+    pub fn newest_snapshot(&self) -> Option<Timestamp> {
+        self.newest_snapshot
+    }
+    /// Earliest expiration time of all snapshots, `None` if none of them expires
+    pub fn next_expiry(&self) -> Option<Timestamp> {
+        self.next_expiry
+    }
+}
+
+impl From<&Backup> for BackupOverview {
+    fn from(value: &Backup) -> Self {
+        let timestamps = value.snapshots().iter().map(Snapshot::timestamp);
+        Self {
+            id: value.id().clone(),
+            device: value.device().clone(),
+            schedule: value.schedule().clone(),
+            file_root: value.file_root().into(),
+            snapshot_count: value.snapshots().len(),
+            oldest_snapshot: timestamps.clone().min(),
+            newest_snapshot: timestamps.max(),
+            next_expiry: value
+                .snapshots()
+                .iter()
+                .filter_map(Snapshot::expiration_time)
+                .min(),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 pub mod backup;
+pub mod backup_overview;
 pub mod schedule;
 pub mod schedule_rule;
 pub mod snapshot;
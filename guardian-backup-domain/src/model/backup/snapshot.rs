@@ -28,10 +28,14 @@ impl Snapshot {
     pub fn timestamp(&self) -> Timestamp {
         self.timestamp
     }
-    pub fn expiration_time(&self) -> Timestamp {
-        self.expiration_time.unwrap()
+    /// `None` if the snapshot never expires
+    pub fn expiration_time(&self) -> Option<Timestamp> {
+        self.expiration_time
     }
     pub fn file_tree_blob(&self) -> &BlobIdentifier {
         &self.file_tree_blob
     }
+    pub fn associated_blobs(&self) -> &[BlobIdentifier] {
+        self.associated_blobs.as_slice()
+    }
 }
@@ -25,4 +25,30 @@ pub trait BlobFetch: Send {
             Ok(res.into())
         }
     }
+
+    /// Starts reading from the beginning again, e.g. to send the blob again after an upload
+    /// broke off. `false` if the blob can only be read once.
+    fn rewind(&mut self) -> impl Future<Output = Result<bool, Self::Error>> + Send {
+        async { Ok(false) }
+    }
+}
+
+impl<B: BlobFetch + ?Sized> BlobFetch for &mut B {
+    type Error = B::Error;
+
+    fn remaining_len(&self) -> u64 {
+        (**self).remaining_len()
+    }
+
+    fn total_len(&self) -> u64 {
+        (**self).total_len()
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> impl Future<Output = Result<usize, Self::Error>> + Send {
+        (**self).read(buf)
+    }
+
+    fn rewind(&mut self) -> impl Future<Output = Result<bool, Self::Error>> + Send {
+        (**self).rewind()
+    }
 }
@@ -0,0 +1,40 @@
+use crate::model::password_hash_algorithms::PasswordHashAlg;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Debug, Formatter};
+
+/// Verifies the secret a user proves their identity with. Only a key derived from the secret
+/// is stored, which can't be used to log in.
+#[derive(Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub enum Credential {
+    /// Stored key of the hash derived from the password with `algorithm`
+    Password {
+        algorithm: PasswordHashAlg,
+        stored_key: Box<[u8]>,
+    },
+    /// Stored key of a token that logs a single device in, with access to its own backups only
+    DeviceToken { stored_key: Box<[u8]> },
+}
+
+impl Debug for Credential {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Credential::")?;
+        match &self {
+            Credential::Password {
+                stored_key,
+                algorithm,
+            } => {
+                write!(
+                    f,
+                    "Password {{stored_key: {:?}...{:?},",
+                    &stored_key[0..4],
+                    &stored_key[(stored_key.len() - 4)..stored_key.len()]
+                )?;
+                Debug::fmt(algorithm, f)?;
+                write!(f, "}}")
+            }
+            Credential::DeviceToken { stored_key } => {
+                write!(f, "DeviceToken {{stored_key: {:?}...}}", &stored_key[0..4])
+            }
+        }
+    }
+}
@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
 #[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceIdentifier {
@@ -12,3 +15,19 @@ impl Default for DeviceIdentifier {
         }
     }
 }
+
+impl Display for DeviceIdentifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.identifier)
+    }
+}
+
+impl FromStr for DeviceIdentifier {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            identifier: s.into(),
+        })
+    }
+}
@@ -23,11 +23,45 @@ impl Duration {
     }
 }
 
+/// Formats the duration in the format accepted by [Duration::from_str], e.g. `3d12h`
+impl Display for Duration {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Duration::Limited { milliseconds } = *self else {
+            return write!(f, "infinite");
+        };
+
+        let minutes = milliseconds / (60 * 1000);
+        let units = [
+            (minutes / (24 * 60), 'd'),
+            (minutes / 60 % 24, 'h'),
+            (minutes % 60, 'm'),
+        ];
+        let mut written = false;
+        for (amount, unit) in units {
+            if amount > 0 {
+                write!(f, "{amount}{unit}")?;
+                written = true;
+            }
+        }
+        if milliseconds % (60 * 1000) > 0 {
+            write!(f, "{}ms", milliseconds % (60 * 1000))
+        } else if !written {
+            write!(f, "0m")
+        } else {
+            Ok(())
+        }
+    }
+}
+
 impl FromStr for Duration {
     type Err = DurationError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let regex = Regex::new(r"(\d+d|\d+h|\d+m)").unwrap();
+        if s == "infinite" {
+            return Ok(Duration::Infinite);
+        }
+        // `ms` before `m`, or milliseconds are read as minutes
+        let regex = Regex::new(r"(\d+)(d|h|ms|m)").unwrap();
 
         if !regex.is_match(s) {
             return Err(DurationError::NoMatches);
@@ -36,11 +70,8 @@ impl FromStr for Duration {
         let mut millis = 0;
 
         for timepart_capture in regex.captures_iter(s) {
-            let time_piece = timepart_capture.get(0).unwrap().as_str();
-            println!("{:?}", time_piece);
-            let (time_amount_str, unit) = time_piece.split_at(time_piece.len() - 1);
-            let time_amount: u64 = time_amount_str.parse().unwrap();
-            match unit {
+            let time_amount: u64 = timepart_capture[1].parse().unwrap();
+            match &timepart_capture[2] {
                 "d" => {
                     millis += 24 * 60 * 60 * 1000 * time_amount;
                 }
@@ -50,6 +81,9 @@ impl FromStr for Duration {
                 "m" => {
                     millis += 60 * 1000 * time_amount;
                 }
+                "ms" => {
+                    millis += time_amount;
+                }
                 _ => {
                     panic!("should be unreachable, check duration regex")
                 }
@@ -76,3 +110,34 @@ impl Display for DurationError {
 }
 
 impl Error for DurationError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::duration::{Duration, MONTH};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_display_round_trips() {
+        assert_eq!(MONTH.to_string(), "30d");
+        assert_eq!(Duration::Infinite.to_string(), "infinite");
+        assert_eq!(Duration::from_str("infinite").unwrap(), Duration::Infinite);
+        assert_eq!(Duration::Limited { milliseconds: 0 }.to_string(), "0m");
+
+        let duration = Duration::from_str("3d12h5m").unwrap();
+        assert_eq!(duration.to_string(), "3d12h5m");
+        assert_eq!(Duration::from_str(&duration.to_string()).unwrap(), duration);
+    }
+
+    #[test]
+    fn test_milliseconds_round_trip() {
+        assert_eq!(
+            Duration::from_str("500ms").unwrap(),
+            Duration::Limited { milliseconds: 500 }
+        );
+
+        for milliseconds in [1, 500, 60 * 1000 + 1, 90 * 60 * 1000 + 250] {
+            let duration = Duration::Limited { milliseconds };
+            assert_eq!(Duration::from_str(&duration.to_string()).unwrap(), duration);
+        }
+    }
+}
@@ -1,4 +1,13 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DirectoryMetadata {}
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DirectoryMetadata {
+    #[serde(default)]
+    pub permissions: Option<u32>,
+}
+
+impl DirectoryMetadata {
+    pub fn permissions(&self) -> Option<u32> {
+        self.permissions
+    }
+}
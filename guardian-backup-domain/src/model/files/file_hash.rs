@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash, Serialize, Deserialize)]
 pub enum FileHash {
@@ -8,3 +9,14 @@ pub enum FileHash {
     #[cfg(any(test, feature = "mocks"))]
     Mock,
 }
+
+/// Formats the hash as lowercase hex
+impl Display for FileHash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileHash::Blake3 { hash } => hash.iter().try_for_each(|e| write!(f, "{e:02x}")),
+            #[cfg(any(test, feature = "mocks"))]
+            FileHash::Mock => write!(f, "mock"),
+        }
+    }
+}
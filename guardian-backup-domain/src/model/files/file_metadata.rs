@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 pub struct FileMetadata {
     pub file_size: u64,
     pub last_modified: u64,
+    #[serde(default)]
+    pub permissions: Option<u32>,
 }
 
 impl FileMetadata {
@@ -13,4 +15,7 @@ impl FileMetadata {
     pub fn file_size(&self) -> u64 {
         self.file_size
     }
+    pub fn permissions(&self) -> Option<u32> {
+        self.permissions
+    }
 }
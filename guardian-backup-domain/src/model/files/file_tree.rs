@@ -33,98 +33,171 @@ impl FileTreeNode {
         }
     }
 
+    /// Lists the changes turning `other` into `self`, where `path` is the location of `self`.
+    /// Files are compared by content hash; reported locations are the parent directories.
     pub fn diff_to<'a>(
         &'a self,
         other: &'a FileTreeNode,
         path: Box<Path>,
     ) -> Box<dyn Iterator<Item = FileTreeDiff> + 'a> {
-        match self {
-            FileTreeNode::File {
-                name,
-                blob,
-                metadata,
-            } => {
-                if let FileTreeNode::File {
-                    name: o_name,
-                    blob: o_blob,
-                    metadata: o_metadata,
-                } = other
-                {
-                    if metadata.last_modified != o_metadata.last_modified {
-                        return Box::new(once(FileTreeDiff {
-                            diff_type: FileTreeDiffType::Updated,
-                            node: self.clone(),
-                            location: path.into(),
-                        }));
-                    } else {
-                        return Box::new(empty());
-                    }
-                } else {
-                    return Box::new(once(FileTreeDiff {
-                        diff_type: FileTreeDiffType::ChangedType,
+        match (self, other) {
+            (FileTreeNode::File { blob, .. }, FileTreeNode::File { blob: o_blob, .. }) => {
+                if blob.hash() != o_blob.hash() {
+                    Box::new(once(FileTreeDiff {
+                        diff_type: FileTreeDiffType::Updated,
                         node: self.clone(),
-                        location: path.into(),
-                    }));
+                        previous: Some(other.clone()),
+                        location: path.parent().unwrap_or(&path).into(),
+                    }))
+                } else {
+                    Box::new(empty())
                 }
             }
-            FileTreeNode::Directory {
-                name,
-                metadata,
-                children,
-            } => {
-                if let FileTreeNode::Directory {
-                    name: o_name,
-                    metadata: o_metadata,
+            (
+                FileTreeNode::Directory { children, .. },
+                FileTreeNode::Directory {
                     children: o_children,
-                } = other
-                {
-                    let new = children
+                    ..
+                },
+            ) => {
+                let new = children
+                    .iter()
+                    .filter(|e| !o_children.iter().any(|o| e.name() == o.name()));
+                let old = children.iter().filter_map(|e| {
+                    o_children
                         .iter()
-                        .filter(|e| !o_children.iter().any(|o| e.name() == o.name()));
-                    let old = children.iter().filter_map(|e| {
+                        .find(|o| e.name() == o.name())
+                        .map(|o| (e, o))
+                });
+                let gone = o_children
+                    .iter()
+                    .filter(|o| !children.iter().any(|e| e.name() == o.name()));
+
+                let path_c = path.clone();
+                let new = new.map(move |e| FileTreeDiff {
+                    diff_type: FileTreeDiffType::Created,
+                    node: e.clone(),
+                    previous: None,
+                    location: path_c.clone(),
+                });
+                let path_c = path.clone();
+                let old = old.flat_map(move |(e, o)| e.diff_to(o, path_c.join(e.name()).into()));
+                let path_c = path.clone();
+                let gone = gone.map(move |o| FileTreeDiff {
+                    diff_type: FileTreeDiffType::Deleted,
+                    node: o.clone(),
+                    previous: None,
+                    location: path_c.clone(),
+                });
+
+                Box::new(gone.chain(old).chain(new))
+            }
+            // The targets of links aren't backed up, there is nothing to compare
+            (FileTreeNode::SymbolicLink { .. }, FileTreeNode::SymbolicLink { .. }) => {
+                Box::new(empty())
+            }
+            _ => Box::new(once(FileTreeDiff {
+                diff_type: FileTreeDiffType::ChangedType,
+                node: self.clone(),
+                previous: Some(other.clone()),
+                location: path.parent().unwrap_or(&path).into(),
+            })),
+        }
+    }
+
+    /// Like [FileTreeNode::diff_to], but pairs deleted and created nodes with identical content
+    /// into [FileTreeDiffType::Moved] diffs, preferring candidates that kept their name. Empty
+    /// files and directories are only paired if they kept their name, any two of them look alike.
+    pub fn diff_with_moves(&self, other: &FileTreeNode, path: Box<Path>) -> Vec<FileTreeDiff> {
+        let mut diffs: Vec<FileTreeDiff> = self.diff_to(other, path).collect();
+        let mut deleted: Vec<usize> = (0..diffs.len())
+            .filter(|&i| matches!(diffs[i].diff_type, FileTreeDiffType::Deleted))
+            .collect();
+        let mut consumed = vec![false; diffs.len()];
+
+        for created in 0..diffs.len() {
+            if !matches!(diffs[created].diff_type, FileTreeDiffType::Created) {
+                continue;
+            }
+            let node = &diffs[created].node;
+            let candidates: Vec<usize> = (0..deleted.len())
+                .filter(|&i| {
+                    let source = &diffs[deleted[i]].node;
+                    source.has_same_content(node)
+                        && (node.size() > 0 || source.name() == node.name())
+                })
+                .collect();
+            let Some(&candidate) = candidates
+                .iter()
+                .find(|&&i| diffs[deleted[i]].node.name() == node.name())
+                .or(candidates.first())
+            else {
+                continue;
+            };
+
+            let source = deleted.swap_remove(candidate);
+            consumed[source] = true;
+            diffs[created].diff_type = FileTreeDiffType::Moved {
+                from: diffs[source].path().into(),
+            };
+        }
+
+        diffs
+            .into_iter()
+            .zip(consumed)
+            .filter_map(|(diff, consumed)| (!consumed).then_some(diff))
+            .collect()
+    }
+
+    /// Compares file hashes and the structure of directories, ignoring the name of this node
+    pub fn has_same_content(&self, other: &FileTreeNode) -> bool {
+        match (self, other) {
+            (FileTreeNode::File { blob, .. }, FileTreeNode::File { blob: o_blob, .. }) => {
+                blob.hash() == o_blob.hash()
+            }
+            (
+                FileTreeNode::Directory { children, .. },
+                FileTreeNode::Directory {
+                    children: o_children,
+                    ..
+                },
+            ) => {
+                children.len() == o_children.len()
+                    && children.iter().all(|e| {
                         o_children
                             .iter()
-                            .find(|o| e.name() == o.name())
-                            .map(|o| (e, o))
-                    });
-                    let gone = o_children
-                        .iter()
-                        .filter(|o| !children.iter().any(|e| e.name() == o.name()));
-
-                    let path_c = path.clone();
-                    let new = new.map(move |e| FileTreeDiff {
-                        diff_type: FileTreeDiffType::Created,
-                        node: e.clone(),
-                        location: path_c.clone(),
-                    });
-                    let path_c = path.clone();
-                    let old =
-                        old.flat_map(move |(e, o)| e.diff_to(o, path_c.join(e.name()).into()));
-                    let path_c = path.clone();
-                    let gone = gone.map(move |o| FileTreeDiff {
-                        diff_type: FileTreeDiffType::Deleted,
-                        node: o.clone(),
-                        location: path_c.clone(),
-                    });
-
-                    return Box::new(gone.chain(old).chain(new));
-                } else {
-                    return Box::new(once(FileTreeDiff {
-                        diff_type: FileTreeDiffType::ChangedType,
-                        node: self.clone(),
-                        location: path.into(),
-                    }));
-                }
-            }
-            FileTreeNode::SymbolicLink { .. } => {
-                unimplemented!()
+                            .any(|o| e.name() == o.name() && e.has_same_content(o))
+                    })
             }
+            _ => false,
+        }
+    }
+
+    /// Looks up the node at `path` relative to this node, an empty path returns this node
+    pub fn find(&self, path: &Path) -> Option<&FileTreeNode> {
+        path.components()
+            .try_fold(self, |node, component| match node {
+                FileTreeNode::Directory { children, .. } => {
+                    children.iter().find(|e| e.name() == component.as_os_str())
+                }
+                _ => None,
+            })
+    }
+
+    /// Sum of the sizes of all files in this subtree
+    pub fn size(&self) -> u64 {
+        match self {
+            FileTreeNode::File { metadata, .. } => metadata.file_size(),
+            FileTreeNode::Directory { children, .. } => children.iter().map(Self::size).sum(),
+            FileTreeNode::SymbolicLink { .. } => 0,
         }
     }
 
     pub fn iter(&self, path: PathBuf) -> Box<dyn Iterator<Item = (PathBuf, &FileTreeNode)> + '_> {
         match self {
-            FileTreeNode::File { name, .. } => Box::new(once((path, self))),
+            FileTreeNode::File { .. } | FileTreeNode::SymbolicLink { .. } => {
+                Box::new(once((path, self)))
+            }
             FileTreeNode::Directory { children, name, .. } => {
                 let dirpath = path.join(name);
 
@@ -133,22 +206,380 @@ impl FileTreeNode {
                         .chain(children.iter().flat_map(move |e| e.iter(dirpath.clone()))),
                 )
             }
-            FileTreeNode::SymbolicLink { .. } => {
-                unimplemented!()
+        }
+    }
+
+    /// Inserts `node` at `path` relative to this node, creating missing parent directories.
+    /// Entries already present under the same name are replaced, except that inserting a
+    /// directory over an existing directory only updates its metadata and keeps the children.
+    pub fn insert(&mut self, path: &Path, node: FileTreeNode) {
+        let mut components = path.components();
+        let Some(first) = components.next() else {
+            return;
+        };
+        let first = first.as_os_str();
+        let rest = components.as_path();
+
+        if !matches!(self, FileTreeNode::Directory { .. }) {
+            *self = FileTreeNode::Directory {
+                name: self.name().clone(),
+                metadata: DirectoryMetadata::default(),
+                children: vec![],
+            };
+        }
+        let FileTreeNode::Directory { children, .. } = self else {
+            unreachable!("self was converted into a directory")
+        };
+
+        let existing = children.iter_mut().find(|e| e.name() == first);
+
+        if !rest.as_os_str().is_empty() {
+            match existing {
+                Some(child) => child.insert(rest, node),
+                None => {
+                    let mut child = FileTreeNode::Directory {
+                        name: first.into(),
+                        metadata: DirectoryMetadata::default(),
+                        children: vec![],
+                    };
+                    child.insert(rest, node);
+                    children.push(child);
+                }
             }
+            return;
+        }
+
+        let node = node.with_name(first.into());
+        match (existing, node) {
+            (
+                Some(FileTreeNode::Directory { metadata, .. }),
+                FileTreeNode::Directory {
+                    metadata: new_metadata,
+                    ..
+                },
+            ) => *metadata = new_metadata,
+            (Some(existing), node) => *existing = node,
+            (None, node) => children.push(node),
         }
     }
+
+    fn with_name(mut self, new_name: OsString) -> Self {
+        match &mut self {
+            FileTreeNode::File { name, .. }
+            | FileTreeNode::Directory { name, .. }
+            | FileTreeNode::SymbolicLink { name, .. } => *name = new_name,
+        }
+        self
+    }
 }
 
+#[derive(Clone)]
 pub struct FileTreeDiff {
     pub diff_type: FileTreeDiffType,
+    /// The node in the expected state, or the removed node for [FileTreeDiffType::Deleted]
     pub node: FileTreeNode,
+    /// The replaced node for [FileTreeDiffType::Updated] and [FileTreeDiffType::ChangedType]
+    pub previous: Option<FileTreeNode>,
+    /// The directory containing the node
     pub location: Box<Path>,
 }
 
+impl FileTreeDiff {
+    pub fn path(&self) -> PathBuf {
+        self.location.join(self.node.name())
+    }
+}
+
+#[derive(Clone)]
 pub enum FileTreeDiffType {
     Created,
     Updated,
     Deleted,
     ChangedType,
+    /// The node already exists at `from` and only has to be renamed
+    Moved {
+        from: Box<Path>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::blobs::blob_identifier::BlobIdentifier;
+    use crate::model::files::directory_metadata::DirectoryMetadata;
+    use crate::model::files::file_hash::FileHash;
+    use crate::model::files::file_metadata::FileMetadata;
+    use crate::model::files::file_tree::{FileTreeDiffType, FileTreeNode};
+    use crate::model::user_identifier::UserIdentifier;
+    use std::path::{Path, PathBuf};
+
+    fn file(name: &str) -> FileTreeNode {
+        FileTreeNode::File {
+            name: name.into(),
+            blob: BlobIdentifier::new(FileHash::Mock, UserIdentifier::new("Mock".into())),
+            metadata: FileMetadata {
+                file_size: 0,
+                last_modified: 0,
+                permissions: None,
+            },
+        }
+    }
+
+    fn file_with_content(name: &str, content: u8, size: u64) -> FileTreeNode {
+        FileTreeNode::File {
+            name: name.into(),
+            blob: BlobIdentifier::new(
+                FileHash::Blake3 {
+                    hash: Box::new([content]),
+                },
+                UserIdentifier::new("Mock".into()),
+            ),
+            metadata: FileMetadata {
+                file_size: size,
+                last_modified: 0,
+                permissions: None,
+            },
+        }
+    }
+
+    fn directory(name: &str) -> FileTreeNode {
+        FileTreeNode::Directory {
+            name: name.into(),
+            metadata: DirectoryMetadata::default(),
+            children: vec![],
+        }
+    }
+
+    #[test]
+    fn test_insert_creates_missing_parents() {
+        let mut root = directory("root");
+        root.insert(Path::new("a/b/c.txt"), file("c.txt"));
+        root.insert(Path::new("a/d.txt"), file("d.txt"));
+
+        let paths: Vec<PathBuf> = root
+            .iter(PathBuf::new())
+            .map(|(path, node)| path.join(node.name()))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "root",
+                "root/a",
+                "root/a/b",
+                "root/a/b/c.txt",
+                "root/a/d.txt"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn test_insert_directory_keeps_children() {
+        let mut root = directory("root");
+        root.insert(Path::new("a/b.txt"), file("b.txt"));
+        root.insert(
+            Path::new("a"),
+            FileTreeNode::Directory {
+                name: "a".into(),
+                metadata: DirectoryMetadata {
+                    permissions: Some(0o700),
+                },
+                children: vec![],
+            },
+        );
+
+        let FileTreeNode::Directory { children, .. } = &root else {
+            panic!("Expected root to stay a directory")
+        };
+        let FileTreeNode::Directory {
+            metadata, children, ..
+        } = &children[0]
+        else {
+            panic!("Expected a to be a directory")
+        };
+        assert_eq!(metadata.permissions(), Some(0o700));
+        assert_eq!(children.len(), 1);
+    }
+
+    #[test]
+    fn test_find_resolves_relative_paths() {
+        let mut root = directory("root");
+        root.insert(Path::new("a/b.txt"), file("b.txt"));
+
+        assert_eq!(root.find(Path::new("")).unwrap().name(), "root");
+        assert_eq!(root.find(Path::new("a/b.txt")).unwrap().name(), "b.txt");
+        assert!(root.find(Path::new("a/b.txt/c")).is_none());
+        assert!(root.find(Path::new("/a")).is_none());
+    }
+
+    #[test]
+    fn test_symbolic_links_are_listed_and_compared() {
+        let mut root = directory("root");
+        let link = FileTreeNode::SymbolicLink {
+            name: "link".into(),
+            target: Box::new(file("a.txt")),
+        };
+        root.insert(Path::new("link"), link);
+
+        let names: Vec<_> = root.iter(PathBuf::new()).map(|(_, e)| e.name()).collect();
+        assert_eq!(names, ["root", "link"]);
+        assert_eq!(root.diff_to(&root, PathBuf::from("root").into()).count(), 0);
+    }
+
+    #[test]
+    fn test_diff_compares_content_hashes() {
+        let mut old = directory("root");
+        old.insert(Path::new("same.txt"), file_with_content("same.txt", 1, 10));
+        old.insert(
+            Path::new("changed.txt"),
+            file_with_content("changed.txt", 2, 10),
+        );
+        old.insert(Path::new("gone.txt"), file_with_content("gone.txt", 3, 10));
+        old.insert(Path::new("sub"), file_with_content("sub", 4, 10));
+
+        let mut new = directory("root");
+        new.insert(Path::new("same.txt"), file_with_content("same.txt", 1, 10));
+        new.insert(
+            Path::new("changed.txt"),
+            file_with_content("changed.txt", 5, 15),
+        );
+        new.insert(
+            Path::new("sub/new.txt"),
+            file_with_content("new.txt", 6, 20),
+        );
+
+        let diffs: Vec<_> = new
+            .diff_to(&old, PathBuf::from("root").into())
+            .map(|e| {
+                let path = e.path();
+                (e.diff_type, path, e.previous.map(|p| p.size()))
+            })
+            .collect();
+
+        assert_eq!(diffs.len(), 3);
+        assert!(matches!(
+            &diffs[0],
+            (FileTreeDiffType::Deleted, path, None) if path == Path::new("root/gone.txt")
+        ));
+        assert!(matches!(
+            &diffs[1],
+            (FileTreeDiffType::Updated, path, Some(10)) if path == Path::new("root/changed.txt")
+        ));
+        assert!(matches!(
+            &diffs[2],
+            (FileTreeDiffType::ChangedType, path, Some(10)) if path == Path::new("root/sub")
+        ));
+        assert_eq!(new.size(), 45);
+    }
+
+    #[test]
+    fn test_diff_with_moves_pairs_identical_content() {
+        let mut old = directory("root");
+        old.insert(
+            Path::new("a/photo.jpg"),
+            file_with_content("photo.jpg", 1, 10),
+        );
+        old.insert(
+            Path::new("a/other.txt"),
+            file_with_content("other.txt", 2, 10),
+        );
+        old.insert(
+            Path::new("notes.txt"),
+            file_with_content("notes.txt", 3, 10),
+        );
+        old.insert(Path::new("gone.txt"), file_with_content("gone.txt", 4, 10));
+
+        let mut new = directory("root");
+        new.insert(
+            Path::new("b/photo.jpg"),
+            file_with_content("photo.jpg", 1, 10),
+        );
+        new.insert(
+            Path::new("b/other.txt"),
+            file_with_content("other.txt", 2, 10),
+        );
+        new.insert(
+            Path::new("renamed.txt"),
+            file_with_content("renamed.txt", 3, 10),
+        );
+        new.insert(
+            Path::new("fresh.txt"),
+            file_with_content("fresh.txt", 5, 10),
+        );
+
+        let diffs: Vec<_> = new
+            .diff_with_moves(&old, PathBuf::from("root").into())
+            .into_iter()
+            .map(|e| {
+                let path = e.path();
+                (e.diff_type, path)
+            })
+            .collect();
+
+        assert_eq!(diffs.len(), 4);
+        assert!(matches!(
+            &diffs[0],
+            (FileTreeDiffType::Deleted, path) if path == Path::new("root/gone.txt")
+        ));
+        assert!(matches!(
+            &diffs[1],
+            (FileTreeDiffType::Moved { from }, path)
+                if from.as_ref() == Path::new("root/a") && path == Path::new("root/b")
+        ));
+        assert!(matches!(
+            &diffs[2],
+            (FileTreeDiffType::Moved { from }, path)
+                if from.as_ref() == Path::new("root/notes.txt")
+                    && path == Path::new("root/renamed.txt")
+        ));
+        assert!(matches!(
+            &diffs[3],
+            (FileTreeDiffType::Created, path) if path == Path::new("root/fresh.txt")
+        ));
+    }
+
+    #[test]
+    fn test_diff_with_moves_pairs_empty_nodes_by_name() {
+        let mut old = directory("root");
+        old.insert(Path::new("a/empty.txt"), file("empty.txt"));
+        old.insert(Path::new("a/.keep"), file(".keep"));
+        old.insert(Path::new("a/cache"), directory("cache"));
+        old.insert(Path::new("b"), directory("b"));
+
+        let mut new = directory("root");
+        new.insert(Path::new("a"), directory("a"));
+        new.insert(Path::new("b/empty.txt"), file("empty.txt"));
+        new.insert(Path::new("b/.gitkeep"), file(".gitkeep"));
+        new.insert(Path::new("b/tmp"), directory("tmp"));
+
+        let diffs: Vec<_> = new
+            .diff_with_moves(&old, PathBuf::from("root").into())
+            .into_iter()
+            .map(|e| {
+                let path = e.path();
+                (e.diff_type, path)
+            })
+            .collect();
+
+        assert!(diffs.iter().any(|e| matches!(
+            e,
+            (FileTreeDiffType::Moved { from }, path)
+                if from.as_ref() == Path::new("root/a/empty.txt")
+                    && path == Path::new("root/b/empty.txt")
+        )));
+        assert!(diffs.iter().any(|e| matches!(
+            e,
+            (FileTreeDiffType::Created, path) if path == Path::new("root/b/.gitkeep")
+        )));
+        assert!(diffs.iter().any(|e| matches!(
+            e,
+            (FileTreeDiffType::Created, path) if path == Path::new("root/b/tmp")
+        )));
+        assert_eq!(
+            diffs
+                .iter()
+                .filter(|e| matches!(e.0, FileTreeDiffType::Moved { .. }))
+                .count(),
+            1
+        );
+    }
 }
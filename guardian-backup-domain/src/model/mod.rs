@@ -1,8 +1,10 @@
 pub mod backup;
 pub mod blobs;
+pub mod credential;
 pub mod device_identifier;
 pub mod duration;
 pub mod files;
+pub mod password_hash_algorithms;
 pub mod timestamp;
 pub mod user;
 pub mod user_identifier;
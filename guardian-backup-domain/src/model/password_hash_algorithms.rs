@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+/// Algorithm and parameters a password hash was derived with
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub enum PasswordHashAlg {
+    Argon2id {
+        salt: Box<[u8]>,
+        parallelism: u32,
+        memory_cost: u32,
+        iterations: u32,
+        version: u32,
+    },
+}
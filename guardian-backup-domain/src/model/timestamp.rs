@@ -1,6 +1,9 @@
 use crate::model::duration::Duration;
 use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use std::ops::Add;
+use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Serialize, Deserialize)]
@@ -32,6 +35,10 @@ impl Timestamp {
         }
     }
 
+    pub fn milliseconds_since_epoch(&self) -> u64 {
+        self.milliseconds_since_epoch
+    }
+
     pub fn from_now_in_millis(diff_in_millis: u64) -> Self {
         Self {
             milliseconds_since_epoch: SystemTime::now()
@@ -55,3 +62,162 @@ impl Add<&Duration> for Timestamp {
         }
     }
 }
+
+const MILLISECONDS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+
+/// Formats the timestamp as UTC date, e.g. `2024-05-01T12:30:00.000Z`
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let days = self.milliseconds_since_epoch / MILLISECONDS_PER_DAY;
+        let millis_of_day = self.milliseconds_since_epoch % MILLISECONDS_PER_DAY;
+        let (year, month, day) = civil_from_days(days as i64);
+        let seconds_of_day = millis_of_day / 1000;
+
+        write!(
+            f,
+            "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+            seconds_of_day / 3600,
+            seconds_of_day / 60 % 60,
+            seconds_of_day % 60,
+            millis_of_day % 1000
+        )
+    }
+}
+
+/// Accepts milliseconds since the unix epoch or a UTC date in the format
+/// `YYYY-MM-DD[THH:MM[:SS[.fff]]][Z]`
+impl FromStr for Timestamp {
+    type Err = TimestampError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(millis) = s.parse() {
+            return Ok(Timestamp::from_milliseconds(millis));
+        }
+        parse_utc_date(s).ok_or(TimestampError::InvalidFormat)
+    }
+}
+
+fn parse_utc_date(s: &str) -> Option<Timestamp> {
+    let s = s.strip_suffix('Z').unwrap_or(s);
+    let (date, time) = s.split_once(['T', ' ']).unwrap_or((s, "00:00"));
+
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = date_parts.next()?.parse().ok()?;
+    let month: u32 = date_parts.next()?.parse().ok()?;
+    let day: u32 = date_parts.next()?.parse().ok()?;
+    let days = days_from_civil(year, month, day);
+    if days < 0 || civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+
+    let mut time_parts = time.splitn(3, ':');
+    let hours: u64 = time_parts.next()?.parse().ok()?;
+    let minutes: u64 = time_parts.next()?.parse().ok()?;
+    let (seconds, fraction) = time_parts
+        .next()
+        .map(|e| e.split_once('.').unwrap_or((e, "")))
+        .unwrap_or(("0", ""));
+    let seconds: u64 = seconds.parse().ok()?;
+    if hours > 23 || minutes > 59 || seconds > 59 || !fraction.bytes().all(|e| e.is_ascii_digit()) {
+        return None;
+    }
+    let millis: u64 = format!("{:0<3}", &fraction[..fraction.len().min(3)])
+        .parse()
+        .ok()?;
+
+    Some(Timestamp::from_milliseconds(
+        days as u64 * MILLISECONDS_PER_DAY
+            + ((hours * 60 + minutes) * 60 + seconds) * 1000
+            + millis,
+    ))
+}
+
+/// Converts days since 1970-01-01 into (year, month, day), see
+/// <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Inverse of [civil_from_days]
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = i64::from((month + 9) % 12);
+    let day_of_year = (153 * shifted_month + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[derive(Debug)]
+pub enum TimestampError {
+    InvalidFormat,
+}
+
+impl Display for TimestampError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimestampError::InvalidFormat => {
+                write!(
+                    f,
+                    "Expected milliseconds since the unix epoch or a UTC date like 2024-05-01T12:30:00Z"
+                )
+            }
+        }
+    }
+}
+
+impl Error for TimestampError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::timestamp::Timestamp;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_display_round_trips() {
+        let timestamp = Timestamp::from_milliseconds(1_714_566_600_123);
+        assert_eq!(timestamp.to_string(), "2024-05-01T12:30:00.123Z");
+        assert_eq!(
+            Timestamp::from_str(&timestamp.to_string()).unwrap(),
+            timestamp
+        );
+        assert_eq!(
+            Timestamp::from_milliseconds(0).to_string(),
+            "1970-01-01T00:00:00.000Z"
+        );
+    }
+
+    #[test]
+    fn test_from_str_accepts_dates_and_milliseconds() {
+        assert_eq!(
+            Timestamp::from_str("1714566600123").unwrap(),
+            Timestamp::from_milliseconds(1_714_566_600_123)
+        );
+        assert_eq!(
+            Timestamp::from_str("2024-05-01").unwrap(),
+            Timestamp::from_milliseconds(1_714_521_600_000)
+        );
+        assert_eq!(
+            Timestamp::from_str("2024-05-01 12:30").unwrap(),
+            Timestamp::from_milliseconds(1_714_566_600_000)
+        );
+        assert!(Timestamp::from_str("2023-02-29").is_err());
+        assert!(Timestamp::from_str("2024-05-01T24:00").is_err());
+    }
+}
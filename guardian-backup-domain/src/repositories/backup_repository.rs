@@ -1,4 +1,7 @@
 use crate::model::backup::backup::{Backup, BackupId};
+use crate::model::backup::backup_overview::BackupOverview;
+use crate::model::backup::snapshot::Snapshot;
+use crate::model::timestamp::Timestamp;
 use crate::model::user_identifier::UserIdentifier;
 
 pub trait BackupRepository {
@@ -12,6 +15,20 @@ pub trait BackupRepository {
         id: &BackupId,
         user: &UserIdentifier,
     ) -> Result<Option<Backup>, Self::Error>;
+    /// The backups of `user` without their snapshots
+    async fn get_backup_overviews(
+        &mut self,
+        user: &UserIdentifier,
+    ) -> Result<Vec<BackupOverview>, Self::Error>;
+    /// At most `limit` snapshots of the backup newer than `after`, ordered by timestamp. `None`
+    /// if the backup doesn't exist.
+    async fn get_snapshots(
+        &mut self,
+        id: &BackupId,
+        user: &UserIdentifier,
+        after: Option<Timestamp>,
+        limit: usize,
+    ) -> Result<Option<Vec<Snapshot>>, Self::Error>;
     async fn update_backup(
         &mut self,
         backup: Backup,
@@ -1,5 +1,7 @@
 use crate::model::blobs::blob_fetch::BlobFetch;
 use crate::model::blobs::blob_identifier::BlobIdentifier;
+use crate::model::timestamp::Timestamp;
+use crate::model::user_identifier::UserIdentifier;
 
 pub trait BlobRepository {
     type Error: std::error::Error + 'static;
@@ -11,4 +13,18 @@ pub trait BlobRepository {
     ) -> Result<(), Self::Error>;
     async fn delete_blob(&mut self, id: &BlobIdentifier) -> Result<(), Self::Error>;
     async fn fetch_blob(&mut self, id: &BlobIdentifier) -> Result<impl BlobFetch, Self::Error>;
+    /// The `length` bytes of the blob from `offset` on, fewer if it ends before
+    async fn fetch_blob_range(
+        &mut self,
+        id: &BlobIdentifier,
+        offset: u64,
+        length: u64,
+    ) -> Result<impl BlobFetch, Self::Error>;
+    /// Bytes the blobs of `user` take
+    async fn used_space(&mut self, user: &UserIdentifier) -> Result<u64, Self::Error>;
+    /// Blobs last inserted before `before`, inserting a stored blob again counts
+    async fn blobs_inserted_before(
+        &mut self,
+        before: Timestamp,
+    ) -> Result<Vec<BlobIdentifier>, Self::Error>;
 }
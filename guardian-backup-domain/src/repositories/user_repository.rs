@@ -1,22 +1,38 @@
+use crate::model::credential::Credential;
 use crate::model::device_identifier::DeviceIdentifier;
 use crate::model::user::User;
 use crate::model::user_identifier::UserIdentifier;
 use std::borrow::Cow;
+use std::future::Future;
 
+/// The credential lookups are `Send`, so logins can run on tasks of their own
 pub trait UserRepository {
-    type Error;
+    type Error: std::error::Error + 'static;
 
     async fn get_user(&self, identifier: &UserIdentifier) -> Result<User, Self::Error>;
+    /// The credential of the user, [None] if the user doesn't exist or can't log in
+    fn get_credential(
+        &self,
+        identifier: &UserIdentifier,
+    ) -> impl Future<Output = Result<Option<Credential>, Self::Error>> + Send;
+    /// The token credential of the device, [None] if the user has no such device
+    fn get_device_credential(
+        &self,
+        user: &UserIdentifier,
+        device: &DeviceIdentifier,
+    ) -> impl Future<Output = Result<Option<Credential>, Self::Error>> + Send;
     async fn get_user_devices(
         &self,
         user: &UserIdentifier,
     ) -> Result<Cow<[DeviceIdentifier]>, Self::Error>;
 
     async fn create_user(&mut self, user: &User) -> Result<(), Self::Error>;
+    /// Adds the device with the token it logs in with, or replaces the token of an existing one
     async fn create_user_device(
         &mut self,
         user: &UserIdentifier,
         device: &DeviceIdentifier,
+        token: Credential,
     ) -> Result<(), Self::Error>;
 
     async fn delete_user(&mut self, identifier: &UserIdentifier) -> Result<(), Self::Error>;
@@ -1,15 +1,49 @@
 use clap::{Parser, Subcommand};
 use guardian_backup_application::model::client_model::{
-    ClientBackupCommand, ClientCommand, ClientSubcommand,
+    ClientBackupCommand, ClientCommand, ClientDeviceCommand, ClientSnapshotCommand,
+    ClientSubcommand, FilePattern, FileVersionSelector,
 };
 
+use crate::output::OutputFormat;
+use guardian_backup_application::model::authentication::DeviceToken;
+use guardian_backup_application::model::certificate_fingerprint::CertificateFingerprint;
+use guardian_backup_application::model::client_config::{ClientConfig, ServerProfile};
+use guardian_backup_application::model::server_address::ServerAddress;
 use guardian_backup_domain::model::backup::backup::BackupId;
-use guardian_backup_domain::model::duration::{Duration, DurationError, MONTH};
+use guardian_backup_domain::model::device_identifier::DeviceIdentifier;
+use guardian_backup_domain::model::duration::{Duration, DurationError};
+use guardian_backup_domain::model::timestamp::Timestamp;
 use std::path::PathBuf;
 use std::str::FromStr;
 
 #[derive(Parser)]
+#[command(
+    after_help = "Exit status: 0 on success, 1 on an unexpected failure, 2 for invalid \
+arguments, 3 if a backup, snapshot, path or file version was not found, 4 if the server failed, \
+5 for errors reading or writing local files or archives"
+)]
 pub struct Cli {
+    /// Set how results are printed
+    #[arg(
+        long,
+        value_enum,
+        env = "GUARDIAN_OUTPUT",
+        global = true,
+        default_value_t
+    )]
+    pub output: OutputFormat,
+    /// Use this profile of the config instead of the default one
+    #[arg(long, env = "GUARDIAN_PROFILE", global = true)]
+    pub profile: Option<String>,
+    /// Connect to this server instead of the one of the profile
+    #[arg(long, env = "GUARDIAN_SERVER", global = true)]
+    pub server: Option<ServerAddress>,
+    /// Log in with this username instead of the one of the profile
+    #[arg(long, env = "GUARDIAN_USER", global = true)]
+    pub user: Option<String>,
+    /// Identify this device differently than configured
+    #[arg(long, env = "GUARDIAN_DEVICE", global = true)]
+    pub device: Option<DeviceIdentifier>,
     #[clap(subcommand)]
     pub entity_type: EntityType,
 }
@@ -21,22 +55,65 @@ pub enum EntityType {
     //    /// Add a server
     //    #[clap(subcommand)]
     //    Server(ServerCommands),
-    /// Add or change to a new Server
+    /// Save the server and account of a profile, and the default options of all commands
     Server {
-        /// Set URL of the backup server
+        /// Set the address of the backup server (e.g. backup.internal:8998 or [fd00::5]:8998)
+        address: ServerAddress,
+        /// Use this profile if no other is selected
         #[arg(long)]
-        url: String,
+        default: bool,
         /// Set username on the backup server
         #[arg(short, long)]
-        user_name: String,
-        /// Set user password on the backup server
-        #[arg(short, long)]
-        password: String,
+        user_name: Option<String>,
+        /// Ask for the user password on the backup server and save it. `GUARDIAN_PASSWORD` is
+        /// used instead if set, the password is never passed as an argument others could see.
+        #[arg(short = 'p', long = "password")]
+        ask_password: bool,
+        /// The password asked for, never taken from the command line
+        #[arg(skip)]
+        password: Option<String>,
+        /// Log in with this device token (gbt_…) instead of the password, see `device create-token`
+        #[arg(long)]
+        token: Option<DeviceToken>,
+        /// Connect without TLS, the password and files can be read on the network
+        #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+        insecure: Option<bool>,
+        /// Set the CA certificate (PEM) the server certificate is checked against
+        #[arg(long)]
+        ca_certificate: Option<PathBuf>,
+        /// Only accept the server certificate with this SHA-256 fingerprint, as logged by the
+        /// server or shown by `openssl x509 -noout -fingerprint -sha256`
+        #[arg(long, visible_alias = "trust-fingerprint")]
+        fingerprint: Option<CertificateFingerprint>,
+        /// Connect to the server now, show the fingerprint of the certificate it presents and pin
+        /// it once confirmed. Without a terminal on stdin it is only pinned with `--yes`.
+        #[arg(long, conflicts_with_all = ["fingerprint", "insecure"])]
+        trust_on_first_use: bool,
+        /// Pin the fingerprint of `--trust-on-first-use` without asking
+        #[arg(short, long, requires = "trust_on_first_use")]
+        yes: bool,
+        /// Set the name of this device shown in its backups
+        #[arg(long)]
+        device_id: Option<DeviceIdentifier>,
+        /// Set how long snapshots are saved if a command does not say (e.g. "3d12h")
+        #[arg(long)]
+        retention_period: Option<String>,
+        /// Set the interval between backups if a command does not say (e.g. "1d" or "infinite")
+        #[arg(long)]
+        interval: Option<String>,
     },
 
     /// Create an (automated) backup, restore from a backup
     #[clap(subcommand)]
     Backup(BackupCommand),
+
+    /// List the snapshots of a backup or show the files in one
+    #[clap(subcommand)]
+    Snapshot(SnapshotCommand),
+
+    /// Manage the devices that back up to the server
+    #[clap(subcommand)]
+    Device(DeviceCommand),
 }
 
 // In case we need more sophisticated server options
@@ -60,10 +137,10 @@ pub enum BackupCommand {
         /// Set path which will be backed up
         #[arg(short, long)]
         backup_root: PathBuf,
-        /// Set how long the backup should be saved (e.g. "3d12h"); default is ~30d
+        /// Set how long the backup should be saved (e.g. "3d12h"); default is the configured one or ~30d
         #[arg(short, long)]
         retention_period: Option<String>,
-        /// SSet the interval between backups (e.g. "3d12h"); default is Infinite
+        /// Set the interval between backups (e.g. "3d12h"); default is the configured one or infinite
         #[arg(short, long)]
         interval: Option<String>,
         /// Set a unique name for the backup to be displayed with
@@ -81,33 +158,220 @@ pub enum BackupCommand {
     },
     /// List all Backups on the server
     List {},
+    /// Stream a snapshot as a tar archive to stdout or into a file
+    Export {
+        /// Select the Backup to export from
+        #[arg(short, long)]
+        backup_id: BackupId,
+        /// Select the snapshot by its timestamp; default is the most recent one
+        #[arg(short, long)]
+        snapshot: Option<Timestamp>,
+        /// Write the archive into this file instead of stdout
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+    },
+    /// Import a tar archive from stdin or a file as a new snapshot
+    Import {
+        /// Add the snapshot to this Backup, it is created if it does not exist
+        #[arg(short, long)]
+        backup_id: BackupId,
+        /// Read the archive from this file instead of stdin
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        /// Set the snapshot timestamp; default is the newest modification time in the archive
+        #[arg(short, long)]
+        timestamp: Option<Timestamp>,
+        /// Set how long the snapshot should be saved (e.g. "3d12h"); default is the configured one or ~30d
+        #[arg(short, long)]
+        retention_period: Option<String>,
+        /// Set the path the archive is restored to; default is its top level directory
+        #[arg(long)]
+        backup_root: Option<PathBuf>,
+    },
+    /// List every distinct version of a file across all snapshots
+    History {
+        /// Select the Backup to search
+        #[arg(short, long)]
+        backup_id: BackupId,
+        /// Path of the file, relative to the backup root or absolute within it
+        #[arg(short, long)]
+        path: PathBuf,
+    },
+    /// Restore a single version of a file to a chosen location
+    RestoreFile {
+        /// Select the Backup to restore from
+        #[arg(short, long)]
+        backup_id: BackupId,
+        /// Path of the file, relative to the backup root or absolute within it
+        #[arg(short, long)]
+        path: PathBuf,
+        /// Select the version in the snapshot with this timestamp; default is the most recent one
+        #[arg(short, long, conflicts_with = "hash")]
+        snapshot: Option<Timestamp>,
+        /// Select the version whose hash starts with this prefix, as shown by `history`
+        #[arg(long)]
+        hash: Option<String>,
+        /// Write the file to this path
+        #[arg(short, long)]
+        target: PathBuf,
+    },
+    /// Search all snapshots for files matching a glob (e.g. "*.docx", "reports/**/q?.pdf")
+    Find {
+        /// Matched against the file name, or the whole path if it contains a '/'
+        pattern: String,
+        /// Treat the pattern as a regular expression searched for in the whole path
+        #[arg(long)]
+        regex: bool,
+        /// Only search this Backup; default is all of them
+        #[arg(short, long)]
+        backup_id: Option<BackupId>,
+    },
+    /// Show added, removed and modified files between two snapshots
+    Diff {
+        /// Select the Backup to compare
+        #[arg(short, long)]
+        backup_id: BackupId,
+        /// Select the older snapshot by its timestamp; default is the most recent one
+        #[arg(short, long)]
+        from: Option<Timestamp>,
+        /// Select the newer snapshot by its timestamp; default is the backup root on disk
+        #[arg(short, long)]
+        to: Option<Timestamp>,
+    },
 }
 
-impl From<Cli> for ClientCommand {
-    fn from(value: Cli) -> Self {
-        match value {
-            Cli { entity_type } => ClientCommand {
-                subcommand: entity_type.into(),
-            },
+#[derive(Subcommand)]
+pub enum SnapshotCommand {
+    /// List all snapshots of a Backup
+    List {
+        /// Select the Backup
+        backup_id: BackupId,
+    },
+    /// Show the file tree, file count, total size and expiration time of a snapshot
+    Show {
+        /// Select the Backup
+        backup_id: BackupId,
+        /// Select the snapshot by its timestamp; default is the most recent one
+        snapshot: Option<Timestamp>,
+        /// Only show directory contents up to this depth
+        #[arg(short, long)]
+        depth: Option<usize>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DeviceCommand {
+    /// Create a token that only allows a device to create and extend its own backups,
+    /// replacing its previous token; requires logging in with the password
+    CreateToken {
+        /// Select the device; default is this one
+        device: Option<DeviceIdentifier>,
+    },
+}
+
+impl Cli {
+    /// Replaces the values of the selected `profile` and `config` that were given as flags or
+    /// environment variables
+    pub fn apply_overrides(&self, profile: &mut ServerProfile, config: &mut ClientConfig) {
+        if let Some(server) = &self.server {
+            profile.server = server.to_string();
+        }
+        if let Some(user) = &self.user {
+            profile.user_name = user.clone();
         }
+        if let Some(device) = &self.device {
+            config.device_id = device.clone();
+        }
+    }
+
+    pub fn configures_server(&self) -> bool {
+        matches!(self.entity_type, EntityType::Server { .. })
+    }
+
+    /// Whether the command streams an archive to stdout, leaving no room for other output
+    pub fn writes_archive_to_stdout(&self) -> bool {
+        matches!(
+            self.entity_type,
+            EntityType::Backup(BackupCommand::Export { file: None, .. })
+        )
     }
 }
 
-impl From<EntityType> for ClientSubcommand {
-    fn from(value: EntityType) -> Self {
+impl TryFrom<Cli> for ClientCommand {
+    type Error = DurationError;
+
+    fn try_from(value: Cli) -> Result<Self, Self::Error> {
+        let mut subcommand = value.entity_type.try_into()?;
+        if let ClientSubcommand::Server { profile, .. } = &mut subcommand {
+            *profile = value.profile;
+        }
+        Ok(ClientCommand { subcommand })
+    }
+}
+
+impl TryFrom<EntityType> for ClientSubcommand {
+    type Error = DurationError;
+
+    fn try_from(value: EntityType) -> Result<Self, Self::Error> {
         match value {
             EntityType::Server {
-                url,
+                address,
+                default,
                 user_name,
                 password,
-            } => ClientSubcommand::Server {
-                url,
+                token,
+                insecure,
+                ca_certificate,
+                fingerprint,
+                trust_on_first_use: _,
+                yes: _,
+                ask_password: _,
+                device_id,
+                retention_period,
+                interval,
+            } => Ok(ClientSubcommand::Server {
+                // Set from the global flag by the conversion of [Cli]
+                profile: None,
+                make_default: default,
+                address: address.to_string(),
                 user_name,
                 password,
+                token,
+                insecure,
+                ca_certificate,
+                fingerprint,
+                device_id,
+                retention_period: parse_duration(retention_period)?,
+                interval: parse_duration(interval)?,
+            }),
+            EntityType::Backup(inner) => Ok(ClientSubcommand::Backup(inner.try_into()?)),
+            EntityType::Snapshot(inner) => Ok(ClientSubcommand::Snapshot(inner.into())),
+            EntityType::Device(inner) => Ok(ClientSubcommand::Device(inner.into())),
+        }
+    }
+}
+
+impl From<DeviceCommand> for ClientDeviceCommand {
+    fn from(value: DeviceCommand) -> Self {
+        match value {
+            DeviceCommand::CreateToken { device } => ClientDeviceCommand::CreateToken { device },
+        }
+    }
+}
+
+impl From<SnapshotCommand> for ClientSnapshotCommand {
+    fn from(value: SnapshotCommand) -> Self {
+        match value {
+            SnapshotCommand::List { backup_id } => ClientSnapshotCommand::List { id: backup_id },
+            SnapshotCommand::Show {
+                backup_id,
+                snapshot,
+                depth,
+            } => ClientSnapshotCommand::Show {
+                id: backup_id,
+                snapshot,
+                max_depth: depth,
             },
-            EntityType::Backup(inner) => {
-                ClientSubcommand::Backup(inner.try_into().expect("Failed to parse"))
-            } //TODO error handling
         }
     }
 }
@@ -131,12 +395,8 @@ impl TryFrom<BackupCommand> for ClientBackupCommand {
                 name,
             } => Ok(ClientBackupCommand::Create {
                 backup_root,
-                retention_period: retention_period
-                    .map(|e| Duration::from_str(e.as_str()))
-                    .unwrap_or(Ok(MONTH))?,
-                interval: interval
-                    .map(|e| Duration::from_str(e.as_str()))
-                    .unwrap_or(Ok(Duration::Infinite))?,
+                retention_period: parse_duration(retention_period)?,
+                interval: parse_duration(interval)?,
                 name,
             }),
             BackupCommand::Restore {
@@ -146,9 +406,73 @@ impl TryFrom<BackupCommand> for ClientBackupCommand {
                 backup_root: file_root,
                 id: backup_id,
             }),
-            BackupCommand::List { .. } => {
-                todo!()
-            }
+            BackupCommand::List { .. } => Ok(ClientBackupCommand::List {}),
+            BackupCommand::Export {
+                backup_id,
+                snapshot,
+                file,
+            } => Ok(ClientBackupCommand::Export {
+                id: backup_id,
+                snapshot,
+                target: file,
+            }),
+            BackupCommand::Import {
+                backup_id,
+                file,
+                timestamp,
+                retention_period,
+                backup_root,
+            } => Ok(ClientBackupCommand::Import {
+                id: backup_id,
+                source: file,
+                timestamp,
+                retention_period: parse_duration(retention_period)?,
+                backup_root,
+            }),
+            BackupCommand::History { backup_id, path } => Ok(ClientBackupCommand::History {
+                id: backup_id,
+                path,
+            }),
+            BackupCommand::RestoreFile {
+                backup_id,
+                path,
+                snapshot,
+                hash,
+                target,
+            } => Ok(ClientBackupCommand::RestoreFile {
+                id: backup_id,
+                path,
+                version: match (snapshot, hash) {
+                    (_, Some(hash)) => FileVersionSelector::Hash(hash),
+                    (Some(snapshot), None) => FileVersionSelector::Snapshot(snapshot),
+                    (None, None) => FileVersionSelector::Latest,
+                },
+                target,
+            }),
+            BackupCommand::Find {
+                pattern,
+                regex,
+                backup_id,
+            } => Ok(ClientBackupCommand::Find {
+                pattern: match regex {
+                    true => FilePattern::Regex(pattern),
+                    false => FilePattern::Glob(pattern),
+                },
+                id: backup_id,
+            }),
+            BackupCommand::Diff {
+                backup_id,
+                from,
+                to,
+            } => Ok(ClientBackupCommand::Diff {
+                id: backup_id,
+                from,
+                to,
+            }),
         }
     }
 }
+
+fn parse_duration(duration: Option<String>) -> Result<Option<Duration>, DurationError> {
+    duration.map(|e| Duration::from_str(e.as_str())).transpose()
+}
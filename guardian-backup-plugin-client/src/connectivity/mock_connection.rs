@@ -11,15 +11,14 @@ use std::convert::Infallible;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 
+#[derive(Clone)]
 pub struct MockConnection();
 
 impl ConnectionClientInterface for MockConnection {
     type Error = Infallible;
+    type Response = MockIncomingResponse;
 
-    async fn send_request(
-        &mut self,
-        _command: Call,
-    ) -> Result<impl IncomingResponse + 'static, Self::Error> {
+    async fn send_request(&mut self, _command: Call) -> Result<Self::Response, Self::Error> {
         Ok(MockIncomingResponse {
             response: Response::Successful,
             blob: None,
@@ -30,7 +29,7 @@ impl ConnectionClientInterface for MockConnection {
         &mut self,
         _command: &Call,
         _blob: impl BlobFetch,
-    ) -> Result<impl IncomingResponse, Self::Error> {
+    ) -> Result<Self::Response, Self::Error> {
         Ok(MockIncomingResponse {
             response: Response::Successful,
             blob: None,
@@ -46,6 +45,7 @@ pub struct MockIncomingResponse {
 
 impl IncomingResponse for MockIncomingResponse {
     type Error = IncomingMockError;
+    type Blob = InMemoryBlobFetch;
 
     fn inner(&self) -> &Response {
         &self.response
@@ -55,7 +55,7 @@ impl IncomingResponse for MockIncomingResponse {
         self.response
     }
 
-    async fn receive_blob(self) -> Result<impl BlobFetch, Self::Error> {
+    async fn receive_blob(self) -> Result<Self::Blob, Self::Error> {
         self.blob.ok_or(IncomingMockError::NoBLOB)
     }
 }
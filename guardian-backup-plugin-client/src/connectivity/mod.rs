@@ -1,3 +1,4 @@
 pub mod mock_connection;
 pub mod tcp_connection;
+pub mod tls;
 pub(crate) mod tokio_blob_fetch;
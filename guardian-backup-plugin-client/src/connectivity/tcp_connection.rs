@@ -1,116 +1,494 @@
 use crate::connectivity::tcp_connection::TcpConnectivityError::{Ciborium, TokioIO};
-use crate::connectivity::tokio_blob_fetch::TokioBlobFetch;
+use crate::connectivity::tls::presented_fingerprint;
+use argon2::{Algorithm, Argon2, Params, Version};
+use guardian_backup_application::model::authentication::{
+    login_proof, DeviceToken, LoginCall, LoginResponse,
+};
 use guardian_backup_application::model::call::Call;
+use guardian_backup_application::model::certificate_fingerprint::CertificateFingerprint;
 use guardian_backup_application::model::connection_interface::{
-    ConnectionClientInterface, IncomingResponse,
+    ConnectionClientInterface, IncomingResponse, TransientError,
+};
+use guardian_backup_application::model::frame::RequestId;
+use guardian_backup_application::model::handshake::{
+    Hello, HelloResponse, Incompatibility, Negotiated, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
 };
 use guardian_backup_application::model::response::Response;
+use guardian_backup_application::model::server_address::ServerAddress;
+use guardian_backup_application::multiplexer::{
+    Demultiplexer, FrameBlobFetch, FrameWriter, IncomingFrames,
+};
 use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
+use guardian_backup_domain::model::device_identifier::DeviceIdentifier;
+use guardian_backup_domain::model::password_hash_algorithms::PasswordHashAlg;
+use guardian_backup_domain::model::user_identifier::UserIdentifier;
+use rustls::pki_types::ServerName;
+use rustls::{CertificateError, ClientConfig};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::error::Error;
-use std::fmt::{Display, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use std::net::SocketAddr;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufStream};
-use tokio::net::TcpStream;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{
+    AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter, ReadHalf, WriteHalf,
+};
+use tokio::net::{lookup_host, TcpStream};
+use tokio::sync::Mutex;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio_rustls::TlsConnector;
+
+/// Time an attempt gets before the next address is tried in parallel (RFC 8305)
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+/// Most memory in KiB a server may make the client spend on deriving the password hash
+const MAX_PASSWORD_HASH_MEMORY: u32 = 256 * 1024;
+/// Upper bound for the hello and login answers of the server, they only carry a few names and
+/// numbers. Like the server, the client checks it before allocating anything.
+const MAX_HANDSHAKE_MESSAGE_LENGTH: u32 = 4096;
+/// Sent to the server in the [Hello] exchange
+const AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// A connection to the server, encrypted or not
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send + Debug {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + Debug> Transport for T {}
+
+/// What a [TcpConnection] logs in with
+#[derive(Debug, Clone)]
+pub enum LoginCredential {
+    /// Gives access to everything of the user
+    Password(String),
+    /// Only allows appending to the backups of `device`
+    DeviceToken {
+        device: DeviceIdentifier,
+        token: DeviceToken,
+    },
+}
 
+type TransportWriter = WriteHalf<Box<dyn Transport>>;
+
+/// A connection to the server. Clones share it, their requests are sent over the same logged in
+/// connection, which is established by the first request and again after it was lost.
+#[derive(Clone)]
 pub struct TcpConnection {
-    addr: SocketAddr,
+    addr: ServerAddress,
+    user: UserIdentifier,
+    credential: LoginCredential,
+    /// `None` connects unencrypted
+    tls: Option<TlsConnector>,
+    state: Arc<Mutex<ConnectionState>>,
 }
 
-impl TcpConnection {
-    pub fn new(addr: SocketAddr) -> Self {
-        Self { addr }
+#[derive(Default)]
+struct ConnectionState {
+    session: Option<Arc<Session>>,
+    /// Hash derived for the last challenge, so it isn't derived again for every login
+    password_hash: Option<(PasswordHashAlg, [u8; 32])>,
+}
+
+/// A logged in connection, a task reads the frames of the server and hands them to the requests
+#[derive(Debug)]
+struct Session {
+    writer: FrameWriter<TransportWriter>,
+    demultiplexer: Demultiplexer<TransportWriter>,
+    next_request: AtomicU32,
+    reader: JoinHandle<()>,
+}
+
+impl Session {
+    fn new(mut rx: BufReader<ReadHalf<Box<dyn Transport>>>, tx: TransportWriter) -> Self {
+        let writer = FrameWriter::new(tx);
+        let demultiplexer = Demultiplexer::new(writer.clone());
+        let reader = demultiplexer.clone();
+        let reader = tokio::spawn(async move {
+            // The server never starts requests, so anything returned is an error
+            let error = match reader.next_request(&mut rx).await {
+                Ok((frame, _)) => std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Unexpected message for request {}", frame.request),
+                ),
+                Err(e) => e,
+            };
+            log::debug!("Connection to the server closed: {error}");
+            reader.close();
+        });
+
+        Self {
+            writer,
+            demultiplexer,
+            next_request: AtomicU32::new(0),
+            reader,
+        }
+    }
+
+    /// Picks the id of the next request and receives the frames answering it
+    fn register(
+        &self,
+    ) -> Result<(RequestId, IncomingFrames<TransportWriter>), TcpConnectivityError> {
+        let request = self.next_request.fetch_add(1, Ordering::Relaxed);
+        Ok((request, self.demultiplexer.register(request)?))
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.reader.abort();
     }
 }
 
 impl TcpConnection {
-    async fn send_request_internal(
-        &mut self,
-        stream: &mut (impl AsyncWrite + Unpin),
-        command: &Call,
-    ) -> Result<(), <Self as ConnectionClientInterface>::Error> {
-        let mut encoded = Vec::new();
-        ciborium::into_writer(&command, &mut encoded).expect("Vec can always grow");
+    pub fn new(
+        addr: impl Into<ServerAddress>,
+        user: UserIdentifier,
+        credential: LoginCredential,
+    ) -> Self {
+        Self {
+            addr: addr.into(),
+            user,
+            credential,
+            tls: None,
+            state: Arc::default(),
+        }
+    }
 
-        stream.write_u32(encoded.len() as u32).await?;
-        stream.write_all(encoded.as_slice()).await?;
-        Ok(())
+    /// Secures the connection with TLS
+    pub fn with_tls(mut self, config: Arc<ClientConfig>) -> Self {
+        self.tls = Some(TlsConnector::from(config));
+        self
     }
 
-    async fn receive_response(
-        &mut self,
-        stream: &mut (impl AsyncRead + Unpin),
-    ) -> Result<Response, <Self as ConnectionClientInterface>::Error> {
-        let response_len = stream.read_u32().await?;
-        let mut response_buf = vec![0; response_len as usize];
-        stream.read_exact(response_buf.as_mut_slice()).await?;
-        let response = ciborium::de::from_reader(response_buf.as_slice())?;
-        Ok(response)
+    /// The logged in connection, connects if there is none yet or it was lost
+    async fn session(&self) -> Result<Arc<Session>, TcpConnectivityError> {
+        let mut state = self.state.lock().await;
+        if let Some(session) = &state.session {
+            if !session.reader.is_finished() {
+                return Ok(session.clone());
+            }
+        }
+
+        let session = Arc::new(self.connect(&mut state).await?);
+        state.session = Some(session.clone());
+        Ok(session)
     }
 
-    async fn send_blob(
-        &mut self,
-        stream: &mut (impl AsyncWrite + Unpin),
-        mut blob: impl BlobFetch,
-    ) -> Result<(), <Self as ConnectionClientInterface>::Error> {
-        stream.write_u64(blob.total_len()).await?;
-        let mut buf = [0; 4096];
-        while blob.remaining_len() > 0 {
-            let read = blob
-                .read(&mut buf)
-                .await
-                .map_err(|e| TcpConnectivityError::BlobFetch(Box::from(e)))?;
-            stream.write_all(buf.split_at(read).0).await?
+    /// Connects, runs the TLS handshake if enabled, agrees on the protocol and logs in
+    async fn connect(&self, state: &mut ConnectionState) -> Result<Session, TcpConnectivityError> {
+        let stream = connect_tcp(&self.addr).await?;
+        let stream: Box<dyn Transport> = match &self.tls {
+            Some(connector) => Box::new(
+                connector
+                    .connect(server_name(&self.addr)?, stream)
+                    .await
+                    .map_err(TcpConnectivityError::Tls)?,
+            ),
+            None => Box::new(stream),
+        };
+
+        let (rx, tx) = tokio::io::split(stream);
+        let mut rx = BufReader::new(rx);
+        let mut tx = BufWriter::new(tx);
+        let negotiated = hello(&mut rx, &mut tx).await?;
+        log::debug!(
+            "Speaking protocol version {} with the features {}",
+            negotiated.version,
+            negotiated.features
+        );
+        self.login(state, &mut rx, &mut tx).await?;
+        // The login flushed every message, nothing is left in the buffer
+        Ok(Session::new(rx, tx.into_inner()))
+    }
+
+    async fn login(
+        &self,
+        state: &mut ConnectionState,
+        rx: &mut (impl AsyncRead + Unpin),
+        tx: &mut (impl AsyncWrite + Unpin),
+    ) -> Result<(), TcpConnectivityError> {
+        let device = match &self.credential {
+            LoginCredential::Password(_) => None,
+            LoginCredential::DeviceToken { device, .. } => Some(device.clone()),
+        };
+        let request = LoginCall::Request {
+            user: self.user.clone(),
+            device,
+        };
+        send_message(tx, &request).await?;
+
+        let response = receive_message(rx).await?;
+        let proof = match (&self.credential, response) {
+            (LoginCredential::Password(_), LoginResponse::Challenge { algorithm, nonce }) => {
+                login_proof(&self.password_hash(state, algorithm)?, &nonce, &self.user)
+            }
+            (
+                LoginCredential::DeviceToken { token, .. },
+                LoginResponse::TokenChallenge { nonce },
+            ) => token.login_proof(&nonce, &self.user),
+            (_, response) => return Err(login_error(response)),
+        };
+        send_message(tx, &LoginCall::Proof(proof)).await?;
+
+        match receive_message(rx).await? {
+            LoginResponse::Accepted => Ok(()),
+            response => Err(login_error(response)),
+        }
+    }
+
+    fn password_hash(
+        &self,
+        state: &mut ConnectionState,
+        algorithm: PasswordHashAlg,
+    ) -> Result<[u8; 32], TcpConnectivityError> {
+        if let Some((cached_algorithm, hash)) = &state.password_hash {
+            if cached_algorithm == &algorithm {
+                return Ok(*hash);
+            }
         }
 
-        Ok(())
+        let PasswordHashAlg::Argon2id {
+            salt,
+            parallelism,
+            memory_cost,
+            iterations,
+            version,
+        } = &algorithm;
+        if *memory_cost > MAX_PASSWORD_HASH_MEMORY {
+            return Err(TcpConnectivityError::PasswordHash(
+                "The server asks for too much memory".into(),
+            ));
+        }
+        let params = Params::new(*memory_cost, *iterations, *parallelism, Some(32))
+            .map_err(|e| TcpConnectivityError::PasswordHash(e.to_string().into()))?;
+        let version = Version::try_from(*version)
+            .map_err(|e| TcpConnectivityError::PasswordHash(e.to_string().into()))?;
+
+        let LoginCredential::Password(password) = &self.credential else {
+            return Err(TcpConnectivityError::UnexpectedLoginResponse);
+        };
+        let mut hash = [0; 32];
+        Argon2::new(Algorithm::Argon2id, version, params)
+            .hash_password_into(password.as_bytes(), salt, &mut hash)
+            .map_err(|e| TcpConnectivityError::PasswordHash(e.to_string().into()))?;
+        state.password_hash = Some((algorithm, hash));
+        Ok(hash)
+    }
+}
+
+/// Fingerprint of the certificate the server at `addr` presents, without checking it
+pub async fn fetch_certificate_fingerprint(
+    addr: &ServerAddress,
+) -> Result<CertificateFingerprint, TcpConnectivityError> {
+    let stream = connect_tcp(addr).await?;
+    presented_fingerprint(stream, server_name(addr)?)
+        .await
+        .map_err(TcpConnectivityError::Tls)
+}
+
+/// Resolves the server address and connects to the first of its addresses that answers
+async fn connect_tcp(addr: &ServerAddress) -> Result<TcpStream, TcpConnectivityError> {
+    let addresses = lookup_host((addr.host(), addr.port())).await?;
+    let stream = connect_happy_eyeballs(interleave_families(addresses.collect())).await?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+fn server_name(addr: &ServerAddress) -> Result<ServerName<'static>, TcpConnectivityError> {
+    ServerName::try_from(addr.host().to_string()).map_err(|e| {
+        TcpConnectivityError::Tls(std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+    })
+}
+
+fn login_error(response: LoginResponse) -> TcpConnectivityError {
+    match response {
+        LoginResponse::Rejected => TcpConnectivityError::LoginRejected,
+        LoginResponse::TooManyAttempts {
+            retry_after_seconds,
+        } => TcpConnectivityError::TooManyLoginAttempts(retry_after_seconds),
+        _ => TcpConnectivityError::UnexpectedLoginResponse,
     }
 }
 
+/// Orders `addresses` alternating between IPv6 and IPv4, starting with the family of the first
+fn interleave_families(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addresses.first() else {
+        return addresses;
+    };
+    let first_is_ipv6 = first.is_ipv6();
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) = addresses
+        .into_iter()
+        .partition(|e| e.is_ipv6() == first_is_ipv6);
+
+    let mut interleaved = Vec::with_capacity(preferred.len() + other.len());
+    preferred.reverse();
+    other.reverse();
+    loop {
+        match (preferred.pop(), other.pop()) {
+            (None, None) => return interleaved,
+            (preferred, other) => interleaved.extend(preferred.into_iter().chain(other)),
+        }
+    }
+}
+
+/// Starts a connection attempt every [CONNECTION_ATTEMPT_DELAY], or as soon as the previous one
+/// failed, and returns the first established connection
+async fn connect_happy_eyeballs(addresses: Vec<SocketAddr>) -> std::io::Result<TcpStream> {
+    let mut pending = addresses.into_iter();
+    let mut attempts = JoinSet::new();
+    let mut last_error = None;
+
+    loop {
+        match pending.next() {
+            Some(address) => {
+                attempts.spawn(TcpStream::connect(address));
+            }
+            None if attempts.is_empty() => {
+                return Err(last_error.unwrap_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        "The server address did not resolve to any IP address",
+                    )
+                }))
+            }
+            None => {}
+        }
+
+        tokio::select! {
+            Some(result) = attempts.join_next() => match result {
+                Ok(Ok(stream)) => return Ok(stream),
+                Ok(Err(e)) => last_error = Some(e),
+                Err(e) => last_error = Some(std::io::Error::other(e)),
+            },
+            _ = tokio::time::sleep(CONNECTION_ATTEMPT_DELAY), if pending.len() > 0 => {}
+        }
+    }
+}
+
+/// Tells the server which protocol versions and features this client speaks, the server picks
+async fn hello(
+    rx: &mut (impl AsyncRead + Unpin),
+    tx: &mut (impl AsyncWrite + Unpin),
+) -> Result<Negotiated, TcpConnectivityError> {
+    let client = Hello::new(AGENT);
+    send_message(tx, &client).await?;
+
+    // Servers predating the hello take it for an invalid login and hang up
+    let response = receive_message(rx).await.map_err(|e| match e {
+        TokioIO(inner) if inner.kind() != std::io::ErrorKind::UnexpectedEof => TokioIO(inner),
+        _ => TcpConnectivityError::NoHello,
+    })?;
+    match response {
+        HelloResponse::Accepted {
+            version,
+            features,
+            agent,
+        } => {
+            let server = Hello {
+                min_version: version,
+                max_version: version,
+                features,
+                agent,
+            };
+            client
+                .negotiate(&server)
+                .map_err(|_| TcpConnectivityError::UnexpectedHelloResponse)
+        }
+        HelloResponse::Incompatible(reason) => Err(TcpConnectivityError::Incompatible(reason)),
+    }
+}
+
+/// Sends a login message, before the connection carries frames
+async fn send_message(
+    tx: &mut (impl AsyncWrite + Unpin),
+    message: &impl Serialize,
+) -> Result<(), TcpConnectivityError> {
+    let mut encoded = Vec::new();
+    ciborium::into_writer(message, &mut encoded).expect("Vec can always grow");
+
+    tx.write_u32(encoded.len() as u32).await?;
+    tx.write_all(encoded.as_slice()).await?;
+    tx.flush().await?;
+    Ok(())
+}
+
+async fn receive_message<T: DeserializeOwned>(
+    rx: &mut (impl AsyncRead + Unpin),
+) -> Result<T, TcpConnectivityError> {
+    let response_len = rx.read_u32().await?;
+    if response_len > MAX_HANDSHAKE_MESSAGE_LENGTH {
+        return Err(TokioIO(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("The server sent a message of {response_len} bytes"),
+        )));
+    }
+    let mut response_buf = vec![0; response_len as usize];
+    rx.read_exact(response_buf.as_mut_slice()).await?;
+    let response = ciborium::de::from_reader(response_buf.as_slice())?;
+    Ok(response)
+}
+
+fn encode(call: &Call) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    ciborium::into_writer(call, &mut encoded).expect("Vec can always grow");
+    encoded
+}
+
 impl ConnectionClientInterface for TcpConnection {
     type Error = TcpConnectivityError;
+    type Response = IncomingTcpResponse;
 
-    async fn send_request(
-        &mut self,
-        command: Call,
-    ) -> Result<impl IncomingResponse + 'static, Self::Error> {
-        let mut stream = BufStream::new(TcpStream::connect(self.addr).await?);
-
-        self.send_request_internal(&mut stream, &command).await?;
-        stream.write_u64(0).await?; // indicate zero sized blob
-        stream.flush().await?;
+    async fn send_request(&mut self, command: Call) -> Result<Self::Response, Self::Error> {
+        let session = self.session().await?;
+        let (request, frames) = session.register()?;
 
-        let response = self.receive_response(&mut stream).await?;
+        session.writer.send(request, &encode(&command)).await?;
 
-        Ok(IncomingTcpResponse { response, stream })
+        IncomingTcpResponse::receive(frames, session).await
     }
 
     async fn send_request_with_blob(
         &mut self,
         command: &Call,
         blob: impl BlobFetch,
-    ) -> Result<impl IncomingResponse, Self::Error> {
-        let mut stream = BufStream::new(TcpStream::connect(self.addr).await?);
+    ) -> Result<Self::Response, Self::Error> {
+        let session = self.session().await?;
+        let (request, frames) = session.register()?;
 
-        self.send_request_internal(&mut stream, command).await?;
-        self.send_blob(&mut stream, blob)
-            .await
-            .map_err(|e| TcpConnectivityError::BlobFetch(Box::from(e)))?;
-        stream.flush().await?;
-
-        let response = self.receive_response(&mut stream).await?;
+        session
+            .writer
+            .send_with_blob(request, &encode(command), blob)
+            .await?;
 
-        Ok(IncomingTcpResponse { response, stream })
+        IncomingTcpResponse::receive(frames, session).await
     }
 }
 
 #[derive(Debug)]
 pub struct IncomingTcpResponse {
     response: Response,
-    stream: BufStream<TcpStream>,
+    frames: IncomingFrames<TransportWriter>,
+    /// Keeps the connection open until the blob of the response was read
+    session: Arc<Session>,
+}
+
+impl IncomingTcpResponse {
+    async fn receive(
+        mut frames: IncomingFrames<TransportWriter>,
+        session: Arc<Session>,
+    ) -> Result<Self, TcpConnectivityError> {
+        let message = frames.message().await?;
+        let response = ciborium::de::from_reader(message.as_slice())?;
+        Ok(Self {
+            response,
+            frames,
+            session,
+        })
+    }
 }
 
 impl IncomingResponse for IncomingTcpResponse {
     type Error = TcpConnectivityError;
+    type Blob = SessionBlobFetch;
 
     fn inner(&self) -> &Response {
         &self.response
@@ -120,13 +498,38 @@ impl IncomingResponse for IncomingTcpResponse {
         self.response
     }
 
-    async fn receive_blob(mut self) -> Result<impl BlobFetch, Self::Error> {
-        let receive_len = self.stream.read_u64().await?;
-        if receive_len == 0 {
-            return Err(TcpConnectivityError::NoBlob);
-        }
-        let fetch = TokioBlobFetch::new(self.stream, receive_len);
-        Ok(fetch)
+    async fn receive_blob(self) -> Result<Self::Blob, Self::Error> {
+        let fetch = self
+            .frames
+            .receive_blob()
+            .await?
+            .ok_or(TcpConnectivityError::NoBlob)?;
+        Ok(SessionBlobFetch {
+            fetch,
+            _session: self.session,
+        })
+    }
+}
+
+/// A blob of a response, keeps the connection open until it was read
+pub struct SessionBlobFetch {
+    fetch: FrameBlobFetch<TransportWriter>,
+    _session: Arc<Session>,
+}
+
+impl BlobFetch for SessionBlobFetch {
+    type Error = std::io::Error;
+
+    fn remaining_len(&self) -> u64 {
+        self.fetch.remaining_len()
+    }
+
+    fn total_len(&self) -> u64 {
+        self.fetch.total_len()
+    }
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.fetch.read(buf).await
     }
 }
 
@@ -134,8 +537,25 @@ impl IncomingResponse for IncomingTcpResponse {
 pub enum TcpConnectivityError {
     TokioIO(tokio::io::Error),
     Ciborium(ciborium::de::Error<std::io::Error>),
-    BlobFetch(Box<dyn Error>),
     NoBlob,
+    NoHello,
+    UnexpectedHelloResponse,
+    Incompatible(Incompatibility),
+    LoginRejected,
+    TooManyLoginAttempts(u64),
+    UnexpectedLoginResponse,
+    PasswordHash(Box<str>),
+    Tls(std::io::Error),
+}
+
+impl TransientError for TcpConnectivityError {
+    fn is_transient(&self) -> bool {
+        match self {
+            TokioIO(inner) | TcpConnectivityError::Tls(inner) => inner.is_transient(),
+            Ciborium(ciborium::de::Error::Io(inner)) => inner.is_transient(),
+            _ => false,
+        }
+    }
 }
 
 impl From<tokio::io::Error> for TcpConnectivityError {
@@ -155,8 +575,72 @@ impl Display for TcpConnectivityError {
         match self {
             TokioIO(inner) => write!(f, "{inner}"),
             Ciborium(inner) => write!(f, "{inner}"),
-            TcpConnectivityError::BlobFetch(inner) => write!(f, "{inner}"),
             TcpConnectivityError::NoBlob => write!(f, "NoBLOB"),
+            TcpConnectivityError::NoHello => write!(
+                f,
+                "The server didn't answer the hello, it may predate the protocol versions. \
+                 Update the server"
+            ),
+            TcpConnectivityError::UnexpectedHelloResponse => {
+                write!(f, "The server answered the hello unexpectedly")
+            }
+            TcpConnectivityError::Incompatible(Incompatibility::Version {
+                min_version,
+                max_version,
+            }) => {
+                let outdated = match *max_version < MIN_PROTOCOL_VERSION {
+                    true => "server",
+                    false => "client",
+                };
+                write!(
+                    f,
+                    "The server speaks protocol versions {min_version} to {max_version}, this \
+                     client {MIN_PROTOCOL_VERSION} to {PROTOCOL_VERSION}. Update the {outdated}"
+                )
+            }
+            TcpConnectivityError::Incompatible(Incompatibility::MissingFeatures(missing)) => {
+                write!(
+                    f,
+                    "The server requires the features {missing} this client lacks. Update the \
+                     client"
+                )
+            }
+            TcpConnectivityError::LoginRejected => {
+                write!(f, "Login rejected, wrong user, password or device token")
+            }
+            TcpConnectivityError::TooManyLoginAttempts(seconds) => {
+                write!(f, "Too many failed logins, retry in {seconds} seconds")
+            }
+            TcpConnectivityError::UnexpectedLoginResponse => {
+                write!(f, "The server answered the login unexpectedly")
+            }
+            TcpConnectivityError::PasswordHash(inner) => {
+                write!(f, "Cannot derive the password hash: {inner}")
+            }
+            TcpConnectivityError::Tls(inner) => {
+                write!(f, "TLS handshake failed: {inner}")?;
+                // Self-signed certificates fail with an unknown issuer, or as CA used as end
+                // entity when created with the openssl defaults
+                let untrusted = inner
+                    .get_ref()
+                    .and_then(|e| e.downcast_ref::<rustls::Error>())
+                    .is_some_and(|e| {
+                        matches!(
+                            e,
+                            rustls::Error::InvalidCertificate(
+                                CertificateError::UnknownIssuer | CertificateError::Other(_)
+                            )
+                        )
+                    });
+                if untrusted {
+                    write!(
+                        f,
+                        ". For a self-signed certificate set its fingerprint or the CA \
+                         certificate with `server`, or pin it with `server --trust-on-first-use`"
+                    )?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -165,30 +649,191 @@ impl Error for TcpConnectivityError {}
 
 #[cfg(test)]
 mod tests {
-    use crate::connectivity::tcp_connection::TcpConnection;
+    use crate::connectivity::tcp_connection::{
+        fetch_certificate_fingerprint, interleave_families, receive_message, send_message,
+        LoginCredential, TcpConnection, TcpConnectivityError,
+    };
+    use crate::connectivity::tls::client_config;
+    use argon2::Params;
+    use guardian_backup_application::blake_hash_service::BlakeHasher;
+    use guardian_backup_application::in_memory_repositories::backup_repository::InMemoryBackupRepository;
     use guardian_backup_application::in_memory_repositories::blob_repository::InMemoryBlobFetch;
+    use guardian_backup_application::in_memory_repositories::blob_repository::InMemoryBlobRepository;
+    use guardian_backup_application::in_memory_repositories::user_repository::InMemoryUserRepository;
+    use guardian_backup_application::model::authentication::{DeviceToken, Scope};
     use guardian_backup_application::model::call::Call;
+    use guardian_backup_application::model::certificate_fingerprint::CertificateFingerprint;
+    use guardian_backup_application::model::client_config::TlsSettings;
     use guardian_backup_application::model::connection_interface::IncomingCall;
     use guardian_backup_application::model::connection_interface::IncomingResponse;
     use guardian_backup_application::model::connection_interface::UnhandledIncomingCall;
     use guardian_backup_application::model::connection_interface::{
         ConnectionClientInterface, ConnectionServerInterface,
     };
-    use guardian_backup_application::model::response::Response;
-    use guardian_backup_application::server_config::ServerConfig;
-    use guardian_backup_domain::model::backup::backup::Backup;
+    use guardian_backup_application::model::frame::{MAX_CHUNK_LENGTH, WINDOW};
+    use guardian_backup_application::model::handshake::{
+        Features, Hello, HelloResponse, Incompatibility, PROTOCOL_VERSION,
+    };
+    use guardian_backup_application::model::mocks::mock_hash_service::MOCK_HASHER;
+    use guardian_backup_application::model::response::{
+        ErrorCode, ErrorResponse, Rejection, Response,
+    };
+    use guardian_backup_application::model::server_address::ServerAddress;
+    use guardian_backup_application::remote_repositories::backup_repository::{
+        RemoteBackupRepository, RemoteBackupRepositoryError,
+    };
+    use guardian_backup_application::remote_repositories::blob_repository::{
+        RemoteBlobRepository, RemoteBlobRepositoryError,
+    };
+    use guardian_backup_application::server_config::{ServerConfig, ServerTlsConfig};
+    use guardian_backup_application::server_service::{MainServerService, ServerService};
+    use guardian_backup_domain::hash_service::{HashService, Hasher};
+    use guardian_backup_domain::model::backup::backup::{Backup, BackupId};
+    use guardian_backup_domain::model::backup::backup_overview::BackupOverview;
+    use guardian_backup_domain::model::backup::snapshot::Snapshot;
     use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
+    use guardian_backup_domain::model::blobs::blob_identifier::BlobIdentifier;
+    use guardian_backup_domain::model::device_identifier::DeviceIdentifier;
+    use guardian_backup_domain::model::files::file_hash::FileHash;
+    use guardian_backup_domain::model::timestamp::Timestamp;
+    use guardian_backup_domain::model::user_identifier::UserIdentifier;
+    use guardian_backup_domain::repositories::backup_repository::BackupRepository;
+    use guardian_backup_domain::repositories::blob_repository::BlobRepository;
+    use guardian_backup_domain::repositories::user_repository::UserRepository;
     use guardian_backup_plugin_server::connectivity::tcp_connectivity::TcpServerConnectivity;
+    use guardian_backup_plugin_server::users_file::{hash_password, parse_users, HASH_LENGTH};
+    use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa, KeyPair};
+    use std::net::SocketAddr;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::time::timeout;
+
+    fn test_users() -> InMemoryUserRepository {
+        let params = Params::new(8, 1, 1, Some(HASH_LENGTH)).unwrap();
+        let phc = hash_password("password", params).unwrap();
+        parse_users(&format!("TestUser:{phc}")).unwrap().users
+    }
+
+    fn test_connection(addr: impl Into<ServerAddress>) -> TcpConnection {
+        TcpConnection::new(
+            addr,
+            UserIdentifier::new("TestUser".into()),
+            LoginCredential::Password("password".into()),
+        )
+    }
+
+    /// Writes `content` into a temporary directory of the test using `config`
+    fn write_temp_file(config: &ServerConfig, name: &str, content: String) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "guardian-tls-{}-{}",
+            std::process::id(),
+            config.bind_to[0].port()
+        ));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    /// Lets the server present `certificate` and answer every request successfully
+    async fn serve_tls(mut config: ServerConfig, certificate: &Certificate, key: &KeyPair) {
+        config.tls = Some(ServerTlsConfig {
+            certificate_chain: write_temp_file(&config, "server.pem", certificate.pem()),
+            private_key: write_temp_file(&config, "server.key", key.serialize_pem()),
+        });
+        let mut server = TcpServerConnectivity::new(&config, test_users())
+            .await
+            .unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let mut incoming = server.receive_request().await.unwrap();
+                incoming.answer(Response::Successful).await.unwrap();
+            }
+        });
+    }
+
+    async fn send_over_tls(
+        address: ServerAddress,
+        settings: &TlsSettings,
+    ) -> Result<Response, TcpConnectivityError> {
+        let mut client = test_connection(address).with_tls(client_config(settings).unwrap());
+        Ok(client.send_request(Call::GetBackups).await?.into_inner())
+    }
+
+    #[tokio::test]
+    async fn test_tls_with_ca_certificate() {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(vec![]).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let server_key = KeyPair::generate().unwrap();
+        let certificate = CertificateParams::new(vec!["localhost".into()])
+            .unwrap()
+            .signed_by(&server_key, &ca, &ca_key)
+            .unwrap();
+
+        let server_config = ServerConfig::test_config();
+        let address = ServerAddress::new("localhost", server_config.bind_to[0].port());
+        let settings = TlsSettings {
+            ca_certificate: Some(write_temp_file(&server_config, "ca.pem", ca.pem())),
+            ..TlsSettings::default()
+        };
+        serve_tls(server_config, &certificate, &server_key).await;
+
+        let response = send_over_tls(address, &settings).await.unwrap();
+        assert_eq!(response, Response::Successful);
+    }
+
+    #[tokio::test]
+    async fn test_tls_with_pinned_certificate() {
+        let self_signed =
+            rcgen::generate_simple_self_signed(vec!["backup.internal".into()]).unwrap();
+        let server_config = ServerConfig::test_config();
+        let address = ServerAddress::from(server_config.bind_to[0]);
+        serve_tls(server_config, &self_signed.cert, &self_signed.key_pair).await;
+
+        // Unknown to the well-known roots
+        let error = send_over_tls(address.clone(), &TlsSettings::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, TcpConnectivityError::Tls(_)));
+        assert!(error.to_string().contains("--trust-on-first-use"));
+
+        let presented = fetch_certificate_fingerprint(&address).await.unwrap();
+        assert_eq!(
+            presented,
+            CertificateFingerprint::of(self_signed.cert.der())
+        );
+
+        let pinned = TlsSettings {
+            fingerprint: Some(presented),
+            ..TlsSettings::default()
+        };
+        let response = send_over_tls(address.clone(), &pinned).await.unwrap();
+        assert_eq!(response, Response::Successful);
+
+        let wrong = TlsSettings {
+            fingerprint: Some(CertificateFingerprint([0; 32])),
+            ..TlsSettings::default()
+        };
+        let error = send_over_tls(address, &wrong).await.unwrap_err();
+        assert!(matches!(error, TcpConnectivityError::Tls(_)));
+    }
 
     #[tokio::test]
     async fn test_send_request() {
         let server_config = ServerConfig::test_config();
-        let server_socket = server_config.bind_to;
+        let server_socket = server_config.bind_to[0];
         let backup = Backup::mock();
         let call = Call::CreateBackup(backup);
         let expected_call = call.clone();
 
-        let mut server = TcpServerConnectivity::new(&server_config).await.unwrap();
+        let mut server = TcpServerConnectivity::new(&server_config, test_users())
+            .await
+            .unwrap();
 
         tokio::spawn(async move {
             let mut incoming = server.receive_request().await.unwrap();
@@ -202,7 +847,7 @@ mod tests {
             incoming.answer(Response::Successful).await.unwrap();
         });
 
-        let mut client = TcpConnection::new(server_socket);
+        let mut client = test_connection(server_socket);
 
         let response = client.send_request(call).await.unwrap();
         assert_eq!(response.inner(), &Response::Successful);
@@ -216,13 +861,15 @@ mod tests {
     #[tokio::test]
     async fn test_send_request_blob() {
         let server_config = ServerConfig::test_config();
-        let server_socket = server_config.bind_to;
+        let server_socket = server_config.bind_to[0];
         let backup = Backup::mock();
         let call = Call::CreateBackup(backup);
         let expected_call = call.clone();
         let test_blob = [0xf0; 4096];
 
-        let mut server = TcpServerConnectivity::new(&server_config).await.unwrap();
+        let mut server = TcpServerConnectivity::new(&server_config, test_users())
+            .await
+            .unwrap();
 
         tokio::spawn(async move {
             let mut incoming = server.receive_request().await.unwrap();
@@ -235,7 +882,7 @@ mod tests {
             incoming.answer(Response::Successful).await.unwrap();
         });
 
-        let mut client = TcpConnection::new(server_socket);
+        let mut client = test_connection(server_socket);
 
         let response = client
             .send_request_with_blob(&call, InMemoryBlobFetch::new(test_blob.into()))
@@ -252,13 +899,15 @@ mod tests {
     #[tokio::test]
     async fn test_receive_blob() {
         let server_config = ServerConfig::test_config();
-        let server_socket = server_config.bind_to;
+        let server_socket = server_config.bind_to[0];
         let backup = Backup::mock();
         let call = Call::CreateBackup(backup);
         let expected_call = call.clone();
         let test_blob = [0xf0; 4096];
 
-        let mut server = TcpServerConnectivity::new(&server_config).await.unwrap();
+        let mut server = TcpServerConnectivity::new(&server_config, test_users())
+            .await
+            .unwrap();
 
         tokio::spawn(async move {
             let mut incoming = server.receive_request().await.unwrap();
@@ -278,7 +927,7 @@ mod tests {
                 .unwrap();
         });
 
-        let mut client = TcpConnection::new(server_socket);
+        let mut client = test_connection(server_socket);
 
         let response = client.send_request(call).await.unwrap();
         assert_eq!(response.inner(), &Response::Successful);
@@ -286,4 +935,508 @@ mod tests {
         let mut blob = response.receive_blob().await.unwrap();
         assert_eq!(blob.read_to_eof().await.unwrap().as_ref(), test_blob);
     }
+
+    #[tokio::test]
+    async fn test_clones_share_the_connection() {
+        let server_config = ServerConfig::test_config();
+        let server_socket = server_config.bind_to[0];
+        // Several windows long, so the transfers can only finish if both are in flight
+        let large_blob = vec![0x5a; 4 * 1024 * 1024];
+        let expected_blob = large_blob.clone();
+
+        let mut server = TcpServerConnectivity::new(&server_config, test_users())
+            .await
+            .unwrap();
+
+        tokio::spawn(async move {
+            let (mut upload, mut download) = (None, None);
+            for _ in 0..2 {
+                let (call, incoming) = server.receive_request().await.unwrap().into_inner();
+                match call {
+                    Call::GetBackups => download = Some(incoming),
+                    _ => upload = Some(incoming),
+                }
+            }
+            let (mut upload, mut download) = (upload.unwrap(), download.unwrap());
+
+            download
+                .answer_with_blob(
+                    Response::Successful,
+                    InMemoryBlobFetch::new(expected_blob.into()),
+                )
+                .await
+                .unwrap();
+            let mut blob = upload.receive_blob().await.unwrap();
+            assert_eq!(blob.read_to_eof().await.unwrap().len(), 4 * 1024 * 1024);
+            drop(blob);
+            upload.answer(Response::Successful).await.unwrap();
+        });
+
+        let mut uploader = test_connection(server_socket);
+        let mut downloader = uploader.clone();
+        let upload = async {
+            let blob = InMemoryBlobFetch::new(large_blob.clone().into());
+            let call = Call::CreateBackup(Backup::mock());
+            uploader
+                .send_request_with_blob(&call, blob)
+                .await
+                .unwrap()
+                .into_inner()
+        };
+        let download = async {
+            let response = downloader.send_request(Call::GetBackups).await.unwrap();
+            let mut blob = response.receive_blob().await.unwrap();
+            blob.read_to_eof().await.unwrap()
+        };
+        let (uploaded, downloaded) = tokio::join!(upload, download);
+
+        assert_eq!(uploaded, Response::Successful);
+        assert_eq!(downloaded.as_ref(), large_blob.as_slice());
+    }
+
+    #[tokio::test]
+    async fn test_connect_to_hostname() {
+        let server_config = ServerConfig::test_config();
+        let port = server_config.bind_to[0].port();
+        let mut server = TcpServerConnectivity::new(&server_config, test_users())
+            .await
+            .unwrap();
+
+        tokio::spawn(async move {
+            let mut incoming = server.receive_request().await.unwrap();
+            incoming.answer(Response::Successful).await.unwrap();
+        });
+
+        // localhost may resolve to ::1 first, where nobody listens
+        let mut client = test_connection(ServerAddress::new("localhost", port));
+        let response = client.send_request(Call::GetBackups).await.unwrap();
+        assert_eq!(response.inner(), &Response::Successful);
+    }
+
+    #[test]
+    fn test_interleave_families() {
+        let addresses = [
+            "[::1]:1",
+            "[::2]:1",
+            "[::3]:1",
+            "127.0.0.1:1",
+            "127.0.0.2:1",
+        ]
+        .map(|e| e.parse().unwrap());
+
+        let interleaved = interleave_families(addresses.to_vec());
+        let order = [0, 3, 1, 4, 2].map(|e| addresses[e]);
+        assert_eq!(interleaved, order);
+    }
+
+    #[tokio::test]
+    async fn test_wrong_password_is_rejected() {
+        let server_config = ServerConfig::test_config();
+        let server_socket = server_config.bind_to[0];
+        let mut server = TcpServerConnectivity::new(&server_config, test_users())
+            .await
+            .unwrap();
+        tokio::spawn(async move { server.receive_request().await.map(|_| ()) });
+
+        let mut client = TcpConnection::new(
+            server_socket,
+            UserIdentifier::new("TestUser".into()),
+            LoginCredential::Password("wrong".into()),
+        );
+        let result = client.send_request(Call::GetBackups).await;
+        assert!(matches!(result, Err(TcpConnectivityError::LoginRejected)));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_handshake_messages_are_refused() {
+        let mut oversized: &[u8] = &u32::MAX.to_be_bytes();
+        let error = receive_message::<HelloResponse>(&mut oversized)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, TcpConnectivityError::TokioIO(e) if e.kind() == std::io::ErrorKind::InvalidData)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_incompatible_server_is_reported() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            // A newer server, then one predating the hello that hangs up on it
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let hello: Hello = receive_message(&mut conn).await.unwrap();
+            assert_eq!(hello.features, Features::SUPPORTED);
+            let reason = Incompatibility::Version {
+                min_version: PROTOCOL_VERSION + 1,
+                max_version: PROTOCOL_VERSION + 2,
+            };
+            send_message(&mut conn, &HelloResponse::Incompatible(reason))
+                .await
+                .unwrap();
+            let (mut conn, _) = listener.accept().await.unwrap();
+            receive_message::<Hello>(&mut conn).await.unwrap();
+        });
+
+        let mut client = test_connection(address);
+        let error = client.send_request(Call::GetBackups).await.unwrap_err();
+        assert!(matches!(error, TcpConnectivityError::Incompatible(_)));
+        assert!(error.to_string().ends_with("Update the client"));
+
+        let error = client.send_request(Call::GetBackups).await.unwrap_err();
+        assert!(matches!(error, TcpConnectivityError::NoHello), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_login_with_device_token() {
+        let server_config = ServerConfig::test_config();
+        let server_socket = server_config.bind_to[0];
+        let user = UserIdentifier::new("TestUser".into());
+        let device = DeviceIdentifier::default();
+        let token = DeviceToken::generate();
+        let mut users = test_users();
+        users
+            .create_user_device(&user, &device, token.credential())
+            .await
+            .unwrap();
+        let mut server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+
+        let expected_device = device.clone();
+        tokio::spawn(async move {
+            let mut incoming = server.receive_request().await.unwrap();
+            assert_eq!(incoming.scope(), &Scope::Device(expected_device));
+            incoming.answer(Response::Successful).await.unwrap();
+        });
+
+        let credential = LoginCredential::DeviceToken { device, token };
+        let mut client = TcpConnection::new(server_socket, user, credential);
+        let response = client.send_request(Call::GetBackups).await.unwrap();
+        assert_eq!(response.inner(), &Response::Successful);
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_reach_the_remote_repositories() {
+        let mut server_config = ServerConfig::test_config();
+        server_config.limits.max_user_upload = 10;
+        let server_socket = server_config.bind_to[0];
+        let mut server = TcpServerConnectivity::new(&server_config, test_users())
+            .await
+            .unwrap();
+        let mut service = MainServerService::new(
+            InMemoryBackupRepository::new(),
+            InMemoryBlobRepository::new(),
+            test_users(),
+            HashService::new(vec![&MOCK_HASHER]),
+        )
+        .with_limits(&server_config.limits);
+        let serving = async {
+            loop {
+                let call = server.receive_request().await.unwrap();
+                service.handle_incoming_request(call).await.unwrap();
+            }
+        };
+
+        let client = async {
+            let connection = test_connection(server_socket);
+            let mut blobs = RemoteBlobRepository::new(connection.clone());
+            let mut backups = RemoteBackupRepository::new(connection);
+            let user = UserIdentifier::new("TestUser".into());
+            let id = BlobIdentifier::new(FileHash::Mock, user.clone());
+
+            let missing = blobs.fetch_blob(&id).await.err().unwrap();
+            assert!(matches!(
+                missing,
+                RemoteBlobRepositoryError::Server(ErrorResponse {
+                    code: ErrorCode::NotFound,
+                    ..
+                })
+            ));
+
+            let blob = InMemoryBlobFetch::new([1; 100].into());
+            let too_much = blobs.insert_blob(id, blob).await.unwrap_err();
+            assert!(matches!(
+                too_much,
+                RemoteBlobRepositoryError::Server(ErrorResponse {
+                    code: ErrorCode::QuotaExceeded,
+                    ..
+                })
+            ));
+
+            let unknown = backups.update_backup(Backup::mock(), &user).await;
+            assert!(matches!(
+                unknown,
+                Err(RemoteBackupRepositoryError::Server(ErrorResponse {
+                    code: ErrorCode::NotFound,
+                    ..
+                }))
+            ));
+        };
+
+        tokio::select! {
+            _ = serving => unreachable!(),
+            _ = client => {}
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backups_and_snapshots_are_fetched_separately() {
+        let server_config = ServerConfig::test_config();
+        let server_socket = server_config.bind_to[0];
+        let mut server = TcpServerConnectivity::new(&server_config, test_users())
+            .await
+            .unwrap();
+        let mut service = MainServerService::new(
+            InMemoryBackupRepository::new(),
+            InMemoryBlobRepository::new(),
+            test_users(),
+            HashService::new(vec![&MOCK_HASHER]),
+        );
+        let serving = async {
+            loop {
+                let call = server.receive_request().await.unwrap();
+                service.handle_incoming_request(call).await.unwrap();
+            }
+        };
+
+        let client = async {
+            let mut backups = RemoteBackupRepository::new(test_connection(server_socket));
+            let user = UserIdentifier::new("TestUser".into());
+            let blob = BlobIdentifier::new(FileHash::Mock, user.clone());
+            let snapshots: Vec<Snapshot> = [3, 1, 2]
+                .map(|e| Snapshot::new(Timestamp::from_milliseconds(e), None, blob.clone(), vec![]))
+                .into();
+            let mock = Backup::mock();
+            let backup = Backup::new(
+                mock.id().clone(),
+                mock.device().clone(),
+                mock.schedule().clone(),
+                mock.file_root().into(),
+                snapshots,
+            );
+            backups.create_backup(&user, backup.clone()).await.unwrap();
+
+            let overviews = backups.get_backup_overviews(&user).await.unwrap();
+            assert_eq!(overviews, vec![BackupOverview::from(&backup)]);
+            assert_eq!(overviews[0].snapshot_count(), 3);
+            assert_eq!(
+                backups.get_backup_by_id(backup.id(), &user).await.unwrap(),
+                Some(backup.clone())
+            );
+            let unknown = BackupId("Unknown".into());
+            assert_eq!(
+                backups.get_backup_by_id(&unknown, &user).await.unwrap(),
+                None
+            );
+
+            let first = backups
+                .get_snapshots(backup.id(), &user, None, 2)
+                .await
+                .unwrap()
+                .unwrap();
+            let second = backups
+                .get_snapshots(backup.id(), &user, Some(first[1].timestamp()), 2)
+                .await
+                .unwrap()
+                .unwrap();
+            let timestamps = |page: &[Snapshot]| {
+                page.iter()
+                    .map(|e| e.timestamp().milliseconds_since_epoch())
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(timestamps(&first), [1, 2]);
+            assert_eq!(timestamps(&second), [3]);
+            let missing = backups.get_snapshots(&unknown, &user, None, 2).await;
+            assert_eq!(missing.unwrap(), None);
+        };
+
+        tokio::select! {
+            _ = serving => unreachable!(),
+            _ = client => {}
+        }
+    }
+
+    #[tokio::test]
+    async fn test_blobs_not_matching_their_hash_are_rejected() {
+        let server_config = ServerConfig::test_config();
+        let server_socket = server_config.bind_to[0];
+        let mut server = TcpServerConnectivity::new(&server_config, test_users())
+            .await
+            .unwrap();
+        let mut service = MainServerService::new(
+            InMemoryBackupRepository::new(),
+            InMemoryBlobRepository::new(),
+            test_users(),
+            HashService::new(vec![&BlakeHasher()]),
+        );
+        let serving = async {
+            loop {
+                let call = server.receive_request().await.unwrap();
+                service.handle_incoming_request(call).await.unwrap();
+            }
+        };
+
+        let client = async {
+            let mut blobs = RemoteBlobRepository::new(test_connection(server_socket));
+            let user = UserIdentifier::new("TestUser".into());
+            let mut hash = BlakeHasher().create_hash();
+            hash.update(&[1; 100]);
+            let id = BlobIdentifier::new(hash.finalize(), user.clone());
+            let rejected = |error, rejection: Rejection| matches!(error, RemoteBlobRepositoryError::Server(e) if e == rejection.into());
+
+            let poisoned = InMemoryBlobFetch::new([2; 100].into());
+            let error = blobs.insert_blob(id.clone(), poisoned).await.unwrap_err();
+            assert!(rejected(error, Rejection::HashMismatch));
+            let missing = blobs.fetch_blob(&id).await.err().unwrap();
+            assert!(matches!(
+                missing,
+                RemoteBlobRepositoryError::Server(ErrorResponse {
+                    code: ErrorCode::NotFound,
+                    ..
+                })
+            ));
+
+            let mock = BlobIdentifier::new(FileHash::Mock, user.clone());
+            let blob = InMemoryBlobFetch::new([1; 100].into());
+            let error = blobs.insert_blob(mock, blob).await.unwrap_err();
+            assert!(rejected(error, Rejection::UnsupportedHash));
+
+            let blob = InMemoryBlobFetch::new([1; 100].into());
+            blobs.insert_blob(id.clone(), blob).await.unwrap();
+            let stored = blobs.fetch_blob(&id).await.unwrap().read_to_eof().await;
+            assert_eq!(stored.unwrap().as_ref(), &[1; 100]);
+        };
+
+        tokio::select! {
+            _ = serving => unreachable!(),
+            _ = client => {}
+        }
+    }
+
+    #[tokio::test]
+    async fn test_blobs_already_stored_can_be_uploaded_again() {
+        let server_config = ServerConfig::test_config();
+        let server_socket = server_config.bind_to[0];
+        let mut server = TcpServerConnectivity::new(&server_config, test_users())
+            .await
+            .unwrap();
+        let mut service = MainServerService::new(
+            InMemoryBackupRepository::new(),
+            InMemoryBlobRepository::new(),
+            test_users(),
+            HashService::new(vec![&BlakeHasher()]),
+        );
+        let serving = async {
+            loop {
+                let call = server.receive_request().await.unwrap();
+                service.handle_incoming_request(call).await.unwrap();
+            }
+        };
+
+        let client = async {
+            let mut blobs = RemoteBlobRepository::new(test_connection(server_socket));
+            // More chunks than fit in the window, the server doesn't read them the second time
+            let data = vec![3; MAX_CHUNK_LENGTH * (WINDOW as usize + 4)];
+            let mut hash = BlakeHasher().create_hash();
+            hash.update(&data);
+            let id = BlobIdentifier::new(hash.finalize(), UserIdentifier::new("TestUser".into()));
+
+            for _ in 0..2 {
+                let blob = InMemoryBlobFetch::new(data.clone().into());
+                blobs.insert_blob(id.clone(), blob).await.unwrap();
+            }
+            let stored = blobs.fetch_blob(&id).await.unwrap().read_to_eof().await;
+            assert_eq!(stored.unwrap().as_ref(), data);
+        };
+
+        tokio::select! {
+            _ = serving => unreachable!(),
+            uploaded = timeout(Duration::from_secs(10), client) => uploaded.unwrap(),
+        }
+    }
+
+    /// Forwards connections to `server`, the first one is cut after `cut_after` bytes of answers
+    async fn cutting_proxy(server: SocketAddr, cut_after: u64) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut limit = cut_after;
+            loop {
+                let (client, _) = listener.accept().await.unwrap();
+                let upstream = TcpStream::connect(server).await.unwrap();
+                let (mut client_rx, mut client_tx) = client.into_split();
+                let (server_rx, mut server_tx) = upstream.into_split();
+                let mut server_rx = server_rx.take(limit);
+                limit = u64::MAX;
+                tokio::spawn(async move {
+                    tokio::select! {
+                        _ = tokio::io::copy(&mut client_rx, &mut server_tx) => {}
+                        _ = tokio::io::copy(&mut server_rx, &mut client_tx) => {}
+                    }
+                });
+            }
+        });
+        address
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_blob_fetches_are_resumed() {
+        let server_config = ServerConfig::test_config();
+        let server_socket = server_config.bind_to[0];
+        let mut server = TcpServerConnectivity::new(&server_config, test_users())
+            .await
+            .unwrap();
+        let mut service = MainServerService::new(
+            InMemoryBackupRepository::new(),
+            InMemoryBlobRepository::new(),
+            test_users(),
+            HashService::new(vec![&BlakeHasher()]),
+        );
+        let serving = async {
+            loop {
+                let call = server.receive_request().await.unwrap();
+                // Answering the cut connection fails
+                let _ = service.handle_incoming_request(call).await;
+            }
+        };
+
+        let client = async {
+            let data: Box<[u8]> = (0..256 * 1024).map(|e| (e % 251) as u8).collect();
+            let mut hash = BlakeHasher().create_hash();
+            hash.update(&data);
+            let id = BlobIdentifier::new(hash.finalize(), UserIdentifier::new("TestUser".into()));
+            let mut direct = RemoteBlobRepository::new(test_connection(server_socket));
+            let blob = InMemoryBlobFetch::new(data.clone().into());
+            direct.insert_blob(id.clone(), blob).await.unwrap();
+
+            let proxy = cutting_proxy(server_socket, 64 * 1024).await;
+            let mut blobs = RemoteBlobRepository::new(test_connection(proxy));
+            let mut fetch = blobs.fetch_blob(&id).await.unwrap();
+            assert_eq!(fetch.total_len(), data.len() as u64);
+            assert_eq!(fetch.read_to_eof().await.unwrap(), data);
+            drop(fetch);
+
+            let mut range = blobs.fetch_blob_range(&id, 1000, 5000).await.unwrap();
+            assert_eq!(range.total_len(), 5000);
+            assert_eq!(&range.read_to_eof().await.unwrap()[..], &data[1000..6000]);
+            drop(range);
+            let mut tail = blobs.fetch_blob_range(&id, 250_000, 100_000).await.unwrap();
+            assert_eq!(&tail.read_to_eof().await.unwrap()[..], &data[250_000..]);
+            drop(tail);
+            let beyond = blobs.fetch_blob_range(&id, 300_000, 1).await.err().unwrap();
+            assert!(matches!(
+                beyond,
+                RemoteBlobRepositoryError::Server(ErrorResponse {
+                    code: ErrorCode::InvalidRequest,
+                    ..
+                })
+            ));
+        };
+
+        tokio::select! {
+            _ = serving => unreachable!(),
+            _ = client => {}
+        }
+    }
 }
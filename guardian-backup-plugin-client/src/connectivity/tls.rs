@@ -0,0 +1,173 @@
+use guardian_backup_application::model::certificate_fingerprint::CertificateFingerprint;
+use guardian_backup_application::model::client_config::TlsSettings;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::ring::default_provider;
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+/// Creates the TLS configuration of connections with `settings`
+pub fn client_config(settings: &TlsSettings) -> Result<Arc<ClientConfig>, TlsConfigError> {
+    let provider = Arc::new(default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let config = match (&settings.fingerprint, &settings.ca_certificate) {
+        (Some(fingerprint), _) => builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedCertificate::new(
+                Some(*fingerprint),
+                provider,
+            )))
+            .with_no_client_auth(),
+        (None, Some(ca_certificate)) => {
+            let mut roots = RootCertStore::empty();
+            for certificate in CertificateDer::pem_file_iter(ca_certificate)
+                .map_err(TlsConfigError::CaCertificate)?
+            {
+                roots.add(certificate.map_err(TlsConfigError::CaCertificate)?)?;
+            }
+            if roots.is_empty() {
+                return Err(TlsConfigError::CaCertificate(
+                    rustls::pki_types::pem::Error::NoItemsFound,
+                ));
+            }
+            builder.with_root_certificates(roots).with_no_client_auth()
+        }
+        (None, None) => {
+            let roots = RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            };
+            builder.with_root_certificates(roots).with_no_client_auth()
+        }
+    };
+    Ok(Arc::new(config))
+}
+
+/// Runs a TLS handshake accepting any certificate and returns the fingerprint of the one the
+/// server presented, so it can be pinned
+pub async fn presented_fingerprint(
+    stream: TcpStream,
+    server_name: ServerName<'static>,
+) -> std::io::Result<CertificateFingerprint> {
+    let provider = Arc::new(default_provider());
+    let verifier = Arc::new(PinnedCertificate::new(None, provider.clone()));
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(std::io::Error::other)?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+
+    TlsConnector::from(Arc::new(config))
+        .connect(server_name, stream)
+        .await?;
+    let presented = *verifier.presented.lock().expect("Not poisoned");
+    presented.ok_or_else(|| std::io::Error::other("The server presented no certificate"))
+}
+
+/// Accepts the server certificate with the expected fingerprint regardless of who signed it and
+/// for which name, like SSH host keys. Without an expected fingerprint any certificate is accepted.
+/// The handshake signatures are still checked, so the server has to own the certificate.
+#[derive(Debug)]
+struct PinnedCertificate {
+    expected: Option<CertificateFingerprint>,
+    presented: Mutex<Option<CertificateFingerprint>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl PinnedCertificate {
+    fn new(expected: Option<CertificateFingerprint>, provider: Arc<CryptoProvider>) -> Self {
+        Self {
+            expected,
+            presented: Mutex::new(None),
+            provider,
+        }
+    }
+}
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let presented = CertificateFingerprint::of(end_entity);
+        *self.presented.lock().expect("Not poisoned") = Some(presented);
+        match self.expected {
+            Some(expected) if expected != presented => Err(rustls::Error::General(format!(
+                "The server certificate has the fingerprint {presented} instead of the pinned \
+                 {expected}"
+            ))),
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[derive(Debug)]
+pub enum TlsConfigError {
+    CaCertificate(rustls::pki_types::pem::Error),
+    Rustls(rustls::Error),
+}
+
+impl From<rustls::Error> for TlsConfigError {
+    fn from(value: rustls::Error) -> Self {
+        TlsConfigError::Rustls(value)
+    }
+}
+
+impl Display for TlsConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsConfigError::CaCertificate(inner) => {
+                write!(f, "Cannot read the CA certificate: {inner}")
+            }
+            TlsConfigError::Rustls(inner) => write!(f, "Invalid TLS configuration: {inner}"),
+        }
+    }
+}
+
+impl Error for TlsConfigError {}
@@ -1,8 +1,7 @@
 use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
 use std::cmp::min;
-use tokio::io::{AsyncRead, AsyncReadExt};
-
-//TODO: Refactor, deduplicate with plugin server
+use std::io::SeekFrom;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
 
 pub struct TokioBlobFetch<R: AsyncRead + Unpin> {
     reader: R,
@@ -20,7 +19,7 @@ impl<R: AsyncRead + Unpin + Send> TokioBlobFetch<R> {
     }
 }
 
-impl<R: AsyncRead + Unpin + Send> BlobFetch for TokioBlobFetch<R> {
+impl<R: AsyncRead + AsyncSeek + Unpin + Send> BlobFetch for TokioBlobFetch<R> {
     type Error = tokio::io::Error;
 
     fn remaining_len(&self) -> u64 {
@@ -42,4 +41,10 @@ impl<R: AsyncRead + Unpin + Send> BlobFetch for TokioBlobFetch<R> {
         self.read += just_read as u64;
         Ok(just_read)
     }
+
+    async fn rewind(&mut self) -> Result<bool, Self::Error> {
+        self.reader.seek(SeekFrom::Start(0)).await?;
+        self.read = 0;
+        Ok(true)
+    }
 }
@@ -1,6 +1,8 @@
-pub mod blake_hash_service;
 pub mod cbor_encoder_service;
 pub mod cli;
 pub mod connectivity;
+pub mod output;
+pub mod tar_archive_service;
 pub mod tokio_file;
 pub mod tokio_file_service;
+pub mod toml_config_service;
@@ -1,32 +1,280 @@
 #![allow(async_fn_in_trait)]
 
-use crate::blake_hash_service::BlakeHasher;
 use crate::cbor_encoder_service::CborEncoderService;
-use crate::connectivity::tcp_connection::TcpConnection;
+use crate::cli::EntityType;
+use crate::connectivity::tcp_connection::{
+    fetch_certificate_fingerprint, LoginCredential, TcpConnection,
+};
+use crate::connectivity::tls;
+use crate::tar_archive_service::TarArchiveService;
 use crate::tokio_file_service::TokioFileService;
+use crate::toml_config_service::TomlConfigService;
 use clap::Parser;
-use guardian_backup_application::client_service::{ClientService, MainClientService};
+use guardian_backup_application::blake_hash_service::BlakeHasher;
+use guardian_backup_application::client_service::{
+    ClientService, MainClientService, MainClientServiceError,
+};
+use guardian_backup_application::config_service::ConfigService;
+use guardian_backup_application::model::certificate_fingerprint::CertificateFingerprint;
+use guardian_backup_application::model::client_config::ServerProfile;
+use guardian_backup_application::model::client_model::ClientCommand;
+use guardian_backup_application::model::server_address::{ServerAddress, DEFAULT_PORT};
 use guardian_backup_application::remote_repositories::backup_repository::RemoteBackupRepository;
 use guardian_backup_application::remote_repositories::blob_repository::RemoteBlobRepository;
+use guardian_backup_application::remote_repositories::user_repository::RemoteUserRepository;
+use guardian_backup_application::retrying_connection::RetryingConnection;
 use guardian_backup_domain::hash_service::HashService;
 use guardian_backup_domain::model::user_identifier::UserIdentifier;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
 
-mod blake_hash_service;
 mod cbor_encoder_service;
 mod cli;
 mod connectivity;
+mod output;
+mod tar_archive_service;
 mod tokio_file;
 mod tokio_file_service;
+mod toml_config_service;
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() {
-    let cli = cli::Cli::parse();
-    let mut client_service: MainClientService<_, _, CborEncoderService, TokioFileService> =
-        MainClientService::new(
-            UserIdentifier::new("TestUser".into()),
-            RemoteBackupRepository::new(TcpConnection::new("127.0.0.1:8998".parse().unwrap())),
-            RemoteBlobRepository::new(TcpConnection::new("127.0.0.1:8998".parse().unwrap())),
-            HashService::new(vec![&BlakeHasher()]),
+async fn main() -> ExitCode {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    let mut cli = cli::Cli::parse();
+    let output_format = cli.output;
+    let fail = |code: &str, message: String, status: u8| {
+        print(
+            std::io::stderr(),
+            &output::render_error(code, &message, output_format),
         );
-    client_service.handle_command(cli.into()).await.unwrap();
+        ExitCode::from(status)
+    };
+
+    let mut config = match TomlConfigService::load_config().await {
+        Ok(config) => config,
+        Err(e) => return fail("config_error", e.to_string(), 5),
+    };
+    let profile = config.profile(cli.profile.as_deref()).cloned();
+    let Some(mut profile) = profile.or_else(|| cli.configures_server().then(Default::default))
+    else {
+        let name = cli.profile.as_ref().unwrap_or(&config.default_profile);
+        return fail("config_error", format!("Unknown profile {name:?}"), 5);
+    };
+    cli.apply_overrides(&mut profile, &mut config);
+    let server_address = match server_address(&profile) {
+        Ok(address) => address,
+        // The server subcommand never connects and is how a broken profile gets fixed
+        Err(_) if cli.configures_server() => ServerAddress::new("127.0.0.1", DEFAULT_PORT),
+        Err((code, message, status)) => return fail(code, message, status),
+    };
+    // A device token replaces the password, which then can't leave the admin's machines
+    let credential = match (&profile.token, &profile.password) {
+        (Some(token), _) => LoginCredential::DeviceToken {
+            device: config.device_id.clone(),
+            token: token.clone(),
+        },
+        (None, Some(password)) => LoginCredential::Password(password.clone()),
+        (None, None) if cli.configures_server() => LoginCredential::Password(String::new()),
+        (None, None) => {
+            let name = cli.profile.as_ref().unwrap_or(&config.default_profile);
+            let message =
+                format!("No password or token set for profile {name:?}, set one with `server`");
+            return fail("config_error", message, 5);
+        }
+    };
+    let user = UserIdentifier::new(profile.user_name.as_str().into());
+    let tls_config = match profile.tls.insecure {
+        true => None,
+        false if cli.configures_server() => None,
+        false => match tls::client_config(&profile.tls) {
+            Ok(tls_config) => Some(tls_config),
+            Err(e) => return fail("config_error", e.to_string(), 5),
+        },
+    };
+
+    if let EntityType::Server {
+        address,
+        fingerprint,
+        trust_on_first_use: true,
+        yes,
+        ..
+    } = &mut cli.entity_type
+    {
+        if !*yes && !std::io::stdin().is_terminal() {
+            let message = "No terminal to confirm the fingerprint on, pass --yes to pin it \
+                           anyway or --fingerprint with the one the server logs";
+            return fail("not_confirmed", message.into(), 2);
+        }
+        let presented = match fetch_certificate_fingerprint(address).await {
+            Ok(presented) => presented,
+            Err(e) => return fail("connection_error", e.to_string(), 4),
+        };
+        if !confirm_fingerprint(address, &presented, *yes) {
+            return fail("not_confirmed", "The fingerprint was not pinned".into(), 2);
+        }
+        *fingerprint = Some(presented);
+    }
+
+    if let EntityType::Server {
+        password,
+        ask_password: true,
+        ..
+    } = &mut cli.entity_type
+    {
+        match read_password() {
+            Ok(read) => *password = Some(read),
+            Err(e) => return fail("invalid_argument", format!("No password read ({e})"), 2),
+        }
+    }
+
+    let archive_on_stdout = cli.writes_archive_to_stdout();
+    let command = match ClientCommand::try_from(cli) {
+        Ok(command) => command,
+        Err(e) => return fail("invalid_argument", e.to_string(), 2),
+    };
+
+    let checkpoints = checkpoint_directory(&server_address);
+    // The repositories share one connection to the server
+    let connection = TcpConnection::new(server_address, user.clone(), credential);
+    let connection = match tls_config {
+        Some(tls_config) => connection.with_tls(tls_config),
+        None => connection,
+    };
+    let connection = RetryingConnection::new(connection, config.retry.clone());
+    let mut client_service: MainClientService<
+        _,
+        _,
+        _,
+        CborEncoderService,
+        TokioFileService,
+        TarArchiveService,
+        TomlConfigService,
+    > = MainClientService::new(
+        user.clone(),
+        RemoteBackupRepository::new(connection.clone()),
+        RemoteBlobRepository::new(connection.clone()),
+        RemoteUserRepository::new(connection),
+        HashService::new(vec![&BlakeHasher()]),
+    )
+    .with_config(config);
+    if let Some(tree_cache) = tree_cache_directory() {
+        client_service = client_service.with_tree_cache(tree_cache);
+    }
+    if let Some(checkpoints) = checkpoints {
+        client_service = client_service.with_checkpoints(checkpoints);
+    }
+    match client_service.handle_command(command).await {
+        Ok(output) => {
+            let rendered = output::render(&output, output_format);
+            match archive_on_stdout {
+                true => print(std::io::stderr(), &rendered),
+                false => print(std::io::stdout(), &rendered),
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => fail(e.code(), e.to_string(), exit_code(&e)),
+    }
+}
+
+/// Address to connect to for `profile`, or the error code, message and exit status
+fn server_address(profile: &ServerProfile) -> Result<ServerAddress, (&'static str, String, u8)> {
+    profile.server.parse().map_err(|e| {
+        let message = format!("Invalid server address {:?} ({e})", profile.server);
+        ("config_error", message, 5)
+    })
+}
+
+/// Shows the fingerprint the server presents so it can be compared with the one the server logs,
+/// and asks whether to trust it unless `yes` already does
+fn confirm_fingerprint(
+    address: &ServerAddress,
+    presented: &CertificateFingerprint,
+    yes: bool,
+) -> bool {
+    let mut err = std::io::stderr();
+    print(
+        &mut err,
+        &format!("{address} presents the certificate fingerprint\n  {presented}"),
+    );
+    if yes {
+        return true;
+    }
+    let _ = write!(err, "Is it the one the server logs at startup? [y/N] ");
+    let _ = err.flush();
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// `GUARDIAN_PASSWORD` if set, otherwise asks for the password on the terminal without echoing it
+fn read_password() -> std::io::Result<String> {
+    match std::env::var("GUARDIAN_PASSWORD") {
+        Ok(password) => Ok(password),
+        Err(_) => rpassword::prompt_password("Password: "),
+    }
+}
+
+/// Unlike `println!` this does not panic if the reader went away, e.g. when piped into `head`
+fn print(mut out: impl Write, rendered: &str) {
+    let _ = writeln!(out, "{rendered}");
+}
+
+/// Exit status of a failed command, as documented in the help text of [cli::Cli]
+fn exit_code(error: &MainClientServiceError) -> u8 {
+    match error {
+        MainClientServiceError::InvalidPattern(_) | MainClientServiceError::AmbiguousVersion => 2,
+        MainClientServiceError::BackupNotFound
+        | MainClientServiceError::SnapshotNotFound
+        | MainClientServiceError::PathNotFound
+        | MainClientServiceError::VersionNotFound => 3,
+        MainClientServiceError::BackupRepositoryError(_)
+        | MainClientServiceError::BlobRepositoryError(_)
+        | MainClientServiceError::UserRepositoryError(_)
+        | MainClientServiceError::FailReceiveBlob(_)
+        | MainClientServiceError::DecodeError(_) => 4,
+        MainClientServiceError::FileServiceError(_)
+        | MainClientServiceError::ArchiveError(_)
+        | MainClientServiceError::ConfigError(_) => 5,
+        MainClientServiceError::NotImplemented => 1,
+    }
+}
+
+/// `$XDG_CACHE_HOME/guardian-backup/trees`, falling back to `~/.cache`
+fn tree_cache_directory() -> Option<PathBuf> {
+    let directory = cache_home()?.join("guardian-backup").join("trees");
+    std::fs::create_dir_all(&directory).ok()?;
+    Some(directory)
+}
+
+/// `$XDG_CACHE_HOME/guardian-backup/checkpoints/<server>`, the uploads of interrupted backups
+/// are only on that server
+fn checkpoint_directory(server: &ServerAddress) -> Option<PathBuf> {
+    let server: String = server
+        .to_string()
+        .chars()
+        .map(|e| {
+            if e.is_ascii_alphanumeric() || e == '.' {
+                e
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let directory = cache_home()?
+        .join("guardian-backup")
+        .join("checkpoints")
+        .join(server);
+    std::fs::create_dir_all(&directory).ok()?;
+    Some(directory)
+}
+
+/// `$XDG_CACHE_HOME`, falling back to `~/.cache`
+fn cache_home() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|e| PathBuf::from(e).join(".cache")))
 }
@@ -0,0 +1,625 @@
+use clap::ValueEnum;
+use guardian_backup_application::model::command_output::{
+    BackupList, CommandOutput, ConfigSummary, DeviceTokenCreated, DiffChange, DiffEntry,
+    ExportResult, FileHistory, FileRestored, FindResult, NodeKind, RestoreResult, SnapshotCreated,
+    SnapshotDetails, SnapshotDiff, SnapshotList, TreeEntry,
+};
+use guardian_backup_domain::model::duration::Duration;
+use guardian_backup_domain::model::timestamp::Timestamp;
+use serde::Serialize;
+use std::fmt::Write;
+
+#[derive(Copy, Clone, Default, Debug, ValueEnum)]
+pub enum OutputFormat {
+    /// Readable text
+    #[default]
+    Human,
+    /// One JSON document per command
+    Json,
+}
+
+/// Renders the output of a command for printing
+pub fn render(output: &CommandOutput, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_string_pretty(output).expect("Command output is always valid JSON")
+        }
+        OutputFormat::Human => match output {
+            CommandOutput::Diff(diff) => render_diff(diff),
+            CommandOutput::History(history) => render_history(history),
+            CommandOutput::Find(result) => render_find(result),
+            CommandOutput::BackupList(list) => render_backup_list(list),
+            CommandOutput::SnapshotList(list) => render_snapshot_list(list),
+            CommandOutput::SnapshotDetails(details) => render_snapshot_details(details),
+            CommandOutput::SnapshotCreated(created) => render_snapshot_created(created),
+            CommandOutput::Restored(restored) => render_restore(restored),
+            CommandOutput::FileRestored(restored) => render_file_restored(restored),
+            CommandOutput::Exported(exported) => render_export(exported),
+            CommandOutput::ConfigUpdated(config) => render_config(config),
+            CommandOutput::DeviceTokenCreated(created) => render_device_token(created),
+        },
+    }
+}
+
+/// JSON document of a failed command, tagged like [CommandOutput]
+#[derive(Serialize)]
+struct ErrorOutput<'a> {
+    r#type: &'static str,
+    code: &'a str,
+    message: &'a str,
+}
+
+/// Renders a failed command, `code` is a stable identifier like `backup_not_found`
+pub fn render_error(code: &str, message: &str, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Json => serde_json::to_string_pretty(&ErrorOutput {
+            r#type: "error",
+            code,
+            message,
+        })
+        .expect("Error output is always valid JSON"),
+        OutputFormat::Human => format!("Error: {message}"),
+    }
+}
+
+fn change_marker(change: DiffChange) -> char {
+    match change {
+        DiffChange::Added => '+',
+        DiffChange::Removed => '-',
+        DiffChange::Modified => 'M',
+        DiffChange::TypeChanged => 'T',
+        DiffChange::Moved => 'R',
+    }
+}
+
+fn render_diff(diff: &SnapshotDiff) -> String {
+    let mut rendered = String::new();
+    let to = match diff.to_snapshot {
+        Some(to) => format!("snapshot {}", Timestamp::from_milliseconds(to)),
+        None => "the files on disk".to_string(),
+    };
+    writeln!(
+        rendered,
+        "Backup {}: snapshot {} -> {to}",
+        diff.backup_id,
+        Timestamp::from_milliseconds(diff.from_snapshot)
+    )
+    .unwrap();
+
+    let path_width = diff
+        .entries
+        .iter()
+        .map(|e| e.path.len() + 1)
+        .max()
+        .unwrap_or(0);
+    for entry in &diff.entries {
+        let marker = change_marker(entry.change);
+        let is_directory = entry.new_kind.or(entry.old_kind) == Some(NodeKind::Directory);
+        let mut path = entry.path.clone();
+        if is_directory {
+            path.push('/');
+        }
+        write!(
+            rendered,
+            "{marker} {path:path_width$}  {:>11}",
+            format_size_delta(entry.size_delta())
+        )
+        .unwrap();
+        if let (Some(old_kind), Some(new_kind)) = (entry.old_kind, entry.new_kind) {
+            if old_kind != new_kind {
+                write!(rendered, "  ({old_kind:?} -> {new_kind:?})").unwrap();
+            }
+        }
+        if let Some(from) = &entry.from {
+            let slash = if is_directory { "/" } else { "" };
+            write!(rendered, "  (from {from}{slash})").unwrap();
+        }
+        rendered.push('\n');
+    }
+
+    let count = |change| diff.entries.iter().filter(|e| e.change == change).count();
+    let total: i128 = diff.entries.iter().map(DiffEntry::size_delta).sum();
+    write!(
+        rendered,
+        "{} added, {} removed, {} modified, {} type changed, {} moved ({})",
+        count(DiffChange::Added),
+        count(DiffChange::Removed),
+        count(DiffChange::Modified),
+        count(DiffChange::TypeChanged),
+        count(DiffChange::Moved),
+        format_size_delta(total)
+    )
+    .unwrap();
+    rendered
+}
+
+fn render_snapshot_created(created: &SnapshotCreated) -> String {
+    let action = match created.new_backup {
+        true => "Created backup",
+        false => "Added snapshot to backup",
+    };
+    format!(
+        "{action} {}: snapshot {}, {} file(s), {}, expires {}",
+        created.backup_id,
+        Timestamp::from_milliseconds(created.summary.timestamp),
+        created.file_count,
+        format_size(u128::from(created.total_size)),
+        format_expiration(created.summary.expiration_time)
+    )
+}
+
+fn render_restore(restored: &RestoreResult) -> String {
+    let mut rendered = String::new();
+    for entry in &restored.changes {
+        write!(rendered, "{} {}", change_marker(entry.change), entry.path).unwrap();
+        if let Some(from) = &entry.from {
+            write!(rendered, "  (from {from})").unwrap();
+        }
+        rendered.push('\n');
+    }
+    write!(
+        rendered,
+        "Restored snapshot {} of backup {} into {} ({} change(s))",
+        Timestamp::from_milliseconds(restored.snapshot),
+        restored.backup_id,
+        restored.target,
+        restored.changes.len()
+    )
+    .unwrap();
+    rendered
+}
+
+fn render_file_restored(restored: &FileRestored) -> String {
+    format!(
+        "Restored {} ({}, {}) from backup {} to {}",
+        restored.path,
+        &restored.hash[..restored.hash.len().min(SHORT_HASH_LEN)],
+        format_size(u128::from(restored.size)),
+        restored.backup_id,
+        restored.target
+    )
+}
+
+fn render_export(exported: &ExportResult) -> String {
+    format!(
+        "Exported snapshot {} of backup {} to {}: {} file(s), {}",
+        Timestamp::from_milliseconds(exported.snapshot),
+        exported.backup_id,
+        exported.target.as_deref().unwrap_or("stdout"),
+        exported.file_count,
+        format_size(u128::from(exported.total_size))
+    )
+}
+
+fn render_device_token(created: &DeviceTokenCreated) -> String {
+    format!(
+        "Token of device {}, it is only shown once:\n{}\n\
+         Use it on the device with `server <ADDRESS> --token <TOKEN>`",
+        created.device, created.token
+    )
+}
+
+fn render_config(config: &ConfigSummary) -> String {
+    let format_default = |milliseconds: Option<u64>, unset: &str| match milliseconds {
+        Some(milliseconds) => Duration::Limited { milliseconds }.to_string(),
+        None => unset.to_string(),
+    };
+    let default = if config.is_default { " (default)" } else { "" };
+    let tls = match (config.insecure, &config.fingerprint, &config.ca_certificate) {
+        (true, _, _) => "disabled, the connection is not encrypted".to_string(),
+        (false, Some(fingerprint), _) => format!("pinned to {fingerprint}"),
+        (false, None, Some(ca_certificate)) => format!("CA {ca_certificate}"),
+        (false, None, None) => "well-known roots".to_string(),
+    };
+    format!(
+        "Saved profile {}{default} of {}\n\
+         Server:           {}\n\
+         User:             {}\n\
+         Password:         {}\n\
+         Device token:     {}\n\
+         TLS:              {tls}\n\
+         Device:           {}\n\
+         Retention period: {}\n\
+         Interval:         {}",
+        config.profile,
+        config.profiles.join(", "),
+        config.server,
+        config.user_name,
+        if config.password_set {
+            "set"
+        } else {
+            "not set"
+        },
+        if config.token_set { "set" } else { "not set" },
+        config.device_id,
+        format_default(config.default_retention_period, "30d"),
+        format_default(config.default_interval, "infinite")
+    )
+}
+
+/// Hex characters of a hash shown in tables, enough to select a version with `--hash`
+const SHORT_HASH_LEN: usize = 12;
+
+fn render_history(history: &FileHistory) -> String {
+    let rows = history.versions.iter().map(|version| {
+        vec![
+            version.hash[..version.hash.len().min(SHORT_HASH_LEN)].to_string(),
+            format_size(u128::from(version.size)),
+            Timestamp::from_milliseconds(version.last_modified).to_string(),
+            Timestamp::from_milliseconds(version.first_snapshot).to_string(),
+            Timestamp::from_milliseconds(version.last_snapshot).to_string(),
+        ]
+    });
+
+    format!(
+        "{} version(s) of {} in backup {}\n{}",
+        history.versions.len(),
+        history.path,
+        history.backup_id,
+        render_table(
+            &[
+                "HASH",
+                "SIZE",
+                "MODIFIED",
+                "FIRST SNAPSHOT",
+                "LAST SNAPSHOT"
+            ],
+            rows
+        )
+    )
+}
+
+fn render_backup_list(list: &BackupList) -> String {
+    let format_timestamp = |timestamp: Option<u64>| match timestamp {
+        Some(timestamp) => Timestamp::from_milliseconds(timestamp).to_string(),
+        None => "-".to_string(),
+    };
+    let format_duration = |milliseconds: Option<u64>| match milliseconds {
+        Some(milliseconds) => Duration::Limited { milliseconds }.to_string(),
+        None => Duration::Infinite.to_string(),
+    };
+
+    let rows = list.backups.iter().map(|backup| {
+        let schedule: Vec<String> = backup
+            .schedule
+            .iter()
+            .map(|e| {
+                format!(
+                    "every {} keep {}",
+                    format_duration(e.interval),
+                    format_duration(e.snapshot_lifetime)
+                )
+            })
+            .collect();
+        vec![
+            backup.id.to_string(),
+            backup.device.clone(),
+            backup.file_root.clone(),
+            backup.snapshot_count.to_string(),
+            format_timestamp(backup.oldest_snapshot),
+            format_timestamp(backup.newest_snapshot),
+            backup.next_expiry.map_or("never".to_string(), |e| {
+                Timestamp::from_milliseconds(e).to_string()
+            }),
+            match schedule.is_empty() {
+                true => "manual".to_string(),
+                false => schedule.join(", "),
+            },
+        ]
+    });
+
+    render_table(
+        &[
+            "ID",
+            "DEVICE",
+            "FILE ROOT",
+            "SNAPSHOTS",
+            "OLDEST",
+            "NEWEST",
+            "NEXT EXPIRY",
+            "SCHEDULE",
+        ],
+        rows,
+    )
+}
+
+fn format_expiration(expiration_time: Option<u64>) -> String {
+    match expiration_time {
+        Some(expiration_time) => Timestamp::from_milliseconds(expiration_time).to_string(),
+        None => "never".to_string(),
+    }
+}
+
+fn render_snapshot_list(list: &SnapshotList) -> String {
+    let rows = list.snapshots.iter().map(|snapshot| {
+        vec![
+            Timestamp::from_milliseconds(snapshot.timestamp).to_string(),
+            format_expiration(snapshot.expiration_time),
+            snapshot.blob_count.to_string(),
+        ]
+    });
+
+    format!(
+        "{} snapshot(s) of backup {}\n{}",
+        list.snapshots.len(),
+        list.backup_id,
+        render_table(&["SNAPSHOT", "EXPIRES", "BLOBS"], rows)
+    )
+}
+
+fn render_snapshot_details(details: &SnapshotDetails) -> String {
+    let mut rendered = String::new();
+    writeln!(
+        rendered,
+        "Backup {}, snapshot {}\n\
+         Expires:     {}\n\
+         Files:       {}\n\
+         Directories: {}\n\
+         Total size:  {}\n\
+         Blobs:       {}\n",
+        details.backup_id,
+        Timestamp::from_milliseconds(details.summary.timestamp),
+        format_expiration(details.summary.expiration_time),
+        details.file_count,
+        details.directory_count,
+        format_size(u128::from(details.total_size)),
+        details.summary.blob_count
+    )
+    .unwrap();
+    render_tree_entry(&mut rendered, &details.tree, "", "");
+    rendered.truncate(rendered.trim_end().len());
+    rendered
+}
+
+/// Draws `entry` and its children with box drawing characters, `prefix` continues the parent lines
+fn render_tree_entry(rendered: &mut String, entry: &TreeEntry, marker: &str, prefix: &str) {
+    let slash = if entry.kind == NodeKind::Directory {
+        "/"
+    } else {
+        ""
+    };
+    writeln!(
+        rendered,
+        "{marker}{}{slash}  ({})",
+        entry.name,
+        format_size(u128::from(entry.size))
+    )
+    .unwrap();
+
+    for (index, child) in entry.children.iter().enumerate() {
+        let is_last = index + 1 == entry.children.len() && entry.omitted_children == 0;
+        let (marker, indent) = match is_last {
+            true => ("└── ", "    "),
+            false => ("├── ", "│   "),
+        };
+        render_tree_entry(
+            rendered,
+            child,
+            &format!("{prefix}{marker}"),
+            &format!("{prefix}{indent}"),
+        );
+    }
+    if entry.omitted_children > 0 {
+        writeln!(rendered, "{prefix}└── … {} more", entry.omitted_children).unwrap();
+    }
+}
+
+/// Left aligns every column to its widest cell
+fn render_table(headers: &[&str], rows: impl IntoIterator<Item = Vec<String>>) -> String {
+    let rows: Vec<Vec<String>> = rows.into_iter().collect();
+    let widths: Vec<usize> = (0..headers.len())
+        .map(|column| {
+            rows.iter()
+                .map(|e| e[column].chars().count())
+                .chain([headers[column].len()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut rendered = String::new();
+    let header_row = headers.iter().map(|e| e.to_string()).collect();
+    for row in [header_row].iter().chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+        writeln!(rendered, "{}", line.join("  ").trim_end()).unwrap();
+    }
+    rendered.truncate(rendered.trim_end().len());
+    rendered
+}
+
+fn render_find(result: &FindResult) -> String {
+    let mut rendered = format!(
+        "{} path(s) match {}\n",
+        result.matches.len(),
+        result.pattern
+    );
+    for found in &result.matches {
+        let slash = if found.kind == NodeKind::Directory {
+            "/"
+        } else {
+            ""
+        };
+        let (Some(first), Some(last)) = (found.snapshots.first(), found.snapshots.last()) else {
+            continue;
+        };
+        writeln!(
+            rendered,
+            "{}: {}{slash}  {}  in {} snapshot(s) from {} to {}",
+            found.backup_id,
+            found.path,
+            format_size(u128::from(last.size)),
+            found.snapshots.len(),
+            Timestamp::from_milliseconds(first.timestamp),
+            Timestamp::from_milliseconds(last.timestamp)
+        )
+        .unwrap();
+    }
+    rendered.truncate(rendered.trim_end().len());
+    rendered
+}
+
+/// Formats a size difference with a sign and binary unit, e.g. `+1.5 KiB`
+fn format_size_delta(delta: i128) -> String {
+    let sign = if delta < 0 { '-' } else { '+' };
+    format!("{sign}{}", format_size(delta.unsigned_abs()))
+}
+
+/// Formats a size with a binary unit, e.g. `1.5 KiB`
+fn format_size(size: u128) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{size} {}", UNITS[unit])
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::{format_size_delta, render, render_error, OutputFormat};
+    use guardian_backup_application::model::command_output::{
+        CommandOutput, DiffChange, DiffEntry, NodeKind, SnapshotDetails, SnapshotDiff,
+        SnapshotSummary, TreeEntry,
+    };
+    use guardian_backup_domain::model::backup::backup::BackupId;
+
+    #[test]
+    fn test_format_size_delta() {
+        assert_eq!(format_size_delta(0), "+0 B");
+        assert_eq!(format_size_delta(-512), "-512 B");
+        assert_eq!(format_size_delta(1536), "+1.5 KiB");
+        assert_eq!(format_size_delta(3 * 1024 * 1024 * 1024), "+3.0 GiB");
+    }
+
+    #[test]
+    fn test_render_diff() {
+        let output = CommandOutput::Diff(SnapshotDiff {
+            backup_id: BackupId("docs".into()),
+            from_snapshot: 0,
+            to_snapshot: None,
+            entries: vec![DiffEntry {
+                path: "notes".into(),
+                change: DiffChange::TypeChanged,
+                from: None,
+                old_kind: Some(NodeKind::File),
+                new_kind: Some(NodeKind::Directory),
+                old_size: Some(2048),
+                new_size: Some(1024),
+                old_hash: Some("ab".into()),
+                new_hash: None,
+            }],
+        });
+
+        assert_eq!(
+            render(&output, OutputFormat::Human),
+            "Backup docs: snapshot 1970-01-01T00:00:00.000Z -> the files on disk\n\
+             T notes/     -1.0 KiB  (File -> Directory)\n\
+             0 added, 0 removed, 0 modified, 1 type changed, 0 moved (-1.0 KiB)"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&output, OutputFormat::Json)).unwrap();
+        assert_eq!(json["type"], "diff");
+        assert_eq!(json["entries"][0]["change"], "type_changed");
+        assert_eq!(json["entries"][0]["old_hash"], "ab");
+        assert!(json["entries"][0].get("new_hash").is_none());
+    }
+
+    #[test]
+    fn test_render_snapshot_details() {
+        let entry =
+            |name: &str, kind, size, children: Vec<TreeEntry>, omitted_children| TreeEntry {
+                name: name.into(),
+                kind,
+                size,
+                children,
+                omitted_children,
+            };
+        let output = CommandOutput::SnapshotDetails(SnapshotDetails {
+            backup_id: BackupId("docs".into()),
+            summary: SnapshotSummary {
+                timestamp: 0,
+                expiration_time: None,
+                blob_count: 3,
+            },
+            file_count: 3,
+            directory_count: 3,
+            total_size: 2048,
+            tree: entry(
+                "docs",
+                NodeKind::Directory,
+                2048,
+                vec![
+                    entry(
+                        "reports",
+                        NodeKind::Directory,
+                        1024,
+                        vec![entry("q1.pdf", NodeKind::File, 1024, vec![], 0)],
+                        0,
+                    ),
+                    entry("todo", NodeKind::Directory, 1024, vec![], 2),
+                ],
+                0,
+            ),
+        });
+
+        assert_eq!(
+            render(&output, OutputFormat::Human),
+            "Backup docs, snapshot 1970-01-01T00:00:00.000Z\n\
+             Expires:     never\n\
+             Files:       3\n\
+             Directories: 3\n\
+             Total size:  2.0 KiB\n\
+             Blobs:       3\n\
+             \n\
+             docs/  (2.0 KiB)\n\
+             ├── reports/  (1.0 KiB)\n\
+             │   └── q1.pdf  (1.0 KiB)\n\
+             └── todo/  (1.0 KiB)\n\
+             \x20   └── … 2 more"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&output, OutputFormat::Json)).unwrap();
+        assert_eq!(json["type"], "snapshot_details");
+        assert_eq!(json["expiration_time"], serde_json::Value::Null);
+        assert_eq!(json["tree"]["children"][1]["omitted_children"], 2);
+        assert!(json["tree"]["children"][0]
+            .get("omitted_children")
+            .is_none());
+    }
+
+    #[test]
+    fn test_render_error() {
+        assert_eq!(
+            render_error(
+                "backup_not_found",
+                "BackupID not found",
+                OutputFormat::Human
+            ),
+            "Error: BackupID not found"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&render_error(
+            "backup_not_found",
+            "BackupID not found",
+            OutputFormat::Json,
+        ))
+        .unwrap();
+        assert_eq!(json["type"], "error");
+        assert_eq!(json["code"], "backup_not_found");
+        assert_eq!(json["message"], "BackupID not found");
+    }
+}
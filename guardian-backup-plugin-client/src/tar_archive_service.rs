@@ -0,0 +1,471 @@
+use crate::tar_archive_service::TarArchiveError::BlobRead;
+use guardian_backup_application::archive_service::{
+    ArchiveEntry, ArchiveEntryKind, ArchiveReader, ArchiveService, ArchiveWriter,
+};
+use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
+use guardian_backup_domain::model::files::directory_metadata::DirectoryMetadata;
+use guardian_backup_domain::model::files::file_metadata::FileMetadata;
+use std::cmp::min;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio_stream::StreamExt;
+use tokio_tar::{Archive, Entries, Entry, EntryType, Header};
+
+const BLOCK_SIZE: u64 = 512;
+/// Largest size the octal ustar size field can hold
+const USTAR_MAX_SIZE: u64 = 0o77777777777;
+const DEFAULT_FILE_MODE: u32 = 0o644;
+const DEFAULT_DIRECTORY_MODE: u32 = 0o755;
+
+pub struct TarArchiveService {}
+
+impl ArchiveService for TarArchiveService {
+    type Writer = TarArchiveWriter<Box<dyn AsyncWrite + Unpin + Send>>;
+    type Reader = TarArchiveReader<Box<dyn AsyncRead + Unpin + Send>>;
+    type Error = TarArchiveError;
+
+    async fn create_writer(target: Option<&Path>) -> Result<Self::Writer, Self::Error> {
+        let output: Box<dyn AsyncWrite + Unpin + Send> = match target {
+            Some(path) => Box::new(tokio::fs::File::create(path).await?),
+            None => Box::new(tokio::io::stdout()),
+        };
+        Ok(TarArchiveWriter::new(output))
+    }
+
+    async fn open_reader(source: Option<&Path>) -> Result<Self::Reader, Self::Error> {
+        let input: Box<dyn AsyncRead + Unpin + Send> = match source {
+            Some(path) => Box::new(tokio::fs::File::open(path).await?),
+            None => Box::new(tokio::io::stdin()),
+        };
+        TarArchiveReader::new(input)
+    }
+}
+
+/// Streams entries into a POSIX (pax) tar archive without buffering whole files
+pub struct TarArchiveWriter<W: AsyncWrite + Unpin> {
+    output: BufWriter<W>,
+}
+
+impl<W: AsyncWrite + Unpin> TarArchiveWriter<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output: BufWriter::new(output),
+        }
+    }
+
+    async fn write_header(
+        &mut self,
+        path: &Path,
+        entry_type: EntryType,
+        size: u64,
+        last_modified: u64,
+        mode: u32,
+    ) -> Result<(), TarArchiveError> {
+        let mut header = Header::new_ustar();
+        header.set_entry_type(entry_type);
+        header.set_size(size);
+        header.set_mtime(last_modified / 1000);
+        header.set_mode(mode);
+        header.set_uid(0);
+        header.set_gid(0);
+
+        let mut pax_records = Vec::new();
+        if header.set_path(path).is_err() {
+            let path_bytes = path.as_os_str().as_encoded_bytes();
+            push_pax_record(&mut pax_records, "path", path_bytes);
+
+            let name = &mut header.as_old_mut().name;
+            let truncated_len = min(path_bytes.len(), name.len());
+            name[..truncated_len].copy_from_slice(&path_bytes[..truncated_len]);
+        }
+        if !last_modified.is_multiple_of(1000) {
+            let mtime = format!("{}.{:03}", last_modified / 1000, last_modified % 1000);
+            push_pax_record(&mut pax_records, "mtime", mtime.as_bytes());
+        }
+        if size > USTAR_MAX_SIZE {
+            push_pax_record(&mut pax_records, "size", size.to_string().as_bytes());
+        }
+
+        if !pax_records.is_empty() {
+            let mut pax_header = Header::new_ustar();
+            pax_header.set_entry_type(EntryType::XHeader);
+            pax_header.set_path("PaxHeader")?;
+            pax_header.set_size(pax_records.len() as u64);
+            pax_header.set_mtime(last_modified / 1000);
+            pax_header.set_mode(DEFAULT_FILE_MODE);
+            pax_header.set_cksum();
+
+            self.output.write_all(pax_header.as_bytes()).await?;
+            self.output.write_all(pax_records.as_slice()).await?;
+            self.write_padding(pax_records.len() as u64).await?;
+        }
+
+        header.set_cksum();
+        self.output.write_all(header.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn write_padding(&mut self, written: u64) -> Result<(), TarArchiveError> {
+        let remainder = written % BLOCK_SIZE;
+        if remainder != 0 {
+            let padding = [0; BLOCK_SIZE as usize];
+            self.output
+                .write_all(&padding[..(BLOCK_SIZE - remainder) as usize])
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: AsyncWrite + Unpin> ArchiveWriter for TarArchiveWriter<W> {
+    type Error = TarArchiveError;
+
+    async fn append_directory(
+        &mut self,
+        path: &Path,
+        metadata: &DirectoryMetadata,
+    ) -> Result<(), Self::Error> {
+        self.write_header(
+            path,
+            EntryType::Directory,
+            0,
+            0,
+            metadata.permissions().unwrap_or(DEFAULT_DIRECTORY_MODE),
+        )
+        .await
+    }
+
+    async fn append_file(
+        &mut self,
+        path: &Path,
+        metadata: &FileMetadata,
+        mut blob: impl BlobFetch,
+    ) -> Result<(), Self::Error> {
+        let size = blob.total_len();
+        self.write_header(
+            path,
+            EntryType::Regular,
+            size,
+            metadata.last_modified(),
+            metadata.permissions().unwrap_or(DEFAULT_FILE_MODE),
+        )
+        .await?;
+
+        let mut chunk = [0; 4096];
+        let mut written = 0;
+        loop {
+            let read = blob
+                .read(&mut chunk)
+                .await
+                .map_err(|e| BlobRead(e.into()))?;
+            if read == 0 {
+                break;
+            }
+
+            self.output.write_all(&chunk[..read]).await?;
+            written += read as u64;
+        }
+
+        if written != size {
+            return Err(TarArchiveError::SizeMismatch {
+                expected: size,
+                actual: written,
+            });
+        }
+
+        self.write_padding(written).await
+    }
+
+    async fn finish(mut self) -> Result<(), Self::Error> {
+        // An archive ends with two zero filled blocks
+        self.output.write_all(&[0; 2 * BLOCK_SIZE as usize]).await?;
+        self.output.flush().await?;
+        self.output.shutdown().await?;
+        Ok(())
+    }
+}
+
+/// Reads directories and regular files of a tar archive, honoring pax and GNU extensions
+pub struct TarArchiveReader<R: AsyncRead + Unpin> {
+    entries: Entries<R>,
+}
+
+impl<R: AsyncRead + Unpin> TarArchiveReader<R> {
+    pub fn new(input: R) -> Result<Self, TarArchiveError> {
+        Ok(Self {
+            entries: Archive::new(input).entries()?,
+        })
+    }
+}
+
+impl<R: AsyncRead + Unpin + Send> ArchiveReader for TarArchiveReader<R> {
+    type Error = TarArchiveError;
+    type Blob = TarEntryBlobFetch<R>;
+
+    async fn next_entry(&mut self) -> Result<Option<ArchiveEntry<Self::Blob>>, Self::Error> {
+        while let Some(entry) = self.entries.next().await {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            let entry_type = entry.header().entry_type();
+            if !entry_type.is_dir() && !entry_type.is_file() {
+                log::warn!("Skipping unsupported archive entry {}", path.display());
+                continue;
+            }
+
+            let permissions = entry.header().mode().ok();
+            let pax_mtime = match entry.pax_extensions().await? {
+                Some(extensions) => extensions
+                    .filter_map(Result::ok)
+                    .find(|e| e.key() == Ok("mtime"))
+                    .and_then(|e| e.value().ok().and_then(parse_pax_mtime)),
+                None => None,
+            };
+            let last_modified = match pax_mtime {
+                Some(last_modified) => last_modified,
+                None => entry.header().mtime()? * 1000,
+            };
+
+            let kind = if entry_type.is_dir() {
+                ArchiveEntryKind::Directory {
+                    metadata: DirectoryMetadata { permissions },
+                }
+            } else {
+                let file_size = entry.header().entry_size()?;
+                ArchiveEntryKind::File {
+                    metadata: FileMetadata {
+                        file_size,
+                        last_modified,
+                        permissions,
+                    },
+                    content: TarEntryBlobFetch {
+                        entry,
+                        total_size: file_size,
+                        read: 0,
+                    },
+                }
+            };
+            return Ok(Some(ArchiveEntry { path, kind }));
+        }
+        Ok(None)
+    }
+}
+
+/// The content of a file entry, read straight from the archive
+pub struct TarEntryBlobFetch<R: AsyncRead + Unpin> {
+    entry: Entry<Archive<R>>,
+    total_size: u64,
+    read: u64,
+}
+
+impl<R: AsyncRead + Unpin + Send> BlobFetch for TarEntryBlobFetch<R> {
+    type Error = std::io::Error;
+
+    fn remaining_len(&self) -> u64 {
+        self.total_size - self.read
+    }
+
+    fn total_len(&self) -> u64 {
+        self.total_size
+    }
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let just_read = self.entry.read(buf).await?;
+        self.read += just_read as u64;
+        Ok(just_read)
+    }
+}
+
+/// Parses a pax `mtime` value (`"<seconds>[.<fraction>]"`) into milliseconds
+fn parse_pax_mtime(value: &str) -> Option<u64> {
+    let (seconds, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let millis = format!("{:0<3}", &fraction[..min(fraction.len(), 3)]);
+    Some(seconds.parse::<u64>().ok()? * 1000 + millis.parse::<u64>().ok()?)
+}
+
+/// Appends a record in the `"<length> <key>=<value>\n"` format, where the length includes itself
+fn push_pax_record(records: &mut Vec<u8>, key: &str, value: &[u8]) {
+    let content_len = key.len() + value.len() + 3; // ' ', '=' and '\n'
+    let mut total_len = content_len + 1;
+    while total_len != content_len + total_len.to_string().len() {
+        total_len = content_len + total_len.to_string().len();
+    }
+
+    records.extend_from_slice(format!("{total_len} {key}=").as_bytes());
+    records.extend_from_slice(value);
+    records.push(b'\n');
+}
+
+#[derive(Debug)]
+pub enum TarArchiveError {
+    Io(std::io::Error),
+    BlobRead(Box<dyn Error>),
+    SizeMismatch { expected: u64, actual: u64 },
+}
+
+impl Display for TarArchiveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TarArchiveError::Io(inner) => write!(f, "Io({inner})"),
+            BlobRead(inner) => write!(f, "BlobRead({inner})"),
+            TarArchiveError::SizeMismatch { expected, actual } => {
+                write!(f, "SizeMismatch(expected {expected} bytes, got {actual})")
+            }
+        }
+    }
+}
+
+impl Error for TarArchiveError {}
+
+impl From<std::io::Error> for TarArchiveError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tar_archive_service::{
+        parse_pax_mtime, push_pax_record, TarArchiveReader, TarArchiveWriter,
+    };
+    use guardian_backup_application::archive_service::{
+        ArchiveEntryKind, ArchiveReader, ArchiveWriter,
+    };
+    use guardian_backup_application::in_memory_repositories::blob_repository::InMemoryBlobFetch;
+    use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
+    use guardian_backup_domain::model::files::directory_metadata::DirectoryMetadata;
+    use guardian_backup_domain::model::files::file_metadata::FileMetadata;
+    use std::path::Path;
+    use tokio::io::AsyncReadExt;
+    use tokio_stream::StreamExt;
+    use tokio_tar::{Archive, EntryType};
+
+    #[test]
+    fn test_pax_record_length_includes_itself() {
+        let mut records = Vec::new();
+        push_pax_record(&mut records, "mtime", b"1700000000.123");
+
+        assert_eq!(records.as_slice(), b"24 mtime=1700000000.123\n");
+        assert_eq!(records.len(), 24);
+    }
+
+    #[test]
+    fn test_parse_pax_mtime() {
+        assert_eq!(parse_pax_mtime("1700000000"), Some(1_700_000_000_000));
+        assert_eq!(parse_pax_mtime("1700000000.5"), Some(1_700_000_000_500));
+        assert_eq!(
+            parse_pax_mtime("1700000000.123456789"),
+            Some(1_700_000_000_123)
+        );
+        assert_eq!(parse_pax_mtime("abc"), None);
+    }
+
+    #[tokio::test]
+    async fn test_written_archive_is_readable() {
+        let long_name = "a".repeat(150);
+        let mut archive_data = Vec::new();
+        let mut writer = TarArchiveWriter::new(&mut archive_data);
+
+        writer
+            .append_directory(
+                Path::new("root"),
+                &DirectoryMetadata {
+                    permissions: Some(0o700),
+                },
+            )
+            .await
+            .unwrap();
+        writer
+            .append_file(
+                Path::new("root").join(&long_name).as_path(),
+                &FileMetadata {
+                    file_size: 1000,
+                    last_modified: 1_700_000_000_123,
+                    permissions: None,
+                },
+                InMemoryBlobFetch::new([0xab; 1000].into()),
+            )
+            .await
+            .unwrap();
+        writer.finish().await.unwrap();
+
+        let mut archive = Archive::new(archive_data.as_slice());
+        let mut entries = archive.entries().unwrap();
+
+        let directory = entries.next().await.unwrap().unwrap();
+        assert_eq!(directory.header().entry_type(), EntryType::Directory);
+        assert_eq!(directory.path().unwrap().as_ref(), Path::new("root"));
+        assert_eq!(directory.header().mode().unwrap(), 0o700);
+        drop(directory);
+
+        let mut file = entries.next().await.unwrap().unwrap();
+        assert_eq!(
+            file.path().unwrap().as_ref(),
+            Path::new("root").join(&long_name)
+        );
+        assert_eq!(file.header().mtime().unwrap(), 1_700_000_000);
+        assert_eq!(file.header().mode().unwrap(), 0o644);
+        let mtime = file
+            .pax_extensions()
+            .await
+            .unwrap()
+            .unwrap()
+            .filter_map(Result::ok)
+            .find(|e| e.key() == Ok("mtime"))
+            .map(|e| e.value().unwrap().to_string());
+        assert_eq!(mtime.as_deref(), Some("1700000000.123"));
+
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).await.unwrap();
+        assert_eq!(content, [0xab; 1000]);
+        drop(file);
+
+        assert!(entries.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reader_reads_written_archive() {
+        let mut archive_data = Vec::new();
+        let mut writer = TarArchiveWriter::new(&mut archive_data);
+        writer
+            .append_directory(Path::new("root"), &DirectoryMetadata::default())
+            .await
+            .unwrap();
+        writer
+            .append_file(
+                Path::new("root/file.txt"),
+                &FileMetadata {
+                    file_size: 3,
+                    last_modified: 1_700_000_000_123,
+                    permissions: Some(0o600),
+                },
+                InMemoryBlobFetch::new([1, 2, 3].into()),
+            )
+            .await
+            .unwrap();
+        writer.finish().await.unwrap();
+
+        let mut reader = TarArchiveReader::new(archive_data.as_slice()).unwrap();
+
+        let directory = reader.next_entry().await.unwrap().unwrap();
+        assert_eq!(directory.path, Path::new("root"));
+        assert!(matches!(directory.kind, ArchiveEntryKind::Directory { .. }));
+
+        let file = reader.next_entry().await.unwrap().unwrap();
+        assert_eq!(file.path, Path::new("root/file.txt"));
+        let ArchiveEntryKind::File {
+            metadata,
+            mut content,
+        } = file.kind
+        else {
+            panic!("Expected a file entry");
+        };
+        assert_eq!(metadata.last_modified(), 1_700_000_000_123);
+        assert_eq!(metadata.permissions(), Some(0o600));
+        assert_eq!(content.total_len(), 3);
+        assert_eq!(content.read_to_eof().await.unwrap().as_ref(), [1, 2, 3]);
+
+        assert!(reader.next_entry().await.unwrap().is_none());
+    }
+}
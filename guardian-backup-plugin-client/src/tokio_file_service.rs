@@ -6,15 +6,21 @@ use guardian_backup_domain::hash_service::{Hasher, PendingHashB, PendingHashExt}
 use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
 use guardian_backup_domain::model::blobs::blob_identifier::BlobIdentifier;
 use guardian_backup_domain::model::files::directory_metadata::DirectoryMetadata;
+use guardian_backup_domain::model::files::file_hash::FileHash;
 use guardian_backup_domain::model::files::file_metadata::FileMetadata;
 use guardian_backup_domain::model::files::file_tree::FileTreeNode;
 use guardian_backup_domain::model::user_identifier::UserIdentifier;
 use std::error::Error;
+use std::ffi::OsString;
 use std::fmt::{Display, Formatter};
+use std::io::SeekFrom;
 use std::ops::Add;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, UNIX_EPOCH};
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+/// Ends the names of files being written, they take the place of the file once complete
+const PARTIAL_SUFFIX: &str = ".guardian-partial";
 
 pub struct TokioFileService {}
 
@@ -50,6 +56,7 @@ impl FileService for TokioFileService {
                         .duration_since(UNIX_EPOCH)
                         .unwrap()
                         .as_millis() as u64,
+                    permissions: permissions(&metadata),
                 },
             });
         } else if metadata.is_dir() {
@@ -57,6 +64,9 @@ impl FileService for TokioFileService {
 
             let mut dir = tokio::fs::read_dir(path).await?;
             while let Some(child) = dir.next_entry().await? {
+                if is_partial_file(&child.path()) {
+                    continue;
+                }
                 children.push(
                     Box::pin(Self::generate_file_tree(
                         child.path().as_path(),
@@ -69,7 +79,9 @@ impl FileService for TokioFileService {
 
             return Ok(FileTreeNode::Directory {
                 name: path.file_name().unwrap().into(),
-                metadata: DirectoryMetadata {},
+                metadata: DirectoryMetadata {
+                    permissions: permissions(&metadata),
+                },
                 children,
             });
         }
@@ -78,7 +90,7 @@ impl FileService for TokioFileService {
     }
 
     async fn delete_file(path: &Path) -> Result<(), Self::Error> {
-        println!("Delete file {}", path.as_os_str().to_str().unwrap());
+        log::info!("Delete file {}", path.display());
 
         #[cfg(feature = "dry-run")]
         return Ok(());
@@ -87,7 +99,7 @@ impl FileService for TokioFileService {
     }
 
     async fn delete_dir_all(path: &Path) -> Result<(), Self::Error> {
-        println!("Delete dir {}", path.as_os_str().to_str().unwrap());
+        log::info!("Delete dir {}", path.display());
 
         #[cfg(feature = "dry-run")]
         return Ok(());
@@ -98,57 +110,158 @@ impl FileService for TokioFileService {
     async fn write_file(
         path: &Path,
         file_meta: &FileMetadata,
-        mut blob: impl BlobFetch,
+        blob: impl BlobFetch,
     ) -> Result<(), Self::Error> {
-        println!("write file {}", path.as_os_str().to_str().unwrap());
+        log::info!("Write file {}", path.display());
 
         #[cfg(feature = "dry-run")]
         return Ok(());
 
-        let mut file = tokio::fs::File::options()
+        let version = format!("{}-{}", file_meta.file_size, file_meta.last_modified);
+        let partial = partial_path(path, &version);
+        let file = tokio::fs::File::options()
             .write(true)
             .create(true)
             .truncate(true)
-            .open(path)
+            .open(&partial)
             .await?;
+        finish_file(path, &partial, file_meta, file, blob).await
+    }
 
-        let mut chunk = [0; 4096];
-
-        loop {
-            let read = blob
-                .read(&mut chunk)
-                .await
-                .map_err(|e| BlobRead(e.into()))?;
-            if read == 0 {
-                break;
-            }
+    async fn partial_length(path: &Path, version: &FileHash) -> Result<u64, Self::Error> {
+        match tokio::fs::metadata(partial_path(path, &version_name(version))).await {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
 
-            file.write_all(&chunk[..read]).await?;
+    async fn continue_file(
+        path: &Path,
+        file_meta: &FileMetadata,
+        version: &FileHash,
+        offset: u64,
+        blob: impl BlobFetch,
+    ) -> Result<(), Self::Error> {
+        match offset {
+            0 => log::info!("Write file {}", path.display()),
+            _ => log::info!("Continue file {} at byte {offset}", path.display()),
         }
 
-        let file_meta = file_meta.clone();
-        let file = file.into_std().await;
-        tokio::task::spawn_blocking(move || {
-            file.set_times(
-                std::fs::FileTimes::new()
-                    .set_modified(UNIX_EPOCH.add(Duration::from_millis(file_meta.last_modified))),
-            )?;
-            Ok::<(), std::io::Error>(())
-        })
-        .await
-        .unwrap()?;
-
-        Ok(())
+        #[cfg(feature = "dry-run")]
+        return Ok(());
+
+        let partial = partial_path(path, &version_name(version));
+        let mut file = tokio::fs::File::options()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&partial)
+            .await?;
+        file.set_len(offset).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        finish_file(path, &partial, file_meta, file, blob).await
     }
 
     async fn create_dir(path: &Path) -> Result<(), Self::Error> {
-        println!("create dir {}", path.as_os_str().to_str().unwrap());
+        log::info!("Create dir {}", path.display());
 
         #[cfg(feature = "dry-run")]
         return Ok(());
 
         Ok(tokio::fs::create_dir(path).await?)
     }
+
+    async fn create_private_dir(path: &Path) -> Result<(), Self::Error> {
+        log::info!("Create private dir {}", path.display());
+
+        #[cfg(feature = "dry-run")]
+        return Ok(());
+
+        let mut builder = tokio::fs::DirBuilder::new();
+        #[cfg(unix)]
+        builder.mode(0o700);
+        Ok(builder.create(path).await?)
+    }
+
+    async fn rename(from: &Path, to: &Path) -> Result<(), Self::Error> {
+        log::info!("Rename {} to {}", from.display(), to.display());
+
+        #[cfg(feature = "dry-run")]
+        return Ok(());
+
+        Ok(tokio::fs::rename(from, to).await?)
+    }
+}
+
+/// Hidden file next to `path`, named after the version so another version is never continued
+fn partial_path(path: &Path, version: &str) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{version}{PARTIAL_SUFFIX}"));
+    path.with_file_name(name)
+}
+
+/// The first 128 bits of the hash, enough to tell the versions of a file apart without making
+/// long file names too long
+fn version_name(version: &FileHash) -> String {
+    let mut name = version.to_string();
+    name.truncate(32);
+    name
+}
+
+fn is_partial_file(path: &Path) -> bool {
+    path.file_name()
+        .map(|e| e.to_string_lossy())
+        .is_some_and(|e| e.starts_with('.') && e.ends_with(PARTIAL_SUFFIX))
+}
+
+/// Writes the rest of the blob to the partial file and moves it to `path`
+async fn finish_file(
+    path: &Path,
+    partial: &Path,
+    file_meta: &FileMetadata,
+    mut file: tokio::fs::File,
+    mut blob: impl BlobFetch,
+) -> Result<(), TokioFileServiceError> {
+    let mut chunk = [0; 4096];
+
+    loop {
+        let read = blob
+            .read(&mut chunk)
+            .await
+            .map_err(|e| BlobRead(e.into()))?;
+        if read == 0 {
+            break;
+        }
+
+        file.write_all(&chunk[..read]).await?;
+    }
+
+    let file_meta = file_meta.clone();
+    let file = file.into_std().await;
+    tokio::task::spawn_blocking(move || {
+        file.set_times(
+            std::fs::FileTimes::new()
+                .set_modified(UNIX_EPOCH.add(Duration::from_millis(file_meta.last_modified))),
+        )?;
+        Ok::<(), std::io::Error>(())
+    })
+    .await
+    .unwrap()?;
+
+    Ok(tokio::fs::rename(partial, path).await?)
+}
+
+#[cfg(unix)]
+fn permissions(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn permissions(_metadata: &std::fs::Metadata) -> Option<u32> {
+    None
 }
 
 #[derive(Debug)]
@@ -0,0 +1,170 @@
+use guardian_backup_application::config_service::ConfigService;
+use guardian_backup_application::model::client_config::ClientConfig;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+
+pub struct TomlConfigService {}
+
+impl TomlConfigService {
+    /// `$GUARDIAN_CONFIG`, or `$XDG_CONFIG_HOME/guardian-backup/config.toml` falling back to `~/.config`
+    pub fn config_path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os("GUARDIAN_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|e| PathBuf::from(e).join(".config")))?;
+        Some(config_home.join("guardian-backup").join("config.toml"))
+    }
+}
+
+impl ConfigService for TomlConfigService {
+    type Error = TomlConfigServiceError;
+
+    async fn load_config() -> Result<ClientConfig, Self::Error> {
+        let Some(path) = Self::config_path() else {
+            return Ok(ClientConfig::default());
+        };
+        match tokio::fs::read_to_string(&path).await {
+            Ok(content) => Ok(toml::from_str(&content)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(ClientConfig::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save_config(config: &ClientConfig) -> Result<(), Self::Error> {
+        let path = Self::config_path().ok_or(TomlConfigServiceError::NoConfigDirectory)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // The file may contain the password. The mode only applies to new files, so files
+        // created otherwise are restricted before anything is written to them.
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&path).await?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))
+                .await?;
+        }
+        file.write_all(toml::to_string(config)?.as_bytes()).await?;
+        Ok(file.flush().await?)
+    }
+}
+
+#[derive(Debug)]
+pub enum TomlConfigServiceError {
+    Tokio(tokio::io::Error),
+    Deserialize(toml::de::Error),
+    Serialize(toml::ser::Error),
+    NoConfigDirectory,
+}
+
+impl Display for TomlConfigServiceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TomlConfigServiceError::Tokio(inner) => write!(f, "Tokio({inner})"),
+            TomlConfigServiceError::Deserialize(inner) => {
+                write!(f, "Invalid config file ({inner})")
+            }
+            TomlConfigServiceError::Serialize(inner) => write!(f, "Serialize({inner})"),
+            TomlConfigServiceError::NoConfigDirectory => {
+                write!(
+                    f,
+                    "Neither GUARDIAN_CONFIG, XDG_CONFIG_HOME nor HOME is set"
+                )
+            }
+        }
+    }
+}
+
+impl Error for TomlConfigServiceError {}
+
+impl From<tokio::io::Error> for TomlConfigServiceError {
+    fn from(value: tokio::io::Error) -> Self {
+        Self::Tokio(value)
+    }
+}
+
+impl From<toml::de::Error> for TomlConfigServiceError {
+    fn from(value: toml::de::Error) -> Self {
+        Self::Deserialize(value)
+    }
+}
+
+impl From<toml::ser::Error> for TomlConfigServiceError {
+    fn from(value: toml::ser::Error) -> Self {
+        Self::Serialize(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use guardian_backup_application::model::client_config::{ClientConfig, ServerProfile};
+    use guardian_backup_domain::model::duration::Duration;
+
+    #[test]
+    fn test_config_without_profiles_is_the_default_profile() {
+        let config: ClientConfig = toml::from_str(
+            r#"
+            server = "10.0.0.2:8998"
+            device_id = "laptop"
+
+            [defaults]
+            retention_period = "7d"
+            "#,
+        )
+        .unwrap();
+
+        let profile = config.profile(None).unwrap();
+        assert_eq!(profile.server, "10.0.0.2:8998");
+        assert_eq!(profile.user_name, ServerProfile::default().user_name);
+        assert_eq!(config.device_id.to_string(), "laptop");
+        assert_eq!(
+            config.defaults.retention_period,
+            Some(Duration::Limited {
+                milliseconds: 7 * 24 * 3600 * 1000
+            })
+        );
+        assert_eq!(config.defaults.interval, None);
+
+        let written = toml::to_string(&config).unwrap();
+        assert!(written.contains("[profiles.default]"));
+        assert!(written.contains("retention_period = \"7d\""));
+        assert!(!written.contains("password"));
+        assert_eq!(toml::from_str::<ClientConfig>(&written).unwrap(), config);
+    }
+
+    #[test]
+    fn test_profiles_select_default() {
+        let config: ClientConfig = toml::from_str(
+            r#"
+            [profiles.office]
+            server = "10.0.0.2:8998"
+            tls = { insecure = true }
+
+            [profiles.home]
+            server = "192.168.1.5:8998"
+            tls = { enabled = true, ca_certificate = "/etc/guardian/nas.pem" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.profiles.len(), 2);
+        assert_eq!(config.default_profile, "home");
+        assert!(!config.profile(None).unwrap().tls.insecure);
+        assert!(config.profile(Some("office")).unwrap().tls.insecure);
+        assert_eq!(
+            config.profile(Some("office")).unwrap().server,
+            "10.0.0.2:8998"
+        );
+        assert!(config.profile(Some("cloud")).is_none());
+    }
+}
@@ -0,0 +1,341 @@
+use crate::connectivity::tcp_connectivity::TcpConnectivityError;
+use argon2::Version;
+use guardian_backup_application::model::authentication::{
+    verify_login_proof, LoginCall, LoginResponse, Scope, NONCE_LENGTH,
+};
+use guardian_backup_application::model::handshake::{Hello, HelloResponse, Negotiated};
+use guardian_backup_application::server_config::PasswordHashing;
+use guardian_backup_domain::model::credential::Credential;
+use guardian_backup_domain::model::password_hash_algorithms::PasswordHashAlg;
+use guardian_backup_domain::model::user_identifier::UserIdentifier;
+use guardian_backup_domain::repositories::user_repository::UserRepository;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Failed logins from one address before it is locked out
+const MAX_FAILED_LOGINS: u32 = 5;
+/// Failed logins of one user from any address before the user is locked out. Higher than the
+/// limit of an address, as anyone can make them.
+const MAX_FAILED_USER_LOGINS: u32 = 20;
+/// How long an address or user is locked out after too many failed logins
+const LOCKOUT: Duration = Duration::from_secs(60);
+/// Failed logins of an address or user are forgotten after this long without another one
+const FAILED_LOGIN_MEMORY: Duration = Duration::from_secs(15 * 60);
+/// Addresses and users whose failed logins are remembered, each. The ones failing longest ago
+/// are forgotten first.
+const MAX_TRACKED: usize = 10_000;
+/// Upper bound for a login message, they only carry a user name or a proof
+const MAX_LOGIN_MESSAGE_LENGTH: u32 = 4096;
+
+/// Sent to clients in the [Hello] exchange
+const AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+struct FailedLogins {
+    count: u32,
+    last_failed: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl FailedLogins {
+    fn is_expired(&self, now: Instant) -> bool {
+        let forgotten_at = self.last_failed + FAILED_LOGIN_MEMORY;
+        forgotten_at <= now && self.locked_until.map_or(true, |until| until <= now)
+    }
+}
+
+/// Failed logins by address or by user, locking out whoever fails `max_failed_logins` times
+struct LoginFailures<K> {
+    max_failed_logins: u32,
+    failed: HashMap<K, FailedLogins>,
+}
+
+impl<K: Hash + Eq + Clone> LoginFailures<K> {
+    fn new(max_failed_logins: u32) -> Self {
+        Self {
+            max_failed_logins,
+            failed: HashMap::new(),
+        }
+    }
+
+    fn lockout_remaining(&mut self, key: &K) -> Option<Duration> {
+        let failed = self.failed.get(key)?;
+        let remaining = failed.locked_until?.checked_duration_since(Instant::now());
+        if remaining.is_none() {
+            self.failed.remove(key);
+        }
+        remaining
+    }
+
+    fn record(&mut self, key: &K) {
+        let now = Instant::now();
+        if self.failed.len() >= MAX_TRACKED && !self.failed.contains_key(key) {
+            self.failed.retain(|_, failed| !failed.is_expired(now));
+            if self.failed.len() >= MAX_TRACKED {
+                let oldest = self
+                    .failed
+                    .iter()
+                    .min_by_key(|(_, failed)| failed.last_failed)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    self.failed.remove(&oldest);
+                }
+            }
+        }
+
+        let failed = self.failed.entry(key.clone()).or_insert(FailedLogins {
+            count: 0,
+            last_failed: now,
+            locked_until: None,
+        });
+        if failed.is_expired(now) {
+            failed.count = 0;
+        }
+        failed.last_failed = now;
+        failed.count += 1;
+        if failed.count >= self.max_failed_logins {
+            failed.count = 0;
+            failed.locked_until = Some(now + LOCKOUT);
+        }
+    }
+
+    fn forget(&mut self, key: &K) {
+        self.failed.remove(key);
+    }
+}
+
+/// Failed logins of the addresses and of the users, one lock guards both
+struct FailedLoginTracking {
+    addresses: LoginFailures<IpAddr>,
+    users: LoginFailures<UserIdentifier>,
+}
+
+/// Runs the login handshake of new connections against the credentials of a [UserRepository]
+/// and locks out addresses and users with too many failed logins. Logins of several connections can run
+/// at once.
+pub struct Authenticator<U: UserRepository> {
+    users: U,
+    /// Derives stable fake salts for unknown users, so they can't be told apart from known ones
+    secret: [u8; 32],
+    /// Parameters of the challenges for unknown users
+    password_hashing: PasswordHashing,
+    failed_logins: Mutex<FailedLoginTracking>,
+}
+
+impl<U: UserRepository> Authenticator<U> {
+    pub fn new(users: U, password_hashing: PasswordHashing) -> Self {
+        Self {
+            users,
+            secret: random(),
+            password_hashing,
+            failed_logins: Mutex::new(FailedLoginTracking {
+                addresses: LoginFailures::new(MAX_FAILED_LOGINS),
+                users: LoginFailures::new(MAX_FAILED_USER_LOGINS),
+            }),
+        }
+    }
+
+    /// Returns the user the client at `client` proved to be and what it may do
+    pub async fn authenticate(
+        &self,
+        rx: &mut (impl AsyncRead + Unpin),
+        tx: &mut (impl AsyncWrite + Unpin),
+        client: IpAddr,
+    ) -> Result<(UserIdentifier, Scope), TcpConnectivityError> {
+        let LoginCall::Request { user, device } = read_message(rx).await? else {
+            return Err(TcpConnectivityError::InvalidLogin);
+        };
+
+        if let Some(retry_after) = self.lockout_remaining(client, &user) {
+            let retry_after_seconds = retry_after.as_secs() + 1;
+            write_message(
+                tx,
+                &LoginResponse::TooManyAttempts {
+                    retry_after_seconds,
+                },
+            )
+            .await?;
+            return Err(TcpConnectivityError::TooManyLoginAttempts);
+        }
+        // Counted as failed until it succeeds, so logins running at once can't exceed the limit
+        self.record_failed_login(client, &user);
+
+        let nonce: [u8; NONCE_LENGTH] = random();
+        let stored_key = match &device {
+            None => {
+                let credential = self
+                    .users
+                    .get_credential(&user)
+                    .await
+                    .map_err(|e| TcpConnectivityError::UserRepository(e.to_string().into()))?;
+                let (algorithm, stored_key) = match credential {
+                    Some(Credential::Password {
+                        algorithm,
+                        stored_key,
+                    }) => (algorithm, Some(stored_key)),
+                    _ => (self.decoy_algorithm(&user), None),
+                };
+                write_message(tx, &LoginResponse::Challenge { algorithm, nonce }).await?;
+                stored_key
+            }
+            Some(device) => {
+                let credential = self
+                    .users
+                    .get_device_credential(&user, device)
+                    .await
+                    .map_err(|e| TcpConnectivityError::UserRepository(e.to_string().into()))?;
+                // Unknown devices get a challenge as well, so they can't be told apart
+                write_message(tx, &LoginResponse::TokenChallenge { nonce }).await?;
+                match credential {
+                    Some(Credential::DeviceToken { stored_key }) => Some(stored_key),
+                    _ => None,
+                }
+            }
+        };
+
+        let LoginCall::Proof(proof) = read_message(rx).await? else {
+            return Err(TcpConnectivityError::InvalidLogin);
+        };
+        let accepted = stored_key
+            .is_some_and(|stored_key| verify_login_proof(&stored_key, &nonce, &user, &proof));
+
+        if accepted {
+            self.forget_failed_logins(client, &user);
+            write_message(tx, &LoginResponse::Accepted).await?;
+            let scope = match device {
+                None => Scope::Full,
+                Some(device) => Scope::Device(device),
+            };
+            Ok((user, scope))
+        } else {
+            write_message(tx, &LoginResponse::Rejected).await?;
+            Err(TcpConnectivityError::LoginRejected(user))
+        }
+    }
+
+    fn failed_logins(&self) -> MutexGuard<'_, FailedLoginTracking> {
+        self.failed_logins.lock().expect("Not poisoned")
+    }
+
+    /// The longer lockout of the address and the user, if any
+    fn lockout_remaining(&self, client: IpAddr, user: &UserIdentifier) -> Option<Duration> {
+        let mut failed_logins = self.failed_logins();
+        let address = failed_logins.addresses.lockout_remaining(&client);
+        let user = failed_logins.users.lockout_remaining(user);
+        address.max(user)
+    }
+
+    fn record_failed_login(&self, client: IpAddr, user: &UserIdentifier) {
+        let mut failed_logins = self.failed_logins();
+        failed_logins.addresses.record(&client);
+        failed_logins.users.record(user);
+    }
+
+    fn forget_failed_logins(&self, client: IpAddr, user: &UserIdentifier) {
+        let mut failed_logins = self.failed_logins();
+        failed_logins.addresses.forget(&client);
+        failed_logins.users.forget(user);
+    }
+
+    fn decoy_algorithm(&self, user: &UserIdentifier) -> PasswordHashAlg {
+        let salt = blake3::keyed_hash(&self.secret, user.to_string().as_bytes());
+        PasswordHashAlg::Argon2id {
+            salt: salt.as_bytes()[..16].into(),
+            parallelism: self.password_hashing.parallelism,
+            memory_cost: self.password_hashing.memory_cost,
+            iterations: self.password_hashing.iterations,
+            version: Version::V0x13.into(),
+        }
+    }
+}
+
+/// Answers the [Hello] a new connection starts with and returns the one of the client. Clients
+/// without a protocol version and the required features in common are told so and refused.
+pub async fn hello(
+    rx: &mut (impl AsyncRead + Unpin),
+    tx: &mut (impl AsyncWrite + Unpin),
+) -> Result<(Hello, Negotiated), TcpConnectivityError> {
+    let client: Hello = read_message(rx).await.map_err(|e| match e {
+        TcpConnectivityError::Io(inner) => TcpConnectivityError::Io(inner),
+        _ => TcpConnectivityError::InvalidHello,
+    })?;
+
+    let server = Hello::new(AGENT);
+    match server.negotiate(&client) {
+        Ok(negotiated) => {
+            let response = HelloResponse::Accepted {
+                version: negotiated.version,
+                features: negotiated.features,
+                agent: server.agent,
+            };
+            write_message(tx, &response).await?;
+            Ok((client, negotiated))
+        }
+        Err(incompatibility) => {
+            write_message(tx, &HelloResponse::Incompatible(incompatibility)).await?;
+            Err(TcpConnectivityError::IncompatibleClient(client))
+        }
+    }
+}
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    getrandom::getrandom(&mut bytes).expect("The OS provides randomness");
+    bytes
+}
+
+async fn read_message<T: DeserializeOwned>(
+    rx: &mut (impl AsyncRead + Unpin),
+) -> Result<T, TcpConnectivityError> {
+    let length = rx.read_u32().await?;
+    if length > MAX_LOGIN_MESSAGE_LENGTH {
+        return Err(TcpConnectivityError::InvalidLogin);
+    }
+    let mut data = vec![0; length as usize];
+    rx.read_exact(data.as_mut_slice()).await?;
+    Ok(ciborium::from_reader(data.as_slice())?)
+}
+
+async fn write_message(
+    tx: &mut (impl AsyncWrite + Unpin),
+    message: &impl Serialize,
+) -> Result<(), TcpConnectivityError> {
+    let mut data = Vec::new();
+    ciborium::into_writer(message, &mut data).expect("Vec can always grow");
+    tx.write_u32(data.len() as u32).await?;
+    tx.write_all(data.as_slice()).await?;
+    tx.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::connectivity::authentication::{Authenticator, MAX_FAILED_USER_LOGINS};
+    use guardian_backup_application::in_memory_repositories::user_repository::InMemoryUserRepository;
+    use guardian_backup_application::server_config::PasswordHashing;
+    use guardian_backup_domain::model::user_identifier::UserIdentifier;
+    use std::net::IpAddr;
+
+    #[test]
+    fn test_users_are_locked_out_from_all_addresses() {
+        let authenticator =
+            Authenticator::new(InMemoryUserRepository::new(), PasswordHashing::default());
+        let user = UserIdentifier::new("TestUser".into());
+        // Every address stays below its own limit
+        for i in 0..MAX_FAILED_USER_LOGINS {
+            let address = IpAddr::from([10, 0, 0, i as u8]);
+            assert_eq!(authenticator.lockout_remaining(address, &user), None);
+            authenticator.record_failed_login(address, &user);
+        }
+
+        let address = IpAddr::from([192, 0, 2, 1]);
+        assert!(authenticator.lockout_remaining(address, &user).is_some());
+        let other = UserIdentifier::new("OtherUser".into());
+        assert_eq!(authenticator.lockout_remaining(address, &other), None);
+    }
+}
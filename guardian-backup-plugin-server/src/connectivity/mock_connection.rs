@@ -1,6 +1,7 @@
 #![cfg(test)]
 
 use guardian_backup_application::in_memory_repositories::blob_repository::InMemoryBlobFetch;
+use guardian_backup_application::model::authentication::Scope;
 use guardian_backup_application::model::call::Call;
 use guardian_backup_application::model::connection_interface::{
     ConnectionServerInterface, IncomingCall, UnhandledIncomingCall,
@@ -22,7 +23,7 @@ impl ConnectionServerInterface for MockConnection {
     type Error = Infallible;
     type Call = IncomingMockCall<CSome<Call>>;
 
-    async fn receive_request(&mut self) -> Result<impl UnhandledIncomingCall, Self::Error> {
+    async fn receive_request(&mut self) -> Result<Self::Call, Self::Error> {
         Ok(IncomingMockCall::new(Call::CreateBackup(Backup::new(
             BackupId("Mock".into()),
             DeviceIdentifier::default(),
@@ -36,6 +37,7 @@ impl ConnectionServerInterface for MockConnection {
 pub struct IncomingMockCall<CallHandled: COptional<Item = Call>> {
     inner: CallHandled,
     user: UserIdentifier,
+    scope: Scope,
 }
 
 impl IncomingMockCall<CNone<Call>> {
@@ -43,6 +45,7 @@ impl IncomingMockCall<CNone<Call>> {
         IncomingMockCall {
             inner: CSome(call),
             user: UserIdentifier::new("MockUser".into()),
+            scope: Scope::Full,
         }
     }
 }
@@ -66,6 +69,10 @@ impl<CallHandled: COptional<Item = Call> + Send> IncomingCall for IncomingMockCa
         &self.user
     }
 
+    fn scope(&self) -> &Scope {
+        &self.scope
+    }
+
     async fn receive_blob(&mut self) -> Result<impl BlobFetch, Self::Error> {
         Ok(InMemoryBlobFetch::new([].into()))
     }
@@ -78,6 +85,7 @@ impl UnhandledIncomingCall for IncomingMockCall<CSome<Call>> {
             IncomingMockCall {
                 inner: CNone::default(),
                 user: self.user,
+                scope: self.scope,
             },
         )
     }
@@ -1,3 +1,4 @@
+pub mod authentication;
 pub mod mock_connection;
 pub mod tcp_connectivity;
-pub mod tokio_blob_fetch;
+pub mod tls;
@@ -1,111 +1,309 @@
-use crate::connectivity::tokio_blob_fetch::TokioBlobFetch;
+use crate::connectivity::authentication::{hello, Authenticator};
+use crate::connectivity::tls::{acceptor, TlsConfigError};
+use guardian_backup_application::model::authentication::Scope;
 use guardian_backup_application::model::call::Call;
 use guardian_backup_application::model::connection_interface::{
     ConnectionServerInterface, IncomingCall, UnhandledIncomingCall,
 };
-use guardian_backup_application::model::response::Response;
-use guardian_backup_application::server_config::ServerConfig;
+use guardian_backup_application::model::frame::RequestId;
+use guardian_backup_application::model::handshake::Hello;
+use guardian_backup_application::model::response::{Rejection, Response};
+use guardian_backup_application::multiplexer::{
+    Demultiplexer, FrameWriter, IncomingFrames, ReadLimits,
+};
+use guardian_backup_application::server_config::{ServerConfig, ServerLimits};
 use guardian_backup_domain::helper::{CNone, COptional, CSome};
 use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
 use guardian_backup_domain::model::user_identifier::UserIdentifier;
-use std::fmt::{Display, Formatter};
-use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
-use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
-use tokio::net::TcpListener;
+use guardian_backup_domain::repositories::user_repository::UserRepository;
+use std::fmt::{Debug, Display, Formatter};
+use std::future::poll_fn;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::task::Poll;
+use tokio::io::{AsyncRead, AsyncWrite, BufReader, BufWriter, WriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio::time::timeout;
+use tokio_rustls::TlsAcceptor;
 
-pub struct TcpServerConnectivity {
-    server_socket: TcpListener,
-}
+/// A connection to a client, encrypted or not
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send + Debug {}
 
-impl TcpServerConnectivity {
-    pub async fn new(config: &ServerConfig) -> std::io::Result<Self> {
-        let listener = TcpListener::bind(config.bind_to).await?;
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + Debug> Transport for T {}
 
-        Ok(Self {
-            server_socket: listener,
-        })
-    }
+type TransportWriter = WriteHalf<Box<dyn Transport>>;
+
+pub struct TcpServerConnectivity<U: UserRepository> {
+    server_sockets: Vec<TcpListener>,
+    /// Listener [accept] polls first, the one after the listener that accepted last
+    next_listener: usize,
+    setup: Arc<ConnectionSetup<U>>,
+    /// A permit per open connection, further connections wait in the backlog of the sockets
+    connections: Arc<Semaphore>,
+    /// Calls read from all connections, every logged in connection has a task reading its frames.
+    /// The tasks stop reading while it is full.
+    calls: mpsc::Receiver<IncomingTcpCall<CSome<Call>>>,
 }
 
-impl ConnectionServerInterface for TcpServerConnectivity {
-    type Error = TcpConnectivityError;
-    type Call = IncomingTcpCall<CSome<Call>>;
+/// What the task of a new connection needs until it is logged in
+struct ConnectionSetup<U: UserRepository> {
+    /// `None` accepts unencrypted connections
+    tls: Option<TlsAcceptor>,
+    authenticator: Authenticator<U>,
+    calls: mpsc::Sender<IncomingTcpCall<CSome<Call>>>,
+    limits: ServerLimits,
+}
 
-    async fn receive_request(&mut self) -> Result<impl UnhandledIncomingCall, Self::Error> {
-        let (incoming, client_address) = self.server_socket.accept().await?;
-        log::info!("New incoming connection from {client_address}");
+impl<U: UserRepository + Send + Sync + 'static> TcpServerConnectivity<U> {
+    /// Listens on the addresses of `config` and lets the users of `users` log in
+    pub async fn new(config: &ServerConfig, users: U) -> Result<Self, TcpConnectivityError> {
+        let tls = match &config.tls {
+            Some(tls_config) => {
+                let (acceptor, fingerprint) = acceptor(tls_config)?;
+                log::info!("Serving TLS with the certificate fingerprint {fingerprint}");
+                Some(acceptor)
+            }
+            None => None,
+        };
 
-        let (rx, tx) = incoming.into_split();
-        let mut rx = BufReader::new(rx);
-        let tx = BufWriter::new(tx);
-
-        let call_length = rx.read_u32().await?;
-        let mut call_data = vec![0; call_length as usize];
-        rx.read_exact(call_data.as_mut_slice()).await?;
-
-        let call = ciborium::from_reader(call_data.as_slice())?;
-        Ok(IncomingTcpCall {
-            user: UserIdentifier::new("TestUser".into()), //TODO actual user authentication
-            rx,
-            tx,
-            call,
+        let mut server_sockets = Vec::with_capacity(config.bind_to.len());
+        for address in &config.bind_to {
+            server_sockets.push(TcpListener::bind(address).await?);
+            log::info!("Listening on {address}");
+        }
+        if server_sockets.is_empty() {
+            return Err(TcpConnectivityError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "No address to listen on",
+            )));
+        }
+
+        let (calls_sender, calls) = mpsc::channel(config.limits.max_connections);
+        let setup = ConnectionSetup {
+            tls,
+            authenticator: Authenticator::new(users, config.password_hashing.clone()),
+            calls: calls_sender,
+            limits: config.limits.clone(),
+        };
+        Ok(Self {
+            server_sockets,
+            next_listener: 0,
+            setup: Arc::new(setup),
+            connections: Arc::new(Semaphore::new(config.limits.max_connections)),
+            calls,
         })
     }
 }
 
-#[derive(Debug)]
-pub struct IncomingTcpCall<CallHandled: COptional<Item = Call>> {
-    rx: BufReader<OwnedReadHalf>,
-    tx: BufWriter<OwnedWriteHalf>,
-    call: CallHandled,
-    user: UserIdentifier,
-}
+impl<U: UserRepository + Send + Sync + 'static> ConnectionSetup<U> {
+    /// Runs the TLS handshake and the login of a new connection, then reads its calls until it
+    /// closes. Failures only concern this connection, so they are logged. Calls over the limits
+    /// and calls that can't be decoded are rejected, the connection stays open.
+    async fn open(
+        self: Arc<Self>,
+        incoming: TcpStream,
+        client_address: SocketAddr,
+        _permit: OwnedSemaphorePermit,
+    ) {
+        log::info!("New incoming connection from {client_address}");
 
-impl<CallHandled: COptional<Item = Call> + Send> IncomingTcpCall<CallHandled> {
-    async fn send_response(
-        &mut self,
-        response: Response,
-    ) -> Result<(), <Self as IncomingCall>::Error> {
-        let mut response_data = Vec::new();
-        ciborium::into_writer(&response, &mut response_data).expect("Vec can always grow");
+        let read_timeout = self.limits.read_timeout;
+        let incoming: Box<dyn Transport> = match &self.tls {
+            Some(acceptor) => match timeout(read_timeout, acceptor.accept(incoming))
+                .await
+                .unwrap_or_else(|e| Err(e.into()))
+            {
+                Ok(stream) => Box::new(stream),
+                Err(e) => {
+                    log::warn!("TLS handshake with {client_address} failed: {e}");
+                    return;
+                }
+            },
+            None => Box::new(incoming),
+        };
+        let (rx, tx) = tokio::io::split(incoming);
+        let mut rx = BufReader::new(rx);
+        let mut tx = BufWriter::new(tx);
+        match timeout(read_timeout, hello(&mut rx, &mut tx))
+            .await
+            .unwrap_or_else(|e| Err(TcpConnectivityError::Io(e.into())))
+        {
+            Ok((client, negotiated)) => log::info!(
+                "{client_address} runs {} with protocol version {} and the features {}",
+                client.agent,
+                negotiated.version,
+                negotiated.features
+            ),
+            Err(e) => {
+                log::warn!("Hello from {client_address} failed: {e}");
+                return;
+            }
+        }
+        let login = timeout(
+            read_timeout,
+            self.authenticator
+                .authenticate(&mut rx, &mut tx, client_address.ip()),
+        )
+        .await
+        .unwrap_or_else(|e| Err(TcpConnectivityError::Io(e.into())));
+        let (user, scope) = match login {
+            Ok(login) => login,
+            Err(e) => {
+                log::warn!("Login from {client_address} failed: {e}");
+                return;
+            }
+        };
 
-        self.tx.write_u32(response_data.len() as u32).await?;
-        self.tx.write_all(response_data.as_slice()).await?;
+        // The login flushed every message, nothing is left in the buffer
+        let writer = FrameWriter::new(tx.into_inner());
+        let demultiplexer = Demultiplexer::new(writer.clone()).with_limits(ReadLimits {
+            max_message_length: self.limits.max_call_length,
+            read_timeout: Some(read_timeout),
+            idle_timeout: Some(self.limits.idle_timeout),
+        });
+        // A permit per call being handled, the frames wait in the socket while none is left
+        let in_flight = Arc::new(Semaphore::new(self.limits.max_calls_per_connection));
+        loop {
+            let permit = match timeout(self.limits.idle_timeout, in_flight.clone().acquire_owned())
+                .await
+            {
+                Ok(permit) => permit.expect("Never closed"),
+                Err(_) => {
+                    log::info!("Closing the connection of {client_address}, its calls don't end");
+                    break;
+                }
+            };
+            let (message, frames) = match demultiplexer.next_request(&mut rx).await {
+                Ok(request) => request,
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    log::info!("{client_address} disconnected");
+                    break;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                    log::info!("Closing the connection of {client_address}, it timed out");
+                    break;
+                }
+                Err(e) => {
+                    log::warn!("Closing the connection of {client_address}: {e}");
+                    break;
+                }
+            };
+            let rejection = match message.oversized {
+                true => Rejection::CallTooLarge {
+                    limit: self.limits.max_call_length,
+                },
+                false => match ciborium::from_reader(message.payload.as_slice()) {
+                    Ok(call) => {
+                        let call = IncomingTcpCall {
+                            request: message.request,
+                            frames: Some(frames),
+                            writer: writer.clone(),
+                            call: CSome(call),
+                            user: user.clone(),
+                            scope: scope.clone(),
+                            _permit: permit,
+                        };
+                        if self.calls.send(call).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                    Err(e) => {
+                        log::warn!("Invalid call from {client_address}: {e}");
+                        Rejection::InvalidCall
+                    }
+                },
+            };
 
-        Ok(())
+            // Answered in the background, reading must never wait for writing. A blob following
+            // the call is skipped as nobody reads its frames.
+            let writer = writer.clone();
+            let response = encode(&Response::Rejected(rejection));
+            tokio::spawn(async move { writer.send(message.request, &response).await });
+        }
+        demultiplexer.close();
+        writer.shutdown().await;
     }
+}
 
-    async fn send_blob(
-        &mut self,
-        mut blob: impl BlobFetch,
-    ) -> Result<(), <Self as IncomingCall>::Error> {
-        debug_assert_eq!(blob.remaining_len(), blob.total_len());
+/// Accepts the next connection on whichever address receives one first. Polling starts at
+/// `next`, which moves past the listener that accepted, so a busy address can't starve the
+/// others.
+async fn accept(
+    listeners: &[TcpListener],
+    next: &mut usize,
+) -> std::io::Result<(TcpStream, SocketAddr)> {
+    poll_fn(|cx| {
+        for offset in 0..listeners.len() {
+            let index = (*next + offset) % listeners.len();
+            if let Poll::Ready(result) = listeners[index].poll_accept(cx) {
+                *next = (index + 1) % listeners.len();
+                return Poll::Ready(result);
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
 
-        self.tx.write_u64(blob.remaining_len()).await?;
+impl<U: UserRepository + Send + Sync + 'static> ConnectionServerInterface
+    for TcpServerConnectivity<U>
+{
+    type Error = TcpConnectivityError;
+    type Call = IncomingTcpCall<CSome<Call>>;
 
-        let mut buf = [0; 1024];
+    /// Connections are accepted in the meantime, each is set up and read on a task of its own
+    async fn receive_request(&mut self) -> Result<Self::Call, Self::Error> {
         loop {
-            let read = blob
-                .read(buf.as_mut_slice())
-                .await
-                .map_err(|e| TcpConnectivityError::BlobFetch(format!("{e:?}").into()))?;
-            if read == 0 {
-                break;
+            let connection = async {
+                let permit = self
+                    .connections
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("Never closed");
+                (
+                    permit,
+                    accept(&self.server_sockets, &mut self.next_listener).await,
+                )
+            };
+            tokio::select! {
+                Some(call) = self.calls.recv() => return Ok(call),
+                (permit, accepted) = connection => {
+                    let (incoming, client_address) = accepted?;
+                    tokio::spawn(self.setup.clone().open(incoming, client_address, permit));
+                }
             }
-
-            self.tx.write_all(buf.split_at(read).0).await?
         }
-        Ok(())
     }
 }
 
+/// A call of a client, the connection may carry other calls of the client at the same time
+#[derive(Debug)]
+pub struct IncomingTcpCall<CallHandled: COptional<Item = Call>> {
+    request: RequestId,
+    /// Taken by [IncomingCall::receive_blob]
+    frames: Option<IncomingFrames<TransportWriter>>,
+    writer: FrameWriter<TransportWriter>,
+    call: CallHandled,
+    user: UserIdentifier,
+    scope: Scope,
+    /// Counts the call towards the calls of its connection until it is dropped
+    _permit: OwnedSemaphorePermit,
+}
+
+fn encode(response: &Response) -> Vec<u8> {
+    let mut response_data = Vec::new();
+    ciborium::into_writer(response, &mut response_data).expect("Vec can always grow");
+    response_data
+}
+
 impl<CallHandled: COptional<Item = Call> + Send> IncomingCall for IncomingTcpCall<CallHandled> {
     type Error = TcpConnectivityError;
 
     async fn answer(&mut self, response: Response) -> Result<(), Self::Error> {
-        self.send_response(response).await?;
-        self.tx.write_u64(0).await?; // Indicate a zero length blob
-        self.tx.flush().await?;
+        self.writer.send(self.request, &encode(&response)).await?;
         Ok(())
     }
 
@@ -114,9 +312,9 @@ impl<CallHandled: COptional<Item = Call> + Send> IncomingCall for IncomingTcpCal
         response: Response,
         blob_data: impl BlobFetch,
     ) -> Result<(), Self::Error> {
-        self.send_response(response).await?;
-        self.send_blob(blob_data).await?;
-        self.tx.flush().await?;
+        self.writer
+            .send_with_blob(self.request, &encode(&response), blob_data)
+            .await?;
         Ok(())
     }
 
@@ -124,13 +322,16 @@ impl<CallHandled: COptional<Item = Call> + Send> IncomingCall for IncomingTcpCal
         &self.user
     }
 
+    fn scope(&self) -> &Scope {
+        &self.scope
+    }
+
     async fn receive_blob(&mut self) -> Result<impl BlobFetch, Self::Error> {
-        let blob_len = self.rx.read_u64().await?;
-        if blob_len == 0 {
-            return Err(TcpConnectivityError::NoBlob);
-        }
-        let fetch = TokioBlobFetch::new(&mut self.rx, blob_len);
-        Ok(fetch)
+        let frames = self.frames.take().ok_or(TcpConnectivityError::NoBlob)?;
+        frames
+            .receive_blob()
+            .await?
+            .ok_or(TcpConnectivityError::NoBlob)
     }
 }
 
@@ -139,10 +340,13 @@ impl UnhandledIncomingCall for IncomingTcpCall<CSome<Call>> {
         (
             self.call.0,
             IncomingTcpCall {
-                rx: self.rx,
-                tx: self.tx,
+                request: self.request,
+                frames: self.frames,
+                writer: self.writer,
                 call: CNone::default(),
                 user: self.user,
+                scope: self.scope,
+                _permit: self._permit,
             },
         )
     }
@@ -158,6 +362,19 @@ pub enum TcpConnectivityError {
     Ciborium(ciborium::de::Error<std::io::Error>),
     BlobFetch(Box<str>),
     NoBlob,
+    InvalidHello,
+    IncompatibleClient(Hello),
+    InvalidLogin,
+    LoginRejected(UserIdentifier),
+    TooManyLoginAttempts,
+    UserRepository(Box<str>),
+    Tls(TlsConfigError),
+}
+
+impl From<TlsConfigError> for TcpConnectivityError {
+    fn from(value: TlsConfigError) -> Self {
+        Self::Tls(value)
+    }
 }
 
 impl From<ciborium::de::Error<std::io::Error>> for TcpConnectivityError {
@@ -179,6 +396,20 @@ impl Display for TcpConnectivityError {
             TcpConnectivityError::Ciborium(inner) => write!(f, "{inner}"),
             TcpConnectivityError::BlobFetch(inner) => write!(f, "{inner}"),
             TcpConnectivityError::NoBlob => write!(f, "NoBLOB"),
+            TcpConnectivityError::InvalidHello => write!(
+                f,
+                "Invalid hello message, the client may predate the protocol versions"
+            ),
+            TcpConnectivityError::IncompatibleClient(client) => write!(
+                f,
+                "Incompatible client {} speaking protocol versions {} to {} with the features {}",
+                client.agent, client.min_version, client.max_version, client.features
+            ),
+            TcpConnectivityError::InvalidLogin => write!(f, "Invalid login message"),
+            TcpConnectivityError::LoginRejected(user) => write!(f, "Wrong password for {user}"),
+            TcpConnectivityError::TooManyLoginAttempts => write!(f, "Too many failed logins"),
+            TcpConnectivityError::UserRepository(inner) => write!(f, "{inner}"),
+            TcpConnectivityError::Tls(inner) => write!(f, "{inner}"),
         }
     }
 }
@@ -187,81 +418,195 @@ impl std::error::Error for TcpConnectivityError {}
 
 #[cfg(test)]
 mod tests {
-    use crate::connectivity::tcp_connectivity::TcpServerConnectivity;
-    use crate::connectivity::tokio_blob_fetch::TokioBlobFetch;
+    use crate::connectivity::tcp_connectivity::{accept, TcpServerConnectivity};
+    use crate::users_file::{hash_password, parse_credential, parse_users, HASH_LENGTH};
+    use argon2::{Algorithm, Argon2, Params, Version};
     use guardian_backup_application::in_memory_repositories::blob_repository::InMemoryBlobFetch;
+    use guardian_backup_application::in_memory_repositories::user_repository::InMemoryUserRepository;
+    use guardian_backup_application::model::authentication::{
+        login_proof, DeviceToken, LoginCall, LoginResponse, Scope,
+    };
     use guardian_backup_application::model::call::Call;
     use guardian_backup_application::model::connection_interface::ConnectionServerInterface;
     use guardian_backup_application::model::connection_interface::IncomingCall;
     use guardian_backup_application::model::connection_interface::UnhandledIncomingCall;
-    use guardian_backup_application::model::response::Response;
+    use guardian_backup_application::model::frame::{FrameHeader, FrameKind, RequestId};
+    use guardian_backup_application::model::handshake::{
+        Features, Hello, HelloResponse, Incompatibility,
+    };
+    use guardian_backup_application::model::response::{Rejection, Response};
+    use guardian_backup_application::multiplexer::{Demultiplexer, FrameWriter, IncomingFrames};
     use guardian_backup_application::server_config::ServerConfig;
     use guardian_backup_domain::model::backup::backup::Backup;
     use guardian_backup_domain::model::blobs::blob_fetch::BlobFetch;
+    use guardian_backup_domain::model::credential::Credential;
+    use guardian_backup_domain::model::device_identifier::DeviceIdentifier;
+    use guardian_backup_domain::model::password_hash_algorithms::PasswordHashAlg;
+    use guardian_backup_domain::model::user_identifier::UserIdentifier;
+    use guardian_backup_domain::repositories::user_repository::UserRepository;
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
     use std::net::SocketAddr;
+    use std::time::Duration;
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
     use tokio::net::tcp::OwnedWriteHalf;
-    use tokio::net::TcpStream;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::time::timeout;
 
-    async fn send_call(addr: SocketAddr) -> TcpStream {
-        let backup = Backup::mock();
-        let call = Call::CreateBackup(backup);
+    /// Users with cheap password hashes, `TestUser` has the password `password`. Returns the
+    /// hash the client derives from it as well.
+    fn test_users() -> (InMemoryUserRepository, Box<[u8]>) {
+        let params = Params::new(8, 1, 1, Some(HASH_LENGTH)).unwrap();
+        let phc = hash_password("password", params.clone()).unwrap();
+        let Ok(Credential::Password {
+            algorithm: PasswordHashAlg::Argon2id { salt, .. },
+            ..
+        }) = parse_credential(&phc)
+        else {
+            panic!("Expected a password credential");
+        };
+        let mut hash = [0; HASH_LENGTH];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(b"password", &salt, &mut hash)
+            .unwrap();
+        let users = parse_users(&format!("TestUser:{phc}")).unwrap().users;
+        (users, hash.into())
+    }
 
+    async fn write_message(conn: &mut TcpStream, message: &impl Serialize) {
         let mut encoded = Vec::new();
-        ciborium::into_writer(&call, &mut encoded).unwrap();
-
-        let mut conn = TcpStream::connect(addr).await.unwrap();
-
+        ciborium::into_writer(message, &mut encoded).unwrap();
         conn.write_u32(encoded.len() as u32).await.unwrap();
         conn.write_all(encoded.as_slice()).await.unwrap();
+    }
 
-        conn
+    async fn read_message<T: DeserializeOwned>(conn: &mut TcpStream) -> T {
+        let length = conn.read_u32().await.unwrap();
+        let mut buf = vec![0; length as usize];
+        conn.read_exact(buf.as_mut_slice()).await.unwrap();
+        ciborium::from_reader(buf.as_slice()).unwrap()
     }
 
-    async fn send_blob(mut stream: OwnedWriteHalf) {
-        let blob_data = [127; 4096];
-        let mut blob = InMemoryBlobFetch::new(blob_data.into());
+    fn encode(message: &impl Serialize) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        ciborium::into_writer(message, &mut encoded).unwrap();
+        encoded
+    }
 
-        stream.write_u64(blob.total_len()).await.unwrap();
-        tokio::spawn(async move {
-            let mut buf = [0; 1000];
+    /// Sends the [Hello] of this build, every connection starts with it
+    async fn hello(conn: &mut TcpStream) {
+        write_message(conn, &Hello::new("test")).await;
+        let response: HelloResponse = read_message(conn).await;
+        assert!(matches!(response, HelloResponse::Accepted { .. }));
+    }
 
-            while blob.remaining_len() > 0 {
-                let read = blob.read(&mut buf).await.unwrap();
-                stream.write_all(buf.split_at(read).0).await.unwrap()
-            }
-            stream.flush().await.unwrap();
-        });
+    /// Runs the login handshake as the client would after deriving `password_hash`
+    async fn login(conn: &mut TcpStream, password_hash: &[u8]) -> LoginResponse {
+        hello(conn).await;
+        let user = UserIdentifier::new("TestUser".into());
+        write_message(
+            conn,
+            &LoginCall::Request {
+                user: user.clone(),
+                device: None,
+            },
+        )
+        .await;
+        let LoginResponse::Challenge { nonce, .. } = read_message(conn).await else {
+            panic!("Expected a challenge");
+        };
+        let proof = login_proof(password_hash, &nonce, &user);
+        write_message(conn, &LoginCall::Proof(proof)).await;
+        read_message(conn).await
     }
 
-    async fn receive_response(stream: &mut TcpStream) -> Response {
-        let response_len = stream.read_u32().await.unwrap();
-        let mut response_buf = Vec::new();
-        response_buf.resize(response_len as usize, 0);
-        stream
-            .read_exact(response_buf.as_mut_slice())
-            .await
-            .unwrap();
+    /// The frames of a logged in connection, like the client sends and receives them
+    struct TestClient {
+        writer: FrameWriter<OwnedWriteHalf>,
+        demultiplexer: Demultiplexer<OwnedWriteHalf>,
+    }
+
+    impl TestClient {
+        fn new(conn: TcpStream) -> Self {
+            let (mut rx, tx) = conn.into_split();
+            let writer = FrameWriter::new(tx);
+            let demultiplexer = Demultiplexer::new(writer.clone());
+            let reader = demultiplexer.clone();
+            tokio::spawn(async move { reader.next_request(&mut rx).await });
+            Self {
+                writer,
+                demultiplexer,
+            }
+        }
+
+        async fn connect(addr: SocketAddr, password_hash: &[u8]) -> Self {
+            let mut conn = TcpStream::connect(addr).await.unwrap();
+            assert_eq!(
+                login(&mut conn, password_hash).await,
+                LoginResponse::Accepted
+            );
+            Self::new(conn)
+        }
+
+        async fn send(&self, request: RequestId, call: &Call) -> IncomingFrames<OwnedWriteHalf> {
+            let frames = self.demultiplexer.register(request).unwrap();
+            self.writer.send(request, &encode(call)).await.unwrap();
+            frames
+        }
 
-        let response = ciborium::de::from_reader(response_buf.as_slice()).unwrap();
+        async fn send_with_blob(
+            &self,
+            request: RequestId,
+            call: &Call,
+            blob: impl BlobFetch,
+        ) -> IncomingFrames<OwnedWriteHalf> {
+            let frames = self.demultiplexer.register(request).unwrap();
+            self.writer
+                .send_with_blob(request, &encode(call), blob)
+                .await
+                .unwrap();
+            frames
+        }
+    }
 
-        response
+    async fn receive_response(frames: &mut IncomingFrames<OwnedWriteHalf>) -> Response {
+        ciborium::from_reader(frames.message().await.unwrap().as_slice()).unwrap()
+    }
+
+    /// Waits until the server closed `conn`, reading whatever it still sends
+    async fn assert_closed(conn: &mut TcpStream) {
+        let mut buf = [0; 1024];
+        let closed = timeout(Duration::from_secs(5), async {
+            while let Ok(1..) = conn.read(&mut buf).await {}
+        });
+        assert!(closed.await.is_ok(), "The server kept the connection open");
     }
 
-    async fn receive_blob(mut stream: TcpStream) -> impl BlobFetch {
-        let total_len = stream.read_u64().await.unwrap();
-        let fetch = TokioBlobFetch::new(stream, total_len);
+    /// Answers every call successfully
+    fn serve(mut server: TcpServerConnectivity<InMemoryUserRepository>) {
+        tokio::spawn(async move {
+            loop {
+                let mut call = server.receive_request().await.unwrap();
+                call.answer(Response::Successful).await.unwrap();
+            }
+        });
+    }
 
-        fetch
+    async fn send_call(addr: SocketAddr, password_hash: &[u8]) -> IncomingFrames<OwnedWriteHalf> {
+        let client = TestClient::connect(addr, password_hash).await;
+        client.send(1, &Call::CreateBackup(Backup::mock())).await
     }
 
     #[tokio::test]
     async fn test_receive_request() {
         let server_config = ServerConfig::test_config();
-        let mut server = TcpServerConnectivity::new(&server_config).await.unwrap();
-        send_call(server_config.bind_to).await;
-
-        let call = server.receive_request().await.unwrap();
+        let (users, hash) = test_users();
+        let mut server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+        let address = server_config.bind_to[0];
+        let (_client, call) = tokio::join!(send_call(address, &hash), server.receive_request());
+        let call = call.unwrap();
 
         if let Call::CreateBackup(_) = call.inner() {
         } else {
@@ -270,12 +615,107 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_send_response() {
+    async fn test_silent_client_does_not_block_others() {
         let server_config = ServerConfig::test_config();
-        let mut server = TcpServerConnectivity::new(&server_config).await.unwrap();
-        let mut client = send_call(server_config.bind_to).await;
+        let (users, hash) = test_users();
+        let mut server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+        let address = server_config.bind_to[0];
+
+        let clients = async {
+            // Connects first but never sends its hello
+            let silent = TcpStream::connect(address).await.unwrap();
+            (silent, send_call(address, &hash).await)
+        };
+        let receive = timeout(Duration::from_secs(5), server.receive_request());
+        let (_clients, call) = tokio::join!(clients, receive);
+
+        assert!(matches!(
+            call.expect("The silent client blocked the server")
+                .unwrap()
+                .inner(),
+            Call::CreateBackup(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_connections_over_the_limit_wait() {
+        let mut server_config = ServerConfig::test_config();
+        server_config.limits.max_connections = 1;
+        let (users, hash) = test_users();
+        let mut server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+        let address = server_config.bind_to[0];
+        tokio::spawn(async move {
+            loop {
+                server.receive_request().await.unwrap();
+            }
+        });
 
-        let mut call = server.receive_request().await.unwrap();
+        let first = TestClient::connect(address, &hash).await;
+        let mut waiting = TcpStream::connect(address).await.unwrap();
+        write_message(&mut waiting, &Hello::new("test")).await;
+        let mut response = [0; 4];
+        let answered = timeout(Duration::from_millis(200), waiting.peek(&mut response)).await;
+        assert!(answered.is_err(), "Answered over the connection limit");
+
+        first.writer.shutdown().await;
+        let response = timeout(Duration::from_secs(5), read_message(&mut waiting)).await;
+        assert!(matches!(response, Ok(HelloResponse::Accepted { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_listen_on_multiple_addresses() {
+        let mut server_config = ServerConfig::test_config();
+        server_config
+            .bind_to
+            .append(&mut ServerConfig::test_config().bind_to);
+        let (users, hash) = test_users();
+        let mut server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+
+        for address in server_config.bind_to {
+            let (_client, call) = tokio::join!(send_call(address, &hash), server.receive_request());
+            let call = call.unwrap();
+            assert!(matches!(call.inner(), Call::CreateBackup(_)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_busy_addresses_do_not_starve_others() {
+        let busy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let other = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let busy_address = busy.local_addr().unwrap();
+        let other_address = other.local_addr().unwrap();
+        // Waiting in the backlogs, connects complete before they are accepted
+        let mut clients = vec![];
+        for address in [busy_address, busy_address, other_address] {
+            clients.push(TcpStream::connect(address).await.unwrap());
+        }
+
+        let listeners = [busy, other];
+        let mut next = 0;
+        let mut accepted = vec![];
+        for _ in 0..2 {
+            let (stream, _) = accept(&listeners, &mut next).await.unwrap();
+            accepted.push(stream.local_addr().unwrap());
+        }
+        assert_eq!(accepted, [busy_address, other_address]);
+    }
+
+    #[tokio::test]
+    async fn test_send_response() {
+        let server_config = ServerConfig::test_config();
+        let (users, hash) = test_users();
+        let mut server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+        let address = server_config.bind_to[0];
+        let (mut client, call) = tokio::join!(send_call(address, &hash), server.receive_request());
+        let mut call = call.unwrap();
 
         call.answer(Response::Successful).await.unwrap();
 
@@ -287,11 +727,21 @@ mod tests {
     #[tokio::test]
     async fn test_receive_blob() {
         let server_config = ServerConfig::test_config();
-        let mut server = TcpServerConnectivity::new(&server_config).await.unwrap();
-        let (_rx, tx) = send_call(server_config.bind_to).await.into_split();
-        send_blob(tx).await;
+        let (users, hash) = test_users();
+        let mut server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+        let address = server_config.bind_to[0];
+        let client = async {
+            let client = TestClient::connect(address, &hash).await;
+            let blob = InMemoryBlobFetch::new([127; 4096].into());
+            client
+                .send_with_blob(1, &Call::CreateBackup(Backup::mock()), blob)
+                .await
+        };
+        let (_client, call) = tokio::join!(client, server.receive_request());
 
-        let mut call = server.receive_request().await.unwrap();
+        let mut call = call.unwrap();
         let mut blob = call.receive_blob().await.unwrap();
 
         let blob_content = blob.read_to_eof().await.unwrap();
@@ -302,10 +752,13 @@ mod tests {
     #[tokio::test]
     async fn test_send_response_with_blob() {
         let server_config = ServerConfig::test_config();
-        let mut server = TcpServerConnectivity::new(&server_config).await.unwrap();
-        let mut client = send_call(server_config.bind_to).await;
-
-        let mut call = server.receive_request().await.unwrap();
+        let (users, hash) = test_users();
+        let mut server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+        let address = server_config.bind_to[0];
+        let (mut client, call) = tokio::join!(send_call(address, &hash), server.receive_request());
+        let mut call = call.unwrap();
 
         call.answer_with_blob(
             Response::Successful,
@@ -317,8 +770,323 @@ mod tests {
         let received_response = receive_response(&mut client).await;
         assert_eq!(received_response, Response::Successful);
 
-        let mut received_blob = receive_blob(client).await;
+        let mut received_blob = client.receive_blob().await.unwrap().unwrap();
         let received_blob_data = received_blob.read_to_eof().await.unwrap();
         assert_eq!(received_blob_data.as_ref(), &[127; 4096])
     }
+
+    #[tokio::test]
+    async fn test_calls_share_a_connection() {
+        let server_config = ServerConfig::test_config();
+        let (users, hash) = test_users();
+        let mut server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+        let address = server_config.bind_to[0];
+        let client = async {
+            let client = TestClient::connect(address, &hash).await;
+            let get_backups = client.send(7, &Call::GetBackups).await;
+            let create_backup = client.send(8, &Call::CreateBackup(Backup::mock())).await;
+            (client, get_backups, create_backup)
+        };
+        let ((_client, mut get_backups, mut create_backup), first) =
+            tokio::join!(client, server.receive_request());
+        let (first_call, mut first) = first.unwrap().into_inner();
+        let (second_call, mut second) = server.receive_request().await.unwrap().into_inner();
+        assert_eq!(first_call, Call::GetBackups);
+        assert!(matches!(second_call, Call::CreateBackup(_)));
+
+        // Answered in the opposite order, each response still reaches its request
+        second.answer(Response::Successful).await.unwrap();
+        first
+            .answer(Response::BackupList(Box::new([])))
+            .await
+            .unwrap();
+        assert_eq!(
+            receive_response(&mut get_backups).await,
+            Response::BackupList(Box::new([]))
+        );
+        assert_eq!(
+            receive_response(&mut create_backup).await,
+            Response::Successful
+        );
+    }
+
+    #[tokio::test]
+    async fn test_calls_over_the_limit_wait() {
+        let mut server_config = ServerConfig::test_config();
+        server_config.limits.max_calls_per_connection = 1;
+        let (users, hash) = test_users();
+        let mut server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+        let address = server_config.bind_to[0];
+        let client = async {
+            let client = TestClient::connect(address, &hash).await;
+            let get_backups = client.send(7, &Call::GetBackups).await;
+            let create_backup = client.send(8, &Call::CreateBackup(Backup::mock())).await;
+            (client, get_backups, create_backup)
+        };
+        let ((_client, mut get_backups, mut create_backup), first) =
+            tokio::join!(client, server.receive_request());
+        let mut first = first.unwrap();
+        assert_eq!(first.inner(), &Call::GetBackups);
+        let waiting = timeout(Duration::from_millis(200), server.receive_request()).await;
+        assert!(
+            waiting.is_err(),
+            "The second call was read while the first ran"
+        );
+
+        first.answer(Response::Successful).await.unwrap();
+        drop(first);
+        let mut second = server.receive_request().await.unwrap();
+        assert!(matches!(second.inner(), Call::CreateBackup(_)));
+        second.answer(Response::Successful).await.unwrap();
+        assert_eq!(
+            receive_response(&mut get_backups).await,
+            Response::Successful
+        );
+        assert_eq!(
+            receive_response(&mut create_backup).await,
+            Response::Successful
+        );
+    }
+
+    #[tokio::test]
+    async fn test_device_token_login_is_scoped_to_the_device() {
+        let server_config = ServerConfig::test_config();
+        let (mut users, _) = test_users();
+        let user = UserIdentifier::new("TestUser".into());
+        let device = DeviceIdentifier::default();
+        let token = DeviceToken::generate();
+        users
+            .create_user_device(&user, &device, token.credential())
+            .await
+            .unwrap();
+        let mut server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+        let address = server_config.bind_to[0];
+
+        let client = async {
+            let mut conn = TcpStream::connect(address).await.unwrap();
+            hello(&mut conn).await;
+            let request = LoginCall::Request {
+                user: user.clone(),
+                device: Some(device.clone()),
+            };
+            write_message(&mut conn, &request).await;
+            let LoginResponse::TokenChallenge { nonce } = read_message(&mut conn).await else {
+                panic!("Expected a token challenge");
+            };
+            let proof = token.login_proof(&nonce, &user);
+            write_message(&mut conn, &LoginCall::Proof(proof)).await;
+            let response: LoginResponse = read_message(&mut conn).await;
+            assert_eq!(response, LoginResponse::Accepted);
+            let client = TestClient::new(conn);
+            client.send(1, &Call::GetBackups).await;
+            client
+        };
+        let (_client, call) = tokio::join!(client, server.receive_request());
+        let (_, call) = call.unwrap().into_inner();
+
+        assert_eq!(call.scope(), &Scope::Device(device));
+    }
+
+    #[tokio::test]
+    async fn test_incompatible_clients_are_told_why() {
+        let server_config = ServerConfig::test_config();
+        let (users, _) = test_users();
+        let mut server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+        let address = server_config.bind_to[0];
+
+        let client = tokio::spawn(async move {
+            let mut conn = TcpStream::connect(address).await.unwrap();
+            let newer = Hello {
+                min_version: 99,
+                max_version: 99,
+                ..Hello::new("test")
+            };
+            write_message(&mut conn, &newer).await;
+            let response: HelloResponse = read_message(&mut conn).await;
+            assert!(matches!(
+                response,
+                HelloResponse::Incompatible(Incompatibility::Version { .. })
+            ));
+
+            let mut conn = TcpStream::connect(address).await.unwrap();
+            let without_chunking = Hello {
+                features: Features::COMPRESSION,
+                ..Hello::new("test")
+            };
+            write_message(&mut conn, &without_chunking).await;
+            let response: HelloResponse = read_message(&mut conn).await;
+            assert_eq!(
+                response,
+                HelloResponse::Incompatible(Incompatibility::MissingFeatures(Features::CHUNKING))
+            );
+        });
+
+        tokio::select! {
+            _ = server.receive_request() => panic!("No client should have been accepted"),
+            client = client => client.unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oversized_and_invalid_calls_are_rejected() {
+        let mut server_config = ServerConfig::test_config();
+        server_config.limits.max_call_length = 64;
+        let (users, hash) = test_users();
+        let server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+        serve(server);
+        let client = TestClient::connect(server_config.bind_to[0], &hash).await;
+
+        let mut oversized = client.demultiplexer.register(1).unwrap();
+        client.writer.send(1, &[0; 1000]).await.unwrap();
+        assert_eq!(
+            receive_response(&mut oversized).await,
+            Response::Rejected(Rejection::CallTooLarge { limit: 64 })
+        );
+
+        let mut garbage = client.demultiplexer.register(2).unwrap();
+        client.writer.send(2, &[0xff, 0x00, 0x17]).await.unwrap();
+        assert_eq!(
+            receive_response(&mut garbage).await,
+            Response::Rejected(Rejection::InvalidCall)
+        );
+
+        // The connection is still usable
+        let mut valid = client.send(3, &Call::GetBackups).await;
+        assert_eq!(receive_response(&mut valid).await, Response::Successful);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_frames_close_only_their_connection() {
+        let server_config = ServerConfig::test_config();
+        let (users, hash) = test_users();
+        let server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+        serve(server);
+        let address = server_config.bind_to[0];
+
+        let unknown_kind = [0, 0, 0, 1, 9, 0, 0, 0, 0];
+        let blob_data_too_long = FrameHeader {
+            request: 1,
+            kind: FrameKind::BlobData,
+            length: u32::MAX,
+        }
+        .to_bytes();
+        let blob_length_without_message = FrameHeader {
+            request: 1,
+            kind: FrameKind::BlobLength,
+            length: 8,
+        }
+        .to_bytes();
+        for garbage in [
+            &unknown_kind[..],
+            &blob_data_too_long,
+            &blob_length_without_message,
+        ] {
+            let mut conn = TcpStream::connect(address).await.unwrap();
+            assert_eq!(login(&mut conn, &hash).await, LoginResponse::Accepted);
+            conn.write_all(garbage).await.unwrap();
+            conn.write_all(&[0; 8]).await.unwrap();
+            assert_closed(&mut conn).await;
+        }
+
+        let mut valid = send_call(address, &hash).await;
+        assert_eq!(receive_response(&mut valid).await, Response::Successful);
+    }
+
+    #[tokio::test]
+    async fn test_idle_and_stalled_connections_are_closed() {
+        let mut server_config = ServerConfig::test_config();
+        server_config.limits.read_timeout = Duration::from_millis(100);
+        server_config.limits.idle_timeout = Duration::from_millis(300);
+        let (users, hash) = test_users();
+        let server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+        serve(server);
+        let address = server_config.bind_to[0];
+
+        let mut without_hello = TcpStream::connect(address).await.unwrap();
+        assert_closed(&mut without_hello).await;
+
+        let mut idle = TcpStream::connect(address).await.unwrap();
+        assert_eq!(login(&mut idle, &hash).await, LoginResponse::Accepted);
+        assert_closed(&mut idle).await;
+
+        let mut stalled = TcpStream::connect(address).await.unwrap();
+        assert_eq!(login(&mut stalled, &hash).await, LoginResponse::Accepted);
+        stalled.write_all(&[0, 0, 0]).await.unwrap();
+        assert_closed(&mut stalled).await;
+
+        // Calls in time are still served
+        let client = TestClient::connect(address, &hash).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let mut valid = client.send(1, &Call::GetBackups).await;
+        assert_eq!(receive_response(&mut valid).await, Response::Successful);
+    }
+
+    #[tokio::test]
+    async fn test_failed_logins_are_locked_out() {
+        let server_config = ServerConfig::test_config();
+        let (users, _) = test_users();
+        let mut server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+        let address = server_config.bind_to[0];
+
+        let client = tokio::spawn(async move {
+            for _ in 0..5 {
+                let mut conn = TcpStream::connect(address).await.unwrap();
+                assert_eq!(login(&mut conn, b"wrong").await, LoginResponse::Rejected);
+            }
+
+            // Even the right password is refused during the lockout
+            let mut conn = TcpStream::connect(address).await.unwrap();
+            hello(&mut conn).await;
+            let user = UserIdentifier::new("TestUser".into());
+            write_message(&mut conn, &LoginCall::Request { user, device: None }).await;
+            let response: LoginResponse = read_message(&mut conn).await;
+            assert!(matches!(response, LoginResponse::TooManyAttempts { .. }));
+        });
+
+        tokio::select! {
+            _ = server.receive_request() => panic!("No login should have been accepted"),
+            client = client => client.unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stored_keys_do_not_log_in() {
+        let server_config = ServerConfig::test_config();
+        let (users, _) = test_users();
+        let user = UserIdentifier::new("TestUser".into());
+        let Ok(Some(Credential::Password { stored_key, .. })) = users.get_credential(&user).await
+        else {
+            panic!("Expected a password credential");
+        };
+        let mut server = TcpServerConnectivity::new(&server_config, users)
+            .await
+            .unwrap();
+        let address = server_config.bind_to[0];
+
+        let client = tokio::spawn(async move {
+            let mut conn = TcpStream::connect(address).await.unwrap();
+            assert_eq!(login(&mut conn, &stored_key).await, LoginResponse::Rejected);
+        });
+
+        tokio::select! {
+            _ = server.receive_request() => panic!("No login should have been accepted"),
+            client = client => client.unwrap(),
+        }
+    }
 }
@@ -0,0 +1,59 @@
+use guardian_backup_application::model::certificate_fingerprint::CertificateFingerprint;
+use guardian_backup_application::server_config::ServerTlsConfig;
+use rustls::crypto::ring::default_provider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use tokio_rustls::TlsAcceptor;
+
+/// Accepts TLS connections with the certificate of `config`, also returns the fingerprint of the
+/// server certificate so it can be logged for clients to pin
+pub fn acceptor(
+    config: &ServerTlsConfig,
+) -> Result<(TlsAcceptor, CertificateFingerprint), TlsConfigError> {
+    let chain = CertificateDer::pem_file_iter(&config.certificate_chain)
+        .and_then(|e| e.collect::<Result<Vec<_>, _>>())
+        .map_err(TlsConfigError::CertificateChain)?;
+    let Some(fingerprint) = chain.first().map(|e| CertificateFingerprint::of(e)) else {
+        return Err(TlsConfigError::CertificateChain(
+            rustls::pki_types::pem::Error::NoItemsFound,
+        ));
+    };
+    let key =
+        PrivateKeyDer::from_pem_file(&config.private_key).map_err(TlsConfigError::PrivateKey)?;
+
+    let server_config = rustls::ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(chain, key)?;
+    Ok((TlsAcceptor::from(Arc::new(server_config)), fingerprint))
+}
+
+#[derive(Debug)]
+pub enum TlsConfigError {
+    CertificateChain(rustls::pki_types::pem::Error),
+    PrivateKey(rustls::pki_types::pem::Error),
+    Rustls(rustls::Error),
+}
+
+impl From<rustls::Error> for TlsConfigError {
+    fn from(value: rustls::Error) -> Self {
+        TlsConfigError::Rustls(value)
+    }
+}
+
+impl Display for TlsConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsConfigError::CertificateChain(inner) => {
+                write!(f, "Cannot read the certificate chain: {inner}")
+            }
+            TlsConfigError::PrivateKey(inner) => write!(f, "Cannot read the private key: {inner}"),
+            TlsConfigError::Rustls(inner) => write!(f, "Invalid certificate or key: {inner}"),
+        }
+    }
+}
+
+impl Error for TlsConfigError {}
@@ -1 +1,2 @@
 pub mod connectivity;
+pub mod users_file;
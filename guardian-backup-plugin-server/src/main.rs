@@ -1,26 +1,281 @@
 #![allow(async_fn_in_trait)]
 
+use argon2::Params;
+use clap::{Parser, Subcommand};
+use guardian_backup_application::blake_hash_service::BlakeHasher;
 use guardian_backup_application::in_memory_repositories::backup_repository::InMemoryBackupRepository;
 use guardian_backup_application::in_memory_repositories::blob_repository::InMemoryBlobRepository;
 use guardian_backup_application::model::connection_interface::ConnectionServerInterface;
-use guardian_backup_application::server_config::ServerConfig;
+use guardian_backup_application::model::response::ToErrorCode;
+use guardian_backup_application::server_config::{
+    GarbageCollection, ServerConfig, ServerLimits, ServerTlsConfig, MIN_GRACE_PERIOD,
+};
 use guardian_backup_application::server_service::{MainServerService, ServerService};
+use guardian_backup_application::shared_repository::SharedRepository;
+use guardian_backup_domain::hash_service::HashService;
+use guardian_backup_domain::repositories::backup_repository::BackupRepository;
+use guardian_backup_domain::repositories::blob_repository::BlobRepository;
+use guardian_backup_domain::repositories::user_repository::UserRepository;
 use guardian_backup_plugin_server::connectivity::tcp_connectivity::TcpServerConnectivity;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+use tokio::task::LocalSet;
+use users_file::UsersFileRepository;
+
+/// Wait after accepting a connection failed
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
 
 pub mod connectivity;
+pub mod users_file;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Server storing the backups of guardian clients",
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    /// Listen on these addresses, e.g. `0.0.0.0:8998,[::]:8998`
+    #[arg(
+        long,
+        env = "GUARDIAN_LISTEN",
+        value_delimiter = ',',
+        default_value = "0.0.0.0:8998"
+    )]
+    listen: Vec<SocketAddr>,
+    /// File with a `name:hash` line per user, the hash is printed by `hash-password`. The server
+    /// writes the devices registered into it, so it and its directory must be writable.
+    #[arg(long, env = "GUARDIAN_USERS_FILE", default_value = "users")]
+    users: PathBuf,
+    /// PEM file with the server certificate, followed by intermediate certificates if any
+    #[arg(
+        long,
+        env = "GUARDIAN_CERTIFICATE",
+        requires = "private_key",
+        required_unless_present = "insecure"
+    )]
+    certificate: Option<PathBuf>,
+    /// PEM file with the private key of the server certificate
+    #[arg(long, env = "GUARDIAN_PRIVATE_KEY", requires = "certificate")]
+    private_key: Option<PathBuf>,
+    /// Serve unencrypted TCP instead of TLS, passwords and files can be read on the network
+    #[arg(long, conflicts_with_all = ["certificate", "private_key"])]
+    insecure: bool,
+    /// Connections served at once, further clients wait until one closes
+    #[arg(
+        long,
+        env = "GUARDIAN_MAX_CONNECTIONS",
+        default_value_t = 64,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    max_connections: u32,
+    /// Calls of one connection handled at once, its frames aren't read while that many are
+    #[arg(
+        long,
+        env = "GUARDIAN_MAX_CALLS_PER_CONNECTION",
+        default_value_t = 16,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    max_calls_per_connection: u32,
+    /// Longest encoded call in bytes, longer ones are rejected
+    #[arg(long, env = "GUARDIAN_MAX_CALL_LENGTH", default_value_t = ServerLimits::default().max_call_length)]
+    max_call_length: u32,
+    /// Longest blob in bytes, longer ones are rejected
+    #[arg(long, env = "GUARDIAN_MAX_BLOB_LENGTH", default_value_t = ServerLimits::default().max_blob_length)]
+    max_blob_length: u64,
+    /// Most bytes of blobs a user may store, raise it for users backing up more
+    #[arg(long, env = "GUARDIAN_MAX_USER_UPLOAD", default_value_t = ServerLimits::default().max_user_upload)]
+    max_user_upload: u64,
+    /// Seconds the login and every frame get to arrive once begun
+    #[arg(
+        long,
+        env = "GUARDIAN_READ_TIMEOUT",
+        default_value_t = 30,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    read_timeout: u64,
+    /// Seconds after which connections sending nothing are closed
+    #[arg(
+        long,
+        env = "GUARDIAN_IDLE_TIMEOUT",
+        default_value_t = 300,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    idle_timeout: u64,
+    /// Minutes between two deletions of the blobs no snapshot references
+    #[arg(
+        long,
+        env = "GUARDIAN_GC_INTERVAL",
+        default_value_t = 60,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    gc_interval: u64,
+    /// Hours unreferenced blobs are kept after their upload, interrupted backups can be resumed
+    /// within half of it
+    #[arg(
+        long,
+        env = "GUARDIAN_GC_GRACE_PERIOD",
+        default_value_t = 7 * 24,
+        value_parser = clap::value_parser!(u64).range(MIN_GRACE_PERIOD.as_secs() / 3600..)
+    )]
+    gc_grace_period: u64,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Read a password from stdin and print the users file line for it
+    HashPassword { user: String },
+}
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() {
-    let backup_repository = InMemoryBackupRepository::new();
+async fn main() -> ExitCode {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let cli = Cli::parse();
+
+    if let Some(Command::HashPassword { user }) = cli.command {
+        return match hash_password(&user) {
+            Ok(line) => {
+                println!("{line}");
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("Error: {e}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let users_file = match users_file::load_users(&cli.users).await {
+        Ok(users_file) => users_file,
+        Err(e) => {
+            eprintln!("Error: {e} ({})", cli.users.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    let users = users_file.users;
+    if users.is_empty() {
+        log::warn!("No users in {}, nobody can log in", cli.users.display());
+    }
+
+    // Logins and device registrations share the users, the calls handled at once all repositories
+    let users = SharedRepository::new(UsersFileRepository::new(users, cli.users.clone()));
+    let backup_repository = SharedRepository::new(InMemoryBackupRepository::new());
     let blob_repository = InMemoryBlobRepository::new();
-    let server_config = ServerConfig::default();
+    let tls = cli
+        .certificate
+        .zip(cli.private_key)
+        .map(|(certificate_chain, private_key)| ServerTlsConfig {
+            certificate_chain,
+            private_key,
+        });
+    if tls.is_none() {
+        log::warn!("Serving unencrypted TCP, passwords and files can be read on the network");
+    }
+    let server_config = ServerConfig {
+        bind_to: cli.listen,
+        tls,
+        limits: ServerLimits {
+            max_connections: cli.max_connections as usize,
+            max_calls_per_connection: cli.max_calls_per_connection as usize,
+            max_call_length: cli.max_call_length,
+            max_blob_length: cli.max_blob_length,
+            max_user_upload: cli.max_user_upload,
+            read_timeout: Duration::from_secs(cli.read_timeout),
+            idle_timeout: Duration::from_secs(cli.idle_timeout),
+        },
+        garbage_collection: GarbageCollection {
+            interval: Duration::from_secs(cli.gc_interval * 60),
+            grace_period: Duration::from_secs(cli.gc_grace_period * 60 * 60),
+        },
+        password_hashing: users_file.password_hashing,
+    };
 
-    let mut service = MainServerService::new(backup_repository, blob_repository);
+    let service = MainServerService::new(
+        backup_repository,
+        blob_repository,
+        users.clone(),
+        HashService::new(vec![&BlakeHasher()]),
+    )
+    .with_limits(&server_config.limits);
 
-    let mut connection = TcpServerConnectivity::new(&server_config).await.unwrap();
+    let mut connection = match TcpServerConnectivity::new(&server_config, users).await {
+        Ok(connection) => connection,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
 
+    // Calls are handled on tasks of their own, the repositories need not be `Send` for that
+    LocalSet::new()
+        .run_until(async move {
+            tokio::task::spawn_local(collect_garbage(
+                service.clone(),
+                server_config.garbage_collection,
+            ));
+            loop {
+                let request = match connection.receive_request().await {
+                    Ok(request) => request,
+                    Err(e) => {
+                        // E.g. out of file descriptors, give connections time to close
+                        log::error!("Cannot accept connections: {e}");
+                        tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                        continue;
+                    }
+                };
+                let mut service = service.clone();
+                tokio::task::spawn_local(async move {
+                    if let Err(e) = service.handle_incoming_request(request).await {
+                        log::warn!("Handling a call failed: {e}");
+                    }
+                });
+            }
+        })
+        .await
+}
+
+async fn collect_garbage<B, L, U>(
+    mut service: MainServerService<B, L, U>,
+    garbage_collection: GarbageCollection,
+) where
+    B: BackupRepository,
+    L: BlobRepository,
+    U: UserRepository,
+    B::Error: ToErrorCode,
+    L::Error: ToErrorCode,
+    U::Error: ToErrorCode,
+{
+    let mut interval = tokio::time::interval(garbage_collection.interval);
+    // The first tick completes at once, nothing is old enough right after the start
+    interval.tick().await;
     loop {
-        let request = connection.receive_request().await.unwrap();
-        service.handle_incoming_request(request).await.unwrap();
+        interval.tick().await;
+        match service
+            .collect_garbage(garbage_collection.grace_period)
+            .await
+        {
+            Ok(0) => {}
+            Ok(deleted) => log::info!("Deleted {deleted} blobs no snapshot references"),
+            Err(e) => log::warn!("Collecting garbage failed: {e}"),
+        }
     }
 }
+
+fn hash_password(user: &str) -> Result<String, Box<dyn std::error::Error>> {
+    if user.is_empty() || user.contains(':') {
+        return Err("The user name must not be empty or contain ':'".into());
+    }
+    let mut password = String::new();
+    std::io::stdin().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err("Expected the password on stdin".into());
+    }
+
+    let phc = users_file::hash_password(password, Params::default())?;
+    Ok(format!("{user}:{phc}"))
+}
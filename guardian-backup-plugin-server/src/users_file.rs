@@ -0,0 +1,501 @@
+use argon2::password_hash::{Ident, Output, ParamsString, PasswordHash, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use guardian_backup_application::in_memory_repositories::user_repository::{
+    InMemoryUserRepository, UserRepositoryError,
+};
+use guardian_backup_application::model::authentication::stored_key;
+use guardian_backup_application::model::response::{ErrorCode, ToErrorCode};
+use guardian_backup_application::server_config::PasswordHashing;
+use guardian_backup_domain::model::credential::Credential;
+use guardian_backup_domain::model::device_identifier::DeviceIdentifier;
+use guardian_backup_domain::model::password_hash_algorithms::PasswordHashAlg;
+use guardian_backup_domain::model::user::User;
+use guardian_backup_domain::model::user_identifier::UserIdentifier;
+use guardian_backup_domain::repositories::user_repository::UserRepository;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+/// Length of the password hashes, the client derives the same length on login
+pub const HASH_LENGTH: usize = 32;
+
+/// Algorithm of the entries, the Argon2id hash of the password is only stored as its
+/// [stored_key]
+const SCRAM_ARGON2ID_IDENT: Ident = Ident::new_unwrap("scram-argon2id");
+
+/// Starts the entries of devices after the user name, no PHC string does
+const DEVICE_PREFIX: &str = "device:";
+
+pub struct UsersFile {
+    pub users: InMemoryUserRepository,
+    /// Parameters most passwords are hashed with
+    pub password_hashing: PasswordHashing,
+}
+
+/// Reads a users file with one `name:$scram-argon2id$v=19$m=…,t=…,p=…$salt$key` entry per line.
+/// The server adds a `name:device:key:device name` line for each device, `key` being the hex
+/// stored key of its token. Empty lines and lines starting with `#` are ignored.
+pub async fn load_users(path: &Path) -> Result<UsersFile, UsersFileError> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .map_err(UsersFileError::Io)?;
+    parse_users(&content)
+}
+
+pub fn parse_users(content: &str) -> Result<UsersFile, UsersFileError> {
+    let mut users = InMemoryUserRepository::new();
+    let mut params_used = HashMap::new();
+    let mut devices = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |reason: &str| UsersFileError::InvalidLine {
+            line: index + 1,
+            reason: reason.into(),
+        };
+        let (name, phc) = line
+            .split_once(':')
+            .ok_or_else(|| invalid("expected name:hash"))?;
+        if let Some(device) = phc.strip_prefix(DEVICE_PREFIX) {
+            let (key, device) = device
+                .split_once(':')
+                .ok_or_else(|| invalid("expected name:device:key:device name"))?;
+            let stored_key = decode_hex(key).ok_or_else(|| invalid("invalid device key"))?;
+            let Ok(device) = device.parse::<DeviceIdentifier>();
+            devices.push((
+                UserIdentifier::new(name.into()),
+                device,
+                Credential::DeviceToken { stored_key },
+            ));
+            continue;
+        }
+        let credential = parse_credential(phc).map_err(invalid)?;
+        if phc.starts_with("$argon2id$") {
+            log::warn!(
+                "The password of {name} in line {} is stored as a plain Argon2id hash, which \
+                 logs in as well. Replace it with the line printed by hash-password.",
+                index + 1
+            );
+        }
+        if let Credential::Password {
+            algorithm:
+                PasswordHashAlg::Argon2id {
+                    memory_cost,
+                    iterations,
+                    parallelism,
+                    ..
+                },
+            ..
+        } = &credential
+        {
+            *params_used
+                .entry((*memory_cost, *iterations, *parallelism))
+                .or_insert(0) += 1;
+        }
+        users.set_credential(UserIdentifier::new(name.into()), credential);
+    }
+    for (user, device, token) in devices {
+        if users.set_device(&user, &device, token).is_err() {
+            log::warn!("Dropping the device {device} of {user}, who has no password entry");
+        }
+    }
+
+    let password_hashing = params_used
+        .into_iter()
+        .max_by_key(|(params, count)| (*count, *params))
+        .map(
+            |((memory_cost, iterations, parallelism), _)| PasswordHashing {
+                memory_cost,
+                iterations,
+                parallelism,
+            },
+        )
+        .unwrap_or_default();
+    Ok(UsersFile {
+        users,
+        password_hashing,
+    })
+}
+
+/// Converts a password entry in the PHC string format into a [Credential]. Plain Argon2id
+/// hashes of older users files are converted into their stored key.
+pub fn parse_credential(phc: &str) -> Result<Credential, &'static str> {
+    let parsed = PasswordHash::new(phc).map_err(|_| "not a PHC string")?;
+    if parsed.algorithm != SCRAM_ARGON2ID_IDENT && parsed.algorithm != argon2::ARGON2ID_IDENT {
+        return Err("only scram-argon2id hashes are supported");
+    }
+    let params = Params::try_from(&parsed).map_err(|_| "invalid argon2 parameters")?;
+    let hash = parsed.hash.ok_or("missing hash")?;
+    if hash.len() != HASH_LENGTH {
+        return Err("hash must be 32 bytes long");
+    }
+    let stored_key = match parsed.algorithm == argon2::ARGON2ID_IDENT {
+        true => stored_key(hash.as_bytes()),
+        false => hash.as_bytes().try_into().expect("Length checked above"),
+    };
+    let mut salt = [0; 64];
+    let salt = parsed
+        .salt
+        .ok_or("missing salt")?
+        .decode_b64(&mut salt)
+        .map_err(|_| "invalid salt")?;
+
+    Ok(Credential::Password {
+        algorithm: PasswordHashAlg::Argon2id {
+            salt: salt.into(),
+            parallelism: params.p_cost(),
+            memory_cost: params.m_cost(),
+            iterations: params.t_cost(),
+            version: parsed.version.unwrap_or(Version::V0x13.into()),
+        },
+        stored_key: stored_key.into(),
+    })
+}
+
+/// The users of a users file, writing their devices back into it whenever they change, so device
+/// tokens still log in after a restart
+pub struct UsersFileRepository {
+    users: InMemoryUserRepository,
+    path: PathBuf,
+}
+
+impl UsersFileRepository {
+    pub fn new(users: InMemoryUserRepository, path: PathBuf) -> Self {
+        Self { users, path }
+    }
+
+    /// Replaces the device lines of the file with the current devices, keeping every other line.
+    /// The file is replaced at once, so a failed write never loses the passwords.
+    async fn save_devices(&self) -> Result<(), std::io::Error> {
+        let content = tokio::fs::read_to_string(&self.path).await?;
+        let mut devices = self
+            .users
+            .devices()
+            .filter_map(|(user, device, token)| match token {
+                Credential::DeviceToken { stored_key } => Some(format!(
+                    "{user}:{DEVICE_PREFIX}{}:{device}",
+                    encode_hex(stored_key)
+                )),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        devices.sort();
+        let mut lines = content
+            .lines()
+            .filter(|line| !is_device_line(line))
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        lines.extend(devices);
+
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let mut file = tokio::fs::File::create(&temporary).await?;
+        #[cfg(unix)]
+        file.set_permissions(tokio::fs::metadata(&self.path).await?.permissions())
+            .await?;
+        file.write_all((lines.join("\n") + "\n").as_bytes()).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&temporary, &self.path).await
+    }
+
+    async fn save(&self) -> Result<(), UsersFileRepositoryError> {
+        self.save_devices().await.map_err(|e| {
+            log::error!("Cannot save the devices to {}: {e}", self.path.display());
+            UsersFileRepositoryError::Save(e)
+        })
+    }
+}
+
+impl UserRepository for UsersFileRepository {
+    type Error = UsersFileRepositoryError;
+
+    async fn get_user(&self, identifier: &UserIdentifier) -> Result<User, Self::Error> {
+        Ok(self.users.get_user(identifier).await?)
+    }
+
+    async fn get_credential(
+        &self,
+        identifier: &UserIdentifier,
+    ) -> Result<Option<Credential>, Self::Error> {
+        Ok(self.users.get_credential(identifier).await?)
+    }
+
+    async fn get_device_credential(
+        &self,
+        user: &UserIdentifier,
+        device: &DeviceIdentifier,
+    ) -> Result<Option<Credential>, Self::Error> {
+        Ok(self.users.get_device_credential(user, device).await?)
+    }
+
+    async fn get_user_devices(
+        &self,
+        user: &UserIdentifier,
+    ) -> Result<Cow<'_, [DeviceIdentifier]>, Self::Error> {
+        Ok(self.users.get_user_devices(user).await?)
+    }
+
+    /// Users without a password can't be written to the file, they are only kept until a restart
+    async fn create_user(&mut self, user: &User) -> Result<(), Self::Error> {
+        Ok(self.users.create_user(user).await?)
+    }
+
+    async fn create_user_device(
+        &mut self,
+        user: &UserIdentifier,
+        device: &DeviceIdentifier,
+        token: Credential,
+    ) -> Result<(), Self::Error> {
+        let name = device.to_string();
+        if name.is_empty() || name.trim() != name || name.contains(char::is_control) {
+            return Err(UsersFileRepositoryError::InvalidDeviceName);
+        }
+        self.users.create_user_device(user, device, token).await?;
+        self.save().await
+    }
+
+    async fn delete_user(&mut self, identifier: &UserIdentifier) -> Result<(), Self::Error> {
+        self.users.delete_user(identifier).await?;
+        self.save().await
+    }
+
+    async fn delete_device(
+        &mut self,
+        user: &UserIdentifier,
+        device: &DeviceIdentifier,
+    ) -> Result<(), Self::Error> {
+        self.users.delete_device(user, device).await?;
+        self.save().await
+    }
+}
+
+fn is_device_line(line: &str) -> bool {
+    let line = line.trim();
+    !line.starts_with('#')
+        && line
+            .split_once(':')
+            .is_some_and(|(_, entry)| entry.starts_with(DEVICE_PREFIX))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|e| format!("{e:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Box<[u8]>> {
+    if hex.is_empty() || !hex.len().is_multiple_of(2) || !hex.bytes().all(|e| e.is_ascii_hexdigit())
+    {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Hashes `password` with a random salt into the PHC string format used by the users file.
+/// Only the [stored_key] of the hash is written, which can't be used to log in.
+pub fn hash_password(password: &str, params: Params) -> Result<String, UsersFileError> {
+    let error = |e: &dyn Display| UsersFileError::Hash(e.to_string());
+    let mut salt = [0; 16];
+    getrandom::getrandom(&mut salt).map_err(|e| error(&e))?;
+    let mut hash = [0; HASH_LENGTH];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+        .hash_password_into(password.as_bytes(), &salt, &mut hash)
+        .map_err(|e| error(&e))?;
+
+    let salt = SaltString::encode_b64(&salt).map_err(|e| error(&e))?;
+    let phc = PasswordHash {
+        algorithm: SCRAM_ARGON2ID_IDENT,
+        version: Some(Version::V0x13.into()),
+        params: ParamsString::try_from(&params).map_err(|e| error(&e))?,
+        salt: Some(salt.as_salt()),
+        hash: Some(Output::new(&stored_key(&hash)).map_err(|e| error(&e))?),
+    };
+    Ok(phc.to_string())
+}
+
+#[derive(Debug)]
+pub enum UsersFileError {
+    Io(std::io::Error),
+    InvalidLine { line: usize, reason: Box<str> },
+    Hash(String),
+}
+
+impl Display for UsersFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsersFileError::Io(inner) => write!(f, "Cannot read the users file: {inner}"),
+            UsersFileError::InvalidLine { line, reason } => {
+                write!(f, "Invalid users file entry in line {line}: {reason}")
+            }
+            UsersFileError::Hash(inner) => write!(f, "Cannot hash the password: {inner}"),
+        }
+    }
+}
+
+impl Error for UsersFileError {}
+
+#[derive(Debug)]
+pub enum UsersFileRepositoryError {
+    Users(UserRepositoryError),
+    Save(std::io::Error),
+    /// Device names are written to a line of their own in the users file
+    InvalidDeviceName,
+}
+
+impl Display for UsersFileRepositoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsersFileRepositoryError::Users(inner) => write!(f, "{inner}"),
+            UsersFileRepositoryError::Save(inner) => {
+                write!(f, "Cannot save the users file: {inner}")
+            }
+            UsersFileRepositoryError::InvalidDeviceName => write!(
+                f,
+                "Device names can't be empty, contain control characters or surrounding spaces"
+            ),
+        }
+    }
+}
+
+impl Error for UsersFileRepositoryError {}
+
+impl From<UserRepositoryError> for UsersFileRepositoryError {
+    fn from(value: UserRepositoryError) -> Self {
+        UsersFileRepositoryError::Users(value)
+    }
+}
+
+impl ToErrorCode for UsersFileRepositoryError {
+    fn error_code(&self) -> ErrorCode {
+        match self {
+            UsersFileRepositoryError::Users(inner) => inner.error_code(),
+            UsersFileRepositoryError::Save(_) => ErrorCode::Internal,
+            UsersFileRepositoryError::InvalidDeviceName => ErrorCode::InvalidRequest,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::users_file::{hash_password, parse_users, UsersFileRepository, HASH_LENGTH};
+    use argon2::password_hash::{PasswordHasher, SaltString};
+    use argon2::{Algorithm, Argon2, Params, Version};
+    use guardian_backup_application::model::authentication::stored_key;
+    use guardian_backup_domain::model::credential::Credential;
+    use guardian_backup_domain::model::device_identifier::DeviceIdentifier;
+    use guardian_backup_domain::model::password_hash_algorithms::PasswordHashAlg;
+    use guardian_backup_domain::model::user_identifier::UserIdentifier;
+    use guardian_backup_domain::repositories::user_repository::UserRepository;
+
+    #[tokio::test]
+    async fn test_parse_users() {
+        let params = Params::new(8, 1, 1, Some(HASH_LENGTH)).unwrap();
+        let phc = hash_password("secret", params).unwrap();
+        assert!(phc.starts_with("$scram-argon2id$v=19$m=8,t=1,p=1$"));
+        let file = parse_users(&format!("# Backup users\n\nalice:{phc}\n")).unwrap();
+        assert_eq!(file.password_hashing.memory_cost, 8);
+
+        let credential = file
+            .users
+            .get_credential(&UserIdentifier::new("alice".into()))
+            .await
+            .unwrap();
+        let Some(Credential::Password {
+            algorithm:
+                PasswordHashAlg::Argon2id {
+                    salt, memory_cost, ..
+                },
+            stored_key,
+        }) = credential
+        else {
+            panic!("Expected the credential of alice");
+        };
+        assert_eq!(salt.len(), 16);
+        assert_eq!(memory_cost, 8);
+        assert_eq!(stored_key.len(), HASH_LENGTH);
+
+        assert!(parse_users("bob").is_err());
+        assert!(parse_users("bob:$pbkdf2-sha256$i=1000$c2FsdA$aGFzaA").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_plain_argon2id_hashes_are_converted() {
+        let params = Params::new(8, 1, 1, Some(HASH_LENGTH)).unwrap();
+        let salt = SaltString::encode_b64(b"0123456789abcdef").unwrap();
+        let phc = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password(b"secret", &salt)
+            .unwrap();
+        let file = parse_users(&format!("bob:{phc}")).unwrap();
+
+        let credential = file
+            .users
+            .get_credential(&UserIdentifier::new("bob".into()))
+            .await
+            .unwrap();
+        let Some(Credential::Password {
+            stored_key: key, ..
+        }) = credential
+        else {
+            panic!("Expected the credential of bob");
+        };
+        assert_eq!(*key, stored_key(phc.hash.unwrap().as_bytes()));
+    }
+
+    #[tokio::test]
+    async fn test_devices_are_written_to_the_users_file() {
+        let params = Params::new(8, 1, 1, Some(HASH_LENGTH)).unwrap();
+        let content = format!(
+            "# Backup users\nalice:{}\nbob:{}\n",
+            hash_password("secret", params.clone()).unwrap(),
+            hash_password("secret", params).unwrap()
+        );
+        let path = std::env::temp_dir().join(format!("guardian-users-{}", std::process::id()));
+        tokio::fs::write(&path, &content).await.unwrap();
+        let alice = UserIdentifier::new("alice".into());
+        let laptop = "Alice's laptop: work".parse::<DeviceIdentifier>().unwrap();
+        let phone = "phone".parse::<DeviceIdentifier>().unwrap();
+        let token = |key: &[u8]| Credential::DeviceToken {
+            stored_key: key.into(),
+        };
+
+        let mut users =
+            UsersFileRepository::new(parse_users(&content).unwrap().users, path.clone());
+        users
+            .create_user_device(&alice, &laptop, token(&[1, 2, 254]))
+            .await
+            .unwrap();
+        users
+            .create_user_device(&alice, &phone, token(&[3]))
+            .await
+            .unwrap();
+        users.delete_device(&alice, &phone).await.unwrap();
+        let invalid = "tablet\nbob:device:00:x"
+            .parse::<DeviceIdentifier>()
+            .unwrap();
+        assert!(users
+            .create_user_device(&alice, &invalid, token(&[4]))
+            .await
+            .is_err());
+
+        let saved = tokio::fs::read_to_string(&path).await.unwrap();
+        tokio::fs::remove_file(&path).await.unwrap();
+        assert_eq!(
+            saved,
+            format!("{content}alice:device:0102fe:Alice's laptop: work\n")
+        );
+        let users = parse_users(&saved).unwrap().users;
+        let Some(Credential::DeviceToken { stored_key }) =
+            users.get_device_credential(&alice, &laptop).await.unwrap()
+        else {
+            panic!("Expected the token of the laptop");
+        };
+        assert_eq!(*stored_key, [1, 2, 254]);
+        assert_eq!(users.get_user_devices(&alice).await.unwrap().len(), 1);
+    }
+}